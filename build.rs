@@ -7,12 +7,21 @@ use std::env;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::process::Command;
 
 fn main() {
     for (name, value) in env::vars() {
         println!("{} = {}", name, value);
     }
 
+    // extensions have to be built by the same compiler as the host
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc).arg("--version").output().unwrap();
+    println!(
+        "cargo:rustc-env=SLITHER_RUSTC_VERSION={}",
+        String::from_utf8_lossy(&version.stdout).trim()
+    );
+
     let path = Path::new(&env::var("OUT_DIR").unwrap()).join("unicode_name_map_gen.rs");
     let mut file = BufWriter::new(File::create(&path).unwrap());
 
@@ -1,6 +1,8 @@
+use std::any::TypeId;
 use std::cell::{Cell, RefCell};
 use std::mem;
 use std::ptr::NonNull;
+use std::time::{Duration, Instant};
 use trace::{Finalize, Trace};
 
 const INITIAL_THRESHOLD: usize = 100;
@@ -11,9 +13,51 @@ const INITIAL_THRESHOLD: usize = 100;
 // number of `new` calls):
 const USED_SPACE_RATIO: f64 = 0.7;
 
+/// Tuning for the collector on the current thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcConfig {
+    /// Bytes allocated before the first collection.
+    pub threshold: usize,
+    /// After a collection the threshold is raised until no more than this
+    /// fraction of it is in use. Lower values collect less often, in larger
+    /// pauses.
+    pub used_space_ratio: f64,
+}
+
+impl Default for GcConfig {
+    fn default() -> GcConfig {
+        GcConfig {
+            threshold: INITIAL_THRESHOLD,
+            used_space_ratio: USED_SPACE_RATIO,
+        }
+    }
+}
+
+/// What the collector on the current thread has done so far.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GcStats {
+    pub bytes_allocated: usize,
+    /// Boxes currently allocated, including garbage not yet collected.
+    pub boxes: usize,
+    pub collections: usize,
+    pub last_pause: Duration,
+    pub longest_pause: Duration,
+    pub total_pause: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GcPhase {
+    Before,
+    After,
+}
+
 struct GcState {
+    // called around every collection on this thread
+    hook: Option<Box<dyn Fn(GcPhase, &GcStats)>>,
     bytes_allocated: usize,
     threshold: usize,
+    used_space_ratio: f64,
+    stats: GcStats,
     boxes_start: Option<NonNull<GcBox<Trace>>>,
 }
 
@@ -58,8 +102,11 @@ pub fn finalizer_safe() -> bool {
 
 /// The garbage collector's internal state.
 thread_local!(static GC_STATE: RefCell<GcState> = RefCell::new(GcState {
+    hook: None,
     bytes_allocated: 0,
     threshold: INITIAL_THRESHOLD,
+    used_space_ratio: USED_SPACE_RATIO,
+    stats: GcStats::default(),
     boxes_start: None,
 }));
 
@@ -70,6 +117,8 @@ pub struct GcBoxHeader {
     roots: Cell<usize>,
     next: Option<NonNull<GcBox<Trace>>>,
     marked: Cell<bool>,
+    // lets `for_each` find the boxes of one type
+    type_id: TypeId,
 }
 
 pub struct GcBox<T: Trace + ?Sized + 'static> {
@@ -90,11 +139,11 @@ impl<T: Trace> GcBox<T> {
             if st.bytes_allocated > st.threshold {
                 collect_garbage(&mut *st);
 
-                if st.bytes_allocated as f64 > st.threshold as f64 * USED_SPACE_RATIO {
+                if st.bytes_allocated as f64 > st.threshold as f64 * st.used_space_ratio {
                     // we didn't collect enough, so increase the
                     // threshold for next time, to avoid thrashing the
                     // collector too much/behaving quadratically.
-                    st.threshold = (st.bytes_allocated as f64 / USED_SPACE_RATIO) as usize
+                    st.threshold = (st.bytes_allocated as f64 / st.used_space_ratio) as usize
                 }
             }
 
@@ -103,6 +152,7 @@ impl<T: Trace> GcBox<T> {
                     roots: Cell::new(1),
                     marked: Cell::new(false),
                     next: st.boxes_start.take(),
+                    type_id: TypeId::of::<T>(),
                 },
                 data: value,
             }));
@@ -111,6 +161,7 @@ impl<T: Trace> GcBox<T> {
 
             // We allocated some bytes! Let's record it
             st.bytes_allocated += mem::size_of::<GcBox<T>>();
+            st.stats.boxes += 1;
 
             // Return the pointer to the newly allocated data
             unsafe { NonNull::new_unchecked(gcbox) }
@@ -152,6 +203,27 @@ impl<T: Trace + ?Sized> GcBox<T> {
 
 /// Collects garbage.
 fn collect_garbage(st: &mut GcState) {
+    let call_hook = |st: &GcState, phase| {
+        if let Some(hook) = &st.hook {
+            let stats = GcStats {
+                bytes_allocated: st.bytes_allocated,
+                ..st.stats
+            };
+            hook(phase, &stats);
+        }
+    };
+    call_hook(st, GcPhase::Before);
+    let start = Instant::now();
+    mark_and_sweep(st);
+    let pause = start.elapsed();
+    st.stats.collections += 1;
+    st.stats.last_pause = pause;
+    st.stats.longest_pause = st.stats.longest_pause.max(pause);
+    st.stats.total_pause += pause;
+    call_hook(st, GcPhase::After);
+}
+
+fn mark_and_sweep(st: &mut GcState) {
     struct Unmarked {
         incoming: *mut Option<NonNull<GcBox<Trace>>>,
         this: NonNull<GcBox<Trace>>,
@@ -185,7 +257,7 @@ fn collect_garbage(st: &mut GcState) {
         unmarked
     }
 
-    unsafe fn sweep(finalized: Vec<Unmarked>, bytes_allocated: &mut usize) {
+    unsafe fn sweep(finalized: Vec<Unmarked>, bytes_allocated: &mut usize, boxes: &mut usize) {
         let _guard = DropGuard::new();
         for node in finalized.into_iter().rev() {
             if (*node.this.as_ptr()).header.marked.get() {
@@ -194,6 +266,7 @@ fn collect_garbage(st: &mut GcState) {
             let incoming = node.incoming;
             let mut node = Box::from_raw(node.this.as_ptr());
             *bytes_allocated -= mem::size_of_val::<GcBox<_>>(&*node);
+            *boxes -= 1;
             *incoming = node.header.next.take();
         }
     }
@@ -207,7 +280,7 @@ fn collect_garbage(st: &mut GcState) {
             Trace::finalize_glue(&(*node.this.as_ptr()).data);
         }
         mark(&mut st.boxes_start);
-        sweep(unmarked, &mut st.bytes_allocated);
+        sweep(unmarked, &mut st.bytes_allocated, &mut st.stats.boxes);
     }
 }
 
@@ -220,3 +293,55 @@ pub fn force_collect() {
         collect_garbage(&mut *st);
     });
 }
+
+/// Sets a function to call before and after each collection on the current
+/// thread. It runs in the middle of an allocation, so it must not allocate
+/// any `Gc` itself.
+pub fn set_hook(hook: Option<Box<dyn Fn(GcPhase, &GcStats)>>) {
+    GC_STATE.with(|st| st.borrow_mut().hook = hook);
+}
+
+/// Changes how often the collector on the current thread runs.
+pub fn configure(config: GcConfig) {
+    GC_STATE.with(|st| {
+        let mut st = st.borrow_mut();
+        st.threshold = config.threshold;
+        st.used_space_ratio = config.used_space_ratio;
+    });
+}
+
+pub fn stats() -> GcStats {
+    GC_STATE.with(|st| {
+        let st = st.borrow();
+        GcStats {
+            bytes_allocated: st.bytes_allocated,
+            ..st.stats
+        }
+    })
+}
+
+/// Calls `f` with every `T` allocated on this thread, including garbage that
+/// hasn't been collected yet, and whether it is a root, i.e. referenced from
+/// outside of any `Gc`. `f` must not allocate any `Gc`.
+pub fn for_each<T: Trace + 'static, F: FnMut(&T, bool)>(mut f: F) {
+    GC_STATE.with(|st| {
+        let st = st.borrow();
+        let mut p = st.boxes_start;
+        while let Some(node) = p {
+            unsafe {
+                let header = &(*node.as_ptr()).header;
+                if header.type_id == TypeId::of::<T>() {
+                    let data = &(*(node.as_ptr() as *mut GcBox<T>)).data;
+                    f(data, header.roots.get() > 0);
+                }
+                p = header.next;
+            }
+        }
+    })
+}
+
+/// The number of bytes of `Gc` boxes currently allocated on this thread,
+/// including any that are unreachable but haven't been collected yet.
+pub fn bytes_allocated() -> usize {
+    GC_STATE.with(|st| st.borrow().bytes_allocated)
+}
@@ -33,7 +33,10 @@ mod trace;
 
 // We re-export the Trace method, as well as some useful internal methods for
 // managing collections or configuring the garbage collector.
-pub use gc::{finalizer_safe, force_collect};
+pub use gc::{
+    bytes_allocated, configure, finalizer_safe, for_each, force_collect, set_hook, stats, GcConfig,
+    GcPhase, GcStats,
+};
 pub use trace::{Finalize, Trace};
 
 ////////
@@ -484,6 +487,17 @@ impl<T: Trace + ?Sized> GcCell<T> {
         }
     }
 
+    /// Immutably borrows the wrapped value, or returns `None` if it is
+    /// currently mutably borrowed.
+    #[inline]
+    pub fn try_borrow(&self) -> Option<GcCellRef<T>> {
+        if self.flags.get().borrowed() == BorrowState::Writing {
+            None
+        } else {
+            Some(self.borrow())
+        }
+    }
+
     /// Mutably borrows the wrapped value.
     ///
     /// The borrow lasts until the returned `GcCellRefMut` exits scope.
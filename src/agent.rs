@@ -1,20 +1,43 @@
-use crate::interpreter::{Assembler, Interpreter, Scope};
+use crate::builtins::async_hooks::{AsyncHooks, AsyncId};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::builtins::net::Net;
+use crate::crash::{History, Running};
+use crate::debugger::{Debugger, PauseHandle, Paused, Resume};
+use crate::event_loop::{EventLoop, Registration, Token};
+use crate::instrument::Instrument;
+use crate::interpreter::{Assembler, Context, Interpreter, Scope, StackFrame};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::intrinsics::create_net_client_prototype;
 use crate::intrinsics::{
     create_array_prototype, create_async_iterator_prototype, create_boolean_prototype,
-    create_error_prototype, create_function_prototype, create_generator_prototype,
-    create_iterator_prototype, create_net_client_prototype, create_number_prototype,
-    create_object_prototype, create_promise, create_promise_prototype, create_regex_prototype,
-    create_string_prototype, create_symbol, create_symbol_prototype,
+    create_deque, create_deque_prototype, create_error, create_error_prototype,
+    create_function_prototype, create_generator_prototype, create_iterator_prototype,
+    create_number_prototype, create_object, create_object_prototype, create_priority_queue,
+    create_priority_queue_prototype, create_promise, create_promise_prototype,
+    create_regex_prototype, create_string_prototype, create_symbol, create_symbol_prototype,
 };
+use crate::intrinsics::{perform_await, promise::new_promise_capability};
 use crate::module::Module;
-use crate::Value;
-use gc::{Gc, GcCell};
-use std::cell::RefCell;
+use crate::output::{OutputSink, Stdio, Stream};
+use crate::permissions::Permissions;
+use crate::pool::Executor;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::pool::{AdaptivePool, Pool};
+use crate::replay::{EventLog, Mode, Outcome};
+use crate::value::{InlineCache, ObjectKey};
+use crate::{FromValue, Value};
+use gc::{Gc, GcCell, GcConfig, GcPhase, GcStats};
+use regex::Regex;
+use std::cell::{Cell, RefCell, RefMut};
 use std::collections::{HashMap, VecDeque};
-use threadpool::ThreadPool;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub struct Intrinsics {
     pub object_prototype: Value,
+    pub object: Value,
     pub array_prototype: Value,
     pub function_prototype: Value,
     pub boolean_prototype: Value,
@@ -30,11 +53,29 @@ pub struct Intrinsics {
     pub async_iterator_prototype: Value,
     pub net_client_prototype: Value,
     pub error_prototype: Value,
+    pub error: Value,
+    pub deque_prototype: Value,
+    pub deque: Value,
+    pub priority_queue_prototype: Value,
+    pub priority_queue: Value,
 }
 
 type JobFn = fn(&Agent, Vec<Value>) -> Result<(), Value>;
+
+// which queue a job waits in. each turn runs every microtask before the next
+// macrotask, and idle jobs only once both are empty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobPriority {
+    // promise reactions
+    Microtask,
+    // timer callbacks
+    Macrotask,
+    Idle,
+}
+
 #[derive(Finalize)]
-struct Job(JobFn, Vec<Value>);
+// the resource the job is a callback of
+struct Job(JobFn, Vec<Value>, AsyncId);
 
 unsafe impl gc::Trace for Job {
     custom_trace!(this, {
@@ -44,32 +85,117 @@ unsafe impl gc::Trace for Job {
 
 #[derive(Debug, Finalize)]
 pub enum MioMapType {
-    Timer(mio::Registration, Value),
-    FS(mio::Registration, Value),
+    // the callback, and the id the timer thread knows it by
+    Timer(Registration, Value, u64),
+    // the promise to settle, and where the pool sends the result
+    #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+    FS(
+        Registration,
+        Value,
+        std::sync::mpsc::Receiver<crate::builtins::fs::FsResponse>,
+    ),
+    #[cfg(not(target_arch = "wasm32"))]
     Net(crate::builtins::net::Net),
 }
 
+// what is known about each entry of the mio map
+pub struct Operation {
+    pub kind: &'static str,
+    pub async_id: AsyncId,
+    // the stack trace of the call that started it
+    pub stack: String,
+    // on the agent's clock
+    pub started: f64,
+}
+
 unsafe impl gc::Trace for MioMapType {
     custom_trace!(this, {
         match this {
-            MioMapType::Timer(_, v) | MioMapType::FS(_, v) => mark(v),
-            _ => {}
+            MioMapType::Timer(_, v, _) => mark(v),
+            #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+            MioMapType::FS(_, v, _) => mark(v),
+            #[cfg(not(target_arch = "wasm32"))]
+            MioMapType::Net(..) => {}
         }
     });
 }
 
 #[derive(Finalize)]
 pub struct Agent {
-    pub assembler: Assembler,
+    pub assembler: RefCell<Assembler>,
     pub intrinsics: Intrinsics,
     pub builtins: HashMap<String, HashMap<String, Value>>,
     pub root_scope: Gc<GcCell<Scope>>,
-    job_queue: GcCell<VecDeque<Job>>,
-    pub mio: mio::Poll,
-    pub mio_map: RefCell<HashMap<mio::Token, MioMapType>>,
-    pub pool: ThreadPool,
+    // where `evaluate` runs, so its bindings outlive each call
+    host_context: Gc<GcCell<Context>>,
+    // one queue for each priority
+    job_queue: GcCell<Vec<VecDeque<Job>>>,
+    job_budget: Option<usize>,
+    // the budget ran out last turn, so the macrotasks left waiting go first
+    jobs_cut_short: Cell<bool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub mio: Arc<mio::Poll>,
+    pub mio_map: RefCell<HashMap<Token, MioMapType>>,
+    // counts up so an operation never gets the token of one still pending
+    next_token: Cell<usize>,
+    pub operations: RefCell<HashMap<Token, Operation>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub pool: Pool,
+    pub timers: crate::builtins::timers::Timers,
+    pub async_hooks: AsyncHooks,
+    events: RefCell<Mode>,
     uncaught_exception_handler: Option<Box<Fn(&Agent, Value) -> ()>>,
+    // set by scripts with process.onUncaughtException
+    uncaught_exception_callback: GcCell<Value>,
+    // non-zero once an exception went uncaught
+    exit_code: Cell<i32>,
+    output: Box<dyn OutputSink>,
+    instrument: Option<Box<dyn Instrument>>,
+    pub(crate) instrument_ops: bool,
+    pub(crate) debugger: Debugger,
+    #[cfg(not(target_arch = "wasm32"))]
+    waker: RefCell<Option<(mio::Registration, mio::SetReadiness)>>,
+    // operations done in place, for the event loop to pick up
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) ready: RefCell<Vec<Token>>,
+    module_loader: Option<Box<ModuleLoader>>,
     modules: GcCell<HashMap<String, Gc<GcCell<Module>>>>,
+    pub call_stack: RefCell<Vec<StackFrame>>,
+    // keep compiled modules in a .slither-cache dir next to their source
+    pub cache_bytecode: bool,
+    // benchmarks whose name doesn't contain this are skipped
+    pub bench_filter: Option<String>,
+    // what standard:process gives scripts as their arguments
+    pub args: Vec<String>,
+    pub permissions: Permissions,
+    call_stack_limit: usize,
+    terminated: Arc<AtomicBool>,
+    time_limit: Option<Duration>,
+    instruction_limit: Option<u64>,
+    heap_limit: Option<usize>,
+    // bytes of strings and buffer contents, which live outside the gc heap.
+    // what was alive when last measured plus what was made since.
+    string_bytes: Cell<usize>,
+    // spent by the current top level evaluation, counting the ops the
+    // countdown lets run
+    instructions: Cell<u64>,
+    // ops left to run before the budget is checked again
+    countdown: Cell<u64>,
+    deadline: Cell<Option<Instant>>,
+    // why the budget ran out, if it has. every check fails with it again
+    // until the script has unwound back to the host.
+    exhausted: Cell<Option<&'static str>>,
+    random: RefCell<Box<dyn FnMut() -> f64>>,
+    clock: Box<dyn Fn() -> f64>,
+    // position of the op being evaluated
+    pub position: Cell<usize>,
+    pub(crate) history: History,
+    // one for each property get and set site, by the id the assembler gave it
+    inline_caches: RefCell<Vec<InlineCache>>,
+    // contexts of calls that have returned, handed out to the next calls
+    spare_contexts: RefCell<Vec<Gc<GcCell<Context>>>>,
+    // compiled regexes by pattern, shared by every literal and compile() call
+    regexes: RefCell<HashMap<String, Regex>>,
 }
 
 unsafe impl gc::Trace for Agent {
@@ -79,19 +205,226 @@ unsafe impl gc::Trace for Agent {
         mark(&this.job_queue);
         // mark(&this.mio_map);
         mark(&this.modules);
+        mark(&this.host_context);
+        mark(&this.async_hooks);
+        mark(&this.uncaught_exception_callback);
     });
 }
 
-impl Agent {
-    pub fn new() -> Agent {
+type GlobalFn = Box<dyn Fn(&Agent) -> Value>;
+
+const DEFAULT_CALL_STACK_LIMIT: usize = 10_000;
+// how many contexts of returned calls are kept for reuse
+const SPARE_CONTEXTS: usize = 64;
+// enough native stack left to throw the error, a debug build uses over
+// 100KB per nested interpreter
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 512 * 1024;
+
+// kept out of the mio map so a waker doesn't count as pending io, mio
+// reserves usize::MAX itself
+#[cfg(not(target_arch = "wasm32"))]
+const WAKER_TOKEN: mio::Token = mio::Token(std::usize::MAX - 1);
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct Waker(mio::SetReadiness);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Waker {
+    pub fn wake(&self) {
+        self.0.set_readiness(mio::Ready::readable()).unwrap();
+    }
+}
+
+// stops whatever script the agent is running, from any thread. the script
+// can't catch it, the host gets an error back from the call that started it
+// and from any other call until it resets the agent.
+#[derive(Clone)]
+pub struct TerminateHandle(Arc<AtomicBool>);
+
+impl TerminateHandle {
+    pub fn terminate(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+// (specifier, referrer) -> (filename, source), or None to use the filesystem
+type ModuleLoader = dyn Fn(&str, &str) -> Option<(String, String)>;
+
+// options for embedders, `Agent::new` uses the defaults
+pub struct AgentBuilder {
+    threads: usize,
+    thread_name: Option<String>,
+    executor: Option<Box<dyn Executor>>,
+    builtin_modules: Option<Vec<String>>,
+    call_stack_limit: usize,
+    time_limit: Option<Duration>,
+    instruction_limit: Option<u64>,
+    heap_limit: Option<usize>,
+    job_budget: Option<usize>,
+    gc: Option<GcConfig>,
+    permissions: Permissions,
+    globals: Vec<(String, GlobalFn)>,
+    random: Option<Box<dyn FnMut() -> f64>>,
+    clock: Option<Box<dyn Fn() -> f64>>,
+    output: Option<Box<dyn OutputSink>>,
+    events: Mode,
+}
+
+impl AgentBuilder {
+    pub fn new() -> AgentBuilder {
+        AgentBuilder {
+            threads: num_cpus::get(),
+            thread_name: None,
+            executor: None,
+            builtin_modules: None,
+            call_stack_limit: DEFAULT_CALL_STACK_LIMIT,
+            time_limit: None,
+            instruction_limit: None,
+            heap_limit: None,
+            job_budget: None,
+            gc: None,
+            permissions: Permissions::allow_all(),
+            globals: Vec::new(),
+            random: None,
+            clock: None,
+            output: None,
+            events: Mode::Live,
+        }
+    }
+
+    // most threads the default pool grows to while running blocking work
+    // like fs calls
+    pub fn threads(mut self, threads: usize) -> AgentBuilder {
+        self.threads = threads;
+        self
+    }
+
+    pub fn thread_name(mut self, name: &str) -> AgentBuilder {
+        self.thread_name = Some(name.to_string());
+        self
+    }
+
+    // runs blocking work on the embedder's own pool instead
+    pub fn executor<E: Executor + 'static>(mut self, executor: E) -> AgentBuilder {
+        self.executor = Some(Box::new(executor));
+        self
+    }
+
+    // only these `standard:` modules can be imported
+    pub fn builtin_modules(mut self, names: &[&str]) -> AgentBuilder {
+        self.builtin_modules = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    // calls nested deeper than this throw instead of growing the stack
+    pub fn call_stack_limit(mut self, limit: usize) -> AgentBuilder {
+        self.call_stack_limit = limit;
+        self
+    }
+
+    // each top level evaluation (a script, module or job) fails once it runs
+    // longer than this
+    pub fn time_limit(mut self, limit: Duration) -> AgentBuilder {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    // likewise for the number of ops it evaluates
+    pub fn instruction_limit(mut self, limit: u64) -> AgentBuilder {
+        self.instruction_limit = Some(limit);
+        self
+    }
+
+    // bytes of garbage collected objects a script may keep alive, along with
+    // the strings and buffer contents they hold. the gc heap belongs to the
+    // thread, so agents sharing one share it.
+    pub fn heap_limit(mut self, bytes: usize) -> AgentBuilder {
+        self.heap_limit = Some(bytes);
+        self
+    }
+
+    // most jobs a turn of the event loop runs before it polls for io again,
+    // so a promise chain that keeps queueing more can't hold up timers and
+    // sockets. unlimited by default, and at least one job so a turn always
+    // makes progress.
+    pub fn job_budget(mut self, jobs: usize) -> AgentBuilder {
+        self.job_budget = Some(jobs.max(1));
+        self
+    }
+
+    // how often the collector runs, a higher threshold trades memory for
+    // fewer pauses. like the heap it applies to the whole thread. the
+    // threshold is divided by the used space ratio, so it is kept to a
+    // fraction no smaller than 1%, and NaN means the default.
+    pub fn gc(mut self, mut config: GcConfig) -> AgentBuilder {
+        let ratio = config.used_space_ratio;
+        config.used_space_ratio = if ratio.is_nan() {
+            GcConfig::default().used_space_ratio
+        } else {
+            ratio.clamp(0.01, 1.0)
+        };
+        self.gc = Some(config);
+        self
+    }
+
+    // what the fs, net and process builtins may access, everything by default
+    pub fn permissions(mut self, permissions: Permissions) -> AgentBuilder {
+        self.permissions = permissions;
+        self
+    }
+
+    // values are created once the agent's intrinsics exist
+    pub fn global<F: Fn(&Agent) -> Value + 'static>(mut self, name: &str, f: F) -> AgentBuilder {
+        self.globals.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    // source of `random()` in standard:math, returning values in [0, 1)
+    pub fn random<F: FnMut() -> f64 + 'static>(mut self, f: F) -> AgentBuilder {
+        self.random = Some(Box::new(f));
+        self
+    }
+
+    // source of `now()` in standard:timers, in milliseconds since the epoch
+    pub fn clock<F: Fn() -> f64 + 'static>(mut self, f: F) -> AgentBuilder {
+        self.clock = Some(Box::new(f));
+        self
+    }
+
+    // receives everything scripts print, stdout and stderr by default
+    pub fn output<S: OutputSink + 'static>(mut self, sink: S) -> AgentBuilder {
+        self.output = Some(Box::new(sink));
+        self
+    }
+
+    // writes the outcome of every timer, fs call and socket read to the log
+    pub fn record<W: Write + 'static>(mut self, log: W) -> AgentBuilder {
+        self.events = Mode::record(Box::new(log));
+        self
+    }
+
+    // does no io, and takes the outcome of each operation from a recorded log
+    pub fn replay(mut self, log: EventLog) -> AgentBuilder {
+        self.events = Mode::replay(log);
+        self
+    }
+
+    pub fn build(self) -> Agent {
+        if let Some(config) = self.gc {
+            gc::configure(config);
+        }
         let object_prototype = create_object_prototype();
         let function_prototype = create_function_prototype(object_prototype.clone());
         let symbol_prototype = create_symbol_prototype(object_prototype.clone());
+        let root_scope = Scope::new(None);
 
         let mut agent = Agent {
-            assembler: Assembler::new(),
+            assembler: RefCell::new(Assembler::new()),
             intrinsics: Intrinsics {
                 object_prototype: object_prototype.clone(),
+                object: Value::Null,
                 array_prototype: Value::Null,
                 function_prototype,
                 boolean_prototype: Value::Null,
@@ -107,23 +440,76 @@ impl Agent {
                 async_iterator_prototype: Value::Null,
                 net_client_prototype: Value::Null,
                 error_prototype: Value::Null,
+                error: Value::Null,
+                deque_prototype: Value::Null,
+                deque: Value::Null,
+                priority_queue_prototype: Value::Null,
+                priority_queue: Value::Null,
             },
             builtins: HashMap::new(),
-            root_scope: Scope::new(None),
-            job_queue: GcCell::new(VecDeque::new()),
-            mio: mio::Poll::new().expect("create mio poll failed"),
+            host_context: Context::new(Scope::new(Some(root_scope.clone()))),
+            root_scope,
+            job_queue: GcCell::new(vec![VecDeque::new(), VecDeque::new(), VecDeque::new()]),
+            job_budget: self.job_budget,
+            jobs_cut_short: Cell::new(false),
+            #[cfg(not(target_arch = "wasm32"))]
+            mio: Arc::new(mio::Poll::new().expect("create mio poll failed")),
             mio_map: RefCell::new(HashMap::new()),
-            pool: ThreadPool::new(num_cpus::get()),
+            next_token: Cell::new(0),
+            operations: RefCell::new(HashMap::new()),
+            #[cfg(not(target_arch = "wasm32"))]
+            pool: Pool::new(match self.executor {
+                Some(executor) => executor,
+                None => Box::new(AdaptivePool::new(self.thread_name, self.threads)),
+            }),
+            timers: Default::default(),
+            async_hooks: Default::default(),
+            events: RefCell::new(self.events),
             uncaught_exception_handler: None,
+            uncaught_exception_callback: GcCell::new(Value::Null),
+            exit_code: Cell::new(0),
+            output: self.output.unwrap_or_else(|| Box::new(Stdio)),
+            instrument: None,
+            instrument_ops: false,
+            debugger: Debugger::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            waker: RefCell::new(None),
+            #[cfg(target_arch = "wasm32")]
+            ready: RefCell::new(Vec::new()),
+            module_loader: None,
             modules: GcCell::new(HashMap::new()),
+            call_stack: RefCell::new(Vec::new()),
+            cache_bytecode: false,
+            bench_filter: None,
+            args: Vec::new(),
+            permissions: self.permissions,
+            call_stack_limit: self.call_stack_limit,
+            terminated: Arc::new(AtomicBool::new(false)),
+            time_limit: self.time_limit,
+            instruction_limit: self.instruction_limit,
+            heap_limit: self.heap_limit,
+            string_bytes: Cell::new(0),
+            instructions: Cell::new(0),
+            countdown: Cell::new(0),
+            deadline: Cell::new(None),
+            exhausted: Cell::new(None),
+            random: RefCell::new(self.random.unwrap_or_else(default_random)),
+            clock: self.clock.unwrap_or_else(|| Box::new(system_clock)),
+            position: Cell::new(0),
+            history: History::default(),
+            inline_caches: RefCell::new(Vec::new()),
+            spare_contexts: RefCell::new(Vec::new()),
+            regexes: RefCell::new(HashMap::new()),
         };
 
+        agent.intrinsics.object = create_object(&agent);
         agent.intrinsics.boolean_prototype = create_boolean_prototype(&agent);
         agent.intrinsics.number_prototype = create_number_prototype(&agent);
         agent.intrinsics.string_prototype = create_string_prototype(&agent);
         agent.intrinsics.regex_prototype = create_regex_prototype(&agent);
         agent.intrinsics.symbol = create_symbol(&agent);
         agent.intrinsics.error_prototype = create_error_prototype(&agent);
+        agent.intrinsics.error = create_error(&agent);
         agent.intrinsics.iterator_prototype = create_iterator_prototype(&agent);
         agent.intrinsics.async_iterator_prototype = create_async_iterator_prototype(&agent);
         agent.intrinsics.generator_prototype = create_generator_prototype(&agent);
@@ -133,38 +519,327 @@ impl Agent {
         agent.intrinsics.promise_prototype = create_promise_prototype(&agent);
         agent.intrinsics.promise = create_promise(&agent);
 
-        agent.intrinsics.net_client_prototype = create_net_client_prototype(&agent);
+        agent.intrinsics.deque_prototype = create_deque_prototype(&agent);
+        agent.intrinsics.deque = create_deque(&agent);
+        agent.intrinsics.priority_queue_prototype = create_priority_queue_prototype(&agent);
+        agent.intrinsics.priority_queue = create_priority_queue(&agent);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            agent.intrinsics.net_client_prototype = create_net_client_prototype(&agent);
+        }
 
         agent.builtins = crate::builtins::create(&agent);
+        if let Some(names) = &self.builtin_modules {
+            agent.builtins.retain(|name, _| names.contains(name));
+        }
 
         {
             let mut scope = agent.root_scope.borrow_mut();
+            scope.create(&agent, "Object", true).unwrap();
+            scope.initialize("Object", agent.intrinsics.object.clone());
+
             scope.create(&agent, "Promise", true).unwrap();
             scope.initialize("Promise", agent.intrinsics.promise.clone());
 
             scope.create(&agent, "Symbol", true).unwrap();
             scope.initialize("Symbol", agent.intrinsics.symbol.clone());
+
+            scope.create(&agent, "Error", true).unwrap();
+            scope.initialize("Error", agent.intrinsics.error.clone());
+
+            scope.create(&agent, "Deque", true).unwrap();
+            scope.initialize("Deque", agent.intrinsics.deque.clone());
+
+            scope.create(&agent, "PriorityQueue", true).unwrap();
+            scope.initialize("PriorityQueue", agent.intrinsics.priority_queue.clone());
+
+            for (name, f) in &self.globals {
+                scope.create(&agent, name, true).unwrap();
+                scope.initialize(name, f(&agent));
+            }
         }
 
         agent
     }
+}
+
+impl Default for AgentBuilder {
+    fn default() -> Self {
+        AgentBuilder::new()
+    }
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn system_clock() -> f64 {
+    let d = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    d.as_secs() as f64 * 1000.0 + f64::from(d.subsec_nanos()) / 1_000_000.0
+}
+
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+fn system_clock() -> f64 {
+    panic!("built for wasm32 without wasi the agent needs a clock, see AgentBuilder::clock")
+}
+
+// stacker can't see the stack of a wasm32 build, where the call stack limit
+// is all there is
+#[cfg(not(target_arch = "wasm32"))]
+fn native_stack_low() -> bool {
+    stacker::remaining_stack().map_or(false, |n| n < STACK_RED_ZONE)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn native_stack_low() -> bool {
+    false
+}
+
+// xorshift64*, seeded from the clock. wasm32 without wasi has no clock of its
+// own, the page passes one to the builder along with a random source if it needs one.
+fn default_random() -> Box<dyn FnMut() -> f64> {
+    #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos() as u64;
+    #[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+    let seed: u64 = 0x853c_49e6_748f_ea9b;
+    let mut state = seed | 1;
+    Box::new(move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let n = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (n >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+impl Agent {
+    pub fn new() -> Agent {
+        AgentBuilder::new().build()
+    }
+
+    pub fn random(&self) -> f64 {
+        (self.random.borrow_mut())()
+    }
+
+    pub fn now(&self) -> f64 {
+        (self.clock)()
+    }
+
+    // a context for a call, reusing one a returned call left behind
+    pub(crate) fn call_context(&self, scope: Gc<GcCell<Scope>>) -> Gc<GcCell<Context>> {
+        match self.spare_contexts.borrow_mut().pop() {
+            Some(ctx) => {
+                ctx.borrow_mut().scope = scope;
+                ctx
+            }
+            None => Context::new(scope),
+        }
+    }
+
+    // only for contexts nothing else holds, so not those of generators or
+    // async functions
+    pub(crate) fn recycle_context(&self, ctx: Gc<GcCell<Context>>) {
+        let mut spare = self.spare_contexts.borrow_mut();
+        if spare.len() < SPARE_CONTEXTS {
+            {
+                // let go of what the call referenced
+                let mut c = ctx.borrow_mut();
+                c.scope = self.root_scope.clone();
+                c.function = None;
+                c.interpreter = None;
+            }
+            spare.push(ctx);
+        }
+    }
+
+    // compiles a pattern the first time it is seen. a Regex is cheap to clone,
+    // its compiled program is shared.
+    pub(crate) fn compile_regex(&self, pattern: &str) -> Result<Regex, Value> {
+        if let Some(re) = self.regexes.borrow().get(pattern) {
+            return Ok(re.clone());
+        }
+        let re = match Regex::new(pattern) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Value::new_error(self, &format!("{}", e)));
+            }
+        };
+        self.regexes
+            .borrow_mut()
+            .insert(pattern.to_string(), re.clone());
+        Ok(re)
+    }
+
+    pub(crate) fn inline_cache(&self, id: usize) -> RefMut<InlineCache> {
+        RefMut::map(self.inline_caches.borrow_mut(), |caches| {
+            if caches.len() <= id {
+                caches.resize(id + 1, InlineCache::default());
+            }
+            &mut caches[id]
+        })
+    }
+
+    // the error to throw when a call would go past the call stack limit, or
+    // would run out of native stack. calls from builtins back into scripts
+    // recurse on the native stack, so that can run out first.
+    pub fn check_call_depth(&self) -> Result<(), Value> {
+        if self.call_stack.borrow().len() >= self.call_stack_limit || native_stack_low() {
+            Err(Value::new_error(self, "maximum call stack size exceeded"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        gc::stats()
+    }
+
+    // called before and after every collection. collections happen in the
+    // middle of allocating, so the hook must not create any values.
+    pub fn set_gc_hook<F: 'static>(&mut self, f: F)
+    where
+        F: Fn(GcPhase, &GcStats),
+    {
+        gc::set_hook(Some(Box::new(f)));
+    }
+
+    pub fn terminate_handle(&self) -> TerminateHandle {
+        TerminateHandle(self.terminated.clone())
+    }
+
+    pub(crate) fn start_budget(&self) {
+        self.instructions.set(0);
+        self.countdown.set(0);
+        self.deadline
+            .set(self.time_limit.map(|limit| Instant::now() + limit));
+    }
+
+    // called before every op, which mostly just counts down to the next
+    // real check of the budget
+    #[inline]
+    pub(crate) fn tick(&self) -> Result<(), Value> {
+        let left = self.countdown.get();
+        if left > 0 {
+            self.countdown.set(left - 1);
+            Ok(())
+        } else {
+            self.check_budget()
+        }
+    }
 
-    pub fn import(&mut self, specifier: &str, referrer: &str) -> Result<Value, Value> {
+    // checks the limits at most every 256 ops, sooner if that would run past
+    // the instruction limit, which the ops let through are counted against
+    fn check_budget(&self) -> Result<(), Value> {
+        if let Some(reason) = self.exhausted.get() {
+            return Err(Value::new_error(self, reason));
+        }
+        if self.terminated.load(Ordering::Relaxed) {
+            return self.exhaust("script terminated");
+        }
+        let n = self.instructions.get() + 1;
+        let mut left = 255;
+        if let Some(limit) = self.instruction_limit {
+            if n > limit {
+                return self.exhaust("instruction limit exceeded");
+            }
+            left = left.min(limit - n);
+        }
+        if let Some(deadline) = self.deadline.get() {
+            if Instant::now() >= deadline {
+                return self.exhaust("time limit exceeded");
+            }
+        }
+        if let Some(limit) = self.heap_limit {
+            // only garbage might be over the limit, collect and measure what
+            // is still alive before failing
+            if gc::bytes_allocated() + self.string_bytes.get() > limit {
+                gc::force_collect();
+                self.string_bytes.set(crate::value::string_bytes());
+                if gc::bytes_allocated() + self.string_bytes.get() > limit {
+                    return self.exhaust("out of memory");
+                }
+            }
+        }
+        self.instructions.set(n + left);
+        self.countdown.set(left);
+        Ok(())
+    }
+
+    // counts a new string or buffer contents against the heap limit
+    #[inline]
+    pub(crate) fn count_bytes(&self, bytes: usize) {
+        if self.heap_limit.is_some() {
+            self.string_bytes.set(self.string_bytes.get() + bytes);
+        }
+    }
+
+    #[cold]
+    fn exhaust(&self, reason: &'static str) -> Result<(), Value> {
+        self.exhausted.set(Some(reason));
+        Err(Value::new_error(self, reason))
+    }
+
+    // no try catches the error while this holds, so a script can't carry on
+    // past its budget by catching it, in a callback or generator say
+    pub(crate) fn budget_exhausted(&self) -> bool {
+        self.exhausted.get().is_some()
+    }
+
+    // once the script has unwound back to the host the agent can run again,
+    // unless it was terminated
+    pub(crate) fn clear_exhausted(&self) {
+        self.exhausted.set(None);
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    // a terminated agent runs nothing, not even the jobs, timers and io it
+    // had pending, until this is called. they carry on afterwards.
+    pub fn reset_terminated(&self) {
+        self.terminated.store(false, Ordering::SeqCst);
+    }
+
+    pub fn import(&self, specifier: &str, referrer: &str) -> Result<Value, Value> {
         let module = self.load(specifier, referrer)?;
         Module::instantiate(self, module.clone())?;
         Module::evaluate(self, module)?;
         Ok(Value::Null)
     }
 
-    pub fn load(&mut self, specifier: &str, referrer: &str) -> Result<Gc<GcCell<Module>>, Value> {
-        let filename = self.resolve(specifier, referrer).unwrap();
+    pub fn load(&self, specifier: &str, referrer: &str) -> Result<Gc<GcCell<Module>>, Value> {
+        let loaded = match &self.module_loader {
+            Some(loader) => loader(specifier, referrer),
+            None => None,
+        };
+        let (filename, source) = match loaded {
+            Some((filename, source)) => (filename, Some(source)),
+            None => match self.resolve(specifier, referrer) {
+                Ok(filename) => (filename, None),
+                Err(e) => {
+                    return Err(Value::new_error(
+                        self,
+                        &format!("cannot find module '{}': {}", specifier, e),
+                    ))
+                }
+            },
+        };
         if !self.modules.borrow().contains_key(&filename) {
-            let source = std::fs::read_to_string(&filename).expect("no such file");
-            let module = Gc::new(GcCell::new(Module::new(
-                filename.as_str(),
-                source.as_str(),
-                self,
-            )?));
+            let module = if let Some(source) = source {
+                // loaded modules may not exist on disk, so skip the cache
+                Module::new(filename.as_str(), source.as_str(), self)?
+            } else if self.cache_bytecode {
+                let source = self.read_source(&filename)?;
+                Module::new_cached(filename.as_str(), source.as_str(), self)?
+            } else {
+                let source = self.read_source(&filename)?;
+                Module::new(filename.as_str(), source.as_str(), self)?
+            };
+            let module = Gc::new(GcCell::new(module));
             self.modules
                 .borrow_mut()
                 .insert(filename.to_string(), module.clone());
@@ -176,6 +851,12 @@ impl Agent {
         }
     }
 
+    fn read_source(&self, filename: &str) -> Result<String, Value> {
+        self.permissions.check_read(self, filename)?;
+        std::fs::read_to_string(filename)
+            .map_err(|e| Value::new_error(self, &format!("unable to read '{}': {}", filename, e)))
+    }
+
     fn resolve(&self, specifier: &str, referrer: &str) -> std::io::Result<String> {
         let filename = std::path::Path::new(referrer)
             .parent()
@@ -205,93 +886,591 @@ impl Agent {
         }
     }
 
+    // the job runs as part of whatever is running now
     pub fn enqueue_job(&self, f: JobFn, args: Vec<Value>) {
-        self.job_queue.borrow_mut().push_back(Job(f, args));
+        self.enqueue_job_for(self.async_hooks.current(), f, args);
+    }
+
+    pub fn enqueue_job_for(&self, id: AsyncId, f: JobFn, args: Vec<Value>) {
+        self.queue_job(id, JobPriority::Microtask, f, args);
+    }
+
+    // enqueue_job for jobs that can wait behind promise reactions
+    pub fn enqueue_job_with(&self, priority: JobPriority, f: JobFn, args: Vec<Value>) {
+        self.queue_job(self.async_hooks.current(), priority, f, args);
+    }
+
+    fn queue_job(&self, id: AsyncId, priority: JobPriority, f: JobFn, args: Vec<Value>) {
+        self.job_queue.borrow_mut()[priority as usize].push_back(Job(f, args, id));
+    }
+
+    pub fn has_jobs(&self) -> bool {
+        self.job_queue
+            .borrow()
+            .iter()
+            .any(|queue| !queue.is_empty())
+    }
+
+    pub(crate) fn new_token(&self) -> Token {
+        let token = self.next_token.get();
+        self.next_token.set(token + 1);
+        Token(token)
+    }
+
+    // registers io with the event loop. the first time a token is added it
+    // gets an async id and the current stack is kept.
+    pub fn add_operation(&self, token: Token, operation: MioMapType) {
+        if !self.operations.borrow().contains_key(&token) {
+            let (kind, resource) = match &operation {
+                MioMapType::Timer(_, callback, _) => ("Timer", callback),
+                #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+                MioMapType::FS(_, promise, _) => ("FS", promise),
+                #[cfg(not(target_arch = "wasm32"))]
+                MioMapType::Net(Net::Client(_, resource))
+                | MioMapType::Net(Net::Lookup(_, _, resource)) => ("Net", resource),
+            };
+            let async_id = self.async_hooks.init(self, kind, resource);
+            self.operations.borrow_mut().insert(
+                token,
+                Operation {
+                    kind,
+                    async_id,
+                    stack: self.stack_trace(),
+                    started: self.now(),
+                },
+            );
+        }
+        self.mio_map.borrow_mut().insert(token, operation);
+    }
+
+    pub fn remove_operation(&self, token: Token) {
+        self.mio_map.borrow_mut().remove(&token);
+        self.operations.borrow_mut().remove(&token);
     }
 
     pub fn run_jobs(&self) {
-        let mut events = mio::Events::with_capacity(128);
-        loop {
-            self.mio
-                .poll(&mut events, Some(std::time::Duration::from_millis(0)))
-                .expect("mio poll failed");
-            for event in events.iter() {
-                let entry = self
-                    .mio_map
-                    .borrow_mut()
-                    .remove(&event.token())
-                    .expect("mio map was missing entry for event");
-                match entry {
-                    MioMapType::Timer(_, callback) => {
-                        self.enqueue_job(call_timer_job, vec![callback]);
-                    }
-                    MioMapType::FS(_, promise) => {
-                        crate::builtins::fs::handle(self, event.token(), promise);
-                    }
-                    MioMapType::Net(n) => {
-                        crate::builtins::net::handle(self, event.token(), n);
+        while self.turn() {}
+    }
+
+    // one pass of the event loop without blocking, for embedders driving it
+    // from their own loop. returns whether there is still work outstanding.
+    pub fn turn(&self) -> bool {
+        self.poll(Some(Duration::from_millis(0)))
+    }
+
+    // like turn, but waits up to timeout (or indefinitely) for io or a wake
+    pub fn poll(&self, timeout: Option<Duration>) -> bool {
+        if self.terminated() {
+            return false;
+        }
+        if self.replaying() {
+            return self.replay_turn();
+        }
+        let timeout = if self.has_jobs() {
+            Some(Duration::from_millis(0))
+        } else {
+            timeout
+        };
+        let tokens = self.event_loop().wait(self, timeout);
+        self.dispatch(&tokens)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn event_loop(&self) -> &dyn EventLoop {
+        &*self.mio
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn event_loop(&self) -> &dyn EventLoop {
+        &crate::event_loop::Microtasks
+    }
+
+    // when the page should call `turn` next for the first pending timer to
+    // fire, on the agent's clock. native builds wait for timers in `poll`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn next_timer(&self) -> Option<f64> {
+        self.timers.next()
+    }
+
+    // handles polled io and runs queued jobs, returning whether io or jobs
+    // are still outstanding
+    pub(crate) fn dispatch(&self, tokens: &[Token]) -> bool {
+        let _running = Running::new(self);
+        for &token in tokens {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                if token == WAKER_TOKEN {
+                    if let Some((_, set_readiness)) = &*self.waker.borrow() {
+                        set_readiness.set_readiness(mio::Ready::empty()).unwrap();
                     }
+                    continue;
                 }
             }
+            let outcome = match self
+                .mio_map
+                .borrow()
+                .get(&token)
+                .expect("mio map was missing entry for event")
+            {
+                MioMapType::Timer(..) => Outcome::Timer,
+                #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+                MioMapType::FS(_, _, response) => {
+                    Outcome::Fs(response.try_recv().expect("fs operation had no response"))
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                MioMapType::Net(Net::Client(stream, _)) => {
+                    Outcome::Net(crate::builtins::net::read(stream.as_ref().unwrap()))
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                MioMapType::Net(Net::Lookup(_, response, _)) => {
+                    Outcome::Net(response.try_recv().expect("lookup had no response"))
+                }
+            };
+            self.handle_event(token, outcome);
+        }
+        self.events.borrow_mut().end_turn();
 
-            loop {
-                let job = self.job_queue.borrow_mut().pop_front();
-                match job {
-                    Some(Job(f, args)) => {
-                        f(self, args).unwrap_or_else(|e: Value| {
-                            self.uncaught_exception(e);
-                        });
-                    }
-                    None => break,
+        self.run_job_queue();
+
+        !self.terminated() && (!self.mio_map.borrow().is_empty() || self.has_jobs())
+    }
+
+    fn handle_event(&self, token: Token, outcome: Outcome) {
+        let entry = self
+            .mio_map
+            .borrow_mut()
+            .remove(&token)
+            .expect("mio map was missing entry for event");
+        let id = match self.operations.borrow().get(&token) {
+            Some(operation) => operation.async_id,
+            None => self.async_hooks.current(),
+        };
+        self.events.borrow_mut().add(id.id, &outcome);
+        match (entry, outcome) {
+            (MioMapType::Timer(_, callback, _), _) => {
+                self.queue_job(id, JobPriority::Macrotask, call_timer_job, vec![callback]);
+            }
+            #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+            (MioMapType::FS(registration, promise, receiver), Outcome::Fs(response)) => {
+                let stack = self.operations.borrow()[&token].stack.clone();
+                // progress comes before the operation is done
+                if let crate::builtins::fs::FsResponse::Progress(..) = response {
+                    self.mio_map.borrow_mut().insert(
+                        token,
+                        MioMapType::FS(registration, promise.clone(), receiver),
+                    );
                 }
+                self.async_hooks.run(self, id, || {
+                    crate::builtins::fs::handle(self, promise, response, stack);
+                })
             }
-            // job queue is empty
+            #[cfg(not(target_arch = "wasm32"))]
+            (MioMapType::Net(n), Outcome::Net(read)) => self.async_hooks.run(self, id, || {
+                crate::builtins::net::handle(self, token, n, read);
+            }),
+            #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+            _ => unreachable!(),
+        }
+        // sockets register again to keep reading
+        if !self.mio_map.borrow().contains_key(&token) {
+            self.operations.borrow_mut().remove(&token);
+        }
+    }
+
+    // none while the queue is being changed
+    pub(crate) fn queued_jobs(&self) -> Option<usize> {
+        self.job_queue
+            .try_borrow()
+            .map(|queues| queues.iter().map(|queue| queue.len()).sum())
+    }
 
-            if self.mio_map.borrow().is_empty() {
+    fn run_job(&self, Job(f, args, id): Job) {
+        self.async_hooks
+            .run(self, id, || f(self, args))
+            .unwrap_or_else(|e: Value| {
+                self.uncaught_exception(e);
+            });
+    }
+
+    // runs jobs by priority until the queues are empty or the budget is spent
+    fn run_job_queue(&self) {
+        let mut budget = self.job_budget.unwrap_or(usize::MAX);
+        if self.jobs_cut_short.replace(false) {
+            let waiting = self.job_queue.borrow()[JobPriority::Macrotask as usize].len();
+            for _ in 0..waiting.min(budget) {
+                if self.terminated() {
+                    return;
+                }
+                let job = self.job_queue.borrow_mut()[JobPriority::Macrotask as usize].pop_front();
+                self.run_job(job.unwrap());
+                budget -= 1;
+            }
+        }
+        loop {
+            if self.terminated() {
+                break;
+            }
+            if budget == 0 {
+                self.jobs_cut_short.set(self.has_jobs());
                 break;
             }
+            let job = self
+                .job_queue
+                .borrow_mut()
+                .iter_mut()
+                .find(|queue| !queue.is_empty())
+                .map(|queue| queue.pop_front().unwrap());
+            match job {
+                Some(job) => {
+                    self.run_job(job);
+                    budget -= 1;
+                }
+                None => break,
+            }
         }
     }
 
-    pub fn set_uncaught_exception_handler<F: 'static>(&mut self, f: F)
-    where
-        F: Fn(&Agent, Value) -> (),
-    {
-        self.uncaught_exception_handler = Some(Box::new(f));
+    pub fn replaying(&self) -> bool {
+        self.events.borrow().is_replay()
     }
 
-    fn uncaught_exception(&self, e: Value) {
-        // TODO: add way to handle this from sl
-        match &self.uncaught_exception_handler {
-            Some(f) => f(self, e),
-            None => {
-                eprintln!("Uncaught Exception: {}", Value::inspect(self, &e));
-                std::process::exit(1);
+    // stands in for poll when replaying. the events of the next recorded turn
+    // are dispatched to the operations with the same async ids, and the loop
+    // is done once the log runs out.
+    fn replay_turn(&self) -> bool {
+        let _running = Running::new(self);
+        // wakes still come through mio
+        let tokens = self.event_loop().wait(self, Some(Duration::from_millis(0)));
+        self.dispatch(&tokens);
+
+        let turn = self.events.borrow_mut().next_turn();
+        for event in turn {
+            let token = self
+                .operations
+                .borrow()
+                .iter()
+                .find(|(token, operation)| {
+                    operation.async_id.id == event.async_id
+                        && operation.kind == event.outcome.kind()
+                        && self.mio_map.borrow().contains_key(token)
+                })
+                .map(|(token, _)| *token);
+            match token {
+                Some(token) => self.handle_event(token, event.outcome),
+                None => {
+                    self.write_output(
+                        Stream::Stderr,
+                        &format!(
+                            "replay diverged: no pending {} operation with async id {}\n",
+                            event.outcome.kind(),
+                            event.async_id
+                        ),
+                    );
+                    self.events.borrow_mut().clear();
+                    break;
+                }
             }
         }
+        self.run_job_queue();
+
+        self.events.borrow().has_events() || self.has_jobs()
     }
 
-    pub fn run(&mut self, specifier: &str, source: &str) -> Result<Value, Value> {
-        match Module::new(specifier, source, self) {
-            Err(e) => Err(e),
-            Ok(module) => {
-                let mut evaluator = Interpreter::new(module.bytecode_position, module.context);
-                evaluator.run(self).unwrap()
+    // a handle other threads can use to interrupt `poll`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn waker(&self) -> Waker {
+        let mut waker = self.waker.borrow_mut();
+        if waker.is_none() {
+            let (registration, set_readiness) = mio::Registration::new2();
+            self.mio
+                .register(
+                    &registration,
+                    WAKER_TOKEN,
+                    mio::Ready::readable(),
+                    mio::PollOpt::edge(),
+                )
+                .unwrap();
+            *waker = Some((registration, set_readiness));
+        }
+        Waker(waker.as_ref().unwrap().1.clone())
+    }
+
+    // runs the event loop until a returned promise settles, other values are
+    // passed through
+    fn settle(&self, value: Value) -> Result<Value, Value> {
+        if !value.has_slot("promise state") {
+            return Ok(value);
+        }
+        loop {
+            let pending = self.turn();
+            match value.get_slot("promise state") {
+                Value::String(ref s) if &**s == "fulfilled" => return Ok(value.get_slot("result")),
+                Value::String(ref s) if &**s == "rejected" => return Err(value.get_slot("result")),
+                _ if !pending => return Err(Value::new_error(self, "promise never settled")),
+                _ => {}
             }
         }
     }
-}
 
-impl Default for Agent {
-    fn default() -> Self {
-        Agent::new()
+    // calls a function, or the binding of that name left by `evaluate`, and
+    // waits for the result if it is a promise
+    pub fn call_function<F: Into<Value>, T: FromValue>(
+        &self,
+        function: F,
+        args: Vec<Value>,
+    ) -> Result<T, Value> {
+        let function = match function.into() {
+            Value::String(name) => self.host_context.borrow().scope.borrow().get(self, &name)?,
+            f => f,
+        };
+        let value = function.call(self, Value::Null, args)?;
+        let value = self.settle(value)?;
+        T::from_value(self, value)
     }
-}
 
-fn call_timer_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value> {
-    args[0].call(agent, Value::Null, Vec::new())?;
-    Ok(())
-}
+    // like `run`, but declarations persist between calls and a returned
+    // promise is waited for
+    pub fn evaluate<T: FromValue>(&mut self, source: &str) -> Result<T, Value> {
+        // relative imports resolve from the working directory
+        let referrer = std::env::current_dir().unwrap_or_default().join("evaluate");
+        let referrer = &*referrer.to_string_lossy();
+        let (index, imports) = crate::module::compile("evaluate", source, self)?;
+        self.assembler
+            .get_mut()
+            .files
+            .push((index, "evaluate".to_string()));
+        let context = self.host_context.clone();
+        for specifier in crate::module::bind_imports(self, referrer, &context, imports)? {
+            self.import(&specifier, referrer)?;
+        }
+        let mut interpreter = Interpreter::new(index, context.clone());
+        let value = match interpreter.run(self) {
+            Ok(result) => result?,
+            // a top level `for await` suspends, it carries on as an async
+            // function would and its promise is waited for below
+            Err(mut suspended) => {
+                context.borrow_mut().interpreter = Some(interpreter);
+                let promise = new_promise_capability(self, self.intrinsics.promise.clone())?;
+                let value = std::mem::replace(&mut suspended.0, Value::Null);
+                let wrapped = Value::WrappedContext(context, Some(Box::new(promise.clone())));
+                perform_await(self, wrapped, value)?;
+                promise
+            }
+        };
+        let value = self.settle(value)?;
+        T::from_value(self, value)
+    }
+
+    // makes `import { ... } from standard:name` resolve to the returned
+    // exports, replacing any module already registered under that name
+    pub fn register_module<F>(&mut self, name: &str, f: F)
+    where
+        F: FnOnce(&Agent) -> HashMap<String, Value>,
+    {
+        let exports = f(self);
+        self.builtins.insert(name.to_string(), exports);
+    }
+
+    // registers the module declared by a native extension, returning the
+    // name it is imported under
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_extension<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<String, Value> {
+        let (name, exports) = crate::extension::load(self, path.as_ref())?;
+        self.builtins.insert(name.clone(), exports);
+        Ok(name)
+    }
+
+    // consulted before the filesystem when resolving and reading imports
+    pub fn set_module_loader<F: 'static>(&mut self, f: F)
+    where
+        F: Fn(&str, &str) -> Option<(String, String)>,
+    {
+        self.module_loader = Some(Box::new(f));
+    }
+
+    pub fn set_output<S: OutputSink + 'static>(&mut self, sink: S) {
+        self.output = Box::new(sink);
+    }
+
+    pub fn write_output(&self, stream: Stream, text: &str) {
+        self.output.write(stream, text);
+    }
+
+    pub(crate) fn output_is_terminal(&self, stream: Stream) -> bool {
+        self.output.is_terminal(stream)
+    }
+
+    pub fn set_instrument<I: Instrument + 'static>(&mut self, instrument: I) {
+        self.instrument_ops = instrument.ops();
+        self.instrument = Some(Box::new(instrument));
+    }
+
+    pub(crate) fn instrument_op(&self, position: usize, name: &'static str) {
+        if let Some(instrument) = &self.instrument {
+            instrument.op(self, position, name);
+        }
+    }
+
+    pub fn source_location(&self, position: usize) -> String {
+        self.assembler.borrow().source_location(position)
+    }
+
+    // where each promise that is still pending was created
+    pub fn pending_promises(&self) -> Vec<String> {
+        crate::intrinsics::promise::pending_positions()
+            .into_iter()
+            .map(|position| self.source_location(position))
+            .collect()
+    }
+
+    // `handler` is called whenever the script stops at a breakpoint, after a
+    // step or when paused, and returns how to go on
+    pub fn set_debugger<F: 'static>(&mut self, handler: F)
+    where
+        F: Fn(&Agent, &Paused) -> Resume,
+    {
+        self.debugger.attach(Box::new(handler));
+    }
+
+    // `file` is the name the module was loaded under, or the original file of
+    // a module with a source map
+    pub fn set_breakpoint(&self, file: &str, line: usize) {
+        self.debugger.set_breakpoint(file, line);
+    }
+
+    pub fn remove_breakpoint(&self, file: &str, line: usize) {
+        self.debugger.remove_breakpoint(file, line);
+    }
+
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.debugger.pause_handle()
+    }
+
+    pub fn set_uncaught_exception_handler<F: 'static>(&mut self, f: F)
+    where
+        F: Fn(&Agent, Value) -> (),
+    {
+        self.uncaught_exception_handler = Some(Box::new(f));
+    }
+
+    pub(crate) fn set_uncaught_exception_callback(&self, callback: Value) {
+        *self.uncaught_exception_callback.borrow_mut() = callback;
+    }
+
+    // the script's callback sees the exception first, and anything it throws
+    // goes on to the embedder's handler. without one the error and its stack
+    // are printed and the exit code is set, the event loop keeps going.
+    pub(crate) fn uncaught_exception(&self, e: Value) {
+        let callback = self.uncaught_exception_callback.borrow().clone();
+        let e = match callback {
+            Value::Null => e,
+            callback => match callback.call(self, Value::Null, vec![e]) {
+                Ok(_) => return,
+                Err(e) => e,
+            },
+        };
+        match &self.uncaught_exception_handler {
+            Some(f) => f(self, e),
+            None => {
+                self.write_output(
+                    Stream::Stderr,
+                    &format!("Uncaught Exception: {}\n", Value::inspect(self, &e)),
+                );
+                self.exit_code.set(1);
+            }
+        }
+    }
+
+    // what the process should exit with, 1 once an exception went uncaught
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.get()
+    }
+
+    pub fn push_frame(&self, function: Option<Value>) {
+        {
+            let mut stack = self.call_stack.borrow_mut();
+            if let Some(caller) = stack.last_mut() {
+                caller.position = self.position.get();
+            }
+            stack.push(StackFrame {
+                function,
+                position: 0,
+            });
+        }
+        // the instrument may look at the stack, so it can't stay borrowed
+        if let Some(instrument) = &self.instrument {
+            let function = self.call_stack.borrow().last().unwrap().function.clone();
+            instrument.enter(self, function.as_ref());
+        }
+    }
+
+    pub fn pop_frame(&self) {
+        if let Some(instrument) = &self.instrument {
+            let function = match self.call_stack.borrow().last() {
+                Some(frame) => frame.function.clone(),
+                None => return,
+            };
+            instrument.exit(self, function.as_ref());
+        }
+        let mut stack = self.call_stack.borrow_mut();
+        stack.pop();
+        if let Some(caller) = stack.last() {
+            self.position.set(caller.position);
+        }
+    }
+
+    pub fn pop_frames(&self, depth: usize) {
+        while self.call_stack.borrow().len() > depth {
+            self.pop_frame();
+        }
+    }
+
+    pub fn stack_trace(&self) -> String {
+        let stack = self.call_stack.borrow();
+        let mut lines = Vec::new();
+        for (i, frame) in stack.iter().enumerate().rev() {
+            let position = if i == stack.len() - 1 {
+                self.position.get()
+            } else {
+                frame.position
+            };
+            let name = match &frame.function {
+                Some(f) => match f.get(self, ObjectKey::from("name")) {
+                    Ok(Value::String(ref s)) if !s.is_empty() => s.to_string(),
+                    _ => "<anonymous>".to_string(),
+                },
+                None => "<module>".to_string(),
+            };
+            lines.push(format!(
+                "    at {} ({})",
+                name,
+                self.assembler.borrow().source_location(position)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn run(&mut self, specifier: &str, source: &str) -> Result<Value, Value> {
+        match Module::new(specifier, source, self) {
+            Err(e) => Err(e),
+            Ok(module) => {
+                let mut evaluator = Interpreter::new(module.bytecode_position, module.context);
+                evaluator.run(self).unwrap()
+            }
+        }
+    }
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Agent::new()
+    }
+}
+
+fn call_timer_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value> {
+    args[0].call(agent, Value::Null, Vec::new())?;
+    Ok(())
+}
 
 macro_rules! test {
     ( $name:ident, $source:expr, $result:expr ) => {
@@ -365,6 +1544,51 @@ test!(
     Ok(Value::from(6))
 );
 
+test!(
+    test_if_else_branches,
+    r#"
+    const yes = true;
+    const no = false;
+    let taken = '';
+    if yes {
+      taken += 'if';
+    } else {
+      taken += 'else';
+    }
+    if no {
+      taken += ' if';
+    } else {
+      taken += ' else';
+    }
+    taken == 'if else' && (yes ? 1 : 2) == 1 && (no ? 1 : 2) == 2;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_try_finally_paths,
+    r#"
+    const x = 'outer';
+    let path = '';
+    try {
+      path += 'try';
+    } catch e {
+      path += ' catch';
+    } finally {
+      path += ' finally';
+    }
+    let seen = null;
+    try {
+      const x = 'inner';
+      throw 1;
+    } catch e {
+      seen = x;
+    }
+    path == 'try finally' && seen == 'outer';
+    "#,
+    Ok(Value::from(true))
+);
+
 test!(
     test_symbols,
     r#"
@@ -397,6 +1621,75 @@ test!(
     Ok(Value::from(true))
 );
 
+#[test]
+fn test_regex_cache() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { compile } from standard:regex;
+            const digits = compile('[0-9]+');
+            const matches = (s) => /[0-9]+/.test(s);
+            [digits.test('a1'), matches('12'), matches('ab')];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![Value::from(true), Value::from(true), Value::from(false)]
+    );
+    assert_eq!(agent.regexes.borrow().len(), 1);
+
+    let bad: Result<Value, Value> = agent.evaluate("compile('(');");
+    assert!(bad.is_err());
+    assert_eq!(agent.regexes.borrow().len(), 1);
+}
+
+#[test]
+fn test_locale() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            const sorted = (words, locale) => {
+              words.sort((a, b) => a.localeCompare(b, locale));
+              let out = words[0];
+              let i = 1;
+              while i < 4 {
+                out += ' ' + words[i];
+                i += 1;
+              }
+              return out;
+            };
+            [
+              sorted(['zebra', 'Äpfel', 'apple', 'Apple'], 'de'),
+              sorted(['öl', 'zoo', 'ost', 'ångest'], 'sv-SE'),
+              sorted(['iyi', 'ılık', 'ince', 'hız'], 'tr'),
+              'résumé'.localeCompare('resume'),
+              'a'.localeCompare('a'),
+              'istanbul'.toLocaleUpperCase('tr'),
+              'DİYARBAKIR'.toLocaleLowerCase('tr-TR'),
+              'TITLE'.toLocaleLowerCase(),
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("Äpfel apple Apple zebra"),
+            Value::from("ost zoo ångest öl"),
+            Value::from("hız ılık ince iyi"),
+            Value::from(1),
+            Value::from(0),
+            Value::from("İSTANBUL"),
+            Value::from("diyarbakır"),
+            Value::from("title"),
+        ]
+    );
+    assert!(agent.evaluate::<Value>("'a'.localeCompare(1);").is_err());
+}
+
 test!(
     test_async,
     r#"
@@ -412,6 +1705,39 @@ test!(
     Ok(Value::from(10))
 );
 
+test!(
+    test_then_on_pending_promise,
+    r#"
+    let resolve = null;
+    const p = new Promise((r) => {
+      resolve = r;
+    });
+    const q = p.then(null).then((v) => v + 1);
+    resolve(1);
+    q;
+    "#,
+    Ok(Value::from(2))
+);
+
+#[test]
+fn test_timer_after_idle() {
+    let mut agent = Agent::new();
+    // the second timer is created once the timer thread has nothing left
+    // to wait for
+    for _ in 0..2 {
+        agent
+            .run(
+                "test_timer_after_idle.sl",
+                r#"
+                import { createTimeout } from standard:timers;
+                createTimeout(() => {}, 1);
+                "#,
+            )
+            .unwrap();
+        agent.run_jobs();
+    }
+}
+
 test!(
     test_for_loop,
     r#"
@@ -478,6 +1804,74 @@ test!(
 );
 */
 
+test!(
+    test_numeric_keys,
+    r#"
+    const a = [1, 2, 3];
+    a[1.5] = 'half';
+    a[-1] = 'minus';
+    const o = {};
+    o[2] = 'two';
+    a[1] == 2 && a[3] == null && a[1.5] == 'half' && a['1.5'] == 'half'
+      && a[-1] == 'minus' && a['1'] == 2 && o['2'] == 'two' && o['02'] == null;
+    "#,
+    Ok(Value::from(true))
+);
+
+#[test]
+fn test_object_key_canonical() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |key: &ObjectKey| {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    assert_eq!(ObjectKey::from("12"), ObjectKey::from(12.0));
+    assert_eq!(
+        hash(&ObjectKey::from("12")),
+        hash(&ObjectKey::from(12usize))
+    );
+    assert_eq!(ObjectKey::from(-0.0), ObjectKey::Number(0));
+    assert_eq!(ObjectKey::from(1.5), ObjectKey::from("1.5"));
+    assert_eq!(ObjectKey::from(-1.0), ObjectKey::from("-1"));
+    assert_eq!(ObjectKey::from(-1), ObjectKey::from("-1"));
+    assert_ne!(ObjectKey::from("012"), ObjectKey::from(12.0));
+    assert_eq!(ObjectKey::from(1e20).to_string(), "1e20");
+
+    let mut keys = vec![
+        ObjectKey::from("b"),
+        ObjectKey::from(10.0),
+        ObjectKey::from("a"),
+        ObjectKey::from(9.0),
+    ];
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec![
+            ObjectKey::from(9.0),
+            ObjectKey::from(10.0),
+            ObjectKey::from("a"),
+            ObjectKey::from("b"),
+        ]
+    );
+}
+
+test!(
+    test_object_identity,
+    r#"
+    const a = {};
+    const b = {};
+    const nan = 0 / 0;
+    a == a && a != b && Object.is(a, a) && !Object.is(a, b)
+      && Object.is(nan, nan) && !Object.is(0, -0) && 0 == -0
+      && Object.is('x', 'x') && Object().constructor == Object;
+    "#,
+    Ok(Value::from(true))
+);
+
 test!(
     test_class,
     r#"
@@ -496,6 +1890,37 @@ test!(
     Ok(Value::from(true))
 );
 
+test!(
+    test_inline_caches,
+    r#"
+    class P {
+      constructor(x) {
+        this.x = x;
+      }
+      get() {
+        return this.x;
+      }
+    }
+    const read = (o) => o.get();
+    const first = new P(1);
+    const second = new P(2);
+    const before = read(first) + read(second);
+    first.get = () => 10;
+    const after = read(first) + read(second);
+    const x = (o) => o.x;
+    const set = (o, v) => {
+      o.x = v;
+    };
+    const o = { x: 1 };
+    const f = { y: 1, x: 1 };
+    set(o, 2);
+    set(f, 3);
+    before == 3 && after == 12 && x({ x: 1, y: 2 }) + x({ y: 2, x: 3 }) == 4
+      && x({ y: 1 }) == null && o.x == 2 && f.x == 3 && f.y == 1;
+    "#,
+    Ok(Value::from(true))
+);
+
 test!(
     test_default_args,
     r#"
@@ -532,3 +1957,3306 @@ test!(
     "#,
     Ok(Value::from(true))
 );
+
+#[test]
+fn test_array_keeps_elements_alive() {
+    let mut agent = Agent::new();
+    let read = agent
+        .run(
+            "test_array_keeps_elements_alive.sl",
+            "const a = [{ v: 1 }, { v: 2 }];\n() => a[1].v;",
+        )
+        .unwrap();
+    gc::force_collect();
+    // anything freed by mistake gets reused here
+    agent
+        .run(
+            "test_array_keeps_elements_alive.sl",
+            "[{ v: 3 }, { v: 4 }];",
+        )
+        .unwrap();
+    assert_eq!(read.call(&agent, Value::Null, vec![]), Ok(Value::from(2)));
+}
+
+test!(
+    test_nullish_coalescing,
+    r#"
+    const a = null ?? 1;
+    const b = 0 ?? 1;
+    const c = '' ?? 'x';
+    let d = null;
+    d ??= 5;
+    d ??= 6;
+    const o = { x: null };
+    o.x ??= 'y';
+    a == 1 && b == 0 && c == '' && d == 5 && o.x == 'y';
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_exponentiation,
+    r#"
+    let a = 2;
+    a **= 3;
+    2 ** 3 ** 2 == 512 && (2 ** 3) ** 2 == 64 && a == 8 && 10 - 2 - 3 == 5;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_compound_assignment,
+    r#"
+    let calls = 0;
+    const o = { x: 1, y: 0, z: 'a' };
+    const get = () => {
+      calls += 1;
+      return o;
+    };
+    get().x += 2;
+    get()['x'] *= 4;
+    get().x -= 2;
+    get().x /= 2;
+    get().x %= 4;
+    get().y ||= 9;
+    get().y &&= 7;
+    get().z &&= 'b';
+    let a = 0;
+    a ||= 3;
+    a &&= a + 1;
+    o.x == 1 && o.y == 7 && o.z == 'b' && a == 4 && calls == 8;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_nested_binary_registers,
+    r#"
+    const a = 1;
+    const o = { x: 0 };
+    o['x'] += a + (a + (a + (a + (a + (a + (a + 1))))));
+    o.x += a + (a + (a + (a + (a + (a + (a + 1))))));
+    a + (a + (a + (a + (a + (a + (a + 1)))))) + o.x;
+    "#,
+    Ok(Value::from(24.0))
+);
+
+test!(
+    test_match,
+    r#"
+    const describe = (v) => match v {
+      0 => 'zero',
+      -1 => 'minus one',
+      'hi' | 1 => 'never',
+      (0, y) => `tuple ${y}`,
+      (x, y) if x > y => 'descending',
+      (_, _) => 'tuple',
+      [a, [b]] => a + b,
+      { kind: 'circle', r } => r * 2,
+      { kind } => kind,
+      n if n > 10 => 'big',
+      _ => 'other',
+    };
+    describe(0) == 'zero'
+      && describe(-1) == 'minus one'
+      && describe((0, 4)) == 'tuple 4'
+      && describe((2, 1)) == 'descending'
+      && describe((1, 2)) == 'tuple'
+      && describe([1, [2]]) == 3
+      && describe({ kind: 'circle', r: 3 }) == 6
+      && describe({ kind: 'square' }) == 'square'
+      && describe(11) == 'big'
+      && describe(5) == 'other'
+      && (true ? 1 : 2) == 1;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_numeric_literals,
+    r#"
+    0xFF == 255 && 0Xff == 255 && 0b1010 == 10 && 0o17 == 15
+      && 1_000_000 == 1000000 && 0b1111_0000 == 240 && 0xdead_beef == 3735928559
+      && 1_0.2_5 == 10.25 && 3.5 == 7 / 2;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_string_escapes,
+    r#"
+    const a = '\x41\u{42}\u{1F600}\u{0043}';
+    const b = `\x41\u{1F600}\$`;
+    const c = """
+line one
+  'line' "two"
+""";
+    const d = '''a\tb''';
+    a == 'AB😀C' && b == 'A😀$' && c == 'line one\n  \'line\' "two"\n'
+      && d == 'a\tb' && '' == "";
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_object_literal_shorthand,
+    r#"
+    const x = 1;
+    const y = 2;
+    const key = 'dyn';
+    const o = {
+      x,
+      y,
+      [key]: 3,
+      [:iterator]: 4,
+      'quoted key': 5,
+      6: 'six',
+      double() {
+        return this.x * 2;
+      },
+    };
+    o.x == 1 && o.y == 2 && o.dyn == 3 && o[:iterator] == 4
+      && o['quoted key'] == 5 && o[6] == 'six' && o.double() == 2;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_const_shadowing,
+    r#"
+    const a = 1;
+    let b = 0;
+    {
+      let a = 2;
+      a += 1;
+      b = a;
+    }
+    const f = (a) => {
+      let c = a;
+      c += 1;
+      return c;
+    };
+    a == 1 && b == 3 && f(1) == 2;
+    "#,
+    Ok(Value::from(true))
+);
+
+#[test]
+fn test_const_assignment() {
+    use crate::value::ObjectKey;
+
+    let sources = [
+        "const a = 1; a = 2;",
+        "const a = 1; a += 2;",
+        "const a = 1; function f() { a ??= 2; }",
+        "function f(x) { x = 1; }",
+        "const f = (x) => x = 1;",
+        "for x in [] { x = 1; }",
+        "try {} catch e { e = 1; }",
+        "class C {} C = 1;",
+        "match 1 { x => x = 2 };",
+    ];
+    for source in sources.iter() {
+        let mut agent = Agent::new();
+        let e = agent.run("test_const_assignment.sl", source).unwrap_err();
+        assert_eq!(
+            e.get(&agent, ObjectKey::from("code")),
+            Ok(Value::from("ConstantAssignment")),
+            "{}",
+            source
+        );
+    }
+}
+
+test!(
+    test_enum,
+    r#"
+    enum Color { Red, Green, Blue }
+    enum Status { Ok = 200, Created, NotFound = 404 }
+    const name = (c) => match c {
+      Color.Red => 'red',
+      Color.Green | Color.Blue => 'other',
+    };
+    let frozen = false;
+    try {
+      Color.Red = 1;
+    } catch {
+      frozen = true;
+    }
+    typeof Color.Red == 'symbol' && Color.Red != Color.Green
+      && Status.Ok == 200 && Status.Created == 201 && Status.NotFound == 404
+      && name(Color.Red) == 'red' && name(Color.Blue) == 'other' && frozen;
+    "#,
+    Ok(Value::from(true))
+);
+
+#[test]
+fn test_enum_non_exhaustive_match() {
+    use crate::value::ObjectKey;
+
+    let mut agent = Agent::new();
+    let e = agent
+        .run(
+            "test_enum_non_exhaustive_match.sl",
+            "enum E { A, B, C } match E.A { E.A => 1, E.B if true => 2, E.C => 3 };",
+        )
+        .unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("code")),
+        Ok(Value::from("NonExhaustiveMatch"))
+    );
+}
+
+test!(
+    test_record,
+    r#"
+    const x = 1;
+    const a = #{ x, y: (2, 'z'), nested: #{ ok: true } };
+    const b = #{ nested: #{ ok: true }, y: (2, 'z'), x: 1 };
+    let rejected = false;
+    try {
+      #{ o: {} };
+    } catch {
+      rejected = true;
+    }
+    let immutable = false;
+    try {
+      a.x = 2;
+    } catch e {
+      immutable = e.message == 'cannot assign to immutable record';
+    }
+    const m = match a {
+      { x: 1, nested: { ok } } => ok,
+      _ => false,
+    };
+    a == b && a != #{ x: 2 } && a.x == 1 && a.nested.ok && typeof a == 'record'
+      && rejected && immutable && m;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_named_tuple,
+    r#"
+    const point = (x: 1, y: 2);
+    const (x: px, y: py) = point;
+    let (a, [b, c]) = (3, [4, 5]);
+    a += 1;
+    const pair = (point, (1, 2));
+    const m = match point {
+      (x: 0, y) => y,
+      (y: 2, x) => x * 10,
+      _ => 0,
+    };
+    let mismatch = false;
+    try {
+      const (x: q) = (1, 2);
+    } catch {
+      mismatch = true;
+    }
+    point.x == 1 && point.0 == 1 && point.y == point.1 && point.length == 2
+      && px == 1 && py == 2 && a == 4 && b + c == 9 && pair.0.x == 1 && pair.1.1 == 2
+      && point == (x: 1, y: 2) && point != (1, 2) && point != (y: 1, x: 2)
+      && m == 10 && mismatch;
+    "#,
+    Ok(Value::from(true))
+);
+
+#[test]
+fn test_named_tuple_names_shared() {
+    let mut agent = Agent::new();
+    let pair = agent
+        .run(
+            "test_named_tuple_names_shared.sl",
+            "const f = (v) => (x: v, y: v);\n(f(1), f(2));",
+        )
+        .unwrap();
+    match pair {
+        Value::Tuple(items, None) => match (&items[0], &items[1]) {
+            (Value::Tuple(_, Some(a)), Value::Tuple(_, Some(b))) => {
+                assert_eq!(**a, vec!["x".to_string(), "y".to_string()]);
+                assert!(std::rc::Rc::ptr_eq(a, b));
+            }
+            _ => panic!("expected named tuples"),
+        },
+        _ => panic!("expected a tuple"),
+    }
+}
+
+#[test]
+fn test_stack_trace() {
+    let source = r#"
+    function inner(x) {
+      return x.y;
+    }
+    function outer() {
+      const v = inner(null);
+      return v;
+    }
+    function caught() {
+      try {
+        outer();
+      } catch e {
+        return e.stack;
+      }
+    }
+    caught() + '\n' + caught();
+    "#;
+    let mut agent = Agent::new();
+    let trace = [
+        "    at inner (test_stack_trace.sl:3:7)",
+        "    at outer (test_stack_trace.sl:6:7)",
+        "    at caught (test_stack_trace.sl:11:9)",
+        "    at <module> (test_stack_trace.sl:16:5)",
+    ]
+    .join("\n");
+    assert_eq!(
+        agent.run("test_stack_trace.sl", source),
+        Ok(Value::from(format!("{}\n{}", trace, trace)))
+    );
+    assert!(agent.call_stack.borrow().is_empty());
+}
+
+test!(
+    test_error_cause,
+    r#"
+    const root = new Error('root');
+    let wrapped = null;
+    try {
+      try {
+        throw root;
+      } catch e {
+        throw new Error('wrapped', { cause: e });
+      }
+    } catch e {
+      wrapped = e;
+    }
+    const plain = new Error('plain', { cause: 5 });
+    const bare = new Error();
+    wrapped.cause == root && wrapped.message == 'wrapped'
+      && wrapped.toString() == 'Error: wrapped\ncaused by: Error: root'
+      && plain.toString() == 'Error: plain\ncaused by: 5'
+      && bare.toString() == 'Error';
+    "#,
+    Ok(Value::from(true))
+);
+
+#[test]
+fn test_syntax_error_diagnostic() {
+    use crate::value::ObjectKey;
+
+    let mut agent = Agent::new();
+    let e = agent
+        .run(
+            "test_syntax_error_diagnostic.sl",
+            "const a = 1;\nlet b = (a +;\n",
+        )
+        .unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("name")),
+        Ok(Value::from("SyntaxError"))
+    );
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("code")),
+        Ok(Value::from("UnexpectedToken"))
+    );
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("message")),
+        Ok(Value::from(
+            r#"unexpected token
+ --> test_syntax_error_diagnostic.sl:2:13
+  |
+2 | let b = (a +;
+  |             ^
+  = hint: check for a missing operator, separator or semicolon"#
+        ))
+    );
+}
+
+#[test]
+fn test_syntax_error_recovery() {
+    use crate::value::ObjectKey;
+
+    let mut agent = Agent::new();
+    let e = agent
+        .run(
+            "test_syntax_error_recovery.sl",
+            r#"
+            const a = 1;
+            let b = (a +;
+            function f(x) {
+              const s = "; }";
+              return x +* 2;
+            }
+            a = 5;
+            let c = 3;
+            "#,
+        )
+        .unwrap_err();
+    let errors = e.get(&agent, ObjectKey::from("errors")).unwrap();
+    let mut found = Vec::new();
+    for i in 0..3 {
+        let d = errors.get(&agent, ObjectKey::from(i)).unwrap();
+        found.push((
+            d.get(&agent, ObjectKey::from("code")).unwrap(),
+            d.get(&agent, ObjectKey::from("line")).unwrap(),
+        ));
+    }
+    assert_eq!(
+        found,
+        vec![
+            (Value::from("UnexpectedToken"), Value::from(3.0)),
+            (Value::from("UnexpectedToken"), Value::from(6.0)),
+            (Value::from("ConstantAssignment"), Value::from(8.0)),
+        ]
+    );
+    assert_eq!(errors.get(&agent, ObjectKey::from(3)), Ok(Value::Null));
+}
+
+#[test]
+fn test_source_map() {
+    use crate::source_map::SourceMap;
+    use crate::value::ObjectKey;
+
+    let mut agent = Agent::new();
+    let e = agent
+        .run(
+            "test_source_map.sl",
+            "function f() {\n  throw new Error('boom');\n}\nf();\n//# sourceMappingURL=data:application/json;base64,eyJ2ZXJzaW9uIjogMywgInNvdXJjZXMiOiBbIm9yaWdpbmFsLnRzIl0sICJuYW1lcyI6IFtdLCAibWFwcGluZ3MiOiAiO0VBU0k7O0FBSUoifQ==\n",
+        )
+        .unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("stack")),
+        Ok(Value::from(
+            "    at f (original.ts:10:5)\n    at <module> (original.ts:14:1)"
+        ))
+    );
+
+    let map = agent.assembler.borrow().source_map();
+    assert_eq!(
+        SourceMap::parse(&map.to_json("test_source_map.sl")),
+        Some(map)
+    );
+}
+
+#[test]
+fn test_source_map_file_permissions() {
+    let dir = std::env::temp_dir().join(format!("slither_test_source_map_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("generated.sl.map"),
+        r#"{"version": 3, "sources": ["original.ts"], "names": [], "mappings": ";EASI;;AAIJ"}"#,
+    )
+    .unwrap();
+    let filename = dir.join("generated.sl").display().to_string();
+    let source = "function f() {\n  throw new Error('boom');\n}\nf();\n//# sourceMappingURL=generated.sl.map\n";
+    let stack = |permissions: Permissions| {
+        let mut agent = AgentBuilder::new().permissions(permissions).build();
+        let e = agent.run(&filename, source).unwrap_err();
+        match e.get(&agent, ObjectKey::from("stack")) {
+            Ok(Value::String(s)) => s.to_string(),
+            _ => String::new(),
+        }
+    };
+    assert!(stack(Permissions::allow_all()).contains("original.ts:10:5"));
+    // the map is only read where the script could read it
+    let mut permissions = Permissions::deny_all();
+    permissions.read = Some(vec![dir.join("elsewhere")]);
+    assert!(!stack(permissions).contains("original.ts"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_incomplete_input() {
+    use crate::parser::Parser;
+
+    let incomplete = |source: &str| {
+        Parser::parse(source)
+            .unwrap_err()
+            .last()
+            .unwrap()
+            .is_incomplete()
+    };
+    assert!(incomplete("function f(x) {\n  return x;\n"));
+    assert!(incomplete("[1,\n"));
+    assert!(incomplete("f(1 +"));
+    assert!(incomplete("const s = `a ${b"));
+    assert!(!incomplete("let y = ;"));
+    assert!(!incomplete("}"));
+}
+
+#[test]
+fn test_check() {
+    assert_eq!(crate::check("test_check.sl", "let a = 1; a += 1;"), Ok(()));
+    let e = crate::check("test_check.sl", "const a = 1;\na = 2;\nlet b = ;").unwrap_err();
+    assert!(e.starts_with("assignment to constant binding\n --> test_check.sl:2:3"));
+    assert!(e.contains("unexpected token\n --> test_check.sl:3:9"));
+}
+
+#[test]
+fn test_dump_ast() {
+    assert_eq!(
+        crate::dump_ast("test_dump_ast.sl", "let a = 1;\na += f(\"x\\n\");"),
+        Ok(concat!(
+            r#"{"type":"Program","version":1,"bindings":[{"name":"a","mutable":true}],"body":["#,
+            r#"{"type":"LexicalInitialization","name":"a","init":{"type":"NumberLiteral","value":1},"line":1,"column":1},"#,
+            r#"{"type":"ExpressionStatement","expression":{"type":"BinaryExpression","operator":"+=","#,
+            r#""left":{"type":"Identifier","name":"a"},"right":{"type":"CallExpression","#,
+            r#""callee":{"type":"Identifier","name":"f"},"arguments":[{"type":"StringLiteral","value":"x\n"}]}},"#,
+            r#""line":2,"column":1}]}"#
+        )
+        .to_string())
+    );
+}
+
+#[test]
+fn test_format() {
+    let source = concat!(
+        "// counts\n",
+        "let n=0;const o={a:1,\"b c\":n, m(x){return x;}};\n",
+        "\n",
+        "\n",
+        "@memo\n",
+        "function f(a,b=1) { if a {n+=1;} else { // none\n",
+        "  } } /* done */\n",
+        "f(1, () => { return n; });\n",
+    );
+    let expected = concat!(
+        "// counts\n",
+        "let n = 0;\n",
+        "const o = {\n",
+        "  a: 1,\n",
+        "  'b c': n,\n",
+        "  m(x) {\n",
+        "    return x;\n",
+        "  },\n",
+        "};\n",
+        "\n",
+        "@memo\n",
+        "function f(a, b = 1) {\n",
+        "  if a {\n",
+        "    n += 1;\n",
+        "  } else {\n",
+        "    // none\n",
+        "  }\n",
+        "} /* done */\n",
+        "f(1, () => {\n",
+        "  return n;\n",
+        "});\n",
+    );
+    assert_eq!(
+        crate::format("test_format.sl", source),
+        Ok(expected.to_string())
+    );
+    assert_eq!(
+        crate::format("test_format.sl", expected),
+        Ok(expected.to_string())
+    );
+    assert!(crate::format("test_format.sl", "let a = ;").is_err());
+}
+
+#[test]
+fn test_format_keeps_literals() {
+    let source = concat!(
+        "const t = ((1, 2), 3);\n",
+        "const n = [0xff, 0b101, 0o17, 1_000, 1.50, t.0.1, t[1]];\n",
+        "const s = [\"\\x41\", \"it's \\\"x\\\"\", '\\u{1F600}', { \"\\x41-b\": 1 }];\n",
+        "match -0x1 { -1 => 0x2, _ => 3 };\n",
+    );
+    let expected = concat!(
+        "const t = ((1, 2), 3);\n",
+        "const n = [0xff, 0b101, 0o17, 1_000, 1.50, t.0.1, t[1]];\n",
+        "const s = ['\\x41', 'it\\'s \"x\"', '\\u{1F600}', { '\\x41-b': 1 }];\n",
+        "match -0x1 { -1 => 0x2, _ => 3 };\n",
+    );
+    assert_eq!(
+        crate::format("test_format.sl", source),
+        Ok(expected.to_string())
+    );
+}
+
+#[test]
+fn test_format_keeps_comments_in_lists() {
+    let source = concat!(
+        "let arr = [\n",
+        "  1, // one\n",
+        "  2 // two\n",
+        "];\n",
+        "const o = { a: 1, /* b */ b: 2,\n",
+        "  // c\n",
+        "  c: 3 };\n",
+        "function f(a, /* second */ b) {\n",
+        "  return a;\n",
+        "}\n",
+        "f(1, 2);\n",
+    );
+    let expected = concat!(
+        "let arr = [\n",
+        "  1, // one\n",
+        "  2, // two\n",
+        "];\n",
+        "const o = {\n",
+        "  a: 1, /* b */\n",
+        "  b: 2,\n",
+        "  // c\n",
+        "  c: 3,\n",
+        "};\n",
+        "function f(\n",
+        "  a, /* second */\n",
+        "  b,\n",
+        ") {\n",
+        "  return a;\n",
+        "}\n",
+        "f(1, 2);\n",
+    );
+    assert_eq!(
+        crate::format("test_format.sl", source),
+        Ok(expected.to_string())
+    );
+    assert_eq!(
+        crate::format("test_format.sl", expected),
+        Ok(expected.to_string())
+    );
+}
+
+#[test]
+fn test_lint() {
+    use crate::{LintConfig, Severity};
+
+    let source = r#"
+import { readFile } from standard:fs;
+let a = 1;
+const b = 2;
+function f(b) {
+  readFile('x');
+  return b;
+  b;
+}
+f(b);
+"#;
+    let lints = |config| {
+        crate::lint("test_lint.sl", source, &config)
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.rule, l.severity, l.line, l.column))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        lints(LintConfig::default()),
+        vec![
+            ("unused-variable", Severity::Warning, 3, 1),
+            ("shadowing", Severity::Warning, 5, 1),
+            ("missing-await", Severity::Warning, 6, 3),
+            ("unreachable-code", Severity::Warning, 8, 3),
+        ]
+    );
+
+    let mut config = LintConfig::default();
+    config.set("unused-variable", Severity::Off).unwrap();
+    config.set("missing-await", Severity::Error).unwrap();
+    assert!(config.set("unknown", Severity::Off).is_err());
+    let found = lints(config);
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[1], ("missing-await", Severity::Error, 6, 3));
+
+    let l = &crate::lint("test_lint.sl", source, &LintConfig::default()).unwrap()[0];
+    assert_eq!(
+        l.render("test_lint.sl", source),
+        "warning[unused-variable]: `a` is declared but never read\n --> test_lint.sl:3:1\n  |\n3 | let a = 1;\n  | ^"
+    );
+}
+
+#[test]
+fn test_disassemble() {
+    let mut agent = Agent::new();
+    let listing = agent.run(
+        "test_disassemble.sl",
+        r#"
+        import { disassemble } from standard:debug;
+        function f(x) {
+          return x ?? 1;
+        }
+        disassemble(f);
+        "#,
+    );
+    assert_eq!(
+        listing,
+        Ok(Value::from(
+            r#"; test_disassemble.sl:4:11
+  016 ResolveIdentifier ["x"]
+  021 JumpIfNotNull [@035]
+  026 LoadF64 [1]
+> 035 Return
+"#
+        ))
+    );
+}
+
+#[test]
+fn test_constant_folding() {
+    let mut agent = Agent::new();
+    let listing = agent.run(
+        "test_constant_folding.sl",
+        r#"
+        import { disassemble } from standard:debug;
+        function f(o) {
+          if 'a' + 'b' == 'ab' && !(2 ** 3 > 8) {
+            return o['day'] * (60 * 60 * 24);
+          }
+          return 0;
+        }
+        disassemble(f);
+        "#,
+    );
+    assert_eq!(
+        listing,
+        Ok(Value::from(
+            r#"; test_constant_folding.sl:5:13
+  016 ResolveIdentifier ["o"]
+  021 LoadNamedProperty ["day"], #0
+  030 StoreAccumulatorInRegister r0
+  035 LoadF64 [86400]
+  044 Mul r0
+  049 Return
+; test_constant_folding.sl:7:11
+  050 LoadF64 [0]
+  059 Return
+"#
+        ))
+    );
+}
+
+#[test]
+fn test_bytecode_cache() {
+    let dir = std::env::temp_dir().join(format!("slither_test_cache_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("lib.sl"),
+        "export function add(a, b = 2) {\n  return a + b;\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.sl"),
+        "import { add } from './lib';\nif (add(1) != 3) {\n  throw 'wrong';\n}\n",
+    )
+    .unwrap();
+    let referrer = dir.join("test").to_str().unwrap().to_string();
+
+    let run = || {
+        let mut agent = Agent::new();
+        agent.cache_bytecode = true;
+        let result = agent.import("main.sl", &referrer);
+        let listing =
+            crate::interpreter::disassemble(agent.assembler.get_mut(), 0, std::usize::MAX, false);
+        (result.is_ok(), listing)
+    };
+    let compiled = run();
+    assert_eq!(
+        std::fs::read_dir(dir.join(".slither-cache"))
+            .unwrap()
+            .count(),
+        2
+    );
+    let cached = run();
+    assert!(compiled.0);
+    assert_eq!(compiled, cached);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_agent_builder() {
+    let mut agent = AgentBuilder::new()
+        .threads(1)
+        .builtin_modules(&["math", "timers"])
+        .call_stack_limit(50)
+        .global("answer", |_| Value::from(42.0))
+        .random(|| 0.25)
+        .clock(|| 1000.0)
+        .build();
+    assert_eq!(
+        agent.run(
+            "test_agent_builder.sl",
+            r#"
+            import { random } from standard:math;
+            import { now } from standard:timers;
+            answer + random() + now();
+            "#,
+        ),
+        Ok(Value::from(1042.25))
+    );
+    assert!(agent
+        .run(
+            "test_agent_builder.sl",
+            "import { print } from standard:debug;"
+        )
+        .is_err());
+    let e = agent
+        .run(
+            "test_agent_builder.sl",
+            "function f(n) { return 1 + f(n + 1); }\nf(0);",
+        )
+        .unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("message")),
+        Ok(Value::from("maximum call stack size exceeded"))
+    );
+}
+
+#[test]
+fn test_register_module() {
+    use crate::interpreter::Context;
+
+    fn double(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+        match args.get(0) {
+            Some(Value::Number(n)) => Ok(Value::from(n * 2.0)),
+            _ => Err(Value::new_error(agent, "argument must be a number")),
+        }
+    }
+
+    let mut agent = Agent::new();
+    agent.register_module("host", |agent| {
+        let mut module = HashMap::new();
+        module.insert(
+            "double".to_string(),
+            Value::new_builtin_function(agent, double),
+        );
+        module.insert("name".to_string(), Value::from("slither"));
+        module
+    });
+    assert_eq!(
+        agent.run(
+            "test_register_module.sl",
+            "import { double, name } from standard:host;\nname == 'slither' && double(21) == 42;",
+        ),
+        Ok(Value::from(true))
+    );
+}
+
+#[test]
+fn test_evaluate_for_await() {
+    use crate::interpreter::Context;
+    use crate::intrinsics::promise::promise_resolve_i;
+
+    // counts up to 3, one promise at a time
+    fn next(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+        let this = ctx.scope.borrow().get_this(agent)?;
+        let n = match this.get_slot("n") {
+            Value::Number(n) => n,
+            _ => unreachable!(),
+        };
+        this.set_slot("n", Value::from(n + 1.0));
+        let result = Value::new_object(agent.intrinsics.object_prototype.clone());
+        result.set(agent, ObjectKey::from("value"), Value::from(n))?;
+        result.set(agent, ObjectKey::from("done"), Value::from(n >= 3.0))?;
+        promise_resolve_i(agent, agent.intrinsics.promise.clone(), result)
+    }
+
+    let mut agent = Agent::new();
+    agent.register_module("counter", |agent| {
+        let counter = Value::new_custom_object(agent.intrinsics.async_iterator_prototype.clone());
+        counter.set_slot("n", Value::from(0.0));
+        counter
+            .set(
+                agent,
+                ObjectKey::from("next"),
+                Value::new_builtin_function(agent, next),
+            )
+            .unwrap();
+        let mut module = HashMap::new();
+        module.insert("counter".to_string(), counter);
+        module
+    });
+    assert_eq!(
+        agent.evaluate::<f64>(
+            r#"
+            import { counter } from standard:counter;
+            let sum = 10;
+            for await n in counter {
+              sum += n;
+            }
+            sum;
+            "#,
+        ),
+        Ok(13.0)
+    );
+    assert_eq!(agent.evaluate::<f64>("sum;"), Ok(13.0));
+}
+
+#[test]
+fn test_load_extension() {
+    let mut agent = Agent::new();
+    let path = std::env::temp_dir().join("slither_test_no_such_extension.so");
+    let e = agent.load_extension(&path).unwrap_err();
+    assert!(Value::inspect(&agent, &e).contains("unable to load extension"));
+}
+
+#[test]
+fn test_value_conversions() {
+    use crate::{FromValue, IntoValue};
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: f64,
+        label: Option<String>,
+        tags: Vec<String>,
+    }
+    value_struct!(Point { x, y, label, tags });
+
+    let mut agent = Agent::new();
+    let point = Point {
+        x: 1,
+        y: 2.5,
+        label: None,
+        tags: vec!["a".to_string(), "b".to_string()],
+    };
+    let value = point.into_value(&agent);
+    assert_eq!(Point::from_value(&agent, value), Ok(point));
+
+    let value = agent
+        .run(
+            "test_value_conversions.sl",
+            "({ x: 3, y: 4, label: 'p', tags: ['c'] });",
+        )
+        .unwrap();
+    assert_eq!(
+        Point::from_value(&agent, value),
+        Ok(Point {
+            x: 3,
+            y: 4.0,
+            label: Some("p".to_string()),
+            tags: vec!["c".to_string()],
+        })
+    );
+
+    let mut map = HashMap::new();
+    map.insert("n".to_string(), vec![1u32, 2]);
+    let value = map.into_value(&agent);
+    assert_eq!(
+        HashMap::<String, Vec<u32>>::from_value(&agent, value),
+        Ok(map)
+    );
+
+    assert!(i32::from_value(&agent, Value::from(1.5)).is_err());
+    assert!(String::from_value(&agent, Value::from(1.0)).is_err());
+}
+
+#[test]
+fn test_host_calls() {
+    let mut agent = Agent::new();
+    let n: f64 = agent
+        .evaluate(
+            r#"
+            import { createTimeout } from standard:timers;
+            function add(a, b) {
+              return a + b;
+            }
+            async function later(v) {
+              await new Promise((resolve) => {
+                createTimeout(resolve, 1);
+              });
+              return v;
+            }
+            async function fail() {
+              throw 'nope';
+            }
+            later(2);
+            "#,
+        )
+        .unwrap();
+    assert_eq!(n, 2.0);
+    assert_eq!(
+        agent.call_function("add", vec![Value::from(1.0), Value::from(2.0)]),
+        Ok(3.0)
+    );
+    assert_eq!(
+        agent.call_function::<_, String>("later", vec![Value::from("x")]),
+        Ok("x".to_string())
+    );
+    assert_eq!(
+        agent.call_function::<_, Value>("fail", vec![]),
+        Err(Value::from("nope"))
+    );
+    let add: Value = agent.evaluate("add;").unwrap();
+    assert_eq!(
+        agent.call_function(add, vec![Value::from(2.0), Value::from(2.0)]),
+        Ok(4.0)
+    );
+}
+
+#[test]
+fn test_module_loader() {
+    let mut agent = Agent::new();
+    agent.set_module_loader(|specifier, referrer| {
+        let source = match specifier {
+            "bundle:main" => "import { b } from './b';\nexport const a = b + 1;\n",
+            "./b" if referrer == "bundle:main" => "export const b = 41;\n",
+            _ => return None,
+        };
+        let filename = specifier.replace("./", "bundle:");
+        Some((filename, source.to_string()))
+    });
+    assert_eq!(
+        agent.evaluate("import { a } from 'bundle:main';\na == 42;"),
+        Ok(true)
+    );
+    let e = agent
+        .evaluate::<Value>("import { c } from './missing';")
+        .unwrap_err();
+    assert!(match e.get(&agent, ObjectKey::from("message")) {
+        Ok(Value::String(s)) => s.starts_with("cannot find module './missing'"),
+        _ => false,
+    });
+}
+
+#[test]
+fn test_permissions() {
+    let dir = std::env::temp_dir().join(format!("slither_test_permissions_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.txt"), "hello").unwrap();
+
+    let mut permissions = Permissions::deny_all();
+    permissions.read = Some(vec![dir.clone()]);
+    permissions.net = Some(vec!["localhost:1".to_string()]);
+    let mut agent = AgentBuilder::new().permissions(permissions).build();
+    let source = format!(
+        r#"
+        import {{ readFile, writeFile }} from standard:fs;
+        import {{ connect }} from standard:net;
+        const denied = (f) => {{
+          try {{
+            f();
+          }} catch e {{
+            return e.code == 'PermissionDenied';
+          }}
+          return false;
+        }};
+        readFile('{dir}/data.txt');
+        denied(() => readFile('{dir}/../outside.txt'))
+          && denied(() => writeFile('{dir}/data.txt', 'x'))
+          && denied(() => connect('127.0.0.1:2'));
+        "#,
+        dir = dir.display()
+    );
+    assert_eq!(
+        agent.run("test_permissions.sl", &source),
+        Ok(Value::from(true))
+    );
+    agent.run_jobs();
+
+    // hostnames allow what they resolve to
+    let allowed = |addr: &str| agent.permissions.check_net(&agent, &addr.parse().unwrap());
+    assert!(allowed("127.0.0.1:1").is_ok());
+    assert!(allowed("127.0.0.1:2").is_err());
+
+    // modules are read like any other file
+    let outside = std::env::temp_dir().join(format!(
+        "slither_test_permissions_outside_{}.sl",
+        std::process::id()
+    ));
+    std::fs::write(dir.join("lib.sl"), "export const v = 1;").unwrap();
+    std::fs::write(&outside, "export const v = 2;").unwrap();
+    let source = format!(
+        "import {{ v }} from '{}';\nv;",
+        dir.join("lib.sl").display()
+    );
+    assert_eq!(agent.evaluate::<Value>(&source), Ok(Value::from(1)));
+    let source = format!("import {{ v }} from '{}';\nv;", outside.display());
+    let e = agent.evaluate::<Value>(&source).unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("code")),
+        Ok(Value::from("PermissionDenied"))
+    );
+    std::fs::remove_file(&outside).unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_copy_directory() {
+    let dir = std::env::temp_dir().join(format!("slither_test_copy_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("from/sub/skipped")).unwrap();
+    std::fs::write(dir.join("from/a.txt"), "aa").unwrap();
+    std::fs::write(dir.join("from/sub/b.txt"), "bbb").unwrap();
+    std::fs::write(dir.join("from/sub/b.tmp"), "x").unwrap();
+    std::fs::write(dir.join("from/sub/skipped/c.txt"), "c").unwrap();
+
+    let mut agent = Agent::new();
+    let source = format!(
+        r#"
+        import {{ copyDirectory }} from standard:fs;
+        let progress = '';
+        let copied = false;
+        let refused = false;
+        const filter = (path, type) => path != 'sub/skipped' && path != 'sub/b.tmp';
+        const onProgress = (p) => {{
+          progress += `${{p.path}} ${{p.copiedFiles}}/${{p.totalFiles}} `;
+          progress += `${{p.copiedBytes}}/${{p.totalBytes}};`;
+        }};
+        const again = () => copyDirectory('{dir}/from', '{dir}/to');
+        const overwrite = () => {{
+          refused = true;
+          return copyDirectory('{dir}/from', '{dir}/to', {{ overwrite: true }});
+        }};
+        copyDirectory('{dir}/from', '{dir}/to', {{ filter, onProgress }})
+          .then(again)
+          .catch(overwrite)
+          .then(() => {{
+            copied = true;
+          }});
+        "#,
+        dir = dir.display()
+    );
+    agent.evaluate::<Value>(&source).unwrap();
+    agent.run_jobs();
+    assert_eq!(
+        agent.evaluate::<Value>("progress;"),
+        Ok(Value::from("a.txt 1/2 2/5;sub/b.txt 2/2 5/5;"))
+    );
+    assert_eq!(agent.evaluate::<bool>("refused && copied;"), Ok(true));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("to/sub/skipped/c.txt")).unwrap(),
+        "c"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_process() {
+    std::env::set_var("SLITHER_TEST_PROCESS", "yes");
+    let source = r#"
+        import { args, env } from standard:process;
+        const a = args();
+        let denied = false;
+        try {
+          env('SLITHER_TEST_PROCESS');
+        } catch e {
+          denied = e.code == 'PermissionDenied';
+        }
+        a[0] == 'x' && a[1] == 'y' && a[2] == null && denied;
+        "#;
+    let mut agent = AgentBuilder::new()
+        .permissions(Permissions::deny_all())
+        .build();
+    agent.args = vec!["x".to_string(), "y".to_string()];
+    assert_eq!(agent.run("test_process.sl", source), Ok(Value::from(true)));
+
+    let mut agent = Agent::new();
+    let result: Value = agent
+        .evaluate(
+            r#"
+            import { env } from standard:process;
+            env('SLITHER_TEST_PROCESS') == 'yes' && env('SLITHER_TEST_PROCESS_UNSET') == null;
+            "#,
+        )
+        .unwrap();
+    assert_eq!(result, Value::from(true));
+}
+
+#[test]
+fn test_load_env_file() {
+    std::env::set_var("SLITHER_TEST_DOTENV_KEPT", "from env");
+    let path = std::env::temp_dir().join(format!("slither_test_{}.env", std::process::id()));
+    std::fs::write(
+        &path,
+        concat!(
+            "# settings\n",
+            "export SLITHER_TEST_DOTENV_HOST=localhost\n",
+            "SLITHER_TEST_DOTENV_URL=\"http://${SLITHER_TEST_DOTENV_HOST}:${SLITHER_TEST_DOTENV_PORT:-80}/\"\n",
+            "SLITHER_TEST_DOTENV_RAW='$SLITHER_TEST_DOTENV_HOST' # literal\n",
+            "SLITHER_TEST_DOTENV_LINES=\"a\n",
+            "b\\tc\"\n",
+            "SLITHER_TEST_DOTENV_KEPT=from file\n",
+            "SLITHER_TEST_DOTENV_SEEN=$SLITHER_TEST_DOTENV_KEPT\n",
+        ),
+    )
+    .unwrap();
+    let source = format!(
+        r#"
+        import {{ env, loadEnvFile }} from standard:process;
+        const loaded = loadEnvFile('{path}');
+        [
+          env('SLITHER_TEST_DOTENV_URL'),
+          env('SLITHER_TEST_DOTENV_RAW'),
+          env('SLITHER_TEST_DOTENV_LINES'),
+          env('SLITHER_TEST_DOTENV_KEPT'),
+          env('SLITHER_TEST_DOTENV_SEEN'),
+          loaded.SLITHER_TEST_DOTENV_KEPT,
+          loadEnvFile('{path}', {{ override: true }}).SLITHER_TEST_DOTENV_SEEN,
+          env('SLITHER_TEST_DOTENV_KEPT'),
+        ];
+        "#,
+        path = path.display()
+    );
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent.evaluate(&source).unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("http://localhost:80/"),
+            Value::from("$SLITHER_TEST_DOTENV_HOST"),
+            Value::from("a\nb\tc"),
+            Value::from("from env"),
+            Value::from("from env"),
+            Value::from("from file"),
+            Value::from("from file"),
+            Value::from("from file"),
+        ]
+    );
+
+    std::fs::write(&path, "SLITHER_TEST_DOTENV_BAD=\"open\n").unwrap();
+    let error = agent
+        .evaluate::<Value>(&format!("loadEnvFile('{}');", path.display()))
+        .unwrap_err();
+    let message = error.get(&agent, ObjectKey::from("message")).unwrap();
+    assert_eq!(
+        message,
+        Value::from(format!("{}:1: unterminated quote", path.display()))
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_storage() {
+    let path = std::env::temp_dir().join(format!("slither_test_{}.db", std::process::id()));
+    let source = format!(
+        r#"
+        import {{ open }} from standard:storage;
+        const db = open('{path}');
+        db.set('user:2', 'bo');
+        db.set('user:1', 'al');
+        db.set('count', 3);
+        db.batch([
+          {{ type: 'set', key: 'user:3', value: 'cy' }},
+          {{ type: 'delete', key: 'user:2' }},
+        ]);
+        let invalid = false;
+        try {{
+          db.batch([{{ type: 'set', key: 'user:4', value: 'di' }}, {{ type: 'nope', key: 'x' }}]);
+        }} catch {{
+          invalid = true;
+        }}
+        [db.delete('count'), db.delete('count'), db.has('user:4'), invalid];
+        "#,
+        path = path.display()
+    );
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent.evaluate(&source).unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from(true),
+            Value::from(false),
+            Value::from(false),
+            Value::from(true),
+        ]
+    );
+
+    // a batch cut off by a crash is dropped
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    std::io::Write::write_all(&mut log, b"set 6b 7378").unwrap();
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(&format!(
+            r#"
+            import {{ open }} from standard:storage;
+            const db = open('{path}');
+            const users = db.entries('user:');
+            db.set('flag', true);
+            const keys = db.keys();
+            [keys[0], keys[1], keys[2], keys[3], users[0][1], users[1][0], db.get('k'), db.get('flag')];
+            "#,
+            path = path.display()
+        ))
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("flag"),
+            Value::from("user:1"),
+            Value::from("user:3"),
+            Value::Null,
+            Value::from("al"),
+            Value::from("user:3"),
+            Value::Null,
+            Value::from(true),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_form() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { encodeUrl, decodeUrl, encodeMultipart, decodeMultipart } from standard:form;
+            const decoded = decodeUrl('a=1&b=x+y%21&a=2&c');
+            const { contentType, body } = encodeMultipart({
+              title: 'hi "there"',
+              upload: { data: 'abc', filename: 'a.txt', contentType: 'text/plain' },
+            });
+            const parts = decodeMultipart(body, contentType);
+            [
+              encodeUrl({ q: 'a b&c', n: 1, list: ['x', 'y'] }),
+              decoded.a[1] == '2' && decoded.b == 'x y!' && decoded.c == '',
+              parts[0].name == 'title' && parts[0].data == 'hi "there"' && parts[0].filename == null,
+              parts[1].filename == 'a.txt' && parts[1].contentType == 'text/plain' && parts[2] == null,
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("list=x&list=y&n=1&q=a+b%26c"),
+            Value::from(true),
+            Value::from(true),
+            Value::from(true),
+        ]
+    );
+    assert!(agent
+        .evaluate::<Value>("decodeMultipart('nope', 'multipart/form-data; boundary=x');")
+        .is_err());
+}
+
+#[test]
+fn test_headers() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { createHeaders, parseCookie, serializeCookie, parseSetCookie } from standard:headers;
+            const h = createHeaders('Content-Type: text/plain\r\nSet-Cookie: a=1\r\n');
+            h.append('set-cookie', 'b=2');
+            h.set('X-Count', 3);
+            const cookie = parseSetCookie(
+              'id=a%20b; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Path=/; HttpOnly; SameSite=lax',
+            );
+            let invalid = false;
+            try {
+              h.set('x', 'a\r\nb: c');
+            } catch {
+              invalid = true;
+            }
+            [
+              h.get('CONTENT-TYPE'),
+              h.get('Set-Cookie'),
+              h.getAll('set-cookie')[1],
+              h.toString(),
+              parseCookie('a=1; b="x%3Dy"; a=2').b,
+              serializeCookie('id', 'a b', {
+                expires: 1445412480000,
+                maxAge: 60,
+                path: '/',
+                secure: true,
+                sameSite: 'Strict',
+              }),
+              cookie.value == 'a b' && cookie.expires == 1445412480000 && cookie.httpOnly
+                && !cookie.secure && cookie.sameSite == 'Lax' && cookie.domain == null,
+              invalid,
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("text/plain"),
+            Value::from("a=1, b=2"),
+            Value::from("b=2"),
+            Value::from("content-type: text/plain\r\nset-cookie: a=1\r\nset-cookie: b=2\r\nx-count: 3\r\n"),
+            Value::from("x=y"),
+            Value::from("id=a%20b; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=60; Path=/; Secure; SameSite=Strict"),
+            Value::from(true),
+            Value::from(true),
+        ]
+    );
+}
+
+#[test]
+fn test_string_builder() {
+    let mut agent = Agent::new();
+    let source = r#"
+        import { StringBuilder } from standard:strings;
+        const b = new StringBuilder('n: ');
+        let i = 0;
+        while i < 3 {
+          b.append(i, ',');
+          i += 1;
+        }
+        b.appendLine().append('x').appendLine(true);
+        [b.toString(), `${StringBuilder().append('y')}`];
+        "#;
+    let result: Vec<String> = agent.evaluate(source).unwrap();
+    assert_eq!(result, vec!["n: 0,1,2,\nxtrue\n", "y"]);
+    assert!(agent
+        .evaluate::<Value>("StringBuilder().append(null);")
+        .is_err());
+}
+
+#[test]
+fn test_immutable() {
+    let mut agent = Agent::new();
+    let source = r#"
+        import { List, Map } from standard:immutable;
+        let list = List([0, 1]);
+        let i = 2;
+        while i < 1100 {
+          list = list.push(i);
+          i += 1;
+        }
+        const changed = list.set(1050, 'x').set(3, 'y');
+        let popped = changed;
+        while popped.size > 30 {
+          popped = popped.pop();
+        }
+
+        let map = Map({ a: 1 });
+        i = 0;
+        while i < 2000 {
+          map = map.set(`k${i}`, i);
+          i += 1;
+        }
+        const keyed = map.set((1, 2), 'tuple').set(#{ x: 1 }, 'record').delete('k7');
+        [
+          list.size, list.get(1050), list.get(3), changed.get(1050), changed.get(3),
+          popped.size, popped.get(29), popped.toArray()[3], list.pop().size,
+          map.size, map.get('k1999'), map.get('a'), map.has('k7'), map.has((1, 2)),
+          keyed.size, keyed.has('k7'), keyed.get((1, 2)), keyed.get(#{ x: 1 }),
+          keyed.delete('missing') == keyed, Map().entries()[0],
+        ];
+        "#;
+    let result: Vec<Value> = agent.evaluate(source).unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from(1100.0),
+            Value::from(1050.0),
+            Value::from(3.0),
+            Value::from("x"),
+            Value::from("y"),
+            Value::from(30.0),
+            Value::from(29.0),
+            Value::from("y"),
+            Value::from(1099.0),
+            Value::from(2001.0),
+            Value::from(1999.0),
+            Value::from(1.0),
+            Value::from(true),
+            Value::from(false),
+            Value::from(2002.0),
+            Value::from(false),
+            Value::from("tuple"),
+            Value::from("record"),
+            Value::from(true),
+            Value::Null,
+        ]
+    );
+    assert!(agent.evaluate::<Value>("list.get(1100);").is_err());
+    assert!(agent.evaluate::<Value>("List().pop();").is_err());
+}
+
+#[test]
+fn test_deque_and_priority_queue() {
+    let mut agent = Agent::new();
+    let source = r#"
+        const d = Deque([2, 3]);
+        d.pushFront(0, 1);
+        d.pushBack(4);
+        const popped = [d.popFront(), d.popBack()];
+        const q = new PriorityQueue();
+        q.push(5, 1, 4, 1.5);
+        const byRank = PriorityQueue((a, b) => a.rank - b.rank);
+        byRank.push({ rank: 3, name: 'c' }, { rank: 1, name: 'a' }, { rank: 2, name: 'b' });
+        byRank.push({ rank: 1, name: 'a2' });
+        [
+          popped[0], popped[1], d.size, d.get(0), d.peekFront(), d.peekBack(), d.toArray()[2],
+          q.pop(), q.pop(), q.peek(), q.size,
+          byRank.pop().name, byRank.pop().name, byRank.pop().name, byRank.pop().name, byRank.pop(),
+          Deque().popBack(),
+        ];
+        "#;
+    let result: Vec<Value> = agent.evaluate(source).unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from(0.0),
+            Value::from(4.0),
+            Value::from(3.0),
+            Value::from(1.0),
+            Value::from(1.0),
+            Value::from(3.0),
+            Value::from(3.0),
+            Value::from(1.0),
+            Value::from(1.5),
+            Value::from(4.0),
+            Value::from(2.0),
+            Value::from("a"),
+            Value::from("a2"),
+            Value::from("b"),
+            Value::from("c"),
+            Value::Null,
+            Value::Null,
+        ]
+    );
+    assert!(agent.evaluate::<Value>("q.push('x');").is_err());
+    assert!(agent.evaluate::<Value>("d.get(3);").is_err());
+}
+
+#[test]
+fn test_vector_math() {
+    let mut agent = Agent::new();
+    agent
+        .evaluate::<Value>(
+            "import { dot, cross, multiply, invert } from standard:math;
+            const m = [0, 1, 0, 2, 0, 0, 0, 0, 4];",
+        )
+        .unwrap();
+    let result: Vec<Vec<f64>> = agent
+        .evaluate(
+            r#"
+            [
+              [dot([1, 2, 3], [4, 5, 6])],
+              cross([1, 0, 0], [0, 1, 0]),
+              multiply([1, 2, 3, 4], [5, 6, 7, 8]),
+              multiply([1, 2, 3, 4], [1, 1]),
+              invert(m),
+              multiply(m, invert(m)),
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            vec![32.0],
+            vec![0.0, 0.0, 1.0],
+            vec![19.0, 22.0, 43.0, 50.0],
+            vec![3.0, 7.0],
+            vec![0.0, 0.5, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.25],
+            vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        ]
+    );
+    assert!(agent.evaluate::<Value>("invert([1, 2, 2, 4]);").is_err());
+    assert!(agent.evaluate::<Value>("multiply(m, [1, 2]);").is_err());
+    assert!(agent.evaluate::<Value>("cross([1, 2], [3, 4]);").is_err());
+}
+
+#[test]
+fn test_intl() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { createNumberFormat, createDateFormat } from standard:intl;
+            const when = 1445412480000;
+            [
+              createNumberFormat('en-US').format(1234567.891),
+              createNumberFormat('de', { minimumFractionDigits: 2 }).format(-1234.5),
+              createNumberFormat('en', { currency: 'USD' }).format(-1234.5),
+              createNumberFormat('de-DE', { currency: 'EUR' }).format(1234.5),
+              createNumberFormat('en', { currency: 'JPY', useGrouping: false }).format(1234.5),
+              createNumberFormat('en', { maximumFractionDigits: 0 }).format(999.5),
+              createDateFormat().format(when),
+              createDateFormat('en', { pattern: "EEEE, MMMM d, yyyy 'at' h:mm a" }).format(when),
+              createDateFormat('fr', { pattern: 'EEE d MMM yy', utcOffset: 1080 }).format(when),
+              createDateFormat('de', { pattern: "HH:mm:ss.SSS 'Uhr'" }).format(when + 5),
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("1,234,567.891"),
+            Value::from("-1.234,50"),
+            Value::from("-$1,234.50"),
+            Value::from("1.234,50\u{a0}€"),
+            Value::from("¥1235"),
+            Value::from("1,000"),
+            Value::from("2015-10-21 07:28:00"),
+            Value::from("Wednesday, October 21, 2015 at 7:28 AM"),
+            Value::from("jeu 22 oct 15"),
+            Value::from("07:28:00.005 Uhr"),
+        ]
+    );
+    assert!(agent
+        .evaluate::<Value>("createDateFormat('en', { pattern: 'yyyy-QQ' });")
+        .is_err());
+}
+
+#[test]
+fn test_inspect_options() {
+    let mut agent = Agent::new();
+    let result: Vec<String> = agent
+        .evaluate(
+            r#"
+            import { inspect } from standard:debug;
+            const config = { list: [1, 2, 3, 4], nested: { deeper: { x: 1 } }, name: 'abcdef' };
+            [
+              inspect(config, { depth: 1, maxArrayItems: 2, maxStringLength: 3 }),
+              inspect([], { depth: 0 }),
+              inspect(1, { colors: true }),
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "{\n  list: [\n    0: 1,\n    1: 2,\n    ... 2 more items\n  ],\n  name: 'abc...',\n  nested: {\n    deeper: [Object],\n  },\n}".to_string(),
+            "[]".to_string(),
+            "\x1b[33m1\x1b[39m".to_string(),
+        ]
+    );
+    let bad: Result<Value, Value> = agent.evaluate("inspect(1, { depth: -1 });");
+    assert!(bad.is_err());
+}
+
+#[test]
+fn test_inspect_hook() {
+    let mut agent = Agent::new();
+    let result: Vec<String> = agent
+        .evaluate(
+            r#"
+            import { inspect } from standard:debug;
+            class Point {
+              constructor(x, y) {
+                this.x = x;
+                this.y = y;
+              }
+              [:inspect]() {
+                return `Point(${this.x}, ${this.y})`;
+              }
+            }
+            const secret = { password: 'hunter2', [:inspect]: () => ({ password: '***' }) };
+            const broken = { [:inspect]: () => { throw 'nope'; } };
+            [inspect([new Point(1, 2)]), inspect(secret), inspect(broken)];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(result[0], "[\n  0: Point(1, 2),\n]");
+    assert_eq!(result[1], "{\n  password: '***',\n}");
+    assert!(result[2].contains("Symbol(inspect)"));
+}
+
+#[test]
+fn test_console() {
+    let captured = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let sink = captured.clone();
+    let mut agent = AgentBuilder::new()
+        .output(move |stream: Stream, text: &str| {
+            sink.borrow_mut().push((stream, text.to_string()));
+        })
+        .build();
+    agent
+        .run(
+            "test_console.sl",
+            r#"
+            import { log, warn, group, groupEnd, table } from standard:console;
+            log('%s is %d%%', 'x', 41.9, [1], 'left');
+            group('outer');
+            warn('careful %o', 'a');
+            groupEnd();
+            log('%f %i', 'no');
+            table([{ a: 1, b: 'x' }, 2]);
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        *captured.borrow(),
+        vec![
+            (Stream::Stdout, "x is 41% [\n  0: 1,\n] left\n".to_string()),
+            (Stream::Stdout, "outer\n".to_string()),
+            (Stream::Stderr, "  careful 'a'\n".to_string()),
+            (Stream::Stdout, "NAN %i\n".to_string()),
+            (
+                Stream::Stdout,
+                [
+                    "┌─────────┬───┬─────┬────────┐",
+                    "│ (index) │ a │ b   │ Values │",
+                    "├─────────┼───┼─────┼────────┤",
+                    "│ 0       │ 1 │ 'x' │        │",
+                    "│ 1       │   │     │ 2      │",
+                    "└─────────┴───┴─────┴────────┘\n",
+                ]
+                .join("\n")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_output_sink() {
+    let captured = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let sink = captured.clone();
+    let mut agent = AgentBuilder::new()
+        .output(move |stream: Stream, text: &str| {
+            sink.borrow_mut().push((stream, text.to_string()));
+        })
+        .build();
+    agent
+        .run(
+            "test_output_sink.sl",
+            "import { print } from standard:debug;\nprint('a', 1);\nprint(true);",
+        )
+        .unwrap();
+    assert_eq!(
+        *captured.borrow(),
+        vec![
+            (Stream::Stdout, "'a' 1\n".to_string()),
+            (Stream::Stdout, "true\n".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_uncaught_exception() {
+    let captured = std::rc::Rc::new(RefCell::new(String::new()));
+    let sink = captured.clone();
+    let mut agent = AgentBuilder::new()
+        .output(move |_: Stream, text: &str| {
+            sink.borrow_mut().push_str(text);
+        })
+        .build();
+    let source = r#"
+        import { onUncaughtException } from standard:process;
+        import { createTimeout } from standard:timers;
+        let caught = null;
+        createTimeout(() => {
+          throw 'printed';
+        }, 0);
+        createTimeout(() => {
+          onUncaughtException((e) => {
+            caught = e;
+          });
+          createTimeout(() => {
+            throw 'handled';
+          }, 0);
+        }, 0);
+        "#;
+    agent.evaluate::<Value>(source).unwrap();
+    agent.run_jobs();
+    assert_eq!(*captured.borrow(), "Uncaught Exception: 'printed'\n");
+    assert_eq!(agent.exit_code(), 1);
+    assert_eq!(
+        agent.evaluate::<String>("caught;"),
+        Ok("handled".to_string())
+    );
+
+    // what the handler throws goes to the embedder
+    let mut agent = Agent::new();
+    let reported = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let sink = reported.clone();
+    agent.set_uncaught_exception_handler(move |_: &Agent, e: Value| {
+        sink.borrow_mut().push(e);
+    });
+    let source = r#"
+        import { onUncaughtException } from standard:process;
+        import { createTimeout } from standard:timers;
+        onUncaughtException((e) => {
+          throw `${e} again`;
+        });
+        createTimeout(() => {
+          throw 'thrown';
+        }, 0);
+        "#;
+    agent.evaluate::<Value>(source).unwrap();
+    agent.run_jobs();
+    assert_eq!(*reported.borrow(), vec![Value::from("thrown again")]);
+    assert_eq!(agent.exit_code(), 0);
+}
+
+#[test]
+fn test_event_loop_turns() {
+    let mut agent = Agent::new();
+    let start = std::time::Instant::now();
+    agent
+        .run(
+            "test_event_loop_turns.sl",
+            r#"
+            import { createTimeout } from standard:timers;
+            createTimeout(() => {}, 1000);
+            "#,
+        )
+        .unwrap();
+
+    let waker = agent.waker();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        waker.wake();
+    });
+    // woken long before the timer or the timeout
+    assert!(agent.poll(Some(Duration::from_secs(5))));
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert!(agent.turn());
+    while agent.poll(None) {}
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[test]
+fn test_cancel_timeout() {
+    let mut agent = Agent::new();
+    let start = std::time::Instant::now();
+    let result: Vec<bool> = agent
+        .evaluate(
+            r#"
+            import { createTimeout } from standard:timers;
+            const timeout = createTimeout(() => {}, 60000);
+            [timeout.cancel(), timeout.cancel()];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(result, vec![true, false]);
+    assert!(!agent.turn());
+    agent.run_jobs();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_job_priorities() {
+    fn call(agent: &Agent, args: Vec<Value>) -> Result<(), Value> {
+        args[0].call(agent, Value::Null, Vec::new())?;
+        Ok(())
+    }
+    let mut agent = Agent::new();
+    agent.evaluate::<Value>("let log = '';").unwrap();
+    let mut append = |name: &str| {
+        agent
+            .evaluate::<Value>(&format!("() => {{ log += '{} '; }};", name))
+            .unwrap()
+    };
+    let (idle, timer, reaction) = (append("idle"), append("timer"), append("reaction"));
+    agent.enqueue_job_with(JobPriority::Idle, call, vec![idle]);
+    agent.enqueue_job_with(JobPriority::Macrotask, call, vec![timer]);
+    agent.enqueue_job(call, vec![reaction]);
+    agent.run_jobs();
+    assert_eq!(
+        agent.evaluate::<String>("log;"),
+        Ok("reaction timer idle ".to_string())
+    );
+
+    // without a budget the promise chain would never let the timer run
+    let mut agent = AgentBuilder::new().job_budget(10).build();
+    let source = r#"
+        import { createTimeout } from standard:timers;
+        let spins = 0;
+        let stopped = false;
+        const spin = () => {
+          spins += 1;
+          if (!stopped) {
+            Promise.resolve().then(spin);
+          }
+        };
+        spin();
+        createTimeout(() => { stopped = true; }, 1);
+        "#;
+    agent.evaluate::<Value>(source).unwrap();
+    assert!(agent.turn());
+    agent.run_jobs();
+    assert_eq!(agent.evaluate::<bool>("stopped && spins > 10;"), Ok(true));
+
+    // a budget of zero still runs a job a turn
+    let mut agent = AgentBuilder::new().job_budget(0).build();
+    agent
+        .evaluate::<Value>(
+            "let n = 0; Promise.resolve().then(() => { n += 1; }).then(() => { n += 1; });",
+        )
+        .unwrap();
+    agent.run_jobs();
+    assert_eq!(agent.evaluate::<f64>("n;"), Ok(2.0));
+}
+
+#[test]
+fn test_time() {
+    let mut agent = Agent::new();
+    let start = std::time::Instant::now();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { sleep, instant, duration } from standard:time;
+            import { inspect } from standard:debug;
+            const d = duration({ hours: 1, minutes: 2, seconds: 3.5 });
+            const began = instant();
+            let slept = false;
+            sleep(duration(20)).then(() => { slept = began.elapsed().toMilliseconds() >= 20; });
+            let invalid = false;
+            try {
+              sleep(-1);
+            } catch {
+              invalid = true;
+            }
+            [
+              d.toString(),
+              d.add(duration(500)).sub(duration({ hours: 1 })).toMilliseconds(),
+              duration(250).mul(2).div(4).toString(),
+              duration(1).compare(d),
+              inspect(duration({ days: 1, seconds: 1 })),
+              instant().since(began).toMilliseconds() >= 0,
+              invalid,
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("1h 2m 3.5s"),
+            Value::from(124_000.0),
+            Value::from("125ms"),
+            Value::from(-1.0),
+            Value::from("Duration(1d 1s)"),
+            Value::from(true),
+            Value::from(true),
+        ]
+    );
+    agent.run_jobs();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    let slept: Value = agent.evaluate("slept;").unwrap();
+    assert_eq!(slept, Value::from(true));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_tokio_runtime() {
+    let mut agent = Agent::new();
+    let start = std::time::Instant::now();
+    agent
+        .run(
+            "test_tokio_runtime.sl",
+            r#"
+            import { createTimeout } from standard:timers;
+            createTimeout(() => {}, 50);
+            "#,
+        )
+        .unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(agent.run_jobs_with_blocking_poll());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn test_executor() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting(Arc<AtomicUsize>);
+    impl Executor for Counting {
+        fn execute(&self, job: Box<dyn FnOnce() + Send>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(job);
+        }
+    }
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let mut agent = AgentBuilder::new()
+        .executor(Counting(count.clone()))
+        .build();
+    agent
+        .run(
+            "test_executor.sl",
+            r#"
+            import { readFile } from standard:fs;
+            readFile('./Cargo.toml');
+            readFile('./Cargo.toml');
+            "#,
+        )
+        .unwrap();
+    agent.run_jobs();
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_multiple_agents() {
+    let dir = std::env::temp_dir().join(format!(
+        "slither_test_multiple_agents_{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let threads = (0..4)
+        .map(|i| {
+            let file = dir.join(format!("{}.txt", i));
+            std::fs::write(&file, i.to_string()).unwrap();
+            std::thread::spawn(move || {
+                let mut agent = Agent::new();
+                let source = format!(
+                    r#"
+                    import {{ readFile }} from standard:fs;
+                    import {{ createTimeout }} from standard:timers;
+                    let fired = false;
+                    createTimeout(() => {{ fired = true; }}, 10);
+                    readFile('{}');
+                    "#,
+                    file.display()
+                );
+                let contents: String = agent.evaluate(&source).unwrap();
+                agent.run_jobs();
+                (contents, agent.evaluate::<bool>("fired;").unwrap())
+            })
+        })
+        .collect::<Vec<_>>();
+    for (i, thread) in threads.into_iter().enumerate() {
+        assert_eq!(thread.join().unwrap(), (i.to_string(), true));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_shared_buffer() {
+    let memory = crate::SharedMemory::new(4);
+    let shared = memory.clone();
+    let waiter = std::thread::spawn(move || {
+        let mut agent = Agent::new();
+        let buffer = Value::new_shared_buffer(&agent, shared);
+        let f: Value = agent
+            .evaluate(
+                "import { wait } from standard:atomics;\n(buffer) => wait(buffer, 0, 0, 5000);",
+            )
+            .unwrap();
+        agent.call_function::<_, String>(f, vec![buffer]).unwrap()
+    });
+
+    let mut agent = Agent::new();
+    let buffer = Value::new_shared_buffer(&agent, memory.clone());
+    let setup: Value = agent
+        .evaluate(
+            r#"
+            import { store, add, compareExchange, length } from standard:atomics;
+            (buffer) => {
+              store(buffer, 1, 250);
+              add(buffer, 1, 10);
+              return compareExchange(buffer, 2, 0, 7) == 0
+                && compareExchange(buffer, 2, 0, 9) == 7
+                && length(buffer) == 4;
+            };
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        agent.call_function::<_, bool>(setup, vec![buffer.clone()]),
+        Ok(true)
+    );
+    let notify: Value = agent
+        .evaluate("import { notify } from standard:atomics;\nnotify;")
+        .unwrap();
+    // keep notifying until the other agent was actually waiting
+    while agent
+        .call_function::<_, f64>(notify.clone(), vec![buffer.clone(), Value::from(0.0)])
+        .unwrap()
+        == 0.0
+    {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(waiter.join().unwrap(), "ok");
+    assert_eq!(memory.load(1), 4);
+}
+
+#[test]
+fn test_terminate_handle() {
+    let mut agent = Agent::new();
+    let handle = agent.terminate_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        handle.terminate();
+    });
+    let result = agent.run(
+        "test_terminate_handle.sl",
+        r#"
+        let caught = false;
+        try {
+          while true {}
+        } catch {
+          caught = true;
+        }
+        caught;
+        "#,
+    );
+    assert!(match result {
+        Err(e) => match e.get(&agent, ObjectKey::from("message")) {
+            Ok(Value::String(s)) => &*s == "script terminated",
+            _ => false,
+        },
+        Ok(_) => false,
+    });
+    // the agent stays terminated until it is reset
+    assert!(agent.run("test_terminate_handle.sl", "1 + 1;").is_err());
+    agent.reset_terminated();
+    assert_eq!(
+        agent.run("test_terminate_handle.sl", "1 + 1;"),
+        Ok(Value::from(2.0))
+    );
+}
+
+#[test]
+fn test_terminate_with_timer_pending() {
+    let mut agent = Agent::new();
+    let handle = agent.terminate_handle();
+    agent
+        .evaluate::<Value>(
+            r#"
+            import { createTimeout } from standard:timers;
+            let ran = 0;
+            createTimeout(() => {
+              while true {}
+            }, 1);
+            createTimeout(() => {
+              ran += 1;
+            }, 20);
+            "#,
+        )
+        .unwrap();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        handle.terminate();
+    });
+    // the looping timer is stopped and the other one doesn't run after it
+    agent.run_jobs();
+    assert!(agent.terminated());
+    assert!(agent.evaluate::<f64>("ran;").is_err());
+    agent.reset_terminated();
+    assert_eq!(agent.evaluate::<f64>("ran;"), Ok(0.0));
+    agent.run_jobs();
+    assert_eq!(agent.evaluate::<f64>("ran;"), Ok(1.0));
+}
+
+#[test]
+fn test_execution_limits() {
+    let message = |agent: &Agent, result: Result<Value, Value>| match result {
+        Err(e) => match e.get(agent, ObjectKey::from("message")) {
+            Ok(Value::String(s)) => s.to_string(),
+            _ => String::new(),
+        },
+        Ok(_) => String::new(),
+    };
+    let source = "try {\n  while true {}\n} catch {}";
+
+    let mut agent = AgentBuilder::new()
+        .time_limit(Duration::from_millis(50))
+        .build();
+    let start = Instant::now();
+    let result = agent.run("test_execution_limits.sl", source);
+    assert_eq!(message(&agent, result), "time limit exceeded");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // the budget is per evaluation
+    assert_eq!(
+        agent.run("test_execution_limits.sl", "1 + 1;"),
+        Ok(Value::from(2.0))
+    );
+
+    let mut agent = AgentBuilder::new().instruction_limit(1000).build();
+    let result = agent.run("test_execution_limits.sl", source);
+    assert_eq!(message(&agent, result), "instruction limit exceeded");
+    assert_eq!(
+        agent.run("test_execution_limits.sl", "1 + 1;"),
+        Ok(Value::from(2.0))
+    );
+}
+
+#[test]
+fn test_limits_cannot_be_caught() {
+    let mut agent = AgentBuilder::new()
+        .time_limit(Duration::from_millis(20))
+        .build();
+    agent.evaluate::<Value>("let caught = 0;").unwrap();
+    // a callback or generator runs in an interpreter of its own, neither its
+    // trys nor the caller's can catch the limit
+    let sources = [
+        "try { [2, 1].sort(() => { try { while true {} } catch {} return 0; }); } catch { caught += 1; }",
+        "gen function g() { try { while true {} } catch {} yield 1; }
+        try { g().next(); } catch { caught += 1; }",
+    ];
+    for source in &sources {
+        let message = match agent.evaluate::<Value>(source) {
+            Err(e) => e.get(&agent, ObjectKey::from("message")),
+            Ok(_) => Ok(Value::Null),
+        };
+        assert_eq!(message, Ok(Value::from("time limit exceeded")));
+    }
+    assert_eq!(agent.evaluate::<f64>("caught;"), Ok(0.0));
+}
+
+#[test]
+fn test_heap_limit() {
+    let mut agent = AgentBuilder::new().heap_limit(256 << 10).build();
+    // garbage alone doesn't hit the limit
+    assert_eq!(
+        agent.run(
+            "test_heap_limit.sl",
+            r#"
+            let i = 0;
+            while i < 20000 {
+              const o = { i };
+              i += 1;
+            }
+            i;
+            "#,
+        ),
+        Ok(Value::from(20000.0))
+    );
+    let result = agent.run(
+        "test_heap_limit.sl",
+        r#"
+        const kept = {};
+        let i = 0;
+        while true {
+          kept[i] = { i };
+          i += 1;
+        }
+        "#,
+    );
+    assert!(match result {
+        Err(e) => match e.get(&agent, ObjectKey::from("message")) {
+            Ok(Value::String(s)) => &*s == "out of memory",
+            _ => false,
+        },
+        Ok(_) => false,
+    });
+}
+
+#[test]
+fn test_heap_limit_strings() {
+    let mut agent = AgentBuilder::new().heap_limit(1 << 20).build();
+    let out_of_memory = |result: Result<Value, Value>, agent: &Agent| match result {
+        Err(e) => match e.get(agent, ObjectKey::from("message")) {
+            Ok(Value::String(s)) => &*s == "out of memory",
+            _ => false,
+        },
+        Ok(_) => false,
+    };
+    // strings that are dropped again don't add up
+    assert_eq!(
+        agent.run(
+            "test_heap_limit_strings.sl",
+            r#"
+            const chunk = 'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx';
+            let i = 0;
+            while i < 20000 {
+              const s = chunk + chunk;
+              i += 1;
+            }
+            i;
+            "#,
+        ),
+        Ok(Value::from(20000.0))
+    );
+    let result = agent.run(
+        "test_heap_limit_strings.sl",
+        r#"
+        const chunk = 'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx';
+        let s = '';
+        while true {
+          s += chunk;
+        }
+        "#,
+    );
+    assert!(out_of_memory(result, &agent));
+    let result = agent.run(
+        "test_heap_limit_strings.sl",
+        r#"
+        import { StringBuilder } from standard:strings;
+        const b = StringBuilder();
+        const chunk = 'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx';
+        while true {
+          b.append(chunk);
+        }
+        "#,
+    );
+    assert!(out_of_memory(result, &agent));
+}
+
+#[test]
+fn test_native_recursion_limit() {
+    let mut agent = Agent::new();
+    assert_eq!(
+        agent.run(
+            "test_native_recursion_limit.sl",
+            r#"
+            function f() {
+              [1].forEach(() => { f(); });
+            }
+            let message = null;
+            try {
+              f();
+            } catch e {
+              message = e.message;
+            }
+            message;
+            "#,
+        ),
+        Ok(Value::from("maximum call stack size exceeded"))
+    );
+    assert_eq!(
+        agent.run(
+            "test_native_recursion_limit.sl",
+            r#"
+            function g(n) {
+              return g(n + 1) + 1;
+            }
+            let message = null;
+            try {
+              g(0);
+            } catch e {
+              message = e.message;
+            }
+            message;
+            "#,
+        ),
+        Ok(Value::from("maximum call stack size exceeded"))
+    );
+}
+
+#[test]
+fn test_gc_config() {
+    let agent = AgentBuilder::new()
+        .gc(GcConfig {
+            threshold: 1 << 30,
+            used_space_ratio: 0.7,
+        })
+        .build();
+    let before = agent.gc_stats();
+    for _ in 0..1000 {
+        Value::new_object(agent.intrinsics.object_prototype.clone());
+    }
+    // nothing collected below the threshold
+    assert_eq!(agent.gc_stats().collections, before.collections);
+    assert!(agent.gc_stats().bytes_allocated > before.bytes_allocated);
+    gc::force_collect();
+    let after = agent.gc_stats();
+    assert_eq!(after.collections, before.collections + 1);
+    assert!(after.bytes_allocated < before.bytes_allocated + 1000);
+    assert!(after.longest_pause >= after.last_pause);
+
+    let ratio = |used_space_ratio| {
+        let builder = AgentBuilder::new().gc(GcConfig {
+            threshold: 1 << 20,
+            used_space_ratio,
+        });
+        builder.gc.unwrap().used_space_ratio
+    };
+    assert_eq!(ratio(0.5), 0.5);
+    assert_eq!(ratio(0.0), 0.01);
+    assert_eq!(ratio(-1.0), 0.01);
+    assert_eq!(ratio(2.0), 1.0);
+    assert_eq!(ratio(std::f64::NAN), GcConfig::default().used_space_ratio);
+}
+
+#[test]
+fn test_memory_usage() {
+    let mut agent = Agent::new();
+    let usage = |agent: &mut Agent, source: &str| -> HashMap<String, Value> {
+        agent.evaluate(source).unwrap()
+    };
+    let before = usage(
+        &mut agent,
+        "import { memoryUsage } from standard:debug;\nmemoryUsage();",
+    );
+    let after = usage(
+        &mut agent,
+        r#"
+        const kept = [];
+        kept[0] = { name: 'a long enough string' };
+        kept[1] = [1, 2, 3];
+        memoryUsage();
+        "#,
+    );
+    let field = |usage: &HashMap<String, Value>, kind: &str, field: &str| -> f64 {
+        match usage[kind].get(&agent, ObjectKey::from(field)) {
+            Ok(Value::Number(n)) => n,
+            _ => panic!("missing {}.{}", kind, field),
+        }
+    };
+    assert!(field(&after, "objects", "count") > field(&before, "objects", "count"));
+    assert!(field(&after, "arrays", "count") >= field(&before, "arrays", "count") + 2.0);
+    assert!(field(&after, "strings", "bytes") >= field(&before, "strings", "bytes") + 20.0);
+    assert!(field(&after, "gc", "collections") >= 0.0);
+    assert!(after["heapBytes"] != Value::Null && after["liveObjects"] != Value::Null);
+}
+
+#[test]
+fn test_gc_hook() {
+    let phases = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let mut agent = Agent::new();
+    {
+        let phases = phases.clone();
+        agent.set_gc_hook(move |phase, stats| phases.borrow_mut().push((phase, stats.collections)));
+    }
+    let _: Value = agent
+        .evaluate("import { gc } from standard:debug;\ngc();")
+        .unwrap();
+    let phases = phases.borrow();
+    let n = phases.len();
+    assert!(n >= 2);
+    assert_eq!(phases[n - 2].0, GcPhase::Before);
+    assert_eq!(phases[n - 1].0, GcPhase::After);
+    assert_eq!(phases[n - 1].1, phases[n - 2].1 + 1);
+}
+
+#[test]
+fn test_heap_snapshot() {
+    let path = std::env::temp_dir().join(format!("slither-heap-{}.json", std::process::id()));
+    let mut agent = Agent::new();
+    let source = format!(
+        r#"
+        import {{ writeHeapSnapshot }} from standard:debug;
+        const holder = {{ payload: [] }};
+        let i = 0;
+        while i < 1000 {{
+          holder.payload[i] = {{ i }};
+          i += 1;
+        }}
+        writeHeapSnapshot({:?});
+        "#,
+        path.to_str().unwrap()
+    );
+    assert_eq!(agent.run("test_heap_snapshot.sl", &source), Ok(Value::Null));
+    let snapshot = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let edge = regex::Regex::new(r#"\{"from":\d+,"to":(\d+),"name":"payload"\}"#).unwrap();
+    let payload = &edge.captures(&snapshot).unwrap()[1];
+    let node = regex::Regex::new(&format!(
+        r#"\{{"id":{},"kind":"array","size":(\d+),"retainedSize":(\d+)"#,
+        payload
+    ))
+    .unwrap();
+    let captures = node.captures(&snapshot).unwrap();
+    let retained = captures[2].parse::<usize>().unwrap();
+    assert!(retained > captures[1].parse::<usize>().unwrap());
+    assert!(retained > 1000 * std::mem::size_of::<crate::value::ObjectInfo>());
+}
+
+#[test]
+fn test_instrument() {
+    use std::rc::Rc;
+
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+        ops: Rc<Cell<usize>>,
+    }
+
+    impl Recorder {
+        fn record(&self, agent: &Agent, event: &str, function: Option<&Value>) {
+            let name = match function {
+                Some(f) => match f.get(agent, ObjectKey::from("name")) {
+                    Ok(Value::String(s)) => s.to_string(),
+                    _ => "<anonymous>".to_string(),
+                },
+                None => "<module>".to_string(),
+            };
+            self.events.borrow_mut().push(format!("{} {}", event, name));
+        }
+    }
+
+    impl Instrument for Recorder {
+        fn enter(&self, agent: &Agent, function: Option<&Value>) {
+            self.record(agent, "enter", function);
+        }
+
+        fn exit(&self, agent: &Agent, function: Option<&Value>) {
+            self.record(agent, "exit", function);
+        }
+
+        fn ops(&self) -> bool {
+            true
+        }
+
+        fn op(&self, agent: &Agent, position: usize, name: &'static str) {
+            assert!(!name.is_empty());
+            assert!(agent
+                .source_location(position)
+                .starts_with("test_instrument.sl"));
+            self.ops.set(self.ops.get() + 1);
+        }
+    }
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let ops = Rc::new(Cell::new(0));
+    let mut agent = Agent::new();
+    agent.set_instrument(Recorder {
+        events: events.clone(),
+        ops: ops.clone(),
+    });
+    assert_eq!(
+        agent.run(
+            "test_instrument.sl",
+            r#"
+            function f() { return 1; }
+            f() + f();
+            "#,
+        ),
+        Ok(Value::from(2.0))
+    );
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "enter <module>",
+            "enter f",
+            "exit f",
+            "enter f",
+            "exit f",
+            "exit <module>",
+        ]
+    );
+    assert!(ops.get() > 0);
+}
+
+#[test]
+fn test_debugger() {
+    use crate::debugger::PauseReason;
+    use std::rc::Rc;
+
+    let stops = Rc::new(RefCell::new(Vec::new()));
+    let mut agent = Agent::new();
+    {
+        let stops = stops.clone();
+        let actions = vec![Resume::StepOver, Resume::StepOut, Resume::StepIn];
+        let actions = RefCell::new(actions.into_iter());
+        agent.set_debugger(move |_, paused| {
+            let sum = paused
+                .scopes()
+                .iter()
+                .flatten()
+                .find(|(name, _)| name == "sum")
+                .map(|(_, value)| value.clone());
+            stops
+                .borrow_mut()
+                .push((paused.reason, paused.file.clone(), paused.line, sum));
+            actions.borrow_mut().next().unwrap_or(Resume::Continue)
+        });
+    }
+    agent.set_breakpoint("test_debugger.sl", 3);
+    assert_eq!(
+        agent.run(
+            "test_debugger.sl",
+            r#"
+function add(a, b) {
+  const sum = a + b;
+  return sum;
+}
+const x = add(1, 2);
+const y = add(x, 3);
+y;
+"#,
+        ),
+        Ok(Value::from(6.0))
+    );
+    let file = "test_debugger.sl".to_string();
+    assert_eq!(
+        *stops.borrow(),
+        vec![
+            (PauseReason::Breakpoint, file.clone(), 3, None),
+            (PauseReason::Step, file.clone(), 4, Some(Value::from(3.0))),
+            (PauseReason::Step, file.clone(), 7, None),
+            (PauseReason::Breakpoint, file.clone(), 3, None),
+        ]
+    );
+
+    agent.pause_handle().pause();
+    assert_eq!(agent.run("test_pause.sl", "1;"), Ok(Value::from(1.0)));
+    assert_eq!(
+        stops.borrow().last(),
+        Some(&(PauseReason::Pause, "test_pause.sl".to_string(), 1, None))
+    );
+}
+
+#[test]
+fn test_async_hooks() {
+    let mut agent = Agent::new();
+    let _: Value = agent
+        .evaluate(
+            r#"
+            import { createHook, executionAsyncId, triggerAsyncId } from standard:async_hooks;
+            import { createTimeout } from standard:timers;
+            let timer = 0;
+            let promises = 0;
+            let resolved = 0;
+            let before = 0;
+            let inside = 0;
+            let trigger = 0;
+            const hook = createHook({
+              init: (id, kind, triggerId) => {
+                if kind == 'Timer' {
+                  timer = id;
+                }
+                if kind == 'Promise' {
+                  promises += 1;
+                }
+              },
+              before: (id) => {
+                if id == timer {
+                  before += 1;
+                }
+              },
+              promiseResolve: (id) => {
+                resolved += 1;
+              },
+            });
+            hook.enable();
+            const top = executionAsyncId();
+            createTimeout(() => {
+              inside = executionAsyncId();
+              trigger = triggerAsyncId();
+            }, 0);
+            Promise.resolve(1).then(() => 2);
+            "#,
+        )
+        .unwrap();
+    agent.run_jobs();
+    let values: Vec<f64> = agent
+        .evaluate("hook.disable();\n[top, timer, inside, trigger, before, promises, resolved];")
+        .unwrap();
+    let (top, timer, inside, trigger, before, promises, resolved) = (
+        values[0], values[1], values[2], values[3], values[4], values[5], values[6],
+    );
+    assert_eq!(top, 1.0);
+    assert!(timer > top);
+    assert_eq!(inside, timer);
+    assert_eq!(trigger, top);
+    assert_eq!(before, 1.0);
+    assert!(promises >= 2.0);
+    assert!(resolved >= 2.0);
+}
+
+#[test]
+fn test_pending_ops() {
+    let mut agent = Agent::new();
+    let ops: Value = agent
+        .evaluate(
+            r#"
+            import { pendingOps } from standard:debug;
+            import { createTimeout } from standard:timers;
+            function schedule() {
+              createTimeout(() => {}, 0);
+            }
+            schedule();
+            pendingOps();
+            "#,
+        )
+        .unwrap();
+    let op = ops.get(&agent, ObjectKey::from(0)).unwrap();
+    let field = |name: &str| op.get(&agent, ObjectKey::from(name)).unwrap();
+    assert_eq!(field("type"), Value::from("Timer"));
+    assert!(match field("stack") {
+        Value::String(s) => s.contains("at schedule"),
+        _ => false,
+    });
+    assert!(match field("age") {
+        Value::Number(n) => n >= 0.0,
+        _ => false,
+    });
+    agent.run_jobs();
+    let ops: Value = agent.evaluate("pendingOps();").unwrap();
+    assert_eq!(ops.get(&agent, ObjectKey::from(0)), Ok(Value::Null));
+}
+
+#[test]
+fn test_promise_map() {
+    let mut agent = Agent::new();
+    let source = r#"
+        import { sleep } from standard:time;
+        let running = 0;
+        let most = 0;
+        const work = async (ms, i) => {
+          running += 1;
+          most = running > most ? running : most;
+          await sleep(ms);
+          running -= 1;
+          return ms * 10 + i;
+        };
+        let mapped = null;
+        Promise.map([30, 10, 20, 5], work, { concurrency: 2 }).then((r) => {
+          mapped = r;
+        });
+        let failed = null;
+        const fail = async (x) => {
+          if (x == 2) {
+            throw 'no 2';
+          }
+          return x;
+        };
+        gen function numbers() {
+          yield 1;
+          yield 2;
+          yield 3;
+        }
+        Promise.map(numbers(), fail).catch((e) => {
+          failed = e;
+        });
+        let empty = null;
+        Promise.map([], fail).then((r) => {
+          empty = r[0] == null;
+        });
+        "#;
+    agent.evaluate::<Value>(source).unwrap();
+    agent.run_jobs();
+    let mapped: Vec<f64> = agent.evaluate("mapped;").unwrap();
+    assert_eq!(mapped, vec![300.0, 101.0, 202.0, 53.0]);
+    assert_eq!(agent.evaluate::<f64>("most;"), Ok(2.0));
+    assert_eq!(agent.evaluate::<String>("failed;"), Ok("no 2".to_string()));
+    assert_eq!(agent.evaluate::<bool>("empty;"), Ok(true));
+    assert!(agent
+        .evaluate::<Value>("Promise.map([1], (x) => x, { concurrency: 0 });")
+        .is_err());
+}
+
+#[test]
+fn test_tasks() {
+    let mut agent = Agent::new();
+    let source = r#"
+        import { withScope } from standard:tasks;
+        import { sleep } from standard:time;
+        let log = '';
+        let result = null;
+        withScope(async (scope) => {
+          scope.spawn(async () => {
+            await sleep(10);
+            log += 'a';
+          });
+          scope.spawn(async () => {
+            await sleep(5);
+            log += 'b';
+          });
+          return 'body';
+        }).then((r) => {
+          result = r;
+        });
+
+        let failure = null;
+        let stopped = false;
+        withScope(async (scope) => {
+          scope.spawn(async (signal) => {
+            signal.onAbort(() => {
+              stopped = true;
+            });
+            await sleep(10);
+            signal.throwIfAborted();
+          });
+          scope.spawn(async () => {
+            throw 'boom';
+          });
+        }).catch((e) => {
+          failure = e;
+        });
+
+        let reason = null;
+        let kept = null;
+        withScope(async (scope) => {
+          kept = scope;
+          scope.spawn(async (signal) => {
+            await sleep(5);
+            signal.throwIfAborted();
+          });
+          scope.cancel('stop');
+        }).catch((e) => {
+          reason = e;
+        });
+        "#;
+    agent.evaluate::<Value>(source).unwrap();
+    agent.run_jobs();
+    assert_eq!(agent.evaluate::<String>("result;"), Ok("body".to_string()));
+    assert_eq!(agent.evaluate::<String>("log;"), Ok("ba".to_string()));
+    assert_eq!(agent.evaluate::<String>("failure;"), Ok("boom".to_string()));
+    assert_eq!(agent.evaluate::<bool>("stopped;"), Ok(true));
+    assert_eq!(agent.evaluate::<String>("reason;"), Ok("stop".to_string()));
+    assert!(agent.evaluate::<Value>("kept.spawn(() => null);").is_err());
+}
+
+#[test]
+fn test_pending_promises() {
+    let mut agent = Agent::new();
+    agent
+        .run(
+            "test_pending_promises.sl",
+            r#"
+const never = new Promise(() => {});
+async function wait() {
+  await never;
+}
+wait();
+Promise.resolve(1);
+"#,
+        )
+        .unwrap();
+    agent.run_jobs();
+    let pending = agent.pending_promises();
+    assert!(pending
+        .iter()
+        .any(|l| l.starts_with("test_pending_promises.sl:2:")));
+    assert!(pending
+        .iter()
+        .any(|l| l.starts_with("test_pending_promises.sl:6:")));
+    assert!(!pending
+        .iter()
+        .any(|l| l.starts_with("test_pending_promises.sl:7:")));
+}
+
+#[test]
+fn test_record_replay() {
+    let dir =
+        std::env::temp_dir().join(format!("slither_test_record_replay_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("data.txt");
+    let log = dir.join("events.log");
+    std::fs::write(&file, "data").unwrap();
+    let source = format!(
+        r#"
+        import {{ readFile, exists }} from standard:fs;
+        import {{ createTimeout }} from standard:timers;
+        let order = '';
+        createTimeout(() => {{ order += 'a'; }}, 20);
+        createTimeout(() => {{ order += 'b'; }}, 1);
+        readFile('{0}').then((s) => {{ order += s; }});
+        exists('{0}').then((e) => {{ order += e ? 'y' : 'n'; }});
+        "#,
+        file.display()
+    );
+
+    let mut agent = AgentBuilder::new()
+        .record(std::fs::File::create(&log).unwrap())
+        .build();
+    let _: Value = agent.evaluate(&source).unwrap();
+    agent.run_jobs();
+    let recorded: String = agent.evaluate("order;").unwrap();
+    assert_eq!(recorded.len(), 7);
+
+    // the replay never touches the file
+    std::fs::remove_file(&file).unwrap();
+    let events = EventLog::read(std::fs::File::open(&log).unwrap()).unwrap();
+    assert_eq!(events.len(), 4);
+    let mut agent = AgentBuilder::new().replay(events).build();
+    let _: Value = agent.evaluate(&source).unwrap();
+    agent.run_jobs();
+    let replayed: String = agent.evaluate("order;").unwrap();
+    assert_eq!(replayed, recorded);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_resolver() {
+    let dir = std::env::temp_dir().join(format!("slither_test_resolver_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let hosts = dir.join("hosts");
+    std::fs::write(
+        &hosts,
+        "# comment\n10.0.0.1 example.test other\n::1 example.test\n",
+    )
+    .unwrap();
+
+    // a nameserver that knows one ipv4 address for anything
+    let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let nameserver = std::thread::spawn(move || {
+        for _ in 0..2 {
+            let mut buf = [0; 512];
+            let (size, from) = server.recv_from(&mut buf).unwrap();
+            let question = &buf[12..size];
+            let is_a = question[question.len() - 3] == 1;
+            let mut answer = vec![buf[0], buf[1], 0x81, 0x80, 0, 1, 0, is_a as u8, 0, 0, 0, 0];
+            answer.extend(question);
+            if is_a {
+                answer.extend(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4, 10, 0, 0, 7]);
+            }
+            server.send_to(&answer, from).unwrap();
+        }
+    });
+
+    let mut agent = Agent::new();
+    let source = format!(
+        r#"
+        import {{ createResolver }} from standard:net;
+        const fromHosts = createResolver({{ hosts: '{}' }});
+        const fromServer = createResolver({{ hosts: false, nameservers: ['{}'] }});
+        let a = null;
+        let b = null;
+        let c = null;
+        fromHosts.resolve('Example.Test').then((v) => {{ a = v; }});
+        fromServer.resolve('example.com').then((v) => {{ b = v; }});
+        fromServer.resolve('127.0.0.2').then((v) => {{ c = v; }});
+        "#,
+        hosts.display(),
+        address
+    );
+    let _: Value = agent.evaluate(&source).unwrap();
+    agent.run_jobs();
+    nameserver.join().unwrap();
+    let result: Vec<Value> = agent
+        .evaluate("[a[0], a[1], a[2], b[0], b[1], c[0]];")
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from("10.0.0.1"),
+            Value::from("::1"),
+            Value::Null,
+            Value::from("10.0.0.7"),
+            Value::Null,
+            Value::from("127.0.0.2"),
+        ]
+    );
+
+    // cached until cleared
+    std::fs::write(&hosts, "10.0.0.2 example.test\n").unwrap();
+    let _: Value = agent
+        .evaluate(
+            r#"
+            fromHosts.resolve('example.test').then((v) => { a = v; });
+            fromServer.resolve('example.com').then((v) => { b = v; });
+            "#,
+        )
+        .unwrap();
+    agent.run_jobs();
+    let cached: Vec<Value> = agent.evaluate("[a[0], b[0]];").unwrap();
+    assert_eq!(
+        cached,
+        vec![Value::from("10.0.0.1"), Value::from("10.0.0.7")]
+    );
+    let _: Value = agent
+        .evaluate("fromHosts.clear(); fromHosts.resolve('example.test').then((v) => { a = v; });")
+        .unwrap();
+    agent.run_jobs();
+    let cleared: Value = agent.evaluate("a[0];").unwrap();
+    assert_eq!(cleared, Value::from("10.0.0.2"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_crash_report() {
+    crate::install_crash_handler();
+    fn report(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+        Ok(Value::from(crate::crash::report(
+            agent,
+            "boom",
+            "src/x.rs:1:1",
+        )))
+    }
+    let mut agent = AgentBuilder::new()
+        .global("report", |agent| Value::new_builtin_function(agent, report))
+        .build();
+    let report: String = agent
+        .evaluate(
+            r#"
+            import { createTimeout } from standard:timers;
+            createTimeout(() => {}, 1000);
+            function inner() {
+              return report();
+            }
+            inner();
+            "#,
+        )
+        .unwrap();
+    assert!(report.contains("message: boom\n"));
+    assert!(report.contains("panicked at: src/x.rs:1:1\n"));
+    assert!(report.contains("    at inner ("));
+    assert!(report.contains("last ops:\n    "));
+    assert!(report.contains("queued jobs: 0\n"));
+    assert!(report.contains("pending operations: 1\n        Timer #"));
+}
+
+#[test]
+fn test_bench() {
+    let mut agent = Agent::new();
+    let result: Value = agent
+        .evaluate(
+            r#"
+            import { bench } from standard:bench;
+            let calls = 0;
+            bench('count', () => { calls += 1; }, { warmup: 5, time: 20 });
+            "#,
+        )
+        .unwrap();
+    let calls: f64 = agent.evaluate("calls;").unwrap();
+    {
+        let field = |name: &str| match result.get(&agent, ObjectKey::from(name)).unwrap() {
+            Value::Number(n) => n,
+            _ => panic!("{} is not a number", name),
+        };
+        assert!(field("iterations") >= 10.0);
+        assert!(calls > field("iterations"));
+        assert!(field("min") <= field("p50"));
+        assert!(field("p50") <= field("p90"));
+        assert!(field("p90") <= field("p99"));
+        assert!(field("p99") <= field("max"));
+    }
+
+    agent.bench_filter = Some("other".to_string());
+    let skipped: Value = agent
+        .evaluate("bench('count', () => { calls += 1; });")
+        .unwrap();
+    assert_eq!(skipped, Value::Null);
+}
+
+#[test]
+fn test_compiler() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { parse, compile, run } from standard:compiler;
+            const ast = parse('let a = 1 + 2; a * 10;');
+            const f = compile(ast);
+            ast.body[0].init.operator = '-';
+            [
+              ast.body[0].init.left.value,
+              f(),
+              f(),
+              compile(ast)(),
+              run('import { max } from standard:math; max(4, 9);'),
+            ];
+            "#,
+        )
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Value::from(1.0),
+            Value::from(30.0),
+            Value::from(30.0),
+            Value::from(-10.0),
+            Value::from(9.0),
+        ]
+    );
+
+    let error = |agent: &mut Agent, source: &str| {
+        let e = agent.evaluate::<Value>(source).unwrap_err();
+        match e.get(agent, ObjectKey::from("message")).unwrap() {
+            Value::String(s) => s.to_string(),
+            _ => panic!("error has no message"),
+        }
+    };
+    assert!(error(&mut agent, "parse('let = ;');").contains("parse:1:5"));
+    assert_eq!(
+        error(
+            &mut agent,
+            "compile({ type: 'Program', bindings: [], body: [{ type: 'BreakStatement' }] });"
+        ),
+        "invalid syntax tree: `BreakStatement` is only allowed in loops"
+    );
+    assert_eq!(
+        error(
+            &mut agent,
+            "compile({ type: 'Program', bindings: [], body: 1 });"
+        ),
+        "invalid syntax tree: `Program.body` must be an array"
+    );
+}
+
+#[test]
+fn test_tokenize() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate(
+            r#"
+            import { tokenize } from standard:compiler;
+            const tokens = [];
+            let i = 0;
+            tokenize('let r = /a\\/b/; // note\nr / 2 + `x${ {a: 1}.a }y`;').forEach((t) => {
+              tokens[i] = `${t.kind} ${t.text} ${t.start.line}:${t.start.column}`;
+              i += 1;
+            });
+            tokens;
+            "#,
+        )
+        .unwrap();
+    let expected = [
+        "keyword let 1:1",
+        "identifier r 1:5",
+        "operator = 1:7",
+        "regex /a\\/b/ 1:9",
+        "punctuation ; 1:15",
+        "comment // note 1:17",
+        "identifier r 2:1",
+        "operator / 2:3",
+        "number 2 2:5",
+        "operator + 2:7",
+        "template `x${ 2:9",
+        "punctuation { 2:14",
+        "identifier a 2:15",
+        "punctuation : 2:16",
+        "number 1 2:18",
+        "punctuation } 2:19",
+        "punctuation . 2:20",
+        "identifier a 2:21",
+        "template }y` 2:23",
+        "punctuation ; 2:26",
+    ];
+    assert_eq!(
+        result,
+        expected.iter().map(|&t| Value::from(t)).collect::<Vec<_>>()
+    );
+
+    let e = agent
+        .evaluate::<Value>("tokenize('let s = \"abc');")
+        .unwrap_err();
+    assert_eq!(
+        e.get(&agent, ObjectKey::from("line")).unwrap(),
+        Value::from(1.0)
+    );
+}
+
+#[test]
+fn test_doc() {
+    let source = r#"
+/// Adds two numbers.
+///
+/// The second defaults to one.
+export function add(a, b = 1) {
+  /// not a doc of add
+  const c = a + b;
+  return c;
+}
+
+// a plain comment
+export const double = async (x) => x * 2;
+
+/// not on the line above
+
+function hidden() {}
+"#;
+    assert_eq!(
+        crate::doc("math.sl", source, true).unwrap(),
+        "# math\n\n## `add(a, b = 1)`\n\nAdds two numbers.\n\nThe second defaults to one.\n\n## `async double(x)`"
+    );
+    assert_eq!(
+        crate::doc("math.sl", source, false).unwrap(),
+        r#"{"module":"math","functions":[{"name":"add","kind":"normal","params":["a","b = 1"],"signature":"add(a, b = 1)","doc":"Adds two numbers.\n\nThe second defaults to one.","line":5},{"name":"double","kind":"async","params":["x"],"signature":"async double(x)","doc":null,"line":12}]}"#
+    );
+}
+
+#[test]
+fn test_shared_strings() {
+    let mut agent = Agent::new();
+    let result: Vec<Value> = agent
+        .evaluate("const s = 'shared'; const o = { s }; [s, 'shared', o.s];")
+        .unwrap();
+    match (&result[0], &result[1], &result[2]) {
+        (Value::String(a), Value::String(b), Value::String(c)) => {
+            assert!(std::rc::Rc::ptr_eq(a, b));
+            assert!(std::rc::Rc::ptr_eq(a, c));
+        }
+        _ => panic!("expected strings"),
+    }
+}
+
+#[test]
+fn test_value_size() {
+    // a string's fat pointer plus the tag
+    assert_eq!(
+        std::mem::size_of::<Value>(),
+        3 * std::mem::size_of::<usize>()
+    );
+}
+
+test!(
+    test_boxed_values,
+    r#"
+    const s = Symbol('s');
+    const t = (1, (x: 2, y: 3), s);
+    t == (1, (x: 2, y: 3), s) && #{ t }.t == t && #{ t }.t != (1, 2, s);
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_reused_contexts,
+    r#"
+    function make(n) {
+      return () => n;
+    }
+    const fs = [make(1), make(2), make(3)];
+    function sum(n) {
+      if n == 0 {
+        return 0;
+      }
+      return n + sum(n - 1);
+    }
+    let total = 0;
+    [1, 2, 3].forEach((x) => {
+      total += make(x)();
+    });
+    fs[0]() + fs[1]() + fs[2]() == 6 && sum(100) == 5050 && total == 6;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_tail_calls,
+    r#"
+    function even(n) {
+      return n == 0 ? true : odd(n - 1);
+    }
+    function odd(n) {
+      return n != 0 && even(n - 1);
+    }
+    function g() {
+      return 1;
+    }
+    function f() {
+      try {
+        return g();
+      } catch {
+        return 'wrong';
+      }
+    }
+    function h() {
+      f();
+      throw 'right';
+    }
+    let caught = null;
+    try {
+      h();
+    } catch e {
+      caught = e;
+    }
+    even(20000) && !odd(20000) && caught == 'right';
+    "#,
+    Ok(Value::from(true))
+);
+
+// a callback started by a builtin runs in its own interpreter, where there is
+// no caller's frame for a tail call to replace
+test!(
+    test_tail_call_from_builtin_callback,
+    r#"
+    function sub(x, y) {
+      return x - y;
+    }
+    const a = [3, 1, 2];
+    a.sort((x, y) => sub(x, y));
+    a[0] == 1 && a[1] == 2 && a[2] == 3;
+    "#,
+    Ok(Value::from(true))
+);
+
+test!(
+    test_tail_call_from_promise_reaction,
+    r#"
+    function inc(v) {
+      return v + 1;
+    }
+    Promise.resolve(1).then((v) => inc(v));
+    "#,
+    Ok(Value::from(2.0))
+);
+
+test!(
+    test_tail_call_from_exported_function,
+    r#"
+    function add(a, b) {
+      return a + b;
+    }
+    export function twice(n) {
+      return add(n, n);
+    }
+    Promise.resolve(4).then(twice);
+    "#,
+    Ok(Value::from(8.0))
+);
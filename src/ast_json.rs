@@ -0,0 +1,375 @@
+use crate::json::{array, number, object, optional, quote};
+use crate::parser::{FunctionKind, Node, Operator, Pattern, Scope};
+
+// bump when the shape of the output changes
+const VERSION: f64 = 1.0;
+
+pub fn to_json(ast: &Node) -> String {
+    match ast {
+        Node::Block(scope, stmts) => {
+            let mut body = statements(stmts);
+            // the parser turns the last expression statement into the completion
+            // value of the program, but it is still a statement in the source
+            if let Some(Node::ParenthesizedExpression(expr)) = last_statement(stmts) {
+                body.pop();
+                body.push(statement(
+                    position_of_last(stmts),
+                    vec![
+                        ("type", quote("ExpressionStatement")),
+                        ("expression", node(expr)),
+                    ],
+                ));
+            }
+            object(vec![
+                ("type", quote("Program")),
+                ("version", number(VERSION)),
+                ("bindings", bindings(scope)),
+                ("body", array(body)),
+            ])
+        }
+        _ => node(ast),
+    }
+}
+
+fn last_statement(stmts: &[Node]) -> Option<&Node> {
+    stmts.iter().rev().find(|s| !is_position(s))
+}
+
+fn position_of_last(stmts: &[Node]) -> Option<(usize, usize)> {
+    match stmts.len() {
+        n if n >= 2 => match &stmts[n - 2] {
+            Node::Position(line, column) => Some((*line, *column)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_position(node: &Node) -> bool {
+    match node {
+        Node::Position(..) => true,
+        _ => false,
+    }
+}
+
+// statements carry the location of the Position marker before them
+fn statement(position: Option<(usize, usize)>, mut fields: Vec<(&str, String)>) -> String {
+    if let Some((line, column)) = position {
+        fields.push(("line", line.to_string()));
+        fields.push(("column", column.to_string()));
+    }
+    object(fields)
+}
+
+fn statements(stmts: &[Node]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut position = None;
+    for stmt in stmts {
+        match stmt {
+            Node::Position(line, column) => position = Some((*line, *column)),
+            _ => out.push(statement(position.take(), node_fields(stmt))),
+        }
+    }
+    out
+}
+
+fn bindings(scope: &Scope) -> String {
+    array(scope.bindings.iter().map(|(name, mutable)| {
+        object(vec![
+            ("name", quote(name)),
+            ("mutable", mutable.to_string()),
+        ])
+    }))
+}
+
+fn nodes(nodes: &[Node]) -> String {
+    array(nodes.iter().map(node))
+}
+
+fn boxed(node: &Option<Box<Node>>) -> String {
+    optional(node.as_ref().map(|n| self::node(n)))
+}
+
+// arrow functions carry the arrow bit on top of their kind, which the node
+// type already says, so only the kind under it is written
+pub(crate) fn kind(kind: FunctionKind) -> String {
+    let kind = kind as u8;
+    quote(if kind & FunctionKind::Async as u8 != 0 {
+        "async"
+    } else if kind & FunctionKind::Generator as u8 != 0 {
+        "generator"
+    } else {
+        "normal"
+    })
+}
+
+fn operator(op: Operator) -> String {
+    quote(op.symbol())
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    let fields =
+        |fields: &[(String, Pattern)]| {
+            array(fields.iter().map(|(name, p)| {
+                object(vec![("name", quote(name)), ("pattern", self::pattern(p))])
+            }))
+        };
+    let patterns = |patterns: &[Pattern]| array(patterns.iter().map(self::pattern));
+    match pattern {
+        Pattern::Wildcard => object(vec![("type", quote("WildcardPattern"))]),
+        Pattern::Binding(name) => object(vec![
+            ("type", quote("BindingPattern")),
+            ("name", quote(name)),
+        ]),
+        Pattern::Value(value) => object(vec![
+            ("type", quote("ValuePattern")),
+            ("value", node(value)),
+        ]),
+        Pattern::Tuple(elements) => object(vec![
+            ("type", quote("TuplePattern")),
+            ("elements", patterns(elements)),
+        ]),
+        Pattern::NamedTuple(elements) => object(vec![
+            ("type", quote("NamedTuplePattern")),
+            ("fields", fields(elements)),
+        ]),
+        Pattern::Array(elements) => object(vec![
+            ("type", quote("ArrayPattern")),
+            ("elements", patterns(elements)),
+        ]),
+        Pattern::Object(properties) => object(vec![
+            ("type", quote("ObjectPattern")),
+            ("properties", fields(properties)),
+        ]),
+        Pattern::Or(alternatives) => object(vec![
+            ("type", quote("OrPattern")),
+            ("alternatives", patterns(alternatives)),
+        ]),
+    }
+}
+
+fn node(node: &Node) -> String {
+    object(node_fields(node))
+}
+
+// the type of the node and its fields, so statements can add their location
+fn node_fields(node: &Node) -> Vec<(&'static str, String)> {
+    macro_rules! fields {
+        ( $type:expr $( , $name:expr => $value:expr )* ) => {
+            vec![ ("type", quote($type)) $( , ($name, $value) )* ]
+        };
+    }
+
+    match node {
+        Node::Position(line, column) => fields!(
+            "Position",
+            "line" => line.to_string(),
+            "column" => column.to_string()
+        ),
+        Node::NullLiteral => fields!("NullLiteral"),
+        Node::TrueLiteral => fields!("BooleanLiteral", "value" => "true".to_string()),
+        Node::FalseLiteral => fields!("BooleanLiteral", "value" => "false".to_string()),
+        Node::NumberLiteral(n) => fields!("NumberLiteral", "value" => number(*n)),
+        Node::StringLiteral(s) => fields!("StringLiteral", "value" => quote(s)),
+        Node::SymbolLiteral(s) => fields!("SymbolLiteral", "name" => quote(s)),
+        Node::RegexLiteral(r) => fields!("RegexLiteral", "pattern" => quote(r)),
+        Node::ObjectLiteral(properties) => {
+            fields!("ObjectLiteral", "properties" => nodes(properties))
+        }
+        Node::ArrayLiteral(elements) => fields!("ArrayLiteral", "elements" => nodes(elements)),
+        Node::TupleLiteral(elements) => fields!("TupleLiteral", "elements" => nodes(elements)),
+        Node::NamedTupleLiteral(fields) => fields!(
+            "NamedTupleLiteral",
+            "fields" => array(fields.iter().map(|(name, value)| {
+                object(vec![("name", quote(name)), ("value", self::node(value))])
+            }))
+        ),
+        Node::RecordLiteral(properties) => {
+            fields!("RecordLiteral", "properties" => nodes(properties))
+        }
+        Node::TemplateLiteral(quasis, expressions) => fields!(
+            "TemplateLiteral",
+            "quasis" => array(quasis.iter().map(|q| quote(q))),
+            "expressions" => nodes(expressions)
+        ),
+        Node::Identifier(name) => fields!("Identifier", "name" => quote(name)),
+        Node::Block(scope, stmts) => fields!(
+            "Block",
+            "bindings" => bindings(scope),
+            "body" => array(statements(stmts))
+        ),
+        Node::IfStatement(test, consequent, alternate) => fields!(
+            "IfStatement",
+            "test" => self::node(test),
+            "consequent" => self::node(consequent),
+            "alternate" => boxed(alternate)
+        ),
+        Node::ConditionalExpression(test, consequent, alternate) => fields!(
+            "ConditionalExpression",
+            "test" => self::node(test),
+            "consequent" => self::node(consequent),
+            "alternate" => self::node(alternate)
+        ),
+        Node::WhileLoop(test, body) => fields!(
+            "WhileLoop",
+            "test" => self::node(test),
+            "body" => self::node(body)
+        ),
+        Node::ForLoop(r#async, binding, iterable, body) => fields!(
+            "ForLoop",
+            "async" => r#async.to_string(),
+            "binding" => quote(binding),
+            "iterable" => self::node(iterable),
+            "body" => self::node(body)
+        ),
+        Node::ExpressionStatement(expr) => {
+            fields!("ExpressionStatement", "expression" => self::node(expr))
+        }
+        Node::UnaryExpression(op, argument) => fields!(
+            "UnaryExpression",
+            "operator" => operator(*op),
+            "argument" => self::node(argument)
+        ),
+        Node::BinaryExpression(op, left, right) => fields!(
+            "BinaryExpression",
+            "operator" => operator(*op),
+            "left" => self::node(left),
+            "right" => self::node(right)
+        ),
+        Node::ParenthesizedExpression(expr) => {
+            fields!("ParenthesizedExpression", "expression" => self::node(expr))
+        }
+        Node::YieldExpression(argument) => {
+            fields!("YieldExpression", "argument" => boxed(argument))
+        }
+        Node::AwaitExpression(argument) => {
+            fields!("AwaitExpression", "argument" => self::node(argument))
+        }
+        Node::ThisExpression => fields!("ThisExpression"),
+        Node::NewExpression(callee) => fields!("NewExpression", "callee" => self::node(callee)),
+        Node::MemberExpression(base, property) => fields!(
+            "MemberExpression",
+            "object" => self::node(base),
+            "property" => quote(property)
+        ),
+        Node::ComputedMemberExpression(base, property) => fields!(
+            "ComputedMemberExpression",
+            "object" => self::node(base),
+            "property" => self::node(property)
+        ),
+        Node::CallExpression(callee, arguments) => fields!(
+            "CallExpression",
+            "callee" => self::node(callee),
+            "arguments" => nodes(arguments)
+        ),
+        Node::TailCallExpression(callee, arguments) => fields!(
+            "TailCallExpression",
+            "callee" => self::node(callee),
+            "arguments" => nodes(arguments)
+        ),
+        Node::FunctionExpression(k, name, parameters, body) => fields!(
+            "FunctionExpression",
+            "kind" => kind(*k),
+            "name" => optional(name.as_ref().map(|n| quote(n))),
+            "parameters" => nodes(parameters),
+            "body" => self::node(body)
+        ),
+        Node::FunctionDeclaration(k, name, parameters, body) => fields!(
+            "FunctionDeclaration",
+            "kind" => kind(*k),
+            "name" => quote(name),
+            "parameters" => nodes(parameters),
+            "body" => self::node(body)
+        ),
+        Node::ArrowFunctionExpression(k, parameters, body) => fields!(
+            "ArrowFunctionExpression",
+            "kind" => kind(*k),
+            "parameters" => nodes(parameters),
+            "body" => self::node(body)
+        ),
+        Node::ClassExpression(name, extends, members) => fields!(
+            "ClassExpression",
+            "name" => quote(name),
+            "extends" => boxed(extends),
+            "members" => nodes(members)
+        ),
+        Node::ClassDeclaration(name, extends, members) => fields!(
+            "ClassDeclaration",
+            "name" => quote(name),
+            "extends" => boxed(extends),
+            "members" => nodes(members)
+        ),
+        Node::EnumDeclaration(name, members) => fields!(
+            "EnumDeclaration",
+            "name" => quote(name),
+            "members" => array(members.iter().map(|(name, value)| {
+                object(vec![
+                    ("name", quote(name)),
+                    ("value", optional(value.map(number))),
+                ])
+            }))
+        ),
+        Node::LexicalInitialization(name, init) => fields!(
+            "LexicalInitialization",
+            "name" => quote(name),
+            "init" => self::node(init)
+        ),
+        Node::LexicalDestructuring(p, init) => fields!(
+            "LexicalDestructuring",
+            "pattern" => pattern(p),
+            "init" => self::node(init)
+        ),
+        Node::ReturnStatement(argument) => {
+            fields!("ReturnStatement", "argument" => boxed(argument))
+        }
+        Node::ThrowStatement(argument) => {
+            fields!("ThrowStatement", "argument" => self::node(argument))
+        }
+        Node::BreakStatement => fields!("BreakStatement"),
+        Node::ContinueStatement => fields!("ContinueStatement"),
+        Node::TryStatement(block, binding, handler, finalizer) => fields!(
+            "TryStatement",
+            "block" => self::node(block),
+            "binding" => optional(binding.as_ref().map(|b| quote(b))),
+            "handler" => boxed(handler),
+            "finalizer" => boxed(finalizer)
+        ),
+        Node::ImportDeclaration(specifier) => {
+            fields!("ImportDeclaration", "specifier" => quote(specifier))
+        }
+        Node::ImportNamedDeclaration(specifier, names) => fields!(
+            "ImportNamedDeclaration",
+            "specifier" => quote(specifier),
+            "bindings" => array(names.iter().map(|n| quote(n)))
+        ),
+        Node::ImportDefaultDeclaration(specifier, name) => fields!(
+            "ImportDefaultDeclaration",
+            "specifier" => quote(specifier),
+            "binding" => quote(name)
+        ),
+        Node::ImportStandardDeclaration(namespace, names) => fields!(
+            "ImportStandardDeclaration",
+            "namespace" => quote(namespace),
+            "bindings" => array(names.iter().map(|n| quote(n)))
+        ),
+        Node::ExportDeclaration(declaration) => {
+            fields!("ExportDeclaration", "declaration" => self::node(declaration))
+        }
+        Node::Initializer(target, value) => fields!(
+            "Initializer",
+            "target" => self::node(target),
+            "value" => self::node(value)
+        ),
+        Node::MatchExpression(value, arms) => fields!(
+            "MatchExpression",
+            "value" => self::node(value),
+            "arms" => nodes(arms)
+        ),
+        Node::MatchArm(_, p, guard, body) => fields!(
+            "MatchArm",
+            "pattern" => pattern(p),
+            "guard" => boxed(guard),
+            "body" => self::node(body)
+        ),
+    }
+}
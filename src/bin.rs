@@ -1,52 +1,472 @@
-use clap::App;
+use clap::{App, Arg, ArgMatches, SubCommand};
+#[cfg(not(target_arch = "wasm32"))]
 use rustyline::{error::ReadlineError, Editor};
-use slither::{disassemble, Agent, Context, Interpreter, Parser, Scope, Value};
+use slither::{
+    check, disassemble, doc, dump_ast, format, lint, Agent, AgentBuilder, EventLog, IntoValue,
+    LintConfig, Parser, Permissions, Severity, Value,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use slither::{Context, Interpreter, Scope};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 fn main() {
+    slither::install_crash_handler();
     let matches = App::new("slither")
         .version("0.1")
         .args_from_usage(
             r#"
         [FILENAME]           'File to run'
+        [ARGS]...            'Arguments passed to the script'
         -d, --disassemble    'Print disassembly instead of running'
+        --dump-ast           'Print the syntax tree as JSON instead of running'
         -e, --eval=[code]    'Code to eval inline'
+        -p, --print=[code]   'Code to eval inline, printing the result'
+        -m, --source-map=[path] 'Write a source map of the compiled bytecode'
+        --no-cache           'Compile every module instead of using .slither-cache'
+        --record=[log]       'Record the outcome of timers, fs calls and sockets to a log'
+        --replay=[log]       'Take the outcome of timers, fs calls and sockets from a recorded log'
+        --allow-env          'Allow reading environment variables'
+        -A, --allow-all      'Allow everything'
         "#,
         )
+        .args(&[
+            permission_arg(
+                "--allow-read=[dirs]... 'Allow reading files, under the given dirs if any'",
+            ),
+            permission_arg(
+                "--allow-write=[dirs]... 'Allow writing files, under the given dirs if any'",
+            ),
+            permission_arg(
+                "--allow-net=[hosts]... 'Allow network access, to the given addresses or hostnames if any'",
+            ),
+            Arg::from_usage("--extension=[path] 'Load a native extension module'")
+                .multiple(true)
+                .number_of_values(1),
+            Arg::from_usage(
+                "--cluster=[workers] 'Run the file on this many agents at once, one per core by default'",
+            )
+            .require_equals(true)
+            .min_values(0),
+        ])
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Print the bytecode listing of a file")
+                .arg_from_usage("<FILE> 'File to disassemble'"),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Run files of benchmarks written with standard:bench")
+                .arg_from_usage("<FILES>... 'Files to run'")
+                .arg_from_usage(
+                    "--filter=[name] 'Only run benchmarks whose name contains this'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Parse and compile files without running them")
+                .arg_from_usage("<FILES>... 'Files to check'"),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Check files for code that is likely a mistake")
+                .arg_from_usage("<FILES>... 'Files to lint'")
+                .args(&[
+                    lint_arg("--allow=[rule] 'Turn off the rule'"),
+                    lint_arg("--warn=[rule] 'Report the rule as a warning'"),
+                    lint_arg("--deny=[rule] 'Report the rule as an error'"),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("doc")
+                .about("Print the exported functions of a module and their doc comments")
+                .arg_from_usage("<FILE> 'Module to document'")
+                .arg_from_usage("--json 'Print JSON instead of markdown'"),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Rewrite files in the canonical style")
+                .arg_from_usage("<FILES>... 'Files to format'")
+                .arg_from_usage("--check 'List the files that aren't formatted instead'"),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("disasm") {
+        let filename = matches.value_of("FILE").unwrap();
+        let result = std::fs::read_to_string(filename)
+            .map_err(|e| format!("unable to read '{}': {}", filename, e))
+            .and_then(|source| disassemble(filename, &source, true));
+        match result {
+            Ok(listing) => print!("{}", listing),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let mut ok = true;
+        for filename in matches.values_of("FILES").unwrap() {
+            let result = std::fs::read_to_string(filename)
+                .map_err(|e| format!("unable to read '{}': {}", filename, e))
+                .and_then(|source| check(filename, &source));
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                ok = false;
+            }
+        }
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        let mut config = LintConfig::default();
+        for (flag, severity) in &[
+            ("allow", Severity::Off),
+            ("warn", Severity::Warning),
+            ("deny", Severity::Error),
+        ] {
+            for rule in matches.values_of(flag).into_iter().flatten() {
+                if let Err(e) = config.set(rule, *severity) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let mut ok = true;
+        for filename in matches.values_of("FILES").unwrap() {
+            let result = std::fs::read_to_string(filename)
+                .map_err(|e| format!("unable to read '{}': {}", filename, e))
+                .and_then(|source| Ok((lint(filename, &source, &config)?, source)));
+            match result {
+                Ok((lints, source)) => {
+                    for l in lints {
+                        eprintln!("{}\n", l.render(filename, &source));
+                        ok &= l.severity != Severity::Error;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ok = false;
+                }
+            }
+        }
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(matches) = matches.subcommand_matches("doc") {
+        let filename = matches.value_of("FILE").unwrap();
+        let result = std::fs::read_to_string(filename)
+            .map_err(|e| format!("unable to read '{}': {}", filename, e))
+            .and_then(|source| doc(filename, &source, !matches.is_present("json")));
+        match result {
+            Ok(docs) => println!("{}", docs),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("fmt") {
+        let mut ok = true;
+        for filename in matches.values_of("FILES").unwrap() {
+            let result = std::fs::read_to_string(filename)
+                .map_err(|e| format!("unable to read '{}': {}", filename, e))
+                .and_then(|source| Ok((format(filename, &source)?, source)));
+            match result {
+                Ok((formatted, source)) if formatted != source => {
+                    if matches.is_present("check") {
+                        println!("{}", filename);
+                        ok = false;
+                    } else if let Err(e) = std::fs::write(filename, formatted) {
+                        eprintln!("unable to write '{}': {}", filename, e);
+                        ok = false;
+                    }
+                }
+                Ok(..) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ok = false;
+                }
+            }
+        }
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Some(bench) = matches.subcommand_matches("bench") {
+        let mut ok = true;
+        for filename in bench.values_of("FILES").unwrap() {
+            println!("{}", filename);
+            let mut agent = new_agent(&matches);
+            agent.cache_bytecode = !matches.is_present("no-cache");
+            agent.bench_filter = bench.value_of("filter").map(String::from);
+            ok &= run_file(&mut agent, filename);
+        }
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let inline = matches
+        .value_of("eval")
+        .or_else(|| matches.value_of("print"));
+
     let source = if matches.is_present("FILENAME") {
         let filename = matches.value_of("FILENAME").unwrap();
         std::fs::read_to_string(filename).unwrap()
-    } else if matches.is_present("eval") {
-        matches.value_of("eval").unwrap().to_string()
+    } else if let Some(code) = inline {
+        // like the repl, allow leaving off the final semicolon
+        if Parser::parse(code).is_err() && Parser::parse(&format!("{};", code)).is_ok() {
+            format!("{};", code)
+        } else {
+            code.to_string()
+        }
     } else {
-        start_repl();
+        start_repl(permissions(&matches));
         return;
     };
 
-    if matches.is_present("disassemble") {
-        disassemble(source.as_str());
-    } else if matches.is_present("eval") {
-        let mut agent = Agent::new();
+    if matches.is_present("dump-ast") {
+        match dump_ast(matches.value_of("FILENAME").unwrap_or("eval"), &source) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if matches.is_present("disassemble") {
+        let filename = matches.value_of("FILENAME").unwrap_or("eval");
+        match disassemble(filename, &source, true) {
+            Ok(listing) => print!("{}", listing),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if inline.is_some() && !matches.is_present("FILENAME") {
+        let mut agent = new_agent(&matches);
         let value = agent.run("eval", source.as_str());
         agent.run_jobs();
+        write_source_map(&matches, &agent, "eval");
         match value {
-            Ok(v) => println!("{}", Value::inspect(&agent, &v)),
-            Err(e) => println!("Uncaught Exception: {}", Value::inspect(&agent, &e)),
+            Ok(v) => {
+                if matches.is_present("print") {
+                    println!("{}", Value::inspect(&agent, &v));
+                }
+                if agent.exit_code() != 0 {
+                    std::process::exit(agent.exit_code());
+                }
+            }
+            Err(e) => {
+                eprintln!("Uncaught Exception: {}", Value::inspect(&agent, &e));
+                std::process::exit(1);
+            }
+        };
+    } else if matches.is_present("cluster") {
+        let filename = matches.value_of("FILENAME").unwrap();
+        let size = match matches.value_of("cluster") {
+            Some(n) => n.parse().unwrap_or_else(|_| {
+                eprintln!("--cluster must be a number of workers");
+                std::process::exit(1);
+            }),
+            None => num_cpus::get(),
         };
+        // every worker would write or take its events from the same log
+        if matches.is_present("record") || matches.is_present("replay") {
+            eprintln!("--record and --replay can't be used with --cluster");
+            std::process::exit(1);
+        }
+        let workers = (0..size)
+            .map(|index| {
+                let filename = filename.to_string();
+                let matches = matches.clone();
+                std::thread::spawn(move || {
+                    let mut agent = new_agent(&matches);
+                    agent.cache_bytecode = !matches.is_present("no-cache");
+                    register_cluster(&mut agent, index, size);
+                    run_file(&mut agent, &filename)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut ok = true;
+        for worker in workers {
+            ok &= worker.join().unwrap_or(false);
+        }
+        if !ok {
+            std::process::exit(1);
+        }
     } else {
         let filename = matches.value_of("FILENAME").unwrap();
-        let referrer = std::env::current_dir().unwrap().join("slither");
-        let referrer = referrer.to_str().unwrap();
+        let mut agent = new_agent(&matches);
+        agent.cache_bytecode = !matches.is_present("no-cache");
+        register_cluster(&mut agent, 0, 1);
+        let ok = run_file(&mut agent, filename);
+        write_source_map(&matches, &agent, filename);
+        if !ok {
+            std::process::exit(1);
+        }
+    }
+}
 
-        let mut agent = Agent::new();
-        agent.import(filename, referrer).unwrap();
-        agent.run_jobs();
+// `standard:cluster` tells each worker which one it is, a file run without
+// --cluster is worker 0 of 1
+fn register_cluster(agent: &mut Agent, index: usize, size: usize) {
+    agent.register_module("cluster", |agent| {
+        let mut module = HashMap::new();
+        module.insert("index".to_string(), index.into_value(agent));
+        module.insert("size".to_string(), size.into_value(agent));
+        module
+    });
+}
+
+fn run_file(agent: &mut Agent, filename: &str) -> bool {
+    // the file being run can always be read, its imports need --allow-read
+    if let Some(read) = &mut agent.permissions.read {
+        read.push(PathBuf::from(filename));
+        read.push(PathBuf::from(filename).with_extension("sl"));
     }
+    let referrer = std::env::current_dir().unwrap().join("slither");
+    let referrer = referrer.to_str().unwrap();
+
+    let result = agent.import(filename, referrer);
+    if let Err(e) = &result {
+        eprintln!("Uncaught Exception: {}", Value::inspect(agent, e));
+    }
+    agent.run_jobs();
+    report_pending_promises(agent);
+    result.is_ok() && agent.exit_code() == 0
 }
 
-fn start_repl() {
-    let mut agent = Agent::new();
+// once the event loop is done nothing can settle these anymore, so whatever
+// was waiting on them never finished
+fn report_pending_promises(agent: &Agent) {
+    let locations = agent.pending_promises();
+    if locations.is_empty() {
+        return;
+    }
+    eprintln!(
+        "warning: {} promise{} never settled",
+        locations.len(),
+        if locations.len() == 1 { "" } else { "s" }
+    );
+    let mut grouped: Vec<(&String, usize)> = Vec::new();
+    for location in &locations {
+        match grouped.last_mut() {
+            Some((last, count)) if *last == location => *count += 1,
+            _ => grouped.push((location, 1)),
+        }
+    }
+    for (location, count) in grouped {
+        if count == 1 {
+            eprintln!("    created at {}", location);
+        } else {
+            eprintln!("    created at {} ({} times)", location, count);
+        }
+    }
+}
+
+fn write_source_map(matches: &ArgMatches, agent: &Agent, file: &str) {
+    if let Some(path) = matches.value_of("source-map") {
+        let json = agent.assembler.borrow().source_map().to_json(file);
+        if let Err(e) = std::fs::write(path, json) {
+            eprintln!("unable to write source map '{}': {}", path, e);
+        }
+    }
+}
+
+// values have to be attached with `=` so the flag can also be given bare
+// before the filename
+fn permission_arg(usage: &str) -> Arg {
+    Arg::from_usage(usage)
+        .require_equals(true)
+        .min_values(0)
+        .use_delimiter(true)
+}
+
+// may be given more than once, one rule each time
+fn lint_arg(usage: &str) -> Arg {
+    Arg::from_usage(usage).multiple(true).number_of_values(1)
+}
+
+fn new_agent(matches: &ArgMatches) -> Agent {
+    let mut builder = AgentBuilder::new().permissions(permissions(matches));
+    if let Some(path) = matches.value_of("record") {
+        match std::fs::File::create(path) {
+            Ok(log) => builder = builder.record(log),
+            Err(e) => {
+                eprintln!("unable to create event log '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = matches.value_of("replay") {
+        match std::fs::File::open(path).and_then(EventLog::read) {
+            Ok(log) => builder = builder.replay(log),
+            Err(e) => {
+                eprintln!("unable to read event log '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut agent = builder.build();
+    agent.args = script_args(matches);
+    #[cfg(not(target_arch = "wasm32"))]
+    for path in matches.values_of("extension").into_iter().flatten() {
+        if let Err(e) = agent.load_extension(path) {
+            eprintln!("{}", Value::inspect(&agent, &e));
+            std::process::exit(1);
+        }
+    }
+    agent
+}
+
+fn script_args(matches: &ArgMatches) -> Vec<String> {
+    matches
+        .values_of("ARGS")
+        .map(|v| v.map(String::from).collect())
+        .unwrap_or_else(Vec::new)
+}
+
+// everything is denied unless allowed by a flag, a flag without values
+// allows everything of that kind
+fn permissions(matches: &ArgMatches) -> Permissions {
+    if matches.is_present("allow-all") {
+        return Permissions::allow_all();
+    }
+    let allowed = |name: &str| -> Option<Vec<String>> {
+        if !matches.is_present(name) {
+            return Some(Vec::new());
+        }
+        let values = matches
+            .values_of(name)
+            .map(|v| v.map(String::from).collect())
+            .unwrap_or_else(Vec::new);
+        if values.is_empty() {
+            None
+        } else {
+            Some(values)
+        }
+    };
+    let paths = |v: Vec<String>| v.into_iter().map(PathBuf::from).collect();
+    Permissions {
+        read: allowed("allow-read").map(paths),
+        write: allowed("allow-write").map(paths),
+        net: allowed("allow-net"),
+        env: matches.is_present("allow-env"),
+    }
+}
+
+// there is no terminal to edit lines in under wasi
+#[cfg(target_arch = "wasm32")]
+fn start_repl(_: Permissions) {
+    eprintln!("the repl isn't available on this platform, pass a file to run");
+    std::process::exit(1);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn start_repl(permissions: Permissions) {
+    let mut agent = AgentBuilder::new().permissions(permissions).build();
 
     agent.set_uncaught_exception_handler(|agent: &Agent, v: Value| {
         println!("Uncaught Exception: {}", Value::inspect(agent, &v));
@@ -55,22 +475,43 @@ fn start_repl() {
     let context = Context::new(Scope::new(Some(agent.root_scope.clone())));
 
     let mut rl = Editor::<()>::new();
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is no history on the first run
+        let _ = rl.load_history(path);
+    }
+    let mut input = String::new();
     loop {
-        let readline = rl.readline(">> ");
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
-                rl.add_history_entry(line.as_ref());
-                let ast = match Parser::parse(&line) {
+                let line = line.trim_end_matches(|c| c == '\n' || c == '\r');
+                rl.add_history_entry(line);
+                input.push_str(line);
+                input.push('\n');
+                let ast = match Parser::parse(&input) {
                     Ok(a) => a,
-                    Err(e) => match Parser::parse((line + ";").as_str()) {
+                    Err(e) => match Parser::parse(&format!("{};", input)) {
                         Ok(a) => a,
+                        // keep reading until braces and brackets are closed
+                        Err(_) if e.last().map_or(false, |d| d.is_incomplete()) => continue,
                         Err(_) => {
-                            println!("Uncaught Exception: {:?}", e);
+                            for d in e {
+                                println!("{}", d.render("repl", &input));
+                            }
+                            input.clear();
                             continue;
                         }
                     },
                 };
-                let index = agent.assembler.assemble(&ast);
+                input.clear();
+                let index = agent.assembler.get_mut().assemble(&ast);
+                agent
+                    .assembler
+                    .get_mut()
+                    .files
+                    .push((index, "repl".to_string()));
                 let mut interpreter = Interpreter::new(index, context.clone());
                 let value = interpreter.run(&agent).unwrap();
                 agent.run_jobs();
@@ -81,6 +522,11 @@ fn start_repl() {
             }
             Err(ReadlineError::Interrupted) => {
                 // println!("CTRL-C");
+                if !input.is_empty() {
+                    // abandon the unfinished input instead of exiting
+                    input.clear();
+                    continue;
+                }
                 break;
             }
             Err(ReadlineError::Eof) => {
@@ -93,4 +539,17 @@ fn start_repl() {
             }
         }
     }
+    if let Some(path) = &history {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("unable to save history: {:?}", e);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os("SLITHER_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".slither_history")),
+    }
 }
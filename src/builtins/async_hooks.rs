@@ -0,0 +1,183 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use gc::GcCell;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+// every promise, timer, fs operation and socket gets an id when it is created,
+// along with the id of whatever was running at the time, its trigger. the
+// callbacks of a resource run with its id as the execution id, so anything
+// they create can be traced back to what scheduled it. the top level runs as
+// id 1.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AsyncId {
+    pub id: u64,
+    pub trigger: u64,
+}
+
+#[derive(Finalize)]
+pub struct AsyncHooks {
+    // the callback objects of enabled hooks
+    hooks: GcCell<Vec<Value>>,
+    next: Cell<u64>,
+    current: Cell<AsyncId>,
+    // set while a hook runs, so the resources it creates don't call hooks
+    running: Cell<bool>,
+}
+
+impl Default for AsyncHooks {
+    fn default() -> Self {
+        AsyncHooks {
+            hooks: GcCell::new(Vec::new()),
+            next: Cell::new(2),
+            current: Cell::new(AsyncId { id: 1, trigger: 0 }),
+            running: Cell::new(false),
+        }
+    }
+}
+
+unsafe impl gc::Trace for AsyncHooks {
+    custom_trace!(this, {
+        mark(&this.hooks);
+    });
+}
+
+impl AsyncHooks {
+    pub fn current(&self) -> AsyncId {
+        self.current.get()
+    }
+
+    pub fn init(&self, agent: &Agent, kind: &str, resource: &Value) -> AsyncId {
+        let id = AsyncId {
+            id: self.next.get(),
+            trigger: self.current.get().id,
+        };
+        self.next.set(id.id + 1);
+        self.call(
+            agent,
+            "init",
+            vec![
+                Value::from(id.id as f64),
+                Value::from(kind),
+                Value::from(id.trigger as f64),
+                resource.clone(),
+            ],
+        );
+        id
+    }
+
+    // runs f as a callback of the resource with that id
+    pub fn run<T, F: FnOnce() -> T>(&self, agent: &Agent, id: AsyncId, f: F) -> T {
+        let previous = self.current.replace(id);
+        self.call(agent, "before", vec![Value::from(id.id as f64)]);
+        let result = f();
+        self.call(agent, "after", vec![Value::from(id.id as f64)]);
+        self.current.set(previous);
+        result
+    }
+
+    pub fn resolve(&self, agent: &Agent, id: AsyncId) {
+        self.call(agent, "promiseResolve", vec![Value::from(id.id as f64)]);
+    }
+
+    fn call(&self, agent: &Agent, name: &str, args: Vec<Value>) {
+        if self.running.get() || self.hooks.borrow().is_empty() {
+            return;
+        }
+        self.running.set(true);
+        let hooks = self.hooks.borrow().clone();
+        for hook in hooks {
+            let result = hook
+                .get(agent, ObjectKey::from(name))
+                .and_then(|f| match f {
+                    Value::Null => Ok(Value::Null),
+                    f => f.call(agent, hook.clone(), args.clone()),
+                });
+            if let Err(e) = result {
+                self.running.set(false);
+                agent.uncaught_exception(e);
+                self.running.set(true);
+            }
+        }
+        self.running.set(false);
+    }
+}
+
+// the id promises were given, they keep it in a slot
+pub fn promise_id(promise: &Value) -> Option<AsyncId> {
+    if !promise.has_slot("async id") {
+        return None;
+    }
+    match (
+        promise.get_slot("async id"),
+        promise.get_slot("async trigger"),
+    ) {
+        (Value::Number(id), Value::Number(trigger)) => Some(AsyncId {
+            id: id as u64,
+            trigger: trigger as u64,
+        }),
+        _ => None,
+    }
+}
+
+fn create_hook(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let callbacks = match args.get(0) {
+        Some(v @ Value::Object(..)) => v.clone(),
+        _ => return Err(Value::new_error(agent, "callbacks must be an object")),
+    };
+    let hook = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let enable = Value::new_builtin_function(agent, enable);
+    enable.set_slot("callbacks", callbacks.clone());
+    hook.set(agent, ObjectKey::from("enable"), enable)?;
+    let disable = Value::new_builtin_function(agent, disable);
+    disable.set_slot("callbacks", callbacks);
+    hook.set(agent, ObjectKey::from("disable"), disable)?;
+    Ok(hook)
+}
+
+fn enable(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let callbacks = ctx.function.clone().unwrap().get_slot("callbacks");
+    let mut hooks = agent.async_hooks.hooks.borrow_mut();
+    if !hooks.contains(&callbacks) {
+        hooks.push(callbacks);
+    }
+    Ok(Value::Null)
+}
+
+fn disable(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let callbacks = ctx.function.clone().unwrap().get_slot("callbacks");
+    agent
+        .async_hooks
+        .hooks
+        .borrow_mut()
+        .retain(|h| *h != callbacks);
+    Ok(Value::Null)
+}
+
+fn execution_async_id(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from(agent.async_hooks.current().id as f64))
+}
+
+fn trigger_async_id(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from(agent.async_hooks.current().trigger as f64))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "createHook".to_string(),
+        Value::new_builtin_function(agent, create_hook),
+    );
+    module.insert(
+        "executionAsyncId".to_string(),
+        Value::new_builtin_function(agent, execution_async_id),
+    );
+    module.insert(
+        "triggerAsyncId".to_string(),
+        Value::new_builtin_function(agent, trigger_async_id),
+    );
+
+    module
+}
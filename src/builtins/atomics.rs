@@ -0,0 +1,204 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// bytes that several agents can hold at once, e.g. handed to each of them
+// through `Value::new_shared_buffer`
+pub struct SharedMemory {
+    bytes: Box<[AtomicU8]>,
+    // index -> (waiting, generation), bumped by notify
+    waiters: Mutex<HashMap<usize, (usize, usize)>>,
+    condvar: Condvar,
+}
+
+impl SharedMemory {
+    pub fn new(length: usize) -> Arc<SharedMemory> {
+        Arc::new(SharedMemory {
+            bytes: (0..length).map(|_| AtomicU8::new(0)).collect(),
+            waiters: Mutex::new(HashMap::new()),
+            condvar: Condvar::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn load(&self, index: usize) -> u8 {
+        self.bytes[index].load(Ordering::SeqCst)
+    }
+
+    pub fn store(&self, index: usize, value: u8) {
+        self.bytes[index].store(value, Ordering::SeqCst);
+    }
+}
+
+impl std::fmt::Debug for SharedMemory {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "SharedMemory({})", self.len())
+    }
+}
+
+fn byte(agent: &Agent, value: Option<&Value>) -> Result<u8, Value> {
+    match value {
+        Some(Value::Number(n)) if n.fract() == 0.0 && *n >= 0.0 && *n <= 255.0 => Ok(*n as u8),
+        _ => Err(Value::new_error(
+            agent,
+            "value must be an integer from 0 to 255",
+        )),
+    }
+}
+
+// the buffer and index every operation starts with
+fn cell(agent: &Agent, args: &[Value]) -> Result<(Arc<SharedMemory>, usize), Value> {
+    let memory = match args.get(0).and_then(Value::shared_memory) {
+        Some(memory) => memory,
+        None => return Err(Value::new_error(agent, "buffer must be a shared buffer")),
+    };
+    match args.get(1) {
+        Some(Value::Number(n)) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < memory.len() => {
+            Ok((memory, *n as usize))
+        }
+        _ => Err(Value::new_error(agent, "index out of range")),
+    }
+}
+
+fn create_shared_buffer(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::Number(n)) if n.fract() == 0.0 && *n >= 0.0 => Ok(Value::new_shared_buffer(
+            agent,
+            SharedMemory::new(*n as usize),
+        )),
+        _ => Err(Value::new_error(agent, "length must be a positive integer")),
+    }
+}
+
+fn length(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0).and_then(Value::shared_memory) {
+        Some(memory) => Ok(Value::from(memory.len() as f64)),
+        None => Err(Value::new_error(agent, "buffer must be a shared buffer")),
+    }
+}
+
+fn load(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    Ok(Value::from(f64::from(memory.load(index))))
+}
+
+fn store(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    memory.store(index, byte(agent, args.get(2))?);
+    Ok(Value::Null)
+}
+
+// wraps around like the underlying byte, returns the previous value
+fn add(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    let value = byte(agent, args.get(2))?;
+    Ok(Value::from(f64::from(
+        memory.bytes[index].fetch_add(value, Ordering::SeqCst),
+    )))
+}
+
+fn compare_exchange(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    let expected = byte(agent, args.get(2))?;
+    let replacement = byte(agent, args.get(3))?;
+    let previous = match memory.bytes[index].compare_exchange(
+        expected,
+        replacement,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    ) {
+        Ok(v) | Err(v) => v,
+    };
+    Ok(Value::from(f64::from(previous)))
+}
+
+// blocks the whole agent until another one notifies the index, so it is
+// only useful across agents
+fn wait(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    let value = byte(agent, args.get(2))?;
+    let deadline = match args.get(3) {
+        Some(Value::Number(ms)) if *ms >= 0.0 => {
+            Some(Instant::now() + Duration::from_millis(*ms as u64))
+        }
+        None | Some(Value::Null) => None,
+        _ => return Err(Value::new_error(agent, "timeout must be a number")),
+    };
+
+    let mut waiters = memory.waiters.lock().unwrap();
+    if memory.load(index) != value {
+        return Ok(Value::from("not-equal"));
+    }
+    let generation = {
+        let entry = waiters.entry(index).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1
+    };
+    let result = loop {
+        if waiters[&index].1 != generation {
+            break "ok";
+        }
+        waiters = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break "timed-out";
+                }
+                memory
+                    .condvar
+                    .wait_timeout(waiters, deadline - now)
+                    .unwrap()
+                    .0
+            }
+            None => memory.condvar.wait(waiters).unwrap(),
+        };
+    };
+    waiters.get_mut(&index).unwrap().0 -= 1;
+    Ok(Value::from(result))
+}
+
+// wakes everything waiting on the index and returns how many that was
+fn notify(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (memory, index) = cell(agent, &args)?;
+    let mut waiters = memory.waiters.lock().unwrap();
+    let woken = match waiters.get_mut(&index) {
+        Some(entry) => {
+            entry.1 = entry.1.wrapping_add(1);
+            entry.0
+        }
+        None => 0,
+    };
+    memory.condvar.notify_all();
+    Ok(Value::from(woken as f64))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    macro_rules! f {
+        ($name:expr, $f:expr) => {
+            module.insert($name.to_string(), Value::new_builtin_function(agent, $f));
+        };
+    }
+    f!("createSharedBuffer", create_shared_buffer);
+    f!("length", length);
+    f!("load", load);
+    f!("store", store);
+    f!("add", add);
+    f!("compareExchange", compare_exchange);
+    f!("wait", wait);
+    f!("notify", notify);
+
+    module
+}
@@ -0,0 +1,149 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::output::Stream;
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// runs a function over and over, first to warm up and to find out how many
+// calls make up a sample long enough for the clock to measure, then for the
+// given time, collecting the time per call of each sample.
+
+const DEFAULT_WARMUP: f64 = 100.0;
+const DEFAULT_TIME: f64 = 1000.0;
+const MIN_SAMPLES: usize = 10;
+// the shortest stretch of calls timed as one sample
+const SAMPLE_TIME: Duration = Duration::from_millis(1);
+
+// how long to spend, in milliseconds, from the options object if given
+fn option(agent: &Agent, options: Option<&Value>, name: &str, default: f64) -> Result<f64, Value> {
+    let value = match options {
+        Some(o @ Value::Object(..)) => o.get(agent, ObjectKey::from(name))?,
+        _ => Value::Null,
+    };
+    match value {
+        Value::Null => Ok(default),
+        Value::Number(n) if n >= 0.0 => Ok(n),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a non-negative number", name),
+        )),
+    }
+}
+
+fn call(agent: &Agent, f: &Value) -> Result<(), Value> {
+    let result = f.call(agent, Value::Null, Vec::new())?;
+    if result.has_slot("promise state") {
+        return Err(Value::new_error(agent, "benchmark must be synchronous"));
+    }
+    Ok(())
+}
+
+// runs f `calls` times, returning the time per call in nanoseconds
+fn sample(agent: &Agent, f: &Value, calls: u64) -> Result<f64, Value> {
+    let start = Instant::now();
+    for _ in 0..calls {
+        call(agent, f)?;
+    }
+    Ok(start.elapsed().as_nanos() as f64 / calls as f64)
+}
+
+// nearest rank
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+fn format_time(ns: f64) -> String {
+    if ns < 1e3 {
+        format!("{:.2}ns", ns)
+    } else if ns < 1e6 {
+        format!("{:.2}µs", ns / 1e3)
+    } else if ns < 1e9 {
+        format!("{:.2}ms", ns / 1e6)
+    } else {
+        format!("{:.2}s", ns / 1e9)
+    }
+}
+
+fn bench(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let name = match args.get(0) {
+        Some(Value::String(s)) => s.to_string(),
+        _ => return Err(Value::new_error(agent, "name must be a string")),
+    };
+    let f = match args.get(1) {
+        Some(f) if f.type_of() == "function" => f.clone(),
+        _ => return Err(Value::new_error(agent, "benchmark must be a function")),
+    };
+    if let Some(filter) = &agent.bench_filter {
+        if !name.contains(filter.as_str()) {
+            return Ok(Value::Null);
+        }
+    }
+    let warmup = option(agent, args.get(2), "warmup", DEFAULT_WARMUP)?;
+    let time = option(agent, args.get(2), "time", DEFAULT_TIME)?;
+
+    let start = Instant::now();
+    let mut warmup_calls = 0;
+    loop {
+        call(agent, &f)?;
+        warmup_calls += 1;
+        if start.elapsed().as_secs_f64() * 1000.0 >= warmup {
+            break;
+        }
+    }
+    let per_call = start.elapsed().as_nanos() as f64 / warmup_calls as f64;
+    let calls = ((SAMPLE_TIME.as_nanos() as f64 / per_call).ceil() as u64).max(1);
+
+    let mut samples = Vec::new();
+    let start = Instant::now();
+    while samples.len() < MIN_SAMPLES || start.elapsed().as_secs_f64() * 1000.0 < time {
+        samples.push(sample(agent, &f, calls)?);
+    }
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    let iterations = samples.len() as u64 * calls;
+
+    let result = Value::new_object(agent.intrinsics.object_prototype.clone());
+    result.set(agent, ObjectKey::from("name"), Value::from(name.as_str()))?;
+    result.set(
+        agent,
+        ObjectKey::from("iterations"),
+        Value::from(iterations as f64),
+    )?;
+    let stats = [
+        ("mean", mean),
+        ("min", samples[0]),
+        ("max", samples[samples.len() - 1]),
+        ("p50", percentile(&samples, 50.0)),
+        ("p90", percentile(&samples, 90.0)),
+        ("p99", percentile(&samples, 99.0)),
+    ];
+    // in nanoseconds
+    for (key, ns) in &stats {
+        result.set(agent, ObjectKey::from(*key), Value::from(*ns))?;
+    }
+    agent.write_output(
+        Stream::Stdout,
+        &format!(
+            "{}: mean {}, p50 {}, p90 {}, p99 {} ({} iterations)\n",
+            name,
+            format_time(mean),
+            format_time(stats[3].1),
+            format_time(stats[4].1),
+            format_time(stats[5].1),
+            iterations
+        ),
+    );
+    Ok(result)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "bench".to_string(),
+        Value::new_builtin_function(agent, bench),
+    );
+
+    module
+}
@@ -0,0 +1,843 @@
+use crate::agent::Agent;
+use crate::ast_json;
+use crate::diagnostics;
+use crate::interpreter::{self, Context, Interpreter};
+use crate::module;
+use crate::parser::{FunctionKind, Node, Operator, Parser, Pattern, Scope, SourceToken};
+use crate::value::{ObjectKey, Value};
+use crate::FromValue;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+
+// the lexer, parser and compiler, for tools written in slither. `tokenize`
+// gives each token with its kind and where it starts and ends. `parse` gives the
+// syntax tree as plain objects in the same shape as --dump-ast, and `compile`
+// turns a tree, whether parsed, rewritten or built from scratch, into a
+// function that runs it. the assembler trusts that its input came from the
+// parser, so a tree is checked for anything the parser would not produce
+// while it is read back.
+
+fn source(agent: &Agent, args: &[Value]) -> Result<String, Value> {
+    match args.get(0) {
+        Some(Value::String(s)) => Ok(s.to_string()),
+        _ => Err(Value::new_error(agent, "source must be a string")),
+    }
+}
+
+fn parse(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let source = source(agent, &args)?;
+    match Parser::parse(&source) {
+        Ok(ast) => Ok(JsonReader::new(agent, &ast_json::to_json(&ast)).value()),
+        Err(d) => Err(diagnostics::to_error(agent, &d, "parse", &source)),
+    }
+}
+
+fn position(agent: &Agent, (line, column): (usize, usize)) -> Result<Value, Value> {
+    let position = Value::new_object(agent.intrinsics.object_prototype.clone());
+    position.set(agent, ObjectKey::from("line"), Value::from(line as f64))?;
+    position.set(agent, ObjectKey::from("column"), Value::from(column as f64))?;
+    Ok(position)
+}
+
+fn token(agent: &Agent, token: SourceToken) -> Result<Value, Value> {
+    let value = Value::new_object(agent.intrinsics.object_prototype.clone());
+    value.set(
+        agent,
+        ObjectKey::from("kind"),
+        Value::from(token.kind.name()),
+    )?;
+    value.set(agent, ObjectKey::from("text"), Value::from(token.text))?;
+    value.set(
+        agent,
+        ObjectKey::from("start"),
+        position(agent, token.start)?,
+    )?;
+    value.set(agent, ObjectKey::from("end"), position(agent, token.end)?)?;
+    Ok(value)
+}
+
+fn tokenize(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let source = source(agent, &args)?;
+    let tokens = match Parser::tokenize(&source) {
+        Ok(tokens) => tokens,
+        Err(d) => return Err(diagnostics::to_error(agent, &[d], "tokenize", &source)),
+    };
+    let array = Value::new_array(agent);
+    for (i, t) in tokens.into_iter().enumerate() {
+        array.set(agent, ObjectKey::from(i), token(agent, t)?)?;
+    }
+    Ok(array)
+}
+
+fn compile(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let ast = args.get(0).cloned().unwrap_or(Value::Null);
+    let program = Reader::new(agent).program(&ast)?;
+    instantiate(agent, "compile", program)
+}
+
+fn run(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let source = source(agent, &args)?;
+    let program = match Parser::parse(&source) {
+        Ok(ast) => ast,
+        Err(d) => return Err(diagnostics::to_error(agent, &d, "run", &source)),
+    };
+    let f = instantiate(agent, "run", program)?;
+    f.call(agent, Value::Null, Vec::new())
+}
+
+// the program becomes the body of a function, so that each call runs it in a
+// scope of its own and its completion value is what the call returns. its
+// imports are bound once, now.
+fn instantiate(agent: &Agent, filename: &str, program: Node) -> Result<Value, Value> {
+    let imports = module::imports(&program);
+    let body = match program {
+        Node::Block(scope, mut stmts) => {
+            let last = stmts.iter().rposition(|s| match s {
+                Node::Position(..) => false,
+                _ => true,
+            });
+            if let Some(last) = last {
+                match stmts.remove(last) {
+                    Node::ExpressionStatement(expr) | Node::ParenthesizedExpression(expr) => {
+                        stmts.insert(last, Node::ReturnStatement(Some(expr)))
+                    }
+                    stmt => stmts.insert(last, stmt),
+                }
+            }
+            Node::Block(Scope::block(scope.bindings), stmts)
+        }
+        _ => unreachable!(),
+    };
+    let function = Node::FunctionExpression(FunctionKind::Normal, None, Vec::new(), Box::new(body));
+    let wrapper = Node::Block(
+        Scope::block(IndexMap::new()),
+        vec![Node::ParenthesizedExpression(Box::new(function))],
+    );
+    let position = {
+        let mut assembler = agent.assembler.borrow_mut();
+        let position = assembler.assemble(&wrapper);
+        assembler.files.push((position, filename.to_string()));
+        position
+    };
+
+    // relative imports resolve from the working directory
+    let referrer = std::env::current_dir().unwrap_or_default().join(filename);
+    let referrer = referrer.to_str().unwrap();
+    let context = Context::new(interpreter::Scope::new(Some(agent.root_scope.clone())));
+    for specifier in module::bind_imports(agent, referrer, &context, imports)? {
+        agent.import(&specifier, referrer)?;
+    }
+    Interpreter::new(position, context).run(agent).unwrap()
+}
+
+// reads back what ast_json wrote, which is always well formed
+struct JsonReader<'a> {
+    agent: &'a Agent,
+    json: &'a [u8],
+    position: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn new(agent: &'a Agent, json: &'a str) -> JsonReader<'a> {
+        JsonReader {
+            agent,
+            json: json.as_bytes(),
+            position: 0,
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.json[self.position] == byte {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Value {
+        match self.json[self.position] {
+            b'{' => {
+                self.position += 1;
+                let object = Value::new_object(self.agent.intrinsics.object_prototype.clone());
+                while !self.eat(b'}') {
+                    self.eat(b',');
+                    let key = self.string();
+                    self.eat(b':');
+                    let value = self.value();
+                    object.set(self.agent, ObjectKey::from(key), value).unwrap();
+                }
+                object
+            }
+            b'[' => {
+                self.position += 1;
+                let array = Value::new_array(self.agent);
+                let mut i = 0;
+                while !self.eat(b']') {
+                    self.eat(b',');
+                    let value = self.value();
+                    array.set(self.agent, ObjectKey::from(i), value).unwrap();
+                    i += 1;
+                }
+                array
+            }
+            b'"' => Value::from(self.string()),
+            b't' => self.word("true", Value::from(true)),
+            b'f' => self.word("false", Value::from(false)),
+            b'n' => self.word("null", Value::Null),
+            _ => {
+                let start = self.position;
+                while self
+                    .json
+                    .get(self.position)
+                    .map_or(false, |b| b"0123456789-+.eE".contains(b))
+                {
+                    self.position += 1;
+                }
+                let number = std::str::from_utf8(&self.json[start..self.position]).unwrap();
+                Value::from(number.parse::<f64>().unwrap())
+            }
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Value) -> Value {
+        self.position += word.len();
+        value
+    }
+
+    fn string(&mut self) -> String {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = self.json[self.position];
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = self.json[self.position];
+                    self.position += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'u' => {
+                            let hex = &self.json[self.position..self.position + 4];
+                            self.position += 4;
+                            let code = u32::from_str_radix(std::str::from_utf8(hex).unwrap(), 16);
+                            std::char::from_u32(code.unwrap()).unwrap()
+                        }
+                        c => c as char,
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                b => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+// turns a tree of objects back into nodes
+struct Reader<'a> {
+    agent: &'a Agent,
+    // the kind of the function being read, none at the top level
+    function: Option<FunctionKind>,
+    loops: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(agent: &'a Agent) -> Reader<'a> {
+        Reader {
+            agent,
+            function: None,
+            loops: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> Value {
+        Value::new_error(self.agent, &format!("invalid syntax tree: {}", message))
+    }
+
+    fn field(&self, node: &Value, name: &str) -> Result<Value, Value> {
+        node.get(self.agent, ObjectKey::from(name))
+    }
+
+    fn type_of(&self, node: &Value) -> Result<String, Value> {
+        if let Value::Object(..) = node {
+            if let Value::String(s) = self.field(node, "type")? {
+                return Ok(s.to_string());
+            }
+        }
+        Err(self.error("expected a node, an object with a type"))
+    }
+
+    // a field that has to be of a certain type, it is named in the error
+    fn typed<T: FromValue>(
+        &self,
+        node: &Value,
+        ty: &str,
+        name: &str,
+        expected: &str,
+    ) -> Result<T, Value> {
+        let value = self.field(node, name)?;
+        T::from_value(self.agent, value)
+            .map_err(|_| self.error(&format!("`{}.{}` must be {}", ty, name, expected)))
+    }
+
+    fn string(&self, node: &Value, ty: &str, name: &str) -> Result<String, Value> {
+        self.typed(node, ty, name, "a string")
+    }
+
+    fn strings(&self, node: &Value, ty: &str, name: &str) -> Result<Vec<String>, Value> {
+        self.typed(node, ty, name, "an array of strings")
+    }
+
+    fn array(&self, node: &Value, ty: &str, name: &str) -> Result<Vec<Value>, Value> {
+        self.typed(node, ty, name, "an array")
+    }
+
+    fn child(&mut self, node: &Value, name: &str) -> Result<Box<Node>, Value> {
+        Ok(Box::new(self.node(&self.field(node, name)?)?))
+    }
+
+    fn optional(&mut self, node: &Value, name: &str) -> Result<Option<Box<Node>>, Value> {
+        match self.field(node, name)? {
+            Value::Null => Ok(None),
+            value => Ok(Some(Box::new(self.node(&value)?))),
+        }
+    }
+
+    fn nodes(&mut self, node: &Value, ty: &str, name: &str) -> Result<Vec<Node>, Value> {
+        self.array(node, ty, name)?
+            .iter()
+            .map(|n| self.node(n))
+            .collect()
+    }
+
+    fn program(&mut self, ast: &Value) -> Result<Node, Value> {
+        if self.type_of(ast)? != "Program" {
+            return Err(self.error("the root must be a `Program`"));
+        }
+        self.body(ast, "Program", true)
+    }
+
+    // the bindings and statements of a program or block
+    fn body(&mut self, node: &Value, ty: &str, top_level: bool) -> Result<Node, Value> {
+        let mut stmts = Vec::new();
+        for stmt in self.array(node, ty, "body")? {
+            let line = self.field(&stmt, "line")?;
+            let column = self.field(&stmt, "column")?;
+            if let (Value::Number(line), Value::Number(column)) = (line, column) {
+                stmts.push(Node::Position(line as usize, column as usize));
+            }
+            stmts.push(self.statement(&stmt, top_level)?);
+        }
+
+        let mut bindings = IndexMap::new();
+        for binding in self.array(node, ty, "bindings")? {
+            let name = self.string(&binding, "binding", "name")?;
+            let mutable = self.typed(&binding, "binding", "mutable", "a boolean")?;
+            bindings.insert(name, mutable);
+        }
+        // a declaration for a binding that is missing would initialize
+        // something that was never created
+        for stmt in &stmts {
+            for name in declared(stmt) {
+                if !bindings.contains_key(name) {
+                    bindings.insert(name.to_string(), false);
+                }
+            }
+        }
+        Ok(Node::Block(Scope::block(bindings), stmts))
+    }
+
+    fn block(&mut self, node: &Value, name: &str) -> Result<Box<Node>, Value> {
+        let block = self.field(node, name)?;
+        if self.type_of(&block)? != "Block" {
+            let ty = self.type_of(node)?;
+            return Err(self.error(&format!("`{}.{}` must be a `Block`", ty, name)));
+        }
+        Ok(Box::new(self.body(&block, "Block", false)?))
+    }
+
+    fn optional_block(&mut self, node: &Value, name: &str) -> Result<Option<Box<Node>>, Value> {
+        match self.field(node, name)? {
+            Value::Null => Ok(None),
+            _ => Ok(Some(self.block(node, name)?)),
+        }
+    }
+
+    fn loop_body(&mut self, node: &Value) -> Result<Box<Node>, Value> {
+        self.loops += 1;
+        let body = self.block(node, "body");
+        self.loops -= 1;
+        body
+    }
+
+    fn statement(&mut self, node: &Value, top_level: bool) -> Result<Node, Value> {
+        let ty = self.type_of(node)?;
+        let ty = ty.as_str();
+        Ok(match ty {
+            "ImportDeclaration"
+            | "ImportNamedDeclaration"
+            | "ImportDefaultDeclaration"
+            | "ImportStandardDeclaration"
+            | "ExportDeclaration"
+                if !top_level =>
+            {
+                return Err(self.error(&format!("`{}` is only allowed at the top level", ty)))
+            }
+            "ImportDeclaration" => Node::ImportDeclaration(self.string(node, ty, "specifier")?),
+            "ImportNamedDeclaration" => Node::ImportNamedDeclaration(
+                self.string(node, ty, "specifier")?,
+                self.strings(node, ty, "bindings")?,
+            ),
+            "ImportDefaultDeclaration" => Node::ImportDefaultDeclaration(
+                self.string(node, ty, "specifier")?,
+                self.string(node, ty, "binding")?,
+            ),
+            "ImportStandardDeclaration" => Node::ImportStandardDeclaration(
+                self.string(node, ty, "namespace")?,
+                self.strings(node, ty, "bindings")?,
+            ),
+            "ExportDeclaration" => {
+                let declaration = self.statement(&self.field(node, "declaration")?, false)?;
+                if declared(&declaration).is_empty() {
+                    return Err(self.error("`ExportDeclaration` must export a declaration"));
+                }
+                Node::ExportDeclaration(Box::new(declaration))
+            }
+            "LexicalInitialization" => Node::LexicalInitialization(
+                self.string(node, ty, "name")?,
+                self.child(node, "init")?,
+            ),
+            "LexicalDestructuring" => Node::LexicalDestructuring(
+                self.pattern(&self.field(node, "pattern")?)?,
+                self.child(node, "init")?,
+            ),
+            "FunctionDeclaration" => {
+                let kind = self.kind(node, ty)?;
+                let (parameters, body) = self.function(kind, node, ty)?;
+                Node::FunctionDeclaration(kind, self.string(node, ty, "name")?, parameters, body)
+            }
+            "ClassDeclaration" => Node::ClassDeclaration(
+                self.string(node, ty, "name")?,
+                self.optional(node, "extends")?,
+                self.members(node, ty)?,
+            ),
+            "EnumDeclaration" => {
+                let mut members = Vec::new();
+                for member in self.array(node, ty, "members")? {
+                    members.push((
+                        self.string(&member, "member", "name")?,
+                        self.typed(&member, "member", "value", "a number or null")?,
+                    ));
+                }
+                Node::EnumDeclaration(self.string(node, ty, "name")?, members)
+            }
+            _ => self.node(node)?,
+        })
+    }
+
+    fn node(&mut self, node: &Value) -> Result<Node, Value> {
+        let ty = self.type_of(node)?;
+        let ty = ty.as_str();
+        Ok(match ty {
+            "NullLiteral" => Node::NullLiteral,
+            "BooleanLiteral" => {
+                if self.typed(node, ty, "value", "a boolean")? {
+                    Node::TrueLiteral
+                } else {
+                    Node::FalseLiteral
+                }
+            }
+            "NumberLiteral" => Node::NumberLiteral(self.typed(node, ty, "value", "a number")?),
+            "StringLiteral" => Node::StringLiteral(self.string(node, ty, "value")?),
+            "SymbolLiteral" => Node::SymbolLiteral(self.string(node, ty, "name")?),
+            "RegexLiteral" => Node::RegexLiteral(self.string(node, ty, "pattern")?),
+            "ObjectLiteral" => Node::ObjectLiteral(self.initializers(node, ty, "properties")?),
+            "ArrayLiteral" => Node::ArrayLiteral(self.nodes(node, ty, "elements")?),
+            "TupleLiteral" => Node::TupleLiteral(self.nodes(node, ty, "elements")?),
+            "NamedTupleLiteral" => {
+                let mut fields = Vec::new();
+                for field in self.array(node, ty, "fields")? {
+                    let value = self.node(&self.field(&field, "value")?)?;
+                    fields.push((self.string(&field, "field", "name")?, value));
+                }
+                Node::NamedTupleLiteral(fields)
+            }
+            "RecordLiteral" => {
+                let properties = self.initializers(node, ty, "properties")?;
+                for property in &properties {
+                    if let Node::Initializer(key, _) = property {
+                        if let Node::StringLiteral(..) = **key {
+                            continue;
+                        }
+                    }
+                    return Err(self.error("`RecordLiteral` keys must be `StringLiteral`s"));
+                }
+                Node::RecordLiteral(properties)
+            }
+            "TemplateLiteral" => {
+                let quasis = self.strings(node, ty, "quasis")?;
+                let expressions = self.nodes(node, ty, "expressions")?;
+                if quasis.len() != expressions.len() + 1 {
+                    return Err(
+                        self.error("`TemplateLiteral` must have one more quasi than expressions")
+                    );
+                }
+                Node::TemplateLiteral(quasis, expressions)
+            }
+            "Identifier" => Node::Identifier(self.string(node, ty, "name")?),
+            "Block" => self.body(node, ty, false)?,
+            "IfStatement" => {
+                let test = self.child(node, "test")?;
+                let consequent = self.block(node, "consequent")?;
+                let alternate = match self.field(node, "alternate")? {
+                    Value::Null => None,
+                    ref a if self.type_of(a)? == "IfStatement" => Some(Box::new(self.node(a)?)),
+                    _ => self.optional_block(node, "alternate")?,
+                };
+                Node::IfStatement(test, consequent, alternate)
+            }
+            "ConditionalExpression" => Node::ConditionalExpression(
+                self.child(node, "test")?,
+                self.child(node, "consequent")?,
+                self.child(node, "alternate")?,
+            ),
+            "WhileLoop" => Node::WhileLoop(self.child(node, "test")?, self.loop_body(node)?),
+            "ForLoop" => {
+                let r#async = self.typed(node, ty, "async", "a boolean")?;
+                if r#async && !self.inside(FunctionKind::Async) {
+                    return Err(self.error("`for await` is only allowed in async functions"));
+                }
+                Node::ForLoop(
+                    r#async,
+                    self.string(node, ty, "binding")?,
+                    self.child(node, "iterable")?,
+                    self.loop_body(node)?,
+                )
+            }
+            "ExpressionStatement" => Node::ExpressionStatement(self.child(node, "expression")?),
+            "UnaryExpression" => {
+                let op = self.operator(node, ty)?;
+                match op {
+                    Operator::Not
+                    | Operator::BitwiseNOT
+                    | Operator::Typeof
+                    | Operator::Void
+                    | Operator::Sub => {}
+                    _ => {
+                        return Err(
+                            self.error(&format!("'{}' is not a unary operator", op.symbol()))
+                        )
+                    }
+                }
+                Node::UnaryExpression(op, self.child(node, "argument")?)
+            }
+            "BinaryExpression" => {
+                let op = self.operator(node, ty)?;
+                let left = self.child(node, "left")?;
+                match op {
+                    Operator::Not | Operator::BitwiseNOT | Operator::Typeof | Operator::Void => {
+                        return Err(
+                            self.error(&format!("'{}' is not a binary operator", op.symbol()))
+                        )
+                    }
+                    _ if op.symbol().ends_with('=') && !is_comparison(op) => match *left {
+                        Node::Identifier(..)
+                        | Node::MemberExpression(..)
+                        | Node::ComputedMemberExpression(..) => {}
+                        _ => return Err(self.error("invalid assignment target")),
+                    },
+                    _ => {}
+                }
+                Node::BinaryExpression(op, left, self.child(node, "right")?)
+            }
+            "ParenthesizedExpression" => {
+                Node::ParenthesizedExpression(self.child(node, "expression")?)
+            }
+            "YieldExpression" => {
+                if !self.inside(FunctionKind::Generator) {
+                    return Err(self.error("`yield` is only allowed in generators"));
+                }
+                Node::YieldExpression(self.optional(node, "argument")?)
+            }
+            "AwaitExpression" => {
+                if !self.inside(FunctionKind::Async) {
+                    return Err(self.error("`await` is only allowed in async functions"));
+                }
+                Node::AwaitExpression(self.child(node, "argument")?)
+            }
+            "ThisExpression" => Node::ThisExpression,
+            "NewExpression" => Node::NewExpression(self.child(node, "callee")?),
+            "MemberExpression" => Node::MemberExpression(
+                self.child(node, "object")?,
+                self.string(node, ty, "property")?,
+            ),
+            "ComputedMemberExpression" => Node::ComputedMemberExpression(
+                self.child(node, "object")?,
+                self.child(node, "property")?,
+            ),
+            // only a call that is returned can be a tail call, which is up
+            // to the return statement
+            "CallExpression" | "TailCallExpression" => Node::CallExpression(
+                self.child(node, "callee")?,
+                self.nodes(node, ty, "arguments")?,
+            ),
+            "FunctionExpression" => {
+                let kind = self.kind(node, ty)?;
+                let name = self.typed(node, ty, "name", "a string or null")?;
+                let (parameters, body) = self.function(kind, node, ty)?;
+                Node::FunctionExpression(kind, name, parameters, body)
+            }
+            "ArrowFunctionExpression" => {
+                let kind = self.kind(node, ty)?;
+                if kind == FunctionKind::Generator {
+                    return Err(self.error("arrow functions can't be generators"));
+                }
+                let kind = kind | FunctionKind::Arrow;
+                let (parameters, body) = self.function(kind, node, ty)?;
+                Node::ArrowFunctionExpression(kind, parameters, body)
+            }
+            "ClassExpression" => Node::ClassExpression(
+                self.string(node, ty, "name")?,
+                self.optional(node, "extends")?,
+                self.members(node, ty)?,
+            ),
+            "ReturnStatement" => {
+                if self.function.is_none() {
+                    return Err(self.error("`return` is only allowed in functions"));
+                }
+                let argument = self.optional(node, "argument")?;
+                Node::ReturnStatement(argument.map(|argument| match *argument {
+                    Node::CallExpression(callee, arguments) => {
+                        Box::new(Node::TailCallExpression(callee, arguments))
+                    }
+                    argument => Box::new(argument),
+                }))
+            }
+            "ThrowStatement" => Node::ThrowStatement(self.child(node, "argument")?),
+            "BreakStatement" | "ContinueStatement" if self.loops == 0 => {
+                return Err(self.error(&format!("`{}` is only allowed in loops", ty)))
+            }
+            "BreakStatement" => Node::BreakStatement,
+            "ContinueStatement" => Node::ContinueStatement,
+            "TryStatement" => Node::TryStatement(
+                self.block(node, "block")?,
+                self.typed(node, ty, "binding", "a string or null")?,
+                self.optional_block(node, "handler")?,
+                self.optional_block(node, "finalizer")?,
+            ),
+            "MatchExpression" => {
+                let value = self.child(node, "value")?;
+                let mut arms = Vec::new();
+                for arm in self.array(node, ty, "arms")? {
+                    if self.type_of(&arm)? != "MatchArm" {
+                        return Err(self.error("`MatchExpression.arms` must be `MatchArm`s"));
+                    }
+                    let pattern = self.pattern(&self.field(&arm, "pattern")?)?;
+                    let bindings = pattern
+                        .bindings()
+                        .iter()
+                        .map(|name| (name.to_string(), false))
+                        .collect();
+                    arms.push(Node::MatchArm(
+                        Scope::block(bindings),
+                        pattern,
+                        self.optional(&arm, "guard")?,
+                        self.child(&arm, "body")?,
+                    ));
+                }
+                Node::MatchExpression(value, arms)
+            }
+            "ImportDeclaration"
+            | "ImportNamedDeclaration"
+            | "ImportDefaultDeclaration"
+            | "ImportStandardDeclaration"
+            | "ExportDeclaration"
+            | "LexicalInitialization"
+            | "LexicalDestructuring"
+            | "FunctionDeclaration"
+            | "ClassDeclaration"
+            | "EnumDeclaration" => {
+                return Err(self.error(&format!("`{}` is only allowed as a statement", ty)))
+            }
+            _ => return Err(self.error(&format!("unknown node type '{}'", ty))),
+        })
+    }
+
+    fn inside(&self, kind: FunctionKind) -> bool {
+        match self.function {
+            Some(f) => f as u8 & kind as u8 != 0,
+            None => false,
+        }
+    }
+
+    fn kind(&self, node: &Value, ty: &str) -> Result<FunctionKind, Value> {
+        match self.string(node, ty, "kind")?.as_str() {
+            "normal" => Ok(FunctionKind::Normal),
+            "async" => Ok(FunctionKind::Async),
+            "generator" => Ok(FunctionKind::Generator),
+            kind => Err(self.error(&format!("unknown function kind '{}'", kind))),
+        }
+    }
+
+    fn operator(&self, node: &Value, ty: &str) -> Result<Operator, Value> {
+        let symbol = self.string(node, ty, "operator")?;
+        match Operator::from_symbol(&symbol) {
+            Some(op) => Ok(op),
+            None => Err(self.error(&format!("unknown operator '{}'", symbol))),
+        }
+    }
+
+    // the parameters and body of a function, which starts a new context for
+    // returns, loops, `await` and `yield`
+    fn function(
+        &mut self,
+        kind: FunctionKind,
+        node: &Value,
+        ty: &str,
+    ) -> Result<(Vec<Node>, Box<Node>), Value> {
+        let function = self.function.replace(kind);
+        let loops = std::mem::replace(&mut self.loops, 0);
+        let parameters = self.parameters(node, ty);
+        let body = self.block(node, "body");
+        self.function = function;
+        self.loops = loops;
+        Ok((parameters?, body?))
+    }
+
+    // names, or names with a default
+    fn parameters(&mut self, node: &Value, ty: &str) -> Result<Vec<Node>, Value> {
+        let mut parameters = Vec::new();
+        for parameter in self.array(node, ty, "parameters")? {
+            let parameter = if self.type_of(&parameter)? == "Initializer" {
+                Node::Initializer(
+                    self.child(&parameter, "target")?,
+                    self.child(&parameter, "value")?,
+                )
+            } else {
+                self.node(&parameter)?
+            };
+            match &parameter {
+                Node::Identifier(..) => {}
+                Node::Initializer(target, _) if matches_identifier(target) => {}
+                _ => {
+                    return Err(
+                        self.error("parameters must be `Identifier`s or `Initializer`s of one")
+                    )
+                }
+            }
+            parameters.push(parameter);
+        }
+        Ok(parameters)
+    }
+
+    fn initializers(&mut self, node: &Value, ty: &str, name: &str) -> Result<Vec<Node>, Value> {
+        let mut initializers = Vec::new();
+        for initializer in self.array(node, ty, name)? {
+            if self.type_of(&initializer)? != "Initializer" {
+                return Err(self.error(&format!("`{}.{}` must be `Initializer`s", ty, name)));
+            }
+            initializers.push(Node::Initializer(
+                self.child(&initializer, "target")?,
+                self.child(&initializer, "value")?,
+            ));
+        }
+        Ok(initializers)
+    }
+
+    fn members(&mut self, node: &Value, ty: &str) -> Result<Vec<Node>, Value> {
+        self.initializers(node, ty, "members")
+    }
+
+    fn pattern(&mut self, pattern: &Value) -> Result<Pattern, Value> {
+        let ty = self.type_of(pattern)?;
+        let ty = ty.as_str();
+        let fields = |reader: &mut Self, name: &str| -> Result<Vec<(String, Pattern)>, Value> {
+            let mut fields = Vec::new();
+            for field in reader.array(pattern, ty, name)? {
+                fields.push((
+                    reader.string(&field, "field", "name")?,
+                    reader.pattern(&reader.field(&field, "pattern")?)?,
+                ));
+            }
+            Ok(fields)
+        };
+        let patterns = |reader: &mut Self, name: &str| -> Result<Vec<Pattern>, Value> {
+            reader
+                .array(pattern, ty, name)?
+                .iter()
+                .map(|p| reader.pattern(p))
+                .collect()
+        };
+        Ok(match ty {
+            "WildcardPattern" => Pattern::Wildcard,
+            "BindingPattern" => Pattern::Binding(self.string(pattern, ty, "name")?),
+            "ValuePattern" => Pattern::Value(self.child(pattern, "value")?),
+            "TuplePattern" => Pattern::Tuple(patterns(self, "elements")?),
+            "NamedTuplePattern" => Pattern::NamedTuple(fields(self, "fields")?),
+            "ArrayPattern" => Pattern::Array(patterns(self, "elements")?),
+            "ObjectPattern" => Pattern::Object(fields(self, "properties")?),
+            "OrPattern" => {
+                let alternatives = patterns(self, "alternatives")?;
+                if alternatives.is_empty() {
+                    return Err(self.error("`OrPattern` needs at least one alternative"));
+                }
+                Pattern::Or(alternatives)
+            }
+            _ => return Err(self.error(&format!("unknown pattern type '{}'", ty))),
+        })
+    }
+}
+
+fn matches_identifier(node: &Node) -> bool {
+    match node {
+        Node::Identifier(..) => true,
+        _ => false,
+    }
+}
+
+fn is_comparison(op: Operator) -> bool {
+    match op {
+        Operator::Equal
+        | Operator::NotEqual
+        | Operator::GreaterThanOrEqual
+        | Operator::LessThanOrEqual => true,
+        _ => false,
+    }
+}
+
+// the names a statement declares in its block
+fn declared(stmt: &Node) -> Vec<&str> {
+    match stmt {
+        Node::LexicalInitialization(name, _)
+        | Node::FunctionDeclaration(_, name, ..)
+        | Node::ClassDeclaration(name, ..)
+        | Node::EnumDeclaration(name, _) => vec![name],
+        Node::LexicalDestructuring(pattern, _) => pattern.bindings(),
+        Node::ExportDeclaration(declaration) => declared(declaration),
+        _ => Vec::new(),
+    }
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "parse".to_string(),
+        Value::new_builtin_function(agent, parse),
+    );
+    module.insert(
+        "compile".to_string(),
+        Value::new_builtin_function(agent, compile),
+    );
+    module.insert("run".to_string(), Value::new_builtin_function(agent, run));
+    module.insert(
+        "tokenize".to_string(),
+        Value::new_builtin_function(agent, tokenize),
+    );
+
+    module
+}
@@ -0,0 +1,238 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::num_util;
+use crate::output::Stream;
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_RESET: &str = "\x1b[39m";
+
+// strings as they are, everything else inspected
+fn display(agent: &Agent, value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        _ => Value::inspect(agent, value),
+    }
+}
+
+fn placeholder(agent: &Agent, spec: char, arg: &Value) -> String {
+    match (spec, arg) {
+        ('s', _) => display(agent, arg),
+        ('d', Value::Number(n)) | ('i', Value::Number(n)) => num_util::to_string(n.trunc()),
+        ('f', Value::Number(n)) => num_util::to_string(*n),
+        ('d', _) | ('i', _) | ('f', _) => num_util::to_string(f64::NAN),
+        _ => Value::inspect(agent, arg),
+    }
+}
+
+// a string first argument may hold placeholders, each taking the next
+// argument: %s, %d and %i for integers, %f, and %o and %O to inspect. %% is
+// a %. arguments left over are appended, separated by spaces.
+fn format(agent: &Agent, args: &[Value]) -> String {
+    let mut rest = args.iter();
+    let mut parts = Vec::new();
+    if let Some(Value::String(template)) = args.get(0) {
+        rest.next();
+        let mut out = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let spec = match chars.peek() {
+                Some(&spec) if "sdifoO%".contains(spec) => spec,
+                _ => {
+                    out.push('%');
+                    continue;
+                }
+            };
+            chars.next();
+            if spec == '%' {
+                out.push('%');
+                continue;
+            }
+            match rest.next() {
+                Some(arg) => out += &placeholder(agent, spec, arg),
+                // with too few arguments the placeholder is left as it is
+                None => {
+                    out.push('%');
+                    out.push(spec);
+                }
+            }
+        }
+        parts.push(out);
+    }
+    parts.extend(rest.map(|v| display(agent, v)));
+    parts.join(" ")
+}
+
+// indents by the open groups and colors the text if it goes to a terminal
+fn write(agent: &Agent, ctx: &Context, stream: Stream, color: Option<&str>, text: &str) {
+    let state = ctx.function.clone().unwrap().get_slot("console");
+    let depth = match state.get_slot("group depth") {
+        Value::Number(n) => n as usize,
+        _ => 0,
+    };
+    let indent = "  ".repeat(depth);
+    let mut out = String::new();
+    for line in text.split('\n') {
+        out += &indent;
+        out += line;
+        out += "\n";
+    }
+    match color {
+        Some(color) if agent.output_is_terminal(stream) => {
+            agent.write_output(stream, &format!("{}{}{}", color, out, ANSI_RESET));
+        }
+        _ => agent.write_output(stream, &out),
+    }
+}
+
+fn log(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    write(agent, ctx, Stream::Stdout, None, &format(agent, &args));
+    Ok(Value::Null)
+}
+
+fn warn(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let text = format(agent, &args);
+    write(agent, ctx, Stream::Stderr, Some(ANSI_YELLOW), &text);
+    Ok(Value::Null)
+}
+
+fn error(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let text = format(agent, &args);
+    write(agent, ctx, Stream::Stderr, Some(ANSI_RED), &text);
+    Ok(Value::Null)
+}
+
+// logs the label, if any, and indents what is logged until groupEnd
+fn group(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    if !args.is_empty() {
+        write(agent, ctx, Stream::Stdout, None, &format(agent, &args));
+    }
+    let state = ctx.function.clone().unwrap().get_slot("console");
+    let depth = match state.get_slot("group depth") {
+        Value::Number(n) => n,
+        _ => 0.0,
+    };
+    state.set_slot("group depth", Value::from(depth + 1.0));
+    Ok(Value::Null)
+}
+
+fn group_end(_: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.clone().unwrap().get_slot("console");
+    if let Value::Number(n) = state.get_slot("group depth") {
+        if n > 0.0 {
+            state.set_slot("group depth", Value::from(n - 1.0));
+        }
+    }
+    Ok(Value::Null)
+}
+
+fn is_table_row(value: &Value) -> bool {
+    match value {
+        Value::Tuple(..) | Value::Record(..) => true,
+        Value::Object(..) => value.type_of() != "function",
+        _ => false,
+    }
+}
+
+// each entry of an array or object is a row. entries that are objects have
+// a column for each of their keys, anything else goes in a Values column.
+fn table(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let data = match args.get(0) {
+        Some(data) if is_table_row(data) => data,
+        _ => return log(agent, args, ctx),
+    };
+
+    let mut columns: Vec<ObjectKey> = Vec::new();
+    let mut has_values = false;
+    let mut rows = Vec::new();
+    for key in data.keys(agent)? {
+        let row = data.get(agent, key.clone())?;
+        let mut cells = HashMap::new();
+        if is_table_row(&row) {
+            for column in row.keys(agent)? {
+                let cell = Value::inspect(agent, &row.get(agent, column.clone())?);
+                if !columns.contains(&column) {
+                    columns.push(column.clone());
+                }
+                cells.insert(column.to_string(), cell);
+            }
+        } else {
+            has_values = true;
+            cells.insert("Values".to_string(), Value::inspect(agent, &row));
+        }
+        rows.push((key.to_string(), cells));
+    }
+
+    let mut header = vec!["(index)".to_string()];
+    header.extend(columns.iter().map(ObjectKey::to_string));
+    if has_values {
+        header.push("Values".to_string());
+    }
+    let mut lines = vec![header.clone()];
+    for (index, mut cells) in rows {
+        let mut line = vec![index];
+        for name in &header[1..] {
+            line.push(cells.remove(name).unwrap_or_default());
+        }
+        lines.push(line);
+    }
+    let widths = (0..header.len())
+        .map(|i| {
+            lines
+                .iter()
+                .map(|line| line[i].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let rule = |left: &str, middle: &str, right: &str| {
+        let dashes = widths.iter().map(|w| "─".repeat(w + 2)).collect::<Vec<_>>();
+        format!("{}{}{}", left, dashes.join(middle), right)
+    };
+    let row = |line: &[String]| {
+        let cells = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, w)| format!(" {}{} ", cell, " ".repeat(w - cell.chars().count())))
+            .collect::<Vec<_>>();
+        format!("│{}│", cells.join("│"))
+    };
+    let mut out = vec![rule("┌", "┬", "┐"), row(&lines[0]), rule("├", "┼", "┤")];
+    for line in &lines[1..] {
+        out.push(row(line));
+    }
+    out.push(rule("└", "┴", "┘"));
+    write(agent, ctx, Stream::Stdout, None, &out.join("\n"));
+    Ok(Value::Null)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    // open groups, shared by every function of the module
+    let state = Value::new_custom_object(Value::Null);
+    state.set_slot("group depth", Value::from(0.0));
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            let f = Value::new_builtin_function(agent, $fn);
+            f.set_slot("console", state.clone());
+            module.insert($name.to_string(), f);
+        };
+    }
+    method!("log", log);
+    method!("warn", warn);
+    method!("error", error);
+    method!("table", table);
+    method!("group", group);
+    method!("groupEnd", group_end);
+
+    module
+}
@@ -1,6 +1,9 @@
 use crate::agent::Agent;
-use crate::interpreter::Context;
-use crate::value::Value;
+use crate::heap_snapshot;
+use crate::interpreter::{self, Context};
+use crate::output::Stream;
+use crate::value::{self, ObjectKey, ObjectKind, Value};
+use crate::IntoValue;
 use std::collections::HashMap;
 
 fn print(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
@@ -8,16 +11,210 @@ fn print(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     for v in args {
         inspected.push(Value::inspect(agent, &v));
     }
-    println!("{}", inspected.join(" "));
+    agent.write_output(Stream::Stdout, &format!("{}\n", inspected.join(" ")));
     Ok(Value::Null)
 }
 
+// a non-negative integer option, null for no limit
+fn limit(agent: &Agent, options: &Value, name: &str) -> Result<Option<usize>, Value> {
+    match options.get(agent, ObjectKey::from(name))? {
+        Value::Null => Ok(None),
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n as usize)),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a non-negative integer", name),
+        )),
+    }
+}
+
+// like print, but returns the string, shaped by depth, maxArrayItems,
+// maxStringLength and colors options
+fn inspect(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).unwrap_or(&Value::Null);
+    let options = match args.get(1) {
+        Some(o @ Value::Object(..)) => value::InspectOptions {
+            depth: limit(agent, o, "depth")?,
+            max_array_items: limit(agent, o, "maxArrayItems")?,
+            max_string_length: limit(agent, o, "maxStringLength")?,
+            colors: o.get(agent, ObjectKey::from("colors"))? == Value::from(true),
+        },
+        Some(Value::Null) | None => value::InspectOptions::default(),
+        Some(_) => return Err(Value::new_error(agent, "options must be an object")),
+    };
+    Ok(Value::from(Value::inspect_with(agent, value, &options)))
+}
+
+fn trace(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let mut inspected = Vec::with_capacity(args.len());
+    for v in args {
+        inspected.push(Value::inspect(agent, &v));
+    }
+    let label = if inspected.is_empty() {
+        "Trace".to_string()
+    } else {
+        format!("Trace: {}", inspected.join(" "))
+    };
+    agent.write_output(
+        Stream::Stdout,
+        &format!("{}\n{}\n", label, agent.stack_trace()),
+    );
+    Ok(Value::Null)
+}
+
+fn disassemble(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(Value::Object(o)) = args.get(0) {
+        if let ObjectKind::BytecodeFunction { position, .. } = o.kind {
+            let assembler = agent.assembler.borrow();
+            let info = assembler
+                .function_info
+                .iter()
+                .find(|f| f.position == position)
+                .unwrap();
+            let listing = interpreter::disassemble(&assembler, info.position, info.end, false);
+            return Ok(Value::from(listing));
+        }
+    }
+    Err(Value::new_error(
+        agent,
+        "argument must be a bytecode function",
+    ))
+}
+
+fn memory_usage(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let usage = value::memory_usage();
+    let stats = agent.gc_stats();
+    let ms = |d: std::time::Duration| Value::from(d.as_secs_f64() * 1000.0);
+
+    let gc = Value::new_object(agent.intrinsics.object_prototype.clone());
+    gc.set(
+        agent,
+        ObjectKey::from("collections"),
+        stats.collections.into_value(agent),
+    )?;
+    gc.set(agent, ObjectKey::from("lastPause"), ms(stats.last_pause))?;
+    gc.set(
+        agent,
+        ObjectKey::from("longestPause"),
+        ms(stats.longest_pause),
+    )?;
+    gc.set(agent, ObjectKey::from("totalPause"), ms(stats.total_pause))?;
+
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    o.set(
+        agent,
+        ObjectKey::from("heapBytes"),
+        stats.bytes_allocated.into_value(agent),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("liveObjects"),
+        stats.boxes.into_value(agent),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("objects"),
+        usage.objects.into_value(agent),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("arrays"),
+        usage.arrays.into_value(agent),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("buffers"),
+        usage.buffers.into_value(agent),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("strings"),
+        usage.strings.into_value(agent),
+    )?;
+    o.set(agent, ObjectKey::from("gc"), gc)?;
+    Ok(o)
+}
+
+// the io and timers keeping the event loop alive, oldest first
+fn pending_ops(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let operations = agent.operations.borrow();
+    let mut operations = operations.values().collect::<Vec<_>>();
+    operations.sort_by_key(|o| o.async_id.id);
+    let mut ops = Vec::with_capacity(operations.len());
+    for operation in operations {
+        let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+        o.set(agent, ObjectKey::from("type"), Value::from(operation.kind))?;
+        o.set(
+            agent,
+            ObjectKey::from("asyncId"),
+            Value::from(operation.async_id.id as f64),
+        )?;
+        o.set(
+            agent,
+            ObjectKey::from("stack"),
+            Value::from(operation.stack.clone()),
+        )?;
+        let age = agent.now() - operation.started;
+        o.set(agent, ObjectKey::from("age"), Value::from(age))?;
+        ops.push(o);
+    }
+    Ok(ops.into_value(agent))
+}
+
+fn collect(_: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    gc::force_collect();
+    Ok(Value::Null)
+}
+
+fn write_heap_snapshot(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_write(agent, filename)?;
+        match std::fs::write(&**filename, heap_snapshot::take()) {
+            Ok(()) => Ok(Value::Null),
+            Err(e) => Err(Value::new_error_with_cause(
+                agent,
+                &format!("unable to write heap snapshot '{}'", filename),
+                e.into_value(agent),
+            )),
+        }
+    } else {
+        Err(Value::new_error(agent, "filename must be a string"))
+    }
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
     module.insert(
         "print".to_string(),
         Value::new_builtin_function(agent, print),
     );
+    module.insert(
+        "inspect".to_string(),
+        Value::new_builtin_function(agent, inspect),
+    );
+    module.insert(
+        "trace".to_string(),
+        Value::new_builtin_function(agent, trace),
+    );
+    module.insert(
+        "disassemble".to_string(),
+        Value::new_builtin_function(agent, disassemble),
+    );
+    module.insert(
+        "memoryUsage".to_string(),
+        Value::new_builtin_function(agent, memory_usage),
+    );
+    module.insert(
+        "pendingOps".to_string(),
+        Value::new_builtin_function(agent, pending_ops),
+    );
+    module.insert(
+        "gc".to_string(),
+        Value::new_builtin_function(agent, collect),
+    );
+    module.insert(
+        "writeHeapSnapshot".to_string(),
+        Value::new_builtin_function(agent, write_heap_snapshot),
+    );
 
     module
 }
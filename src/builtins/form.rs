@@ -0,0 +1,330 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::num_util;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use crate::IntoValue;
+use std::collections::HashMap;
+
+// the fields of a form by name. a field whose value is an array is given
+// once for each of its items.
+pub(super) fn fields(agent: &Agent, value: &Value) -> Result<Vec<(String, Value)>, Value> {
+    let mut fields = Vec::new();
+    for key in value.keys(agent)? {
+        let field = value.get(agent, key.clone())?;
+        let items = match &field {
+            Value::Object(o) => match &o.kind {
+                ObjectKind::Array(items) => items.borrow().clone(),
+                _ => vec![field.clone()],
+            },
+            _ => vec![field.clone()],
+        };
+        for item in items {
+            fields.push((key.to_string(), item));
+        }
+    }
+    Ok(fields)
+}
+
+fn text(agent: &Agent, value: &Value) -> Result<String, Value> {
+    match value {
+        Value::String(s) => Ok(s.to_string()),
+        Value::Number(n) => Ok(num_util::to_string(*n)),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(Value::new_error(
+            agent,
+            "form values must be strings, numbers or booleans",
+        )),
+    }
+}
+
+fn bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(s) => Some(s.as_bytes().to_vec()),
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(b) => Some(b.borrow().clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// letters, digits and `*-._` are kept, spaces become `+` and everything else
+// is percent encoded
+fn encode_component(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push('+'),
+            _ => out += &format!("%{:02X}", b),
+        }
+    }
+    out
+}
+
+// escapes that aren't two hex digits are left as they are
+pub(super) fn percent_decode(s: &str) -> String {
+    let s = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'%' && i + 2 < s.len() {
+            let hex = std::str::from_utf8(&s[i + 1..i + 3])
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(b) = hex {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(s[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// a `+` is a space, an encoded one is `%2B`
+fn decode_component(s: &str) -> String {
+    percent_decode(&s.replace('+', " "))
+}
+
+fn encode_url(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).cloned().unwrap_or(Value::Null);
+    let mut pairs = Vec::new();
+    for (name, value) in fields(agent, &value)? {
+        pairs.push(format!(
+            "{}={}",
+            encode_component(&name),
+            encode_component(&text(agent, &value)?)
+        ));
+    }
+    Ok(Value::from(pairs.join("&")))
+}
+
+// a name given more than once gets an array of its values
+fn decode_url(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let source = match args.get(0) {
+        Some(Value::String(s)) => s.clone(),
+        _ => return Err(Value::new_error(agent, "form must be a string")),
+    };
+    let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+    for pair in source.split('&').filter(|p| !p.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        let name = decode_component(parts.next().unwrap());
+        let value = decode_component(parts.next().unwrap_or(""));
+        match fields.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value),
+            None => fields.push((name, vec![value])),
+        }
+    }
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, mut values) in fields {
+        let value = if values.len() == 1 {
+            Value::from(values.remove(0))
+        } else {
+            values.into_value(agent)
+        };
+        o.set(agent, ObjectKey::from(name.as_str()), value)?;
+    }
+    Ok(o)
+}
+
+fn quote(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+// a field is a string, number or boolean, or a file given as an object with
+// `data` (a string or buffer) and optionally `filename` and `contentType`
+fn encode_multipart(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let value = args.get(0).cloned().unwrap_or(Value::Null);
+    let mut parts = Vec::new();
+    for (name, value) in fields(agent, &value)? {
+        let mut head = format!("Content-Disposition: form-data; name=\"{}\"", quote(&name));
+        let data = match &value {
+            Value::Object(_) => {
+                let data = bytes(&value.get(agent, ObjectKey::from("data"))?).ok_or_else(|| {
+                    Value::new_error(agent, "file data must be a string or buffer")
+                })?;
+                if let Value::String(filename) = value.get(agent, ObjectKey::from("filename"))? {
+                    head += &format!("; filename=\"{}\"", quote(&filename));
+                }
+                let content_type = match value.get(agent, ObjectKey::from("contentType"))? {
+                    Value::String(t) => t.to_string(),
+                    _ => "application/octet-stream".to_string(),
+                };
+                head += &format!("\r\nContent-Type: {}", content_type);
+                data
+            }
+            _ => text(agent, &value)?.into_bytes(),
+        };
+        parts.push((head, data));
+    }
+
+    // anything not found in the parts will do
+    let mut boundary = "slither-form-boundary".to_string();
+    while parts
+        .iter()
+        .any(|(_, data)| find(data, boundary.as_bytes(), 0).is_some())
+    {
+        boundary += &format!("{:x}", boundary.len());
+    }
+
+    let mut body = Vec::new();
+    for (head, data) in parts {
+        body.extend(format!("--{}\r\n{}\r\n\r\n", boundary, head).into_bytes());
+        body.extend(data);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{}--\r\n", boundary).into_bytes());
+
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    o.set(
+        agent,
+        ObjectKey::from("contentType"),
+        Value::from(format!("multipart/form-data; boundary={}", boundary)),
+    )?;
+    o.set(
+        agent,
+        ObjectKey::from("body"),
+        Value::new_buffer_from_vec(agent, body),
+    )?;
+    Ok(o)
+}
+
+// the value of a `key=value` parameter in a header, quoted or not
+fn parameter(header: &str, key: &str) -> Option<String> {
+    for param in header.split(';').skip(1) {
+        let mut parts = param.trim().splitn(2, '=');
+        if parts.next().map(|k| k.trim().eq_ignore_ascii_case(key)) == Some(true) {
+            let value = parts.next()?.trim();
+            return Some(value.trim_matches('"').to_string());
+        }
+    }
+    None
+}
+
+struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    data: Vec<u8>,
+}
+
+fn parse_multipart(body: &[u8], boundary: &str) -> Option<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut start = find(body, &delimiter, 0)? + delimiter.len();
+    loop {
+        if body[start..].starts_with(b"--") {
+            return Some(parts);
+        }
+        if !body[start..].starts_with(b"\r\n") {
+            return None;
+        }
+        start += 2;
+        let head_end = find(body, b"\r\n\r\n", start)?;
+        let head = String::from_utf8_lossy(&body[start..head_end]);
+        let mut end_delimiter = b"\r\n".to_vec();
+        end_delimiter.extend(&delimiter);
+        let end = find(body, &end_delimiter, head_end + 4)?;
+
+        let mut name = None;
+        let mut filename = None;
+        let mut content_type = None;
+        for line in head.split("\r\n") {
+            let mut header = line.splitn(2, ':');
+            let key = header.next().unwrap().trim();
+            let value = header.next().unwrap_or("").trim();
+            if key.eq_ignore_ascii_case("content-disposition") {
+                name = parameter(value, "name");
+                filename = parameter(value, "filename");
+            } else if key.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.to_string());
+            }
+        }
+        parts.push(Part {
+            name: name?,
+            filename,
+            content_type,
+            data: body[head_end + 4..end].to_vec(),
+        });
+        start = end + end_delimiter.len();
+    }
+}
+
+// each part is an object with `name`, `filename` and `contentType`, null if
+// not given, and `data`, a buffer for files and a string otherwise
+fn decode_multipart(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let body = args
+        .get(0)
+        .and_then(bytes)
+        .ok_or_else(|| Value::new_error(agent, "body must be a string or buffer"))?;
+    let boundary = match args.get(1) {
+        Some(Value::String(t)) => parameter(t, "boundary")
+            .ok_or_else(|| Value::new_error(agent, "content type has no boundary"))?,
+        _ => return Err(Value::new_error(agent, "content type must be a string")),
+    };
+    let parts = parse_multipart(&body, &boundary)
+        .ok_or_else(|| Value::new_error(agent, "malformed multipart body"))?;
+
+    let mut values = Vec::new();
+    for part in parts {
+        let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+        o.set(agent, ObjectKey::from("name"), Value::from(part.name))?;
+        let data = if part.filename.is_some() {
+            Value::new_buffer_from_vec(agent, part.data)
+        } else {
+            Value::from(String::from_utf8_lossy(&part.data).into_owned())
+        };
+        o.set(
+            agent,
+            ObjectKey::from("filename"),
+            part.filename.into_value(agent),
+        )?;
+        o.set(
+            agent,
+            ObjectKey::from("contentType"),
+            part.content_type.into_value(agent),
+        )?;
+        o.set(agent, ObjectKey::from("data"), data)?;
+        values.push(o);
+    }
+    Ok(values.into_value(agent))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "encodeUrl".to_string(),
+        Value::new_builtin_function(agent, encode_url),
+    );
+    module.insert(
+        "decodeUrl".to_string(),
+        Value::new_builtin_function(agent, decode_url),
+    );
+    module.insert(
+        "encodeMultipart".to_string(),
+        Value::new_builtin_function(agent, encode_multipart),
+    );
+    module.insert(
+        "decodeMultipart".to_string(),
+        Value::new_builtin_function(agent, decode_multipart),
+    );
+
+    module
+}
@@ -1,27 +1,92 @@
 use crate::agent::{Agent, MioMapType};
+use crate::event_loop::Registration;
 use crate::interpreter::Context;
 use crate::intrinsics::promise::new_promise_capability;
 use crate::value::{ObjectKey, Value};
-use lazy_static::lazy_static;
-use mio::{PollOpt, Ready, Registration, Token};
+use crate::IntoValue;
+#[cfg(not(target_arch = "wasm32"))]
+use mio::{PollOpt, Ready};
 use std::collections::HashMap;
-use std::sync::Mutex;
-
-lazy_static! {
-    static ref RESPONSES: Mutex<HashMap<Token, FsResponse>> = Mutex::new(HashMap::new());
-}
+use std::path::Path;
+use std::sync::mpsc::channel;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::sync_channel;
 
 pub enum FsResponse {
     Read(String),
-    Metadata(std::fs::Metadata),
+    Metadata(Stat),
     Exists(bool),
     Success,
-    Error(String),
+    // a description of the operation and the io error it failed with
+    Error(String, std::io::Error),
+    // what is in a directory being copied, before it is filtered
+    Listed(Vec<Entry>),
+    // sent after each file copied, the operation carries on
+    Progress(Progress),
+}
+
+// something found in a directory, by its path from the directory with `/`
+// between names
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: String,
+    pub kind: &'static str,
+    pub size: u64,
+}
+
+// how far along copyDirectory is, and the last file it copied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub path: String,
+    pub files: u64,
+    pub total_files: u64,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+// what getMetadata reports, times in milliseconds since the epoch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stat {
+    pub kind: &'static str,
+    pub size: u64,
+    pub modified: u64,
+    pub accessed: u64,
+    pub created: u64,
+    pub readonly: bool,
+}
+
+impl Stat {
+    fn new(m: &std::fs::Metadata) -> Stat {
+        let ft = m.file_type();
+        let kind = if ft.is_file() {
+            "file"
+        } else if ft.is_dir() {
+            "directory"
+        } else if ft.is_symlink() {
+            "symlink"
+        } else {
+            unreachable!();
+        };
+        let ms = |t: std::io::Result<std::time::SystemTime>| {
+            let d = t
+                .unwrap()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap();
+            d.as_secs() * 1000 + u64::from(d.subsec_millis())
+        };
+        Stat {
+            kind,
+            size: m.len(),
+            modified: ms(m.modified()),
+            accessed: ms(m.accessed()),
+            created: ms(m.created()),
+            readonly: m.permissions().readonly(),
+        }
+    }
 }
 
-pub fn handle(agent: &Agent, token: Token, promise: Value) {
-    let fsr = RESPONSES.lock().unwrap().remove(&token).unwrap();
-    match fsr {
+pub fn handle(agent: &Agent, promise: Value, response: FsResponse, stack: String) {
+    match response {
         FsResponse::Read(s) => {
             promise
                 .get_slot("resolve")
@@ -35,39 +100,14 @@ pub fn handle(agent: &Agent, token: Token, promise: Value) {
                     $target.set(agent, ObjectKey::from($name), $value).unwrap();
                 };
             }
-            let ft = m.file_type();
-            if ft.is_file() {
-                p!(o, "type", Value::from("file"));
-            } else if ft.is_dir() {
-                p!(o, "type", Value::from("directory"));
-            } else if ft.is_symlink() {
-                p!(o, "type", Value::from("symlink"));
-            } else {
-                unreachable!();
-            }
-            p!(o, "size", Value::from(m.len() as f64));
-            macro_rules! t {
-                ($name:expr, $value:expr) => {
-                    let d = $value
-                        .unwrap()
-                        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                        .unwrap();
-                    let seconds = d.as_secs();
-                    let subsec_millis = u64::from(d.subsec_millis());
-                    let ms = seconds * 1000 + subsec_millis;
-                    p!(o, $name, Value::from(ms as f64));
-                };
-            }
-            t!("modifiedAt", m.modified());
-            t!("accessedAt", m.accessed());
-            t!("createdAt", m.created());
+            p!(o, "type", Value::from(m.kind));
+            p!(o, "size", Value::from(m.size as f64));
+            p!(o, "modifiedAt", Value::from(m.modified as f64));
+            p!(o, "accessedAt", Value::from(m.accessed as f64));
+            p!(o, "createdAt", Value::from(m.created as f64));
 
             let permissions = Value::new_object(agent.intrinsics.object_prototype.clone());
-            p!(
-                permissions,
-                "read",
-                Value::from(!m.permissions().readonly())
-            );
+            p!(permissions, "read", Value::from(!m.readonly));
             p!(o, "permissions", permissions);
 
             promise
@@ -87,47 +127,161 @@ pub fn handle(agent: &Agent, token: Token, promise: Value) {
                 .call(agent, promise, vec![])
                 .unwrap();
         }
-        FsResponse::Error(s) => {
+        FsResponse::Error(message, e) => {
+            let e = Value::new_error_with_cause(agent, &message, e.into_value(agent));
+            e.set(agent, ObjectKey::from("stack"), Value::from(stack))
+                .unwrap();
             promise
                 .get_slot("reject")
-                .call(agent, promise, vec![Value::new_error(agent, s.as_str())])
+                .call(agent, promise, vec![e])
                 .unwrap();
         }
+        FsResponse::Listed(entries) => copy_entries(agent, promise, entries),
+        FsResponse::Progress(p) => {
+            let callback = promise.get_slot("copy progress");
+            if callback == Value::Null {
+                return;
+            }
+            let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+            macro_rules! p {
+                ($name:expr, $value:expr) => {
+                    o.set(agent, ObjectKey::from($name), $value).unwrap();
+                };
+            }
+            p!("path", Value::from(p.path));
+            p!("copiedFiles", Value::from(p.files as f64));
+            p!("totalFiles", Value::from(p.total_files as f64));
+            p!("copiedBytes", Value::from(p.bytes as f64));
+            p!("totalBytes", Value::from(p.total_bytes as f64));
+            if let Err(e) = callback.call(agent, Value::Null, vec![o]) {
+                agent.uncaught_exception(e);
+            }
+        }
+    }
+}
+
+// registers the operation with the event loop and runs f on the pool. its
+// result comes back on a channel of the operation's own, kept in the mio map
+// entry, which the event loop reads once the registration is ready. when
+// replaying the work isn't done, what it came to is read from the log.
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn<F>(agent: &Agent, promise: &Value, f: F)
+where
+    F: FnOnce() -> FsResponse + Send + 'static,
+{
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.new_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    let (sender, receiver) = channel();
+    agent.add_operation(
+        token,
+        MioMapType::FS(registration, promise.clone(), receiver),
+    );
+
+    if !agent.replaying() {
+        agent.pool.execute(move || {
+            // fails only if the agent is gone, then nobody is waiting for it
+            if sender.send(f()).is_ok() {
+                set_readiness.set_readiness(Ready::readable()).unwrap();
+            }
+        });
+    }
+}
+
+// wasi has no threads, so the work is done right away and the event loop
+// settles the promise on its next turn
+#[cfg(target_os = "wasi")]
+fn spawn<F>(agent: &Agent, promise: &Value, f: F)
+where
+    F: FnOnce() -> FsResponse + Send + 'static,
+{
+    let token = agent.new_token();
+    let (sender, receiver) = channel();
+    agent.add_operation(
+        token,
+        MioMapType::FS(Registration, promise.clone(), receiver),
+    );
+
+    if !agent.replaying() {
+        sender.send(f()).unwrap();
+        agent.ready.borrow_mut().push(token);
+    }
+}
+
+// like spawn, but f can report progress as it goes. the channel has room for
+// one message, so each one waits for the event loop to take the last and
+// the readiness set after it always gets an event of its own.
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_with_progress<F>(agent: &Agent, promise: &Value, f: F)
+where
+    F: FnOnce(&dyn Fn(Progress)) -> FsResponse + Send + 'static,
+{
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.new_token();
+
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    let (sender, receiver) = sync_channel(1);
+    agent.add_operation(
+        token,
+        MioMapType::FS(registration, promise.clone(), receiver),
+    );
+
+    if !agent.replaying() {
+        agent.pool.execute(move || {
+            let send = |response| {
+                if sender.send(response).is_ok() {
+                    set_readiness.set_readiness(Ready::readable()).unwrap();
+                }
+            };
+            let response = f(&|p| send(FsResponse::Progress(p)));
+            send(response);
+        });
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn spawn_with_progress<F>(agent: &Agent, promise: &Value, f: F)
+where
+    F: FnOnce(&dyn Fn(Progress)) -> FsResponse + Send + 'static,
+{
+    let token = agent.new_token();
+    let (sender, receiver) = channel();
+    agent.add_operation(
+        token,
+        MioMapType::FS(Registration, promise.clone(), receiver),
+    );
+
+    if !agent.replaying() {
+        let send = |response| {
+            sender.send(response).unwrap();
+            agent.ready.borrow_mut().push(token);
+        };
+        let response = f(&|p| send(FsResponse::Progress(p)));
+        send(response);
     }
 }
 
 fn read_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_read(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::read_to_string(filename) {
-                Ok(s) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Read(s));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-            });
+
+        let message = format!("unable to read file '{}'", filename);
+        spawn(agent, &promise, move || {
+            match std::fs::read_to_string(filename) {
+                Ok(s) => FsResponse::Read(s),
+                Err(e) => FsResponse::Error(message, e),
+            }
+        });
 
         Ok(promise)
     } else {
@@ -137,38 +291,20 @@ fn read_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Valu
 
 fn write_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_write(agent, filename)?;
         if let Some(Value::String(contents)) = args.get(1) {
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-            let (registration, set_readiness) = Registration::new2();
-            let token = Token(agent.mio_map.borrow().len());
-
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
-
             let filename = filename.to_string();
+
+            let message = format!("unable to write file '{}'", filename);
             let contents = contents.to_string();
-            agent
-                .pool
-                .execute(move || match std::fs::write(filename, contents) {
-                    Ok(()) => {
-                        RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                        set_readiness.set_readiness(Ready::readable()).unwrap();
-                    }
-                    Err(e) => {
-                        RESPONSES
-                            .lock()
-                            .unwrap()
-                            .insert(token, FsResponse::Error(format!("{}", e)));
-                        set_readiness.set_readiness(Ready::readable()).unwrap();
-                    }
-                });
+            spawn(agent, &promise, move || {
+                match std::fs::write(filename, contents) {
+                    Ok(()) => FsResponse::Success,
+                    Err(e) => FsResponse::Error(message, e),
+                }
+            });
 
             Ok(promise)
         } else {
@@ -181,36 +317,18 @@ fn write_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Val
 
 fn remove_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_write(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::remove_file(filename) {
-                Ok(()) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-            });
+
+        let message = format!("unable to remove file '{}'", filename);
+        spawn(agent, &promise, move || {
+            match std::fs::remove_file(filename) {
+                Ok(()) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
+            }
+        });
 
         Ok(promise)
     } else {
@@ -220,39 +338,16 @@ fn remove_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Va
 
 fn get_metadata(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_read(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::metadata(filename) {
-                Ok(metadata) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Metadata(metadata));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-            });
+
+        let message = format!("unable to get metadata of '{}'", filename);
+        spawn(agent, &promise, move || match std::fs::metadata(filename) {
+            Ok(metadata) => FsResponse::Metadata(Stat::new(&metadata)),
+            Err(e) => FsResponse::Error(message, e),
+        });
 
         Ok(promise)
     } else {
@@ -263,34 +358,16 @@ fn get_metadata(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, V
 fn copy(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
+            agent.permissions.check_read(agent, from)?;
+            agent.permissions.check_write(agent, to)?;
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-            let (registration, set_readiness) = Registration::new2();
-            let token = Token(agent.mio_map.borrow().len());
-
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
-
             let from = from.to_string();
             let to = to.to_string();
-            agent.pool.execute(move || match std::fs::copy(from, to) {
-                Ok(_) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
+            let message = format!("unable to copy '{}' to '{}'", from, to);
+            spawn(agent, &promise, move || match std::fs::copy(from, to) {
+                Ok(_) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
             });
 
             Ok(promise)
@@ -305,34 +382,16 @@ fn copy(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
 fn move_(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
+            agent.permissions.check_write(agent, from)?;
+            agent.permissions.check_write(agent, to)?;
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-            let (registration, set_readiness) = Registration::new2();
-            let token = Token(agent.mio_map.borrow().len());
-
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
-
             let from = from.to_string();
             let to = to.to_string();
-            agent.pool.execute(move || match std::fs::rename(from, to) {
-                Ok(_) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
+            let message = format!("unable to move '{}' to '{}'", from, to);
+            spawn(agent, &promise, move || match std::fs::rename(from, to) {
+                Ok(_) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
             });
 
             Ok(promise)
@@ -353,42 +412,28 @@ fn symlink(from: String, to: String) -> std::io::Result<()> {
     }
 }
 
-#[cfg(not(windows))]
+#[cfg(unix)]
 fn symlink(from: String, to: String) -> std::io::Result<()> {
     std::os::unix::fs::symlink(from, to)
 }
 
+#[cfg(target_os = "wasi")]
+fn symlink(from: String, to: String) -> std::io::Result<()> {
+    std::os::wasi::fs::symlink_path(from, to)
+}
+
 fn create_symlink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(from)) = args.get(0) {
         if let Some(Value::String(to)) = args.get(1) {
+            agent.permissions.check_write(agent, to)?;
             let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-            let (registration, set_readiness) = Registration::new2();
-            let token = Token(agent.mio_map.borrow().len());
-
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::FS(registration, promise.clone()));
-
             let from = from.to_string();
             let to = to.to_string();
-            agent.pool.execute(move || match symlink(from, to) {
-                Ok(()) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
+            let message = format!("unable to link '{}' to '{}'", to, from);
+            spawn(agent, &promise, move || match symlink(from, to) {
+                Ok(()) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
             });
 
             Ok(promise)
@@ -402,28 +447,12 @@ fn create_symlink(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value,
 
 fn exists(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_read(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent.pool.execute(move || {
-            let exists = std::path::Path::new(filename.as_str()).exists();
-            RESPONSES
-                .lock()
-                .unwrap()
-                .insert(token, FsResponse::Exists(exists));
-            set_readiness.set_readiness(Ready::readable()).unwrap();
+        spawn(agent, &promise, move || {
+            FsResponse::Exists(std::path::Path::new(filename.as_str()).exists())
         });
 
         Ok(promise)
@@ -434,36 +463,18 @@ fn exists(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
 
 fn create_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_write(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::create_dir(filename) {
-                Ok(()) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-            });
+
+        let message = format!("unable to create directory '{}'", filename);
+        spawn(agent, &promise, move || {
+            match std::fs::create_dir(filename) {
+                Ok(()) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
+            }
+        });
 
         Ok(promise)
     } else {
@@ -473,36 +484,18 @@ fn create_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Valu
 
 fn remove_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     if let Some(Value::String(filename)) = args.get(0) {
+        agent.permissions.check_write(agent, filename)?;
         let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
 
-        let (registration, set_readiness) = Registration::new2();
-        let token = Token(agent.mio_map.borrow().len());
-
-        agent
-            .mio
-            .register(&registration, token, Ready::readable(), PollOpt::edge())
-            .unwrap();
-        agent
-            .mio_map
-            .borrow_mut()
-            .insert(token, MioMapType::FS(registration, promise.clone()));
-
         let filename = filename.to_string();
-        agent
-            .pool
-            .execute(move || match std::fs::remove_dir(filename) {
-                Ok(()) => {
-                    RESPONSES.lock().unwrap().insert(token, FsResponse::Success);
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-                Err(e) => {
-                    RESPONSES
-                        .lock()
-                        .unwrap()
-                        .insert(token, FsResponse::Error(format!("{}", e)));
-                    set_readiness.set_readiness(Ready::readable()).unwrap();
-                }
-            });
+
+        let message = format!("unable to remove directory '{}'", filename);
+        spawn(agent, &promise, move || {
+            match std::fs::remove_dir(filename) {
+                Ok(()) => FsResponse::Success,
+                Err(e) => FsResponse::Error(message, e),
+            }
+        });
 
         Ok(promise)
     } else {
@@ -510,6 +503,202 @@ fn remove_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Valu
     }
 }
 
+// everything below a directory, each directory before what is in it
+fn walk(root: &Path, relative: &str, entries: &mut Vec<Entry>) -> std::io::Result<()> {
+    let mut children = std::fs::read_dir(root.join(relative))?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let file_type = child.file_type()?;
+        if file_type.is_dir() {
+            entries.push(Entry {
+                path: path.clone(),
+                kind: "directory",
+                size: 0,
+            });
+            walk(root, &path, entries)?;
+        } else if file_type.is_symlink() {
+            entries.push(Entry {
+                path,
+                kind: "symlink",
+                size: 0,
+            });
+        } else {
+            entries.push(Entry {
+                path,
+                kind: "file",
+                size: child.metadata()?.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// symbolic links are copied as links to the same place
+fn copy_tree(
+    from: &Path,
+    to: &Path,
+    entries: Vec<Entry>,
+    overwrite: bool,
+    progress: &dyn Fn(Progress),
+) -> std::io::Result<()> {
+    let copied = entries.iter().filter(|e| e.kind != "directory");
+    let total_files = copied.clone().count() as u64;
+    let total_bytes = copied.clone().map(|e| e.size).sum();
+    if !overwrite {
+        for entry in copied {
+            let target = to.join(&entry.path);
+            if target.symlink_metadata().is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("'{}' already exists", target.display()),
+                ));
+            }
+        }
+    }
+
+    std::fs::create_dir_all(to)?;
+    let mut files = 0;
+    let mut bytes = 0;
+    for entry in entries {
+        let (source, target) = (from.join(&entry.path), to.join(&entry.path));
+        match entry.kind {
+            "directory" => {
+                std::fs::create_dir_all(&target)?;
+                continue;
+            }
+            "symlink" => {
+                if target.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&target)?;
+                }
+                let link = std::fs::read_link(&source)?;
+                symlink(
+                    link.to_string_lossy().into_owned(),
+                    target.to_string_lossy().into_owned(),
+                )?;
+            }
+            _ => {
+                std::fs::copy(&source, &target)?;
+            }
+        }
+        files += 1;
+        bytes += entry.size;
+        progress(Progress {
+            path: entry.path,
+            files,
+            total_files,
+            bytes,
+            total_bytes,
+        });
+    }
+    Ok(())
+}
+
+// filters what was listed and copies the rest. a directory filtered out is
+// skipped with everything in it.
+fn copy_entries(agent: &Agent, promise: Value, entries: Vec<Entry>) {
+    let filter = promise.get_slot("copy filter");
+    let mut kept = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    for entry in entries {
+        if skipped
+            .iter()
+            .any(|dir| entry.path.starts_with(&format!("{}/", dir)))
+        {
+            continue;
+        }
+        if filter != Value::Null {
+            let args = vec![Value::from(entry.path.as_str()), Value::from(entry.kind)];
+            match filter.call(agent, Value::Null, args) {
+                Ok(Value::Boolean(true)) => {}
+                Ok(_) => {
+                    if entry.kind == "directory" {
+                        skipped.push(entry.path);
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    promise
+                        .get_slot("reject")
+                        .call(agent, promise.clone(), vec![e])
+                        .unwrap();
+                    return;
+                }
+            }
+        }
+        kept.push(entry);
+    }
+
+    let (from, to) = match (promise.get_slot("copy from"), promise.get_slot("copy to")) {
+        (Value::String(from), Value::String(to)) => (from.to_string(), to.to_string()),
+        _ => unreachable!(),
+    };
+    let overwrite = promise.get_slot("copy overwrite") == Value::from(true);
+    let message = format!("unable to copy '{}' to '{}'", from, to);
+    spawn_with_progress(agent, &promise, move |progress| {
+        match copy_tree(Path::new(&from), Path::new(&to), kept, overwrite, progress) {
+            Ok(()) => FsResponse::Success,
+            Err(e) => FsResponse::Error(message, e),
+        }
+    });
+}
+
+// options are `overwrite`, to replace files that are already there rather
+// than fail before copying anything, `filter(path, type)`, which keeps an
+// entry by returning true, and `onProgress`, called with how far the copy
+// has got after each file
+fn copy_directory(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (from, to) = match (args.get(0), args.get(1)) {
+        (Some(Value::String(from)), Some(Value::String(to))) => (from.to_string(), to.to_string()),
+        (Some(Value::String(_)), _) => return Err(Value::new_error(agent, "to must be a string")),
+        _ => return Err(Value::new_error(agent, "from must be a string")),
+    };
+    agent.permissions.check_read(agent, &from)?;
+    agent.permissions.check_write(agent, &to)?;
+    let option = |name| match args.get(2) {
+        None | Some(Value::Null) => Ok(Value::Null),
+        Some(options) => options.get(agent, ObjectKey::from(name)),
+    };
+    let overwrite = match option("overwrite")? {
+        Value::Null => false,
+        Value::Boolean(b) => b,
+        _ => return Err(Value::new_error(agent, "overwrite must be a boolean")),
+    };
+    let function = |name| match option(name)? {
+        Value::Null => Ok(Value::Null),
+        f if f.type_of() == "function" => Ok(f),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be a function", name),
+        )),
+    };
+    let filter = function("filter")?;
+    let on_progress = function("onProgress")?;
+
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    promise.set_slot("copy from", Value::from(from.as_str()));
+    promise.set_slot("copy to", Value::from(to.as_str()));
+    promise.set_slot("copy overwrite", Value::from(overwrite));
+    promise.set_slot("copy filter", filter);
+    promise.set_slot("copy progress", on_progress);
+
+    let message = format!("unable to copy '{}' to '{}'", from, to);
+    spawn(agent, &promise, move || {
+        let mut entries = Vec::new();
+        match walk(Path::new(&from), "", &mut entries) {
+            Ok(()) => FsResponse::Listed(entries),
+            Err(e) => FsResponse::Error(message, e),
+        }
+    });
+
+    Ok(promise)
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
 
@@ -529,6 +718,7 @@ pub fn create(agent: &Agent) -> HashMap<String, Value> {
     // watch
     method!("createDirectory", create_directory);
     method!("removeDirectory", remove_directory);
+    method!("copyDirectory", copy_directory);
     // readDirectory
 
     module
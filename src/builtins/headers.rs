@@ -0,0 +1,498 @@
+use super::form::{fields, percent_decode};
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::num_util;
+use crate::value::{ObjectKey, Value};
+use crate::IntoValue;
+use std::collections::HashMap;
+
+// a header map keeps its entries in a list slot, as (name, value) tuples in
+// the order they were added. names are lowercased, so lookups ignore case.
+type Entries = Vec<(String, String)>;
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn header_name(agent: &Agent, value: Option<&Value>) -> Result<String, Value> {
+    match value {
+        Some(Value::String(name)) if is_token(name) => Ok(name.to_ascii_lowercase()),
+        _ => Err(Value::new_error(agent, "invalid header name")),
+    }
+}
+
+// a line break in a value would start another header
+fn header_value(agent: &Agent, value: Option<&Value>) -> Result<String, Value> {
+    let value = match value {
+        Some(Value::String(s)) => s.to_string(),
+        Some(Value::Number(n)) => num_util::to_string(*n),
+        _ => return Err(Value::new_error(agent, "invalid header value")),
+    };
+    if value.contains(&['\r', '\n', '\0'][..]) {
+        return Err(Value::new_error(agent, "invalid header value"));
+    }
+    Ok(value.trim().to_string())
+}
+
+fn load(agent: &Agent, ctx: &Context) -> Result<(Value, Entries), Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("header entries") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    let entries = match this.get_slot("header entries") {
+        Value::List(list) => list
+            .borrow()
+            .iter()
+            .map(|entry| match entry {
+                Value::Tuple(items, _) => match (&items[0], &items[1]) {
+                    (Value::String(name), Value::String(value)) => {
+                        (name.to_string(), value.to_string())
+                    }
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            })
+            .collect(),
+        _ => unreachable!(),
+    };
+    Ok((this, entries))
+}
+
+// slots hand out copies, so changes are stored back
+fn store(headers: &Value, entries: Entries) {
+    let list = Value::new_list();
+    if let Value::List(l) = &list {
+        l.borrow_mut()
+            .extend(entries.into_iter().map(|(name, value)| {
+                Value::Tuple(Box::new(vec![Value::from(name), Value::from(value)]), None)
+            }));
+    }
+    headers.set_slot("header entries", list);
+}
+
+fn values_of(entries: &[(String, String)], name: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|(n, _)| n == name)
+        .map(|(_, v)| v.clone())
+        .collect()
+}
+
+// all the values of a header joined with commas, or null if it isn't set
+fn get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (_, entries) = load(agent, ctx)?;
+    let values = values_of(&entries, &header_name(agent, args.get(0))?);
+    if values.is_empty() {
+        Ok(Value::Null)
+    } else {
+        Ok(Value::from(values.join(", ")))
+    }
+}
+
+// set-cookie can't be joined with commas, so it is read with getAll
+fn get_all(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (_, entries) = load(agent, ctx)?;
+    Ok(values_of(&entries, &header_name(agent, args.get(0))?).into_value(agent))
+}
+
+fn has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (_, entries) = load(agent, ctx)?;
+    let name = header_name(agent, args.get(0))?;
+    Ok(Value::from(entries.iter().any(|(n, _)| *n == name)))
+}
+
+fn set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (this, mut entries) = load(agent, ctx)?;
+    let name = header_name(agent, args.get(0))?;
+    let value = header_value(agent, args.get(1))?;
+    entries.retain(|(n, _)| *n != name);
+    entries.push((name, value));
+    store(&this, entries);
+    Ok(Value::Null)
+}
+
+fn append(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (this, mut entries) = load(agent, ctx)?;
+    let name = header_name(agent, args.get(0))?;
+    let value = header_value(agent, args.get(1))?;
+    entries.push((name, value));
+    store(&this, entries);
+    Ok(Value::Null)
+}
+
+fn delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (this, mut entries) = load(agent, ctx)?;
+    let name = header_name(agent, args.get(0))?;
+    entries.retain(|(n, _)| *n != name);
+    store(&this, entries);
+    Ok(Value::Null)
+}
+
+// an array of (name, value) tuples
+fn entries(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (this, _) = load(agent, ctx)?;
+    match this.get_slot("header entries") {
+        Value::List(list) => Ok(list
+            .borrow()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_value(agent)),
+        _ => unreachable!(),
+    }
+}
+
+// `name: value` lines, as they are sent
+fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (_, entries) = load(agent, ctx)?;
+    Ok(Value::from(
+        entries
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>(),
+    ))
+}
+
+// from an object of names to values or arrays of values, or from the
+// `Name: value` lines of a request or response
+fn create_headers(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let proto = ctx.function.clone().unwrap().get_slot("headers prototype");
+    let headers = Value::new_custom_object(proto);
+    let mut entries = Vec::new();
+    match args.get(0) {
+        None | Some(Value::Null) => {}
+        Some(Value::String(text)) => {
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                let mut line = line.splitn(2, ':');
+                let name = header_name(agent, line.next().map(Value::from).as_ref())?;
+                let value = header_value(agent, line.next().map(Value::from).as_ref())?;
+                entries.push((name, value));
+            }
+        }
+        Some(init) => {
+            for (name, value) in fields(agent, init)? {
+                let name = header_name(agent, Some(&Value::from(name)))?;
+                entries.push((name, header_value(agent, Some(&value))?));
+            }
+        }
+    }
+    store(&headers, entries);
+    Ok(headers)
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// days since the epoch of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub(super) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// `Wed, 21 Oct 2015 07:28:00 GMT` from milliseconds since the epoch
+fn http_date(ms: f64) -> String {
+    let seconds = (ms / 1000.0).floor() as i64;
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[(days + 4).rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// milliseconds since the epoch of a cookie date, which browsers read
+// loosely: the fields may come in any order and be separated by spaces,
+// commas or dashes
+fn parse_http_date(s: &str) -> Option<f64> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for field in s.split(&[' ', ',', '-'][..]) {
+        if field.is_empty() {
+            continue;
+        }
+        if time.is_none() && field.contains(':') {
+            let hms = field
+                .split(':')
+                .map(|n| n.parse::<i64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            if hms.len() != 3 {
+                return None;
+            }
+            time = Some(hms[0] * 3600 + hms[1] * 60 + hms[2]);
+        } else if let Some(m) = MONTHS
+            .iter()
+            .position(|m| field.len() >= 3 && field[..3].eq_ignore_ascii_case(m))
+        {
+            month = Some(m as i64 + 1);
+        } else if let Ok(n) = field.parse::<i64>() {
+            if day.is_none() && field.len() <= 2 {
+                day = Some(n);
+            } else if year.is_none() {
+                // two digit years are from 1970 to 2069
+                year = Some(match n {
+                    0..=69 => n + 2000,
+                    70..=99 => n + 1900,
+                    _ => n,
+                });
+            }
+        }
+    }
+    let days = days_from_civil(year?, month?, day?);
+    Some(((days * 86400 + time?) * 1000) as f64)
+}
+
+// percent encodes what isn't allowed in a cookie value
+fn escape_cookie(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e if b != b'%' => {
+                out.push(b as char)
+            }
+            _ => out += &format!("%{:02X}", b),
+        }
+    }
+    out
+}
+
+// the cookies sent in a Cookie header, with their values unescaped. the
+// first of cookies with the same name wins.
+fn parse_cookie(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let header = match args.get(0) {
+        Some(Value::String(s)) => s.clone(),
+        _ => return Err(Value::new_error(agent, "header must be a string")),
+    };
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let mut seen = Vec::new();
+    for pair in header.split(';') {
+        let mut pair = pair.splitn(2, '=');
+        let name = pair.next().unwrap().trim();
+        let value = match pair.next() {
+            Some(value) => value.trim().trim_matches('"'),
+            None => continue,
+        };
+        if name.is_empty() || seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+        o.set(
+            agent,
+            ObjectKey::from(name),
+            Value::from(percent_decode(value)),
+        )?;
+    }
+    Ok(o)
+}
+
+// a Set-Cookie header. options are `expires` (milliseconds since the epoch),
+// `maxAge` (seconds), `domain`, `path`, `secure`, `httpOnly` and `sameSite`
+// ('Strict', 'Lax' or 'None').
+fn serialize_cookie(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let name = match args.get(0) {
+        Some(Value::String(name)) if is_token(name) => name.clone(),
+        _ => return Err(Value::new_error(agent, "invalid cookie name")),
+    };
+    let value = match args.get(1) {
+        Some(Value::String(value)) => escape_cookie(value),
+        _ => return Err(Value::new_error(agent, "cookie value must be a string")),
+    };
+    let mut cookie = format!("{}={}", name, value);
+    let options = args.get(2).cloned().unwrap_or(Value::Null);
+    if options == Value::Null {
+        return Ok(Value::from(cookie));
+    }
+    let option = |name: &str| options.get(agent, ObjectKey::from(name));
+    let attribute = |value: Value, name: &str| -> Result<Option<String>, Value> {
+        match value {
+            Value::Null => Ok(None),
+            Value::String(s) if !s.contains(&[';', '\r', '\n'][..]) => Ok(Some(s.to_string())),
+            _ => Err(Value::new_error(agent, &format!("invalid cookie {}", name))),
+        }
+    };
+
+    match option("expires")? {
+        Value::Null => {}
+        Value::Number(ms) if ms.is_finite() => cookie += &format!("; Expires={}", http_date(ms)),
+        _ => return Err(Value::new_error(agent, "cookie expires must be a number")),
+    }
+    match option("maxAge")? {
+        Value::Null => {}
+        Value::Number(n) if n.is_finite() => {
+            cookie += &format!("; Max-Age={}", num_util::to_string(n.floor()))
+        }
+        _ => return Err(Value::new_error(agent, "cookie maxAge must be a number")),
+    }
+    if let Some(domain) = attribute(option("domain")?, "domain")? {
+        cookie += &format!("; Domain={}", domain);
+    }
+    if let Some(path) = attribute(option("path")?, "path")? {
+        cookie += &format!("; Path={}", path);
+    }
+    if option("secure")?.to_bool() {
+        cookie += "; Secure";
+    }
+    if option("httpOnly")?.to_bool() {
+        cookie += "; HttpOnly";
+    }
+    match option("sameSite")? {
+        Value::Null => {}
+        Value::String(s) if ["Strict", "Lax", "None"].contains(&&*s) => {
+            cookie += &format!("; SameSite={}", s);
+        }
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "cookie sameSite must be 'Strict', 'Lax' or 'None'",
+            ))
+        }
+    }
+    Ok(Value::from(cookie))
+}
+
+// a Set-Cookie header back into `name`, `value` and the options
+// serializeCookie takes. attributes that aren't given are null, or false for
+// the flags. an unreadable expiry is ignored, as browsers do.
+fn parse_set_cookie(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let header = match args.get(0) {
+        Some(Value::String(s)) => s.clone(),
+        _ => return Err(Value::new_error(agent, "header must be a string")),
+    };
+    let mut attributes = header.split(';');
+    let mut pair = attributes.next().unwrap().splitn(2, '=');
+    let name = pair.next().unwrap().trim();
+    let value = match pair.next() {
+        Some(value) if !name.is_empty() => value.trim().trim_matches('"'),
+        _ => return Err(Value::new_error(agent, "malformed Set-Cookie header")),
+    };
+
+    let o = Value::new_object(agent.intrinsics.object_prototype.clone());
+    macro_rules! p {
+        ($name:expr, $value:expr) => {
+            o.set(agent, ObjectKey::from($name), $value)?;
+        };
+    }
+    p!("name", Value::from(name));
+    p!("value", Value::from(percent_decode(value)));
+    p!("expires", Value::Null);
+    p!("maxAge", Value::Null);
+    p!("domain", Value::Null);
+    p!("path", Value::Null);
+    p!("secure", Value::from(false));
+    p!("httpOnly", Value::from(false));
+    p!("sameSite", Value::Null);
+    for attribute in attributes {
+        let mut attribute = attribute.splitn(2, '=');
+        let key = attribute.next().unwrap().trim().to_ascii_lowercase();
+        let value = attribute.next().unwrap_or("").trim();
+        match key.as_str() {
+            "expires" => {
+                if let Some(ms) = parse_http_date(value) {
+                    p!("expires", Value::from(ms));
+                }
+            }
+            "max-age" => {
+                if let Ok(n) = value.parse::<i64>() {
+                    p!("maxAge", Value::from(n as f64));
+                }
+            }
+            // a leading dot is ignored
+            "domain" => {
+                p!("domain", Value::from(value.trim_start_matches('.')));
+            }
+            "path" => {
+                p!("path", Value::from(value));
+            }
+            "secure" => {
+                p!("secure", Value::from(true));
+            }
+            "httponly" => {
+                p!("httpOnly", Value::from(true));
+            }
+            "samesite" => {
+                let same_site = match value.to_ascii_lowercase().as_str() {
+                    "strict" => "Strict",
+                    "lax" => "Lax",
+                    "none" => "None",
+                    _ => continue,
+                };
+                p!("sameSite", Value::from(same_site));
+            }
+            _ => {}
+        }
+    }
+    Ok(o)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("get", get);
+    method!("getAll", get_all);
+    method!("has", has);
+    method!("set", set);
+    method!("append", append);
+    method!("delete", delete);
+    method!("entries", entries);
+    method!("toString", to_string);
+
+    let f = Value::new_builtin_function(agent, create_headers);
+    f.set_slot("headers prototype", proto);
+    module.insert("createHeaders".to_string(), f);
+    module.insert(
+        "parseCookie".to_string(),
+        Value::new_builtin_function(agent, parse_cookie),
+    );
+    module.insert(
+        "serializeCookie".to_string(),
+        Value::new_builtin_function(agent, serialize_cookie),
+    );
+    module.insert(
+        "parseSetCookie".to_string(),
+        Value::new_builtin_function(agent, parse_set_cookie),
+    );
+
+    module
+}
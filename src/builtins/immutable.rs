@@ -0,0 +1,616 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// lists and maps are tries of arrays 32 wide. an update copies the arrays on
+// the path to what changed and shares every other one with the version it was
+// made from, so it costs O(log n) and neither version can see the other's
+// changes. the arrays never leave this module, so nothing else mutates them.
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+fn new_node(agent: &Agent, children: Vec<Value>) -> Value {
+    let node = Value::new_array(agent);
+    if let Value::Object(o) = &node {
+        if let ObjectKind::Array(values) = &o.kind {
+            *values.borrow_mut() = children;
+        }
+    }
+    node
+}
+
+fn children(node: &Value) -> Vec<Value> {
+    match node {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => values.borrow().clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn child(node: &Value, i: usize) -> Value {
+    match node {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => values.borrow()[i].clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn this_object(agent: &Agent, ctx: &Context, slot: &str) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot(slot) {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn slot_number(object: &Value, slot: &str) -> usize {
+    match object.get_slot(slot) {
+        Value::Number(n) => n as usize,
+        _ => unreachable!(),
+    }
+}
+
+// the prototype lists and maps get, kept in the state each function carries
+fn prototype(ctx: &Context, name: &str) -> Value {
+    ctx.function
+        .clone()
+        .unwrap()
+        .get_slot("immutable")
+        .get_slot(name)
+}
+
+// lists
+
+// the root's height is `shift`, each level taking BITS bits of an index. the
+// leaves are filled from the left, so every node but the last on each level
+// is full.
+fn new_list(agent: &Agent, prototype: Value, root: Value, shift: usize, size: usize) -> Value {
+    let list = Value::new_custom_object(prototype);
+    list.set_slot("list root", root);
+    list.set_slot("list shift", Value::from(shift as f64));
+    list.set_slot("list size", Value::from(size as f64));
+    list.set(agent, ObjectKey::from("size"), Value::from(size as f64))
+        .unwrap();
+    if let Value::Object(o) = &list {
+        o.freeze();
+    }
+    list
+}
+
+fn index(agent: &Agent, value: Option<&Value>, size: usize) -> Result<usize, Value> {
+    match value {
+        Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 && (*n as usize) < size => {
+            Ok(*n as usize)
+        }
+        _ => Err(Value::new_error(agent, "index out of range")),
+    }
+}
+
+fn list_get(root: &Value, shift: usize, i: usize) -> Value {
+    let mut node = root.clone();
+    let mut level = shift;
+    while level > 0 {
+        node = child(&node, (i >> level) & MASK);
+        level -= BITS;
+    }
+    child(&node, i & MASK)
+}
+
+fn list_set(agent: &Agent, node: &Value, level: usize, i: usize, value: Value) -> Value {
+    let mut c = children(node);
+    let sub = (i >> level) & MASK;
+    c[sub] = if level == 0 {
+        value
+    } else {
+        list_set(agent, &c[sub], level - BITS, i, value)
+    };
+    new_node(agent, c)
+}
+
+// a branch down to a leaf holding just the value
+fn new_path(agent: &Agent, level: usize, value: Value) -> Value {
+    if level == 0 {
+        new_node(agent, vec![value])
+    } else {
+        new_node(agent, vec![new_path(agent, level - BITS, value)])
+    }
+}
+
+fn list_push(agent: &Agent, node: &Value, level: usize, i: usize, value: Value) -> Value {
+    let mut c = children(node);
+    let sub = (i >> level) & MASK;
+    if level == 0 {
+        c.push(value);
+    } else if sub < c.len() {
+        c[sub] = list_push(agent, &c[sub], level - BITS, i, value);
+    } else {
+        c.push(new_path(agent, level - BITS, value));
+    }
+    new_node(agent, c)
+}
+
+// None once the node has nothing left in it
+fn list_pop(agent: &Agent, node: &Value, level: usize, i: usize) -> Option<Value> {
+    let mut c = children(node);
+    if level == 0 {
+        c.pop();
+    } else {
+        let sub = (i >> level) & MASK;
+        match list_pop(agent, &c[sub], level - BITS, i) {
+            Some(n) => c[sub] = n,
+            None => {
+                c.pop();
+            }
+        }
+    }
+    if c.is_empty() {
+        None
+    } else {
+        Some(new_node(agent, c))
+    }
+}
+
+fn list_values(node: &Value, level: usize, out: &mut Vec<Value>) {
+    for c in children(node) {
+        if level == 0 {
+            out.push(c);
+        } else {
+            list_values(&c, level - BITS, out);
+        }
+    }
+}
+
+struct ListParts {
+    root: Value,
+    shift: usize,
+    size: usize,
+}
+
+fn this_list(agent: &Agent, ctx: &Context) -> Result<ListParts, Value> {
+    let list = this_object(agent, ctx, "list root")?;
+    Ok(ListParts {
+        root: list.get_slot("list root"),
+        shift: slot_number(&list, "list shift"),
+        size: slot_number(&list, "list size"),
+    })
+}
+
+fn push_value(agent: &Agent, list: ListParts, value: Value) -> ListParts {
+    let ListParts { root, shift, size } = list;
+    if size == WIDTH << shift {
+        // full, so the tree grows a level
+        let root = new_node(agent, vec![root, new_path(agent, shift, value)]);
+        ListParts {
+            root,
+            shift: shift + BITS,
+            size: size + 1,
+        }
+    } else {
+        ListParts {
+            root: list_push(agent, &root, shift, size, value),
+            shift,
+            size: size + 1,
+        }
+    }
+}
+
+fn list_method_get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let list = this_list(agent, ctx)?;
+    let i = index(agent, args.get(0), list.size)?;
+    Ok(list_get(&list.root, list.shift, i))
+}
+
+// a new list with the value at i replaced
+fn list_method_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let list = this_list(agent, ctx)?;
+    let i = index(agent, args.get(0), list.size)?;
+    let value = args.get(1).cloned().unwrap_or(Value::Null);
+    let root = list_set(agent, &list.root, list.shift, i, value);
+    Ok(new_list(
+        agent,
+        prototype(ctx, "list prototype"),
+        root,
+        list.shift,
+        list.size,
+    ))
+}
+
+// a new list with the value added at the end
+fn list_method_push(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let list = this_list(agent, ctx)?;
+    let value = args.get(0).cloned().unwrap_or(Value::Null);
+    let list = push_value(agent, list, value);
+    Ok(new_list(
+        agent,
+        prototype(ctx, "list prototype"),
+        list.root,
+        list.shift,
+        list.size,
+    ))
+}
+
+// a new list without the last value
+fn list_method_pop(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let list = this_list(agent, ctx)?;
+    if list.size == 0 {
+        return Err(Value::new_error(agent, "list is empty"));
+    }
+    let mut root = list_pop(agent, &list.root, list.shift, list.size - 1)
+        .unwrap_or_else(|| new_node(agent, Vec::new()));
+    let mut shift = list.shift;
+    // a root with a single branch is replaced by that branch
+    while shift > 0 && children(&root).len() == 1 {
+        root = child(&root, 0);
+        shift -= BITS;
+    }
+    Ok(new_list(
+        agent,
+        prototype(ctx, "list prototype"),
+        root,
+        shift,
+        list.size - 1,
+    ))
+}
+
+fn list_method_to_array(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let list = this_list(agent, ctx)?;
+    let mut values = Vec::with_capacity(list.size);
+    list_values(&list.root, list.shift, &mut values);
+    Ok(new_node(agent, values))
+}
+
+// with or without `new`, optionally from an array of values
+fn list(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let values = match args.get(0) {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Object(o)) => match &o.kind {
+            ObjectKind::Array(values) => values.borrow().clone(),
+            _ => return Err(Value::new_error(agent, "values must be an array")),
+        },
+        _ => return Err(Value::new_error(agent, "values must be an array")),
+    };
+    let mut list = ListParts {
+        root: new_node(agent, Vec::new()),
+        shift: 0,
+        size: 0,
+    };
+    for value in values {
+        list = push_value(agent, list, value);
+    }
+    Ok(new_list(
+        agent,
+        prototype(ctx, "list prototype"),
+        list.root,
+        list.shift,
+        list.size,
+    ))
+}
+
+// maps
+
+// a hash array mapped trie: each level takes BITS bits of the key's hash to
+// pick a slot, which is null, an entry tuple of key and value, or a node one
+// level down. keys whose hashes are equal end up in a bucket, an array of
+// their entries, once the bits run out.
+const HASH_BITS: usize = 64;
+
+fn hash_of(agent: &Agent, key: &Value) -> Result<u64, Value> {
+    match key {
+        Value::Number(n) if n.is_nan() => Err(Value::new_error(agent, "NaN can't be a key")),
+        Value::Null
+        | Value::Boolean(_)
+        | Value::String(_)
+        | Value::Number(_)
+        | Value::Symbol(_)
+        | Value::Object(_)
+        | Value::Tuple(..)
+        | Value::Record(_) => {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            Ok(hasher.finish())
+        }
+        _ => Err(Value::new_error(agent, "invalid key")),
+    }
+}
+
+fn entry(key: Value, value: Value) -> Value {
+    Value::Tuple(Box::new(vec![key, value]), None)
+}
+
+fn empty_node(agent: &Agent, shift: usize) -> Value {
+    if shift >= HASH_BITS {
+        new_node(agent, Vec::new())
+    } else {
+        new_node(agent, vec![Value::Null; WIDTH])
+    }
+}
+
+fn map_get(node: &Value, hash: u64, shift: usize, key: &Value) -> Option<Value> {
+    if shift >= HASH_BITS {
+        return children(node).into_iter().find_map(|e| match e {
+            Value::Tuple(kv, _) if &kv[0] == key => Some(kv[1].clone()),
+            _ => None,
+        });
+    }
+    match child(node, (hash >> shift) as usize & MASK) {
+        Value::Null => None,
+        Value::Tuple(kv, _) if &kv[0] == key => Some(kv[1].clone()),
+        Value::Tuple(..) => None,
+        next => map_get(&next, hash, shift + BITS, key),
+    }
+}
+
+// the new node, and whether the key wasn't there before
+fn map_set(
+    agent: &Agent,
+    node: &Value,
+    hash: u64,
+    shift: usize,
+    key: Value,
+    value: Value,
+) -> Result<(Value, bool), Value> {
+    let mut c = children(node);
+    if shift >= HASH_BITS {
+        let added = match c
+            .iter()
+            .position(|e| matches!(e, Value::Tuple(kv, _) if kv[0] == key))
+        {
+            Some(i) => {
+                c[i] = entry(key, value);
+                false
+            }
+            None => {
+                c.push(entry(key, value));
+                true
+            }
+        };
+        return Ok((new_node(agent, c), added));
+    }
+    let i = (hash >> shift) as usize & MASK;
+    let added = match c[i].clone() {
+        Value::Null => {
+            c[i] = entry(key, value);
+            true
+        }
+        Value::Tuple(kv, _) if kv[0] == key => {
+            c[i] = entry(key, value);
+            false
+        }
+        Value::Tuple(kv, _) => {
+            // two keys in one slot, so both move a level down
+            let (k, v) = (kv[0].clone(), kv[1].clone());
+            let next = empty_node(agent, shift + BITS);
+            let (next, _) = map_set(agent, &next, hash_of(agent, &k)?, shift + BITS, k, v)?;
+            let (next, _) = map_set(agent, &next, hash, shift + BITS, key, value)?;
+            c[i] = next;
+            true
+        }
+        next => {
+            let (next, added) = map_set(agent, &next, hash, shift + BITS, key, value)?;
+            c[i] = next;
+            added
+        }
+    };
+    Ok((new_node(agent, c), added))
+}
+
+fn is_empty(node: &Value) -> bool {
+    children(node).iter().all(|c| c == &Value::Null)
+}
+
+// the new node, or None if the key isn't there
+fn map_delete(agent: &Agent, node: &Value, hash: u64, shift: usize, key: &Value) -> Option<Value> {
+    let mut c = children(node);
+    if shift >= HASH_BITS {
+        let i = c
+            .iter()
+            .position(|e| matches!(e, Value::Tuple(kv, _) if &kv[0] == key))?;
+        c.remove(i);
+        return Some(new_node(agent, c));
+    }
+    let i = (hash >> shift) as usize & MASK;
+    match c[i].clone() {
+        Value::Null => return None,
+        Value::Tuple(kv, _) if &kv[0] == key => c[i] = Value::Null,
+        Value::Tuple(..) => return None,
+        next => {
+            let next = map_delete(agent, &next, hash, shift + BITS, key)?;
+            // nodes left empty are dropped rather than kept around
+            c[i] = if is_empty(&next) { Value::Null } else { next };
+        }
+    }
+    Some(new_node(agent, c))
+}
+
+fn map_entries(node: &Value, out: &mut Vec<(Value, Value)>) {
+    for c in children(node) {
+        match c {
+            Value::Null => {}
+            Value::Tuple(kv, _) => out.push((kv[0].clone(), kv[1].clone())),
+            next => map_entries(&next, out),
+        }
+    }
+}
+
+fn new_map(agent: &Agent, prototype: Value, root: Value, size: usize) -> Value {
+    let map = Value::new_custom_object(prototype);
+    map.set_slot("map root", root);
+    map.set_slot("map size", Value::from(size as f64));
+    map.set(agent, ObjectKey::from("size"), Value::from(size as f64))
+        .unwrap();
+    if let Value::Object(o) = &map {
+        o.freeze();
+    }
+    map
+}
+
+fn this_map(agent: &Agent, ctx: &Context) -> Result<(Value, usize), Value> {
+    let map = this_object(agent, ctx, "map root")?;
+    Ok((map.get_slot("map root"), slot_number(&map, "map size")))
+}
+
+fn key_argument(agent: &Agent, args: &[Value]) -> Result<(Value, u64), Value> {
+    let key = args.get(0).cloned().unwrap_or(Value::Null);
+    let hash = hash_of(agent, &key)?;
+    Ok((key, hash))
+}
+
+// the value for the key, null if there is none
+fn map_method_get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, _) = this_map(agent, ctx)?;
+    let (key, hash) = key_argument(agent, &args)?;
+    Ok(map_get(&root, hash, 0, &key).unwrap_or(Value::Null))
+}
+
+fn map_method_has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, _) = this_map(agent, ctx)?;
+    let (key, hash) = key_argument(agent, &args)?;
+    Ok(Value::from(map_get(&root, hash, 0, &key).is_some()))
+}
+
+// a new map with the key set to the value
+fn map_method_set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, size) = this_map(agent, ctx)?;
+    let (key, hash) = key_argument(agent, &args)?;
+    let value = args.get(1).cloned().unwrap_or(Value::Null);
+    let (root, added) = map_set(agent, &root, hash, 0, key, value)?;
+    let size = if added { size + 1 } else { size };
+    Ok(new_map(agent, prototype(ctx, "map prototype"), root, size))
+}
+
+// a new map without the key, or this one if it doesn't have it
+fn map_method_delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, size) = this_map(agent, ctx)?;
+    let (key, hash) = key_argument(agent, &args)?;
+    match map_delete(agent, &root, hash, 0, &key) {
+        Some(root) => Ok(new_map(
+            agent,
+            prototype(ctx, "map prototype"),
+            root,
+            size - 1,
+        )),
+        None => ctx.scope.borrow().get_this(agent),
+    }
+}
+
+// keys, values and entries come in no particular order, but the same one for
+// all three
+fn map_method_keys(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, size) = this_map(agent, ctx)?;
+    let mut entries = Vec::with_capacity(size);
+    map_entries(&root, &mut entries);
+    Ok(new_node(
+        agent,
+        entries.into_iter().map(|(k, _)| k).collect(),
+    ))
+}
+
+fn map_method_values(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, size) = this_map(agent, ctx)?;
+    let mut entries = Vec::with_capacity(size);
+    map_entries(&root, &mut entries);
+    Ok(new_node(
+        agent,
+        entries.into_iter().map(|(_, v)| v).collect(),
+    ))
+}
+
+// [key, value] arrays
+fn map_method_entries(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let (root, size) = this_map(agent, ctx)?;
+    let mut entries = Vec::with_capacity(size);
+    map_entries(&root, &mut entries);
+    Ok(new_node(
+        agent,
+        entries
+            .into_iter()
+            .map(|(k, v)| new_node(agent, vec![k, v]))
+            .collect(),
+    ))
+}
+
+// with or without `new`, optionally from an object's own properties
+fn map(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let mut root = empty_node(agent, 0);
+    let mut size = 0;
+    match args.get(0) {
+        None | Some(Value::Null) => {}
+        Some(o @ Value::Object(_)) => {
+            for key in o.keys(agent)? {
+                let value = o.get(agent, key.clone())?;
+                let key = match &key {
+                    ObjectKey::String(s) => Value::String(s.clone()),
+                    ObjectKey::Number(n) => Value::from(n.to_string()),
+                    ObjectKey::Symbol(s) => Value::Symbol(Box::new(s.clone())),
+                };
+                let (next, added) = map_set(agent, &root, hash_of(agent, &key)?, 0, key, value)?;
+                root = next;
+                if added {
+                    size += 1;
+                }
+            }
+        }
+        _ => return Err(Value::new_error(agent, "entries must be an object")),
+    }
+    Ok(new_map(agent, prototype(ctx, "map prototype"), root, size))
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let state = Value::new_custom_object(Value::Null);
+    let list_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let map_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    state.set_slot("list prototype", list_prototype.clone());
+    state.set_slot("map prototype", map_prototype.clone());
+
+    let function = |f| {
+        let f = Value::new_builtin_function(agent, f);
+        f.set_slot("immutable", state.clone());
+        f
+    };
+    macro_rules! method {
+        ($proto:expr, $name:expr, $fn:ident) => {
+            $proto
+                .set(agent, ObjectKey::from($name), function($fn))
+                .unwrap();
+        };
+    }
+    method!(list_prototype, "get", list_method_get);
+    method!(list_prototype, "set", list_method_set);
+    method!(list_prototype, "push", list_method_push);
+    method!(list_prototype, "pop", list_method_pop);
+    method!(list_prototype, "toArray", list_method_to_array);
+    method!(map_prototype, "get", map_method_get);
+    method!(map_prototype, "has", map_method_has);
+    method!(map_prototype, "set", map_method_set);
+    method!(map_prototype, "delete", map_method_delete);
+    method!(map_prototype, "keys", map_method_keys);
+    method!(map_prototype, "values", map_method_values);
+    method!(map_prototype, "entries", map_method_entries);
+
+    let constructor = |f, prototype: Value| {
+        let constructor = function(f);
+        constructor
+            .set(agent, ObjectKey::from("prototype"), prototype.clone())
+            .unwrap();
+        prototype
+            .set(agent, ObjectKey::from("constructor"), constructor.clone())
+            .unwrap();
+        constructor
+    };
+    module.insert("List".to_string(), constructor(list, list_prototype));
+    module.insert("Map".to_string(), constructor(map, map_prototype));
+
+    module
+}
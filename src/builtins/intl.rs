@@ -0,0 +1,334 @@
+use super::headers::civil_from_days;
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::locale;
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+
+// formatters are objects with a `format` function carrying the settings they
+// were created with in its slots
+
+fn language(agent: &Agent, locale: Option<&Value>) -> Result<String, Value> {
+    match locale.unwrap_or(&Value::Null) {
+        Value::String(tag) => Ok(locale::language(tag)),
+        Value::Null => Ok(String::new()),
+        _ => Err(Value::new_error(agent, "locale must be a string")),
+    }
+}
+
+fn option(agent: &Agent, options: Option<&Value>, name: &str) -> Result<Value, Value> {
+    match options {
+        None | Some(Value::Null) => Ok(Value::Null),
+        Some(o @ Value::Object(_)) => o.get(agent, ObjectKey::from(name)),
+        _ => Err(Value::new_error(agent, "options must be an object")),
+    }
+}
+
+fn digits_option(agent: &Agent, options: Option<&Value>, name: &str) -> Result<Option<f64>, Value> {
+    match option(agent, options, name)? {
+        Value::Null => Ok(None),
+        Value::Number(n) if (0.0..=20.0).contains(&n) && n.fract() == 0.0 => Ok(Some(n)),
+        _ => Err(Value::new_error(
+            agent,
+            &format!("{} must be an integer from 0 to 20", name),
+        )),
+    }
+}
+
+// the symbol of a currency and the digits it is written with
+fn currency(code: &str) -> (&str, usize) {
+    match code {
+        "USD" => ("$", 2),
+        "EUR" => ("€", 2),
+        "GBP" => ("£", 2),
+        "JPY" => ("¥", 0),
+        "TRY" => ("₺", 2),
+        _ => (code, 2),
+    }
+}
+
+// separators every three digits from the right
+fn group(digits: &str, separator: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out += separator;
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn format_number(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let n = match args.get(0) {
+        Some(Value::Number(n)) => *n,
+        _ => return Err(Value::new_error(agent, "argument must be a number")),
+    };
+    let f = ctx.function.clone().unwrap();
+    let conventions = match f.get_slot("language") {
+        Value::String(language) => locale::conventions(&language),
+        _ => unreachable!(),
+    };
+    let (minimum, maximum) = match (f.get_slot("minimum"), f.get_slot("maximum")) {
+        (Value::Number(min), Value::Number(max)) => (min as usize, max as usize),
+        _ => unreachable!(),
+    };
+    if n.is_nan() {
+        return Ok(Value::from("NaN"));
+    }
+
+    let mut out = if n.is_infinite() {
+        "∞".to_string()
+    } else {
+        // halves round away from zero, not to even as format! does
+        let scale = 10f64.powi(maximum as i32);
+        let rounded = match (n.abs() * scale).round() / scale {
+            r if r.is_finite() => r,
+            _ => n.abs(),
+        };
+        let fixed = format!("{:.*}", maximum, rounded);
+        let mut parts = fixed.splitn(2, '.');
+        let integer = parts.next().unwrap();
+        let mut fraction = parts.next().unwrap_or("").to_string();
+        while fraction.len() > minimum && fraction.ends_with('0') {
+            fraction.pop();
+        }
+        let mut out = if f.get_slot("grouping") == Value::from(true) {
+            group(integer, conventions.group)
+        } else {
+            integer.to_string()
+        };
+        if !fraction.is_empty() {
+            out += conventions.decimal;
+            out += &fraction;
+        }
+        out
+    };
+    if let Value::String(code) = f.get_slot("currency") {
+        let (symbol, _) = currency(&code);
+        out = if !conventions.currency_first {
+            format!("{}\u{a0}{}", out, symbol)
+        } else if symbol == &*code {
+            format!("{}\u{a0}{}", symbol, out)
+        } else {
+            format!("{}{}", symbol, out)
+        };
+    }
+    if n < 0.0 {
+        out.insert(0, '-');
+    }
+    Ok(Value::from(out))
+}
+
+// options are `minimumFractionDigits`, `maximumFractionDigits`,
+// `useGrouping` and `currency`, a code like `EUR`
+fn create_number_format(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let language = language(agent, args.get(0))?;
+    let options = args.get(1);
+    let code = match option(agent, options, "currency")? {
+        Value::Null => None,
+        Value::String(code) if code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase()) => {
+            Some(code)
+        }
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "currency must be a three letter code like 'EUR'",
+            ))
+        }
+    };
+    let (default_minimum, default_maximum) = match &code {
+        Some(code) => (currency(code).1 as f64, currency(code).1 as f64),
+        None => (0.0, 3.0),
+    };
+    let minimum = digits_option(agent, options, "minimumFractionDigits")?;
+    let maximum = digits_option(agent, options, "maximumFractionDigits")?;
+    let (minimum, maximum) = match (minimum, maximum) {
+        (Some(min), Some(max)) if min > max => {
+            return Err(Value::new_error(
+                agent,
+                "minimumFractionDigits is more than maximumFractionDigits",
+            ))
+        }
+        (Some(min), Some(max)) => (min, max),
+        (Some(min), None) => (min, min.max(default_maximum)),
+        (None, Some(max)) => (default_minimum.min(max), max),
+        (None, None) => (default_minimum, default_maximum),
+    };
+    let grouping = match option(agent, options, "useGrouping")? {
+        Value::Null => true,
+        Value::Boolean(b) => b,
+        _ => return Err(Value::new_error(agent, "useGrouping must be a boolean")),
+    };
+
+    let format = Value::new_builtin_function(agent, format_number);
+    format.set_slot("language", Value::from(language));
+    format.set_slot("minimum", Value::from(minimum));
+    format.set_slot("maximum", Value::from(maximum));
+    format.set_slot("grouping", Value::from(grouping));
+    format.set_slot("currency", code.map(Value::String).unwrap_or(Value::Null));
+    let formatter = Value::new_object(agent.intrinsics.object_prototype.clone());
+    formatter.set(agent, ObjectKey::from("format"), format)?;
+    Ok(formatter)
+}
+
+enum Field {
+    Text(String),
+    // a pattern letter and how many times it is repeated
+    Letter(char, usize),
+}
+
+// letters stand for fields, as in `yyyy-MM-dd`, and text in single quotes is
+// kept as it is, with `''` for a quote
+fn parse_pattern(pattern: &str) -> Result<Vec<Field>, String> {
+    let mut fields = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let mut text = String::new();
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                    text.push('\'');
+                } else {
+                    loop {
+                        match chars.next() {
+                            Some('\'') if chars.peek() == Some(&'\'') => {
+                                chars.next();
+                                text.push('\'');
+                            }
+                            Some('\'') => break,
+                            Some(c) => text.push(c),
+                            None => return Err("unterminated quote in pattern".to_string()),
+                        }
+                    }
+                }
+                fields.push(Field::Text(text));
+            }
+            'y' | 'M' | 'd' | 'E' | 'H' | 'h' | 'm' | 's' | 'S' | 'a' => {
+                let mut count = 1;
+                while chars.peek() == Some(&c) {
+                    chars.next();
+                    count += 1;
+                }
+                fields.push(Field::Letter(c, count));
+            }
+            c if c.is_ascii_alphabetic() => {
+                return Err(format!("unknown pattern letter '{}'", c));
+            }
+            c => fields.push(Field::Text(c.to_string())),
+        }
+    }
+    Ok(fields)
+}
+
+fn format_date(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = match args.get(0) {
+        Some(Value::Number(ms)) if ms.is_finite() => *ms,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "argument must be milliseconds since the epoch",
+            ))
+        }
+    };
+    let f = ctx.function.clone().unwrap();
+    let (conventions, pattern, offset) = match (
+        f.get_slot("language"),
+        f.get_slot("pattern"),
+        f.get_slot("offset"),
+    ) {
+        (Value::String(language), Value::String(pattern), Value::Number(offset)) => {
+            (locale::conventions(&language), pattern, offset)
+        }
+        _ => unreachable!(),
+    };
+
+    let ms = (ms + offset * 60_000.0).floor() as i64;
+    let days = ms.div_euclid(86_400_000);
+    let time = ms.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    let weekday = (days + 4).rem_euclid(7) as usize;
+    let hour = time / 3_600_000;
+
+    let mut out = String::new();
+    for field in parse_pattern(&pattern).unwrap() {
+        let (letter, count) = match field {
+            Field::Text(text) => {
+                out += &text;
+                continue;
+            }
+            Field::Letter(letter, count) => (letter, count),
+        };
+        let number = |n: i64| format!("{:0width$}", n, width = count);
+        out += &match letter {
+            'y' if count == 2 => format!("{:02}", year.rem_euclid(100)),
+            'y' => number(year),
+            'M' if count >= 4 => conventions.months[month as usize - 1].to_string(),
+            'M' if count == 3 => conventions.months[month as usize - 1]
+                .chars()
+                .take(3)
+                .collect(),
+            'M' => number(month),
+            'd' => number(day),
+            'E' if count >= 4 => conventions.days[weekday].to_string(),
+            'E' => conventions.days[weekday].chars().take(3).collect(),
+            'H' => number(hour),
+            'h' => number(if hour % 12 == 0 { 12 } else { hour % 12 }),
+            'm' => number(time / 60_000 % 60),
+            's' => number(time / 1000 % 60),
+            'S' => format!("{:03}", time % 1000).chars().take(count).collect(),
+            'a' => if hour < 12 { "AM" } else { "PM" }.to_string(),
+            _ => unreachable!(),
+        };
+    }
+    Ok(Value::from(out))
+}
+
+// options are `pattern`, `yyyy-MM-dd HH:mm:ss` if not given, and
+// `utcOffset`, the minutes dates are ahead of utc. month and day names come
+// from the locale, shortened to three letters for `MMM` and `EEE`.
+fn create_date_format(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let language = language(agent, args.get(0))?;
+    let options = args.get(1);
+    let pattern = match option(agent, options, "pattern")? {
+        Value::Null => "yyyy-MM-dd HH:mm:ss".into(),
+        Value::String(pattern) => pattern,
+        _ => return Err(Value::new_error(agent, "pattern must be a string")),
+    };
+    if let Err(e) = parse_pattern(&pattern) {
+        return Err(Value::new_error(agent, &e));
+    }
+    let offset = match option(agent, options, "utcOffset")? {
+        Value::Null => 0.0,
+        Value::Number(n) if n.abs() < 24.0 * 60.0 => n,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "utcOffset must be a number of minutes less than a day",
+            ))
+        }
+    };
+
+    let format = Value::new_builtin_function(agent, format_date);
+    format.set_slot("language", Value::from(language));
+    format.set_slot("pattern", Value::String(pattern));
+    format.set_slot("offset", Value::from(offset));
+    let formatter = Value::new_object(agent.intrinsics.object_prototype.clone());
+    formatter.set(agent, ObjectKey::from("format"), format)?;
+    Ok(formatter)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "createNumberFormat".to_string(),
+        Value::new_builtin_function(agent, create_number_format),
+    );
+    module.insert(
+        "createDateFormat".to_string(),
+        Value::new_builtin_function(agent, create_date_format),
+    );
+
+    module
+}
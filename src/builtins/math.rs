@@ -1,6 +1,6 @@
 use crate::agent::Agent;
 use crate::interpreter::Context;
-use crate::value::Value;
+use crate::value::{ObjectKind, Value};
 use std::collections::HashMap;
 
 fn min(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
@@ -41,11 +41,187 @@ fn max(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
     Ok(Value::from(numbers[0]))
 }
 
+fn random(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from(agent.random()))
+}
+
+// vectors are arrays of 2 to 4 numbers, and matrices arrays of 4, 9 or 16,
+// their rows one after another
+
+fn numbers(agent: &Agent, value: Option<&Value>) -> Result<Vec<f64>, Value> {
+    if let Some(Value::Object(o)) = value {
+        if let ObjectKind::Array(values) = &o.kind {
+            let mut numbers = Vec::new();
+            for value in values.borrow().iter() {
+                match value {
+                    Value::Number(n) => numbers.push(*n),
+                    _ => break,
+                }
+            }
+            if numbers.len() == values.borrow().len() {
+                return Ok(numbers);
+            }
+        }
+    }
+    Err(Value::new_error(
+        agent,
+        "argument must be an array of numbers",
+    ))
+}
+
+fn vector(agent: &Agent, value: Option<&Value>) -> Result<Vec<f64>, Value> {
+    let v = numbers(agent, value)?;
+    if (2..=4).contains(&v.len()) {
+        Ok(v)
+    } else {
+        Err(Value::new_error(
+            agent,
+            "vector must have 2, 3 or 4 numbers",
+        ))
+    }
+}
+
+// the matrix and how many rows it has
+fn matrix(agent: &Agent, value: Option<&Value>) -> Result<(Vec<f64>, usize), Value> {
+    let m = numbers(agent, value)?;
+    match m.len() {
+        4 => Ok((m, 2)),
+        9 => Ok((m, 3)),
+        16 => Ok((m, 4)),
+        _ => Err(Value::new_error(
+            agent,
+            "matrix must have 4, 9 or 16 numbers",
+        )),
+    }
+}
+
+fn array(agent: &Agent, numbers: Vec<f64>) -> Value {
+    let array = Value::new_array(agent);
+    if let Value::Object(o) = &array {
+        if let ObjectKind::Array(values) = &o.kind {
+            *values.borrow_mut() = numbers.into_iter().map(Value::from).collect();
+        }
+    }
+    array
+}
+
+fn dot(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let a = vector(agent, args.get(0))?;
+    let b = vector(agent, args.get(1))?;
+    if a.len() != b.len() {
+        return Err(Value::new_error(agent, "vectors must be the same size"));
+    }
+    Ok(Value::from(
+        a.iter().zip(&b).map(|(a, b)| a * b).sum::<f64>(),
+    ))
+}
+
+fn cross(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let a = vector(agent, args.get(0))?;
+    let b = vector(agent, args.get(1))?;
+    if a.len() != 3 || b.len() != 3 {
+        return Err(Value::new_error(agent, "vectors must have 3 numbers"));
+    }
+    Ok(array(
+        agent,
+        vec![
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ],
+    ))
+}
+
+// a matrix times a matrix of the same size, or times a vector with as many
+// numbers as the matrix has rows
+fn multiply(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (a, n) = matrix(agent, args.get(0))?;
+    let b = numbers(agent, args.get(1))?;
+    let columns = if b.len() == n {
+        1
+    } else if b.len() == a.len() {
+        n
+    } else {
+        return Err(Value::new_error(
+            agent,
+            "can only multiply by a matrix of the same size or a vector as long as a row",
+        ));
+    };
+    let mut out = vec![0.0; n * columns];
+    for row in 0..n {
+        for column in 0..columns {
+            out[row * columns + column] = (0..n)
+                .map(|k| a[row * n + k] * b[k * columns + column])
+                .sum();
+        }
+    }
+    Ok(array(agent, out))
+}
+
+// by gauss-jordan elimination, picking the largest pivot in each column
+fn invert(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let (mut a, n) = matrix(agent, args.get(0))?;
+    let mut out = vec![0.0; n * n];
+    for i in 0..n {
+        out[i * n + i] = 1.0;
+    }
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|x, y| {
+                a[x * n + column]
+                    .abs()
+                    .partial_cmp(&a[y * n + column].abs())
+                    .unwrap()
+            })
+            .unwrap();
+        if a[pivot * n + column] == 0.0 || a[pivot * n + column].is_nan() {
+            return Err(Value::new_error(agent, "matrix is not invertible"));
+        }
+        for k in 0..n {
+            a.swap(pivot * n + k, column * n + k);
+            out.swap(pivot * n + k, column * n + k);
+        }
+        let scale = a[column * n + column];
+        for k in 0..n {
+            a[column * n + k] /= scale;
+            out[column * n + k] /= scale;
+        }
+        for row in 0..n {
+            let factor = a[row * n + column];
+            if row == column || factor == 0.0 {
+                continue;
+            }
+            for k in 0..n {
+                a[row * n + k] -= factor * a[column * n + k];
+                out[row * n + k] -= factor * out[column * n + k];
+            }
+        }
+    }
+    Ok(array(agent, out))
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
 
     module.insert("min".to_string(), Value::new_builtin_function(agent, min));
     module.insert("max".to_string(), Value::new_builtin_function(agent, max));
+    module.insert(
+        "random".to_string(),
+        Value::new_builtin_function(agent, random),
+    );
+    module.insert("dot".to_string(), Value::new_builtin_function(agent, dot));
+    module.insert(
+        "cross".to_string(),
+        Value::new_builtin_function(agent, cross),
+    );
+    module.insert(
+        "multiply".to_string(),
+        Value::new_builtin_function(agent, multiply),
+    );
+    module.insert(
+        "invert".to_string(),
+        Value::new_builtin_function(agent, invert),
+    );
 
     macro_rules! C {
         ($n:ident) => {
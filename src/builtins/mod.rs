@@ -2,20 +2,60 @@ use crate::agent::Agent;
 use crate::value::Value;
 use std::collections::HashMap;
 
+pub mod async_hooks;
+pub mod atomics;
+#[cfg(not(target_arch = "wasm32"))]
+mod bench;
+mod compiler;
+mod console;
 mod debug;
+mod form;
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
 pub mod fs;
+mod headers;
+mod immutable;
+mod intl;
 mod math;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod net;
-mod timers;
+mod process;
+mod regex;
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+mod storage;
+mod strings;
+mod tasks;
+mod time;
+pub mod timers;
 
+// net and bench need the os, which a wasm32 build doesn't have. wasi gives
+// it a filesystem, which fs and storage need.
 pub fn create(agent: &Agent) -> HashMap<String, HashMap<String, Value>> {
     let mut builtins = HashMap::new();
 
+    builtins.insert("async_hooks".to_string(), async_hooks::create(agent));
+    builtins.insert("atomics".to_string(), atomics::create(agent));
+    #[cfg(not(target_arch = "wasm32"))]
+    builtins.insert("bench".to_string(), bench::create(agent));
+    builtins.insert("compiler".to_string(), compiler::create(agent));
+    builtins.insert("console".to_string(), console::create(agent));
     builtins.insert("debug".to_string(), debug::create(agent));
+    builtins.insert("form".to_string(), form::create(agent));
+    builtins.insert("headers".to_string(), headers::create(agent));
+    builtins.insert("immutable".to_string(), immutable::create(agent));
+    builtins.insert("intl".to_string(), intl::create(agent));
+    builtins.insert("tasks".to_string(), tasks::create(agent));
+    builtins.insert("time".to_string(), time::create(agent));
     builtins.insert("timers".to_string(), timers::create(agent));
+    #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
     builtins.insert("fs".to_string(), fs::create(agent));
+    #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+    builtins.insert("storage".to_string(), storage::create(agent));
+    #[cfg(not(target_arch = "wasm32"))]
     builtins.insert("net".to_string(), net::create(agent));
     builtins.insert("math".to_string(), math::create(agent));
+    builtins.insert("process".to_string(), process::create(agent));
+    builtins.insert("regex".to_string(), regex::create(agent));
+    builtins.insert("strings".to_string(), strings::create(agent));
 
     builtins
 }
@@ -1,58 +1,79 @@
 use crate::agent::{Agent, MioMapType};
 use crate::interpreter::Context;
 use crate::intrinsics::net_client_prototype::{get_or_create_reject, get_or_create_resolve};
-use crate::value::Value;
+use crate::intrinsics::promise::new_promise_capability;
+use crate::value::{ObjectKey, ObjectKind, Value};
 use crate::IntoValue;
-use mio::{net::TcpStream, PollOpt, Ready, Token};
+use mio::{net::TcpStream, PollOpt, Ready, Registration, Token};
 use std::collections::HashMap;
 use std::io::prelude::*;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
 
+// the stream is missing when replaying, nothing is sent and what is received
+// comes from the log. a lookup is a resolver's, resolved on the pool.
 #[derive(Debug, Finalize)]
 pub enum Net {
-    Client(TcpStream, Value),
+    Client(Option<TcpStream>, Value),
+    Lookup(Registration, Receiver<NetRead>, Value),
 }
 
 unsafe impl gc::Trace for Net {
     custom_trace!(this, {
         match this {
-            Net::Client(_, v) => mark(v),
+            Net::Client(_, v) | Net::Lookup(_, _, v) => mark(v),
         }
     });
 }
 
-pub fn handle(agent: &Agent, token: Token, net: Net) {
+// what became of a read from a socket, or of a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetRead {
+    Data(Vec<u8>),
+    End,
+    Error(String),
+    // the addresses of a host and how many seconds they may be cached for
+    Resolved(Vec<IpAddr>, u32),
+}
+
+pub fn read(stream: &TcpStream) -> NetRead {
+    match stream.take_error() {
+        Ok(Some(e)) | Err(e) => NetRead::Error(format!("{}", e)),
+        Ok(None) => {
+            let mut buf = Vec::new();
+            let mut stream = stream;
+            match stream.read_to_end(&mut buf) {
+                Ok(size) if size == 0 => NetRead::End,
+                Ok(_) => NetRead::Data(buf),
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => NetRead::Data(buf),
+                Err(e) => NetRead::Error(format!("{}", e)),
+            }
+        }
+    }
+}
+
+pub fn handle(agent: &Agent, token: Token, net: Net, read: NetRead) {
     match net {
-        Net::Client(mut stream, client) => match stream.take_error() {
-            Ok(Some(e)) | Err(e) => {
-                let e = Value::new_error(agent, &format!("{}", e));
-                get_or_create_reject(agent, client, e);
-            }
-            Ok(None) => {
-                let mut buf = Vec::new();
-                match stream.read_to_end(&mut buf) {
-                    Ok(size) if size == 0 => {
-                        get_or_create_resolve(agent, client, Value::Null, true);
-                        return;
-                    }
-                    Ok(_) => {
-                        let r = Value::new_buffer_from_vec(agent, buf);
-                        get_or_create_resolve(agent, client.clone(), r, false);
-                    }
-                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        let r = Value::new_buffer_from_vec(agent, buf);
-                        get_or_create_resolve(agent, client.clone(), r, false);
-                    }
-                    Err(e) => {
-                        let e = Value::new_error(agent, &format!("{}", e));
-                        get_or_create_reject(agent, client.clone(), e);
-                    }
+        Net::Client(stream, client) => {
+            match read {
+                NetRead::End => {
+                    get_or_create_resolve(agent, client, Value::Null, true);
+                    return;
+                }
+                NetRead::Data(buf) => {
+                    let r = Value::new_buffer_from_vec(agent, buf);
+                    get_or_create_resolve(agent, client.clone(), r, false);
                 }
-                agent
-                    .mio_map
-                    .borrow_mut()
-                    .insert(token, MioMapType::Net(Net::Client(stream, client)));
+                NetRead::Error(e) => {
+                    let e = Value::new_error(agent, &e);
+                    get_or_create_reject(agent, client.clone(), e);
+                }
+                NetRead::Resolved(..) => unreachable!(),
             }
-        },
+            agent.add_operation(token, MioMapType::Net(Net::Client(stream, client)));
+        }
+        Net::Lookup(_, _, lookup) => settle_lookup(agent, lookup, read),
     }
 }
 
@@ -63,32 +84,483 @@ fn connect(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value>
                 Ok(v) => v,
                 Err(e) => return Err(e.into_value(agent)),
             };
-            let stream = match TcpStream::connect(&addr) {
-                Ok(v) => v,
-                Err(e) => return Err(e.into_value(agent)),
+            agent.permissions.check_net(agent, &addr)?;
+            let token = agent.new_token();
+            let stream = if agent.replaying() {
+                None
+            } else {
+                let stream = match TcpStream::connect(&addr) {
+                    Ok(v) => v,
+                    Err(e) => return Err(e.into_value(agent)),
+                };
+                match agent
+                    .mio
+                    .register(&stream, token, Ready::readable(), PollOpt::edge())
+                {
+                    Ok(_) => {}
+                    Err(e) => return Err(e.into_value(agent)),
+                }
+                Some(stream)
             };
-            let token = Token(agent.mio_map.borrow().len());
-            match agent
-                .mio
-                .register(&stream, token, Ready::readable(), PollOpt::edge())
-            {
-                Ok(_) => {}
-                Err(e) => return Err(e.into_value(agent)),
-            }
             let client = Value::new_custom_object(agent.intrinsics.net_client_prototype.clone());
             client.set_slot("net client buffer", Value::new_list());
             client.set_slot("net client queue", Value::new_list());
             client.set_slot("net client token", Value::from(token.0 as f64));
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::Net(Net::Client(stream, client.clone())));
+            agent.add_operation(token, MioMapType::Net(Net::Client(stream, client.clone())));
             Ok(client)
         }
         _ => Err(Value::new_error(agent, "address must be a string")),
     }
 }
 
+// a resolver remembers the addresses it looked up for as long as their ttl
+// allows, and a host being looked up is only asked for once however many
+// resolve it meanwhile. it asks its nameservers, if it was given any, and
+// the system resolver otherwise, after the hosts file.
+
+// as used by the system resolver
+#[cfg(not(windows))]
+const HOSTS_FILE: &str = "/etc/hosts";
+#[cfg(windows)]
+const HOSTS_FILE: &str = "C:\\Windows\\System32\\drivers\\etc\\hosts";
+
+const DEFAULT_TTL: u32 = 60;
+const DEFAULT_TIMEOUT: u64 = 5000;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+// the addresses given for a host by the lines of a hosts file
+fn hosts_lookup(contents: &str, host: &str) -> Vec<IpAddr> {
+    let mut addresses = Vec::new();
+    for line in contents.lines() {
+        let mut fields = line.split('#').next().unwrap().split_whitespace();
+        let address = match fields.next().and_then(|a| a.parse().ok()) {
+            Some(address) => address,
+            None => continue,
+        };
+        if fields.any(|name| name.eq_ignore_ascii_case(host)) && !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+    addresses
+}
+
+fn dns_query(id: u16, host: &str, record: u16) -> Vec<u8> {
+    let mut query = Vec::new();
+    query.extend(&id.to_be_bytes());
+    // recursion desired, one question
+    query.extend(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(&record.to_be_bytes());
+    query.extend(&[0, 1]);
+    query
+}
+
+// the offset just past a name, which may end in a pointer to another
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)? as usize;
+        if length == 0 {
+            return Some(offset + 1);
+        }
+        if length & 0xc0 == 0xc0 {
+            return Some(offset + 2);
+        }
+        offset += length + 1;
+    }
+}
+
+// the addresses in the answer to a query and the lowest of their ttls. the
+// nameserver is expected to follow cnames, the records they point to are
+// among the answers.
+fn dns_answer(message: &[u8], id: u16) -> Option<Result<(Vec<IpAddr>, u32), String>> {
+    let u16_at = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([
+            *message.get(offset)?,
+            *message.get(offset + 1)?,
+        ]))
+    };
+    if u16_at(0)? != id || message[2] & 0x80 == 0 {
+        return None;
+    }
+    match message[3] & 0x0f {
+        0 => {}
+        3 => return Some(Err("host not found".to_string())),
+        rcode => return Some(Err(format!("nameserver failed with code {}", rcode))),
+    }
+    let mut offset = 12;
+    for _ in 0..u16_at(4)? {
+        offset = skip_name(message, offset)? + 4;
+    }
+    let mut addresses = Vec::new();
+    let mut ttl = u32::MAX;
+    for _ in 0..u16_at(6)? {
+        offset = skip_name(message, offset)?;
+        let record = u16_at(offset)?;
+        let record_ttl = (u32::from(u16_at(offset + 4)?) << 16) | u32::from(u16_at(offset + 6)?);
+        let length = u16_at(offset + 8)? as usize;
+        let data = message.get(offset + 10..offset + 10 + length)?;
+        let address = match (record, length) {
+            (TYPE_A, 4) => IpAddr::from([data[0], data[1], data[2], data[3]]),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                IpAddr::from(octets)
+            }
+            _ => {
+                offset += 10 + length;
+                continue;
+            }
+        };
+        addresses.push(address);
+        ttl = ttl.min(record_ttl);
+        offset += 10 + length;
+    }
+    Some(Ok((addresses, ttl)))
+}
+
+// asks each nameserver in turn for the ipv4 and ipv6 addresses of a host
+fn ask_nameservers(host: &str, nameservers: &[SocketAddr], timeout: Duration) -> NetRead {
+    let mut error = "no nameserver answered".to_string();
+    for nameserver in nameservers {
+        let local: SocketAddr = if nameserver.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = match UdpSocket::bind(local).and_then(|s| {
+            s.set_read_timeout(Some(timeout))?;
+            s.connect(nameserver)?;
+            Ok(s)
+        }) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error = e.to_string();
+                continue;
+            }
+        };
+        let id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u16)
+            .unwrap_or(0);
+        let ids = [id, id.wrapping_add(1)];
+        for (&id, &record) in ids.iter().zip(&[TYPE_A, TYPE_AAAA]) {
+            if let Err(e) = socket.send(&dns_query(id, host, record)) {
+                error = e.to_string();
+            }
+        }
+
+        let mut addresses = Vec::new();
+        let mut ttl = u32::MAX;
+        let mut answered = 0;
+        let mut buf = [0; 512];
+        while answered < ids.len() {
+            let size = match socket.recv(&mut buf) {
+                Ok(size) => size,
+                Err(e) => {
+                    error = e.to_string();
+                    break;
+                }
+            };
+            // anything that isn't an answer to one of the queries is ignored
+            match ids.iter().find_map(|&id| dns_answer(&buf[..size], id)) {
+                Some(Ok((found, found_ttl))) => {
+                    addresses.extend(found);
+                    if found_ttl < ttl {
+                        ttl = found_ttl;
+                    }
+                    answered += 1;
+                }
+                Some(Err(e)) => {
+                    error = e;
+                    answered += 1;
+                }
+                None => {}
+            }
+        }
+        if !addresses.is_empty() {
+            return NetRead::Resolved(addresses, ttl);
+        }
+    }
+    NetRead::Error(format!("unable to resolve '{}': {}", host, error))
+}
+
+fn find_addresses(
+    host: &str,
+    hosts: Option<&str>,
+    nameservers: &[SocketAddr],
+    options: (u32, u64),
+) -> NetRead {
+    let (ttl, timeout) = options;
+    if let Some(path) = hosts {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            let addresses = hosts_lookup(&contents, host);
+            if !addresses.is_empty() {
+                return NetRead::Resolved(addresses, ttl);
+            }
+        }
+    }
+    if !nameservers.is_empty() {
+        return ask_nameservers(host, nameservers, Duration::from_millis(timeout));
+    }
+    match (host, 0).to_socket_addrs() {
+        Ok(found) => {
+            let mut addresses = Vec::new();
+            for address in found {
+                if !addresses.contains(&address.ip()) {
+                    addresses.push(address.ip());
+                }
+            }
+            NetRead::Resolved(addresses, ttl)
+        }
+        Err(e) => NetRead::Error(format!("unable to resolve '{}': {}", host, e)),
+    }
+}
+
+fn addresses_value(agent: &Agent, addresses: &Value) -> Value {
+    match addresses {
+        Value::Tuple(items, _) => items.to_vec().into_value(agent),
+        _ => unreachable!(),
+    }
+}
+
+// settles everything waiting on a lookup, and caches what it found
+fn settle_lookup(agent: &Agent, lookup: Value, read: NetRead) {
+    let resolver = lookup.get_slot("resolver");
+    let key = match lookup.get_slot("host") {
+        Value::String(host) => ObjectKey::from(&*host),
+        _ => unreachable!(),
+    };
+    resolver
+        .get_slot("resolver pending")
+        .set(agent, key.clone(), Value::Null)
+        .unwrap();
+    let waiting = match lookup.get_slot("waiting") {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => values.borrow().clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    match read {
+        NetRead::Resolved(addresses, ttl) => {
+            let addresses = Value::Tuple(
+                Box::new(
+                    addresses
+                        .iter()
+                        .map(|a| Value::from(a.to_string()))
+                        .collect(),
+                ),
+                None,
+            );
+            if ttl > 0 {
+                let expires = agent.now() + f64::from(ttl) * 1000.0;
+                let entry = Value::Tuple(
+                    Box::new(vec![Value::from(expires), addresses.clone()]),
+                    None,
+                );
+                resolver
+                    .get_slot("resolver cache")
+                    .set(agent, key, entry)
+                    .unwrap();
+            }
+            for promise in waiting {
+                let value = addresses_value(agent, &addresses);
+                promise
+                    .get_slot("resolve")
+                    .call(agent, promise, vec![value])
+                    .unwrap();
+            }
+        }
+        NetRead::Error(e) => {
+            for promise in waiting {
+                let error = Value::new_error(agent, &e);
+                error
+                    .set(agent, ObjectKey::from("code"), Value::from("NotFound"))
+                    .unwrap();
+                promise
+                    .get_slot("reject")
+                    .call(agent, promise, vec![error])
+                    .unwrap();
+            }
+        }
+        NetRead::Data(..) | NetRead::End => unreachable!(),
+    }
+}
+
+// a promise for the addresses of a host, as strings
+fn resolve(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("resolver cache") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    let host = match args.get(0) {
+        Some(Value::String(host)) if !host.is_empty() => host.to_ascii_lowercase(),
+        _ => return Err(Value::new_error(agent, "host must be a string")),
+    };
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+
+    if let Ok(address) = host.parse::<IpAddr>() {
+        let value = vec![address.to_string()].into_value(agent);
+        promise
+            .get_slot("resolve")
+            .call(agent, promise.clone(), vec![value])?;
+        return Ok(promise);
+    }
+
+    let key = ObjectKey::from(host.as_str());
+    if let Value::Tuple(entry, _) = this.get_slot("resolver cache").get(agent, key.clone())? {
+        if let Value::Number(expires) = entry[0] {
+            if agent.now() < expires {
+                let value = addresses_value(agent, &entry[1]);
+                promise
+                    .get_slot("resolve")
+                    .call(agent, promise.clone(), vec![value])?;
+                return Ok(promise);
+            }
+        }
+    }
+
+    let pending = this.get_slot("resolver pending");
+    if let lookup @ Value::Object(..) = pending.get(agent, key.clone())? {
+        if let Value::Object(o) = lookup.get_slot("waiting") {
+            if let ObjectKind::Array(values) = &o.kind {
+                values.borrow_mut().push(promise.clone());
+            }
+        }
+        return Ok(promise);
+    }
+
+    let lookup = Value::new_custom_object(Value::Null);
+    lookup.set_slot("resolver", this.clone());
+    lookup.set_slot("host", Value::from(host.as_str()));
+    lookup.set_slot("waiting", vec![promise.clone()].into_value(agent));
+    pending.set(agent, key, lookup.clone())?;
+
+    let hosts = match this.get_slot("resolver hosts") {
+        Value::String(path) => Some(path.to_string()),
+        _ => None,
+    };
+    let nameservers = match this.get_slot("resolver nameservers") {
+        Value::Tuple(items, _) => items
+            .iter()
+            .map(|n| match n {
+                Value::String(s) => s.parse().unwrap(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<SocketAddr>>(),
+        _ => unreachable!(),
+    };
+    let options = match (
+        this.get_slot("resolver ttl"),
+        this.get_slot("resolver timeout"),
+    ) {
+        (Value::Number(ttl), Value::Number(timeout)) => (ttl as u32, timeout as u64),
+        _ => unreachable!(),
+    };
+
+    let (registration, set_readiness) = Registration::new2();
+    let token = agent.new_token();
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    let (sender, receiver) = channel();
+    agent.add_operation(
+        token,
+        MioMapType::Net(Net::Lookup(registration, receiver, lookup)),
+    );
+    if !agent.replaying() {
+        agent.pool.execute(move || {
+            let read = find_addresses(&host, hosts.as_deref(), &nameservers, options);
+            if sender.send(read).is_ok() {
+                set_readiness.set_readiness(Ready::readable()).unwrap();
+            }
+        });
+    }
+    Ok(promise)
+}
+
+// forgets every cached address
+fn clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot("resolver cache") {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    this.set_slot("resolver cache", Value::new_object(Value::Null));
+    Ok(Value::Null)
+}
+
+// options are `nameservers`, an array of `ip:port` to ask instead of the
+// system resolver, `hosts`, the path of a hosts file to read first or false
+// to skip it, `ttl`, the seconds to cache what the hosts file or the system
+// resolver gave, and `timeout`, the milliseconds to wait on a nameserver
+fn create_resolver(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let options = args.get(0).cloned().unwrap_or(Value::Null);
+    let option = |name: &str| -> Result<Value, Value> {
+        if options == Value::Null {
+            Ok(Value::Null)
+        } else {
+            options.get(agent, ObjectKey::from(name))
+        }
+    };
+
+    let mut nameservers = Vec::new();
+    match option("nameservers")? {
+        Value::Null => {}
+        list => {
+            for key in list.keys(agent)? {
+                let address = match list.get(agent, key)? {
+                    Value::String(s) => s.parse::<SocketAddr>().map_err(|e| e.into_value(agent))?,
+                    _ => return Err(Value::new_error(agent, "nameservers must be strings")),
+                };
+                agent.permissions.check_net(agent, &address)?;
+                nameservers.push(Value::from(address.to_string()));
+            }
+        }
+    }
+    let hosts = match option("hosts")? {
+        Value::Null | Value::Boolean(true) => Value::from(HOSTS_FILE),
+        Value::Boolean(false) => Value::Null,
+        Value::String(path) => {
+            agent.permissions.check_read(agent, &path)?;
+            Value::String(path)
+        }
+        _ => return Err(Value::new_error(agent, "hosts must be a path or false")),
+    };
+    let number = |name: &str, default: f64| -> Result<Value, Value> {
+        match option(name)? {
+            Value::Null => Ok(Value::from(default)),
+            Value::Number(n) if n >= 0.0 && n.is_finite() => Ok(Value::from(n.floor())),
+            _ => Err(Value::new_error(
+                agent,
+                &format!("{} must be a number of at least 0", name),
+            )),
+        }
+    };
+
+    let resolver =
+        Value::new_custom_object(ctx.function.clone().unwrap().get_slot("resolver prototype"));
+    resolver.set_slot("resolver cache", Value::new_object(Value::Null));
+    resolver.set_slot("resolver pending", Value::new_object(Value::Null));
+    resolver.set_slot(
+        "resolver nameservers",
+        Value::Tuple(Box::new(nameservers), None),
+    );
+    resolver.set_slot("resolver hosts", hosts);
+    resolver.set_slot("resolver ttl", number("ttl", f64::from(DEFAULT_TTL))?);
+    resolver.set_slot(
+        "resolver timeout",
+        number("timeout", DEFAULT_TIMEOUT as f64)?,
+    );
+    Ok(resolver)
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
     module.insert(
@@ -96,5 +568,24 @@ pub fn create(agent: &Agent) -> HashMap<String, Value> {
         Value::new_builtin_function(agent, connect),
     );
 
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    proto
+        .set(
+            agent,
+            ObjectKey::from("resolve"),
+            Value::new_builtin_function(agent, resolve),
+        )
+        .unwrap();
+    proto
+        .set(
+            agent,
+            ObjectKey::from("clear"),
+            Value::new_builtin_function(agent, clear),
+        )
+        .unwrap();
+    let f = Value::new_builtin_function(agent, create_resolver);
+    f.set_slot("resolver prototype", proto);
+    module.insert("createResolver".to_string(), f);
+
     module
 }
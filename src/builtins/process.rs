@@ -0,0 +1,229 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+use crate::IntoValue;
+use std::collections::HashMap;
+
+// what the script was run with, after its filename
+fn args(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(agent.args.into_value(agent))
+}
+
+// an environment variable, or null if it isn't set
+fn env(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(name)) => {
+            agent.permissions.check_env(agent)?;
+            Ok(std::env::var(&**name).ok().into_value(agent))
+        }
+        _ => Err(Value::new_error(agent, "name must be a string")),
+    }
+}
+
+// replaces `$NAME`, `${NAME}` and `${NAME:-default}` with the value of a
+// variable, or nothing if it isn't set. with escapes, as in double quotes,
+// `\n`, `\t` and a backslash before any other character are read too.
+fn expand(
+    raw: &str,
+    escapes: bool,
+    resolve: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escapes => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err("unterminated ${".to_string()),
+                    }
+                }
+                let mut parts = inner.splitn(2, ":-");
+                let name = parts.next().unwrap();
+                match (resolve(name), parts.next()) {
+                    (Some(value), _) if !value.is_empty() => out += &value,
+                    (_, Some(default)) => out += &expand(default, false, resolve)?,
+                    (value, None) => out += &value.unwrap_or_default(),
+                }
+            }
+            '$' if chars
+                .peek()
+                .map_or(false, |c| c.is_ascii_alphabetic() || *c == '_') =>
+            {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                out += &resolve(&name).unwrap_or_default();
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+// `NAME=value` lines, optionally starting with `export`. values may be
+// single quoted, taken as they are, or double quoted, which can span lines
+// and hold escapes. unquoted and double quoted values are expanded, and
+// `#` starts a comment outside of quotes.
+fn parse_env(
+    source: &str,
+    resolve: &dyn Fn(&[(String, String)], &str) -> Option<String>,
+) -> Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut lines = source.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap().trim();
+        let rest = match parts.next() {
+            Some(rest) => rest.trim_start(),
+            None => return Err((i, "expected NAME=value".to_string())),
+        };
+        let mut name_chars = name.chars();
+        if !name_chars
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            || !name_chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err((i, format!("invalid variable name '{}'", name)));
+        }
+
+        let error = |e| (i, e);
+        let value = match rest.chars().next() {
+            Some(quote @ '\'') | Some(quote @ '"') => {
+                // the quoted text and whatever follows it on its last line
+                let mut text = rest[1..].to_string();
+                let end = loop {
+                    let mut escaped = false;
+                    let end = text.char_indices().find(|&(_, c)| {
+                        let end = c == quote && !escaped;
+                        escaped = quote == '"' && c == '\\' && !escaped;
+                        end
+                    });
+                    if let Some((end, _)) = end {
+                        break end;
+                    }
+                    match lines.next() {
+                        Some((_, line)) => {
+                            text.push('\n');
+                            text += line;
+                        }
+                        None => return Err((i, "unterminated quote".to_string())),
+                    }
+                };
+                let after = text[end + 1..].trim_start();
+                if !after.is_empty() && !after.starts_with('#') {
+                    return Err((i, "unexpected text after quoted value".to_string()));
+                }
+                if quote == '"' {
+                    expand(&text[..end], true, &|n| resolve(&vars, n)).map_err(error)?
+                } else {
+                    text[..end].to_string()
+                }
+            }
+            _ => {
+                let raw = match rest.find(" #").or_else(|| rest.find("\t#")) {
+                    Some(comment) => &rest[..comment],
+                    None => rest,
+                };
+                expand(raw.trim_end(), false, &|n| resolve(&vars, n)).map_err(error)?
+            }
+        };
+        vars.retain(|(n, _)| n != name);
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+// reads a `.env` file into the environment. variables that are already set
+// are kept unless `override` is true, and are what the file sees when it
+// refers to them. returns an object of what the file set.
+fn load_env_file(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let filename = match args.get(0) {
+        Some(Value::String(filename)) => filename.to_string(),
+        _ => return Err(Value::new_error(agent, "filename must be a string")),
+    };
+    let overrides = match args.get(1) {
+        None | Some(Value::Null) => false,
+        Some(options) => match options.get(agent, ObjectKey::from("override"))? {
+            Value::Null => false,
+            Value::Boolean(b) => b,
+            _ => return Err(Value::new_error(agent, "override must be a boolean")),
+        },
+    };
+    agent.permissions.check_read(agent, &filename)?;
+    agent.permissions.check_env(agent)?;
+    let source = std::fs::read_to_string(&filename).map_err(|e| {
+        Value::new_error(agent, &format!("unable to read file '{}': {}", filename, e))
+    })?;
+
+    let resolve = |vars: &[(String, String)], name: &str| {
+        let defined = vars.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        match std::env::var(name).ok() {
+            Some(value) if !overrides => Some(value),
+            value => defined.or(value),
+        }
+    };
+    let vars = parse_env(&source, &resolve).map_err(|(line, message)| {
+        Value::new_error(agent, &format!("{}:{}: {}", filename, line + 1, message))
+    })?;
+
+    let loaded = Value::new_object(agent.intrinsics.object_prototype.clone());
+    for (name, value) in vars {
+        if overrides || std::env::var_os(&name).is_none() {
+            std::env::set_var(&name, &value);
+        }
+        loaded.set(agent, ObjectKey::from(name.as_str()), Value::from(value))?;
+    }
+    Ok(loaded)
+}
+
+// called with whatever a callback, like a timer's, throws that nothing
+// catches, in place of printing it. null removes the handler.
+fn on_uncaught_exception(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let handler = args.get(0).cloned().unwrap_or(Value::Null);
+    if handler != Value::Null && handler.type_of() != "function" {
+        return Err(Value::new_error(
+            agent,
+            "handler must be a function or null",
+        ));
+    }
+    agent.set_uncaught_exception_callback(handler);
+    Ok(Value::Null)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert("args".to_string(), Value::new_builtin_function(agent, args));
+    module.insert("env".to_string(), Value::new_builtin_function(agent, env));
+    module.insert(
+        "loadEnvFile".to_string(),
+        Value::new_builtin_function(agent, load_env_file),
+    );
+    module.insert(
+        "onUncaughtException".to_string(),
+        Value::new_builtin_function(agent, on_uncaught_exception),
+    );
+
+    module
+}
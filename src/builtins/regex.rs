@@ -0,0 +1,22 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::Value;
+use std::collections::HashMap;
+
+// compiles a pattern ahead of time. literals with the same pattern reuse it.
+fn compile(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    match args.get(0) {
+        Some(Value::String(pattern)) => Value::new_regex_object(agent, pattern),
+        _ => Err(Value::new_error(agent, "pattern must be a string")),
+    }
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+    module.insert(
+        "compile".to_string(),
+        Value::new_builtin_function(agent, compile),
+    );
+
+    module
+}
@@ -0,0 +1,404 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::replay::{hex, unhex};
+use crate::value::{ObjectKey, ObjectKind, Value};
+use crate::IntoValue;
+use std::collections::HashMap;
+use std::io::Write;
+
+// a store is a log of writes, one line per batch, so a batch is either all
+// there or, if the process died while writing it, a partial last line that
+// is dropped when the store is next opened. the log is rewritten with only
+// the live entries once it has grown to several times their number.
+//
+// a line is `set <key> <value>` and `delete <key>` repeated, with keys in hex
+// and values as a type letter followed by their contents. entries are kept
+// in memory in that encoding, so nothing handed out aliases the store.
+//
+// a store isn't meant to be opened by more than one process at a time.
+
+const HEADER: &str = "slither-storage 1";
+
+enum Op {
+    Set(String, String),
+    Delete(String),
+}
+
+fn encode(agent: &Agent, value: &Value) -> Result<String, Value> {
+    match value {
+        Value::String(s) => Ok(format!("s{}", hex(s.as_bytes()))),
+        Value::Number(n) => Ok(format!("n{:?}", n)),
+        Value::Boolean(b) => Ok(if *b { "t" } else { "f" }.to_string()),
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Buffer(b) => Ok(format!("x{}", hex(&b.borrow()))),
+            _ => Err(Value::new_error(
+                agent,
+                "value must be a string, number, boolean or buffer",
+            )),
+        },
+        _ => Err(Value::new_error(
+            agent,
+            "value must be a string, number, boolean or buffer",
+        )),
+    }
+}
+
+fn valid(encoded: &str) -> bool {
+    let split = match (encoded.get(..1), encoded.get(1..)) {
+        (Some(kind), Some(rest)) => (kind, rest),
+        _ => return false,
+    };
+    match split {
+        ("s", s) => unhex(s).map_or(false, |b| String::from_utf8(b).is_ok()),
+        ("n", n) => n.parse::<f64>().is_ok(),
+        ("t", "") | ("f", "") => true,
+        ("x", x) => unhex(x).is_some(),
+        _ => false,
+    }
+}
+
+fn decode(agent: &Agent, encoded: &str) -> Value {
+    match encoded.split_at(1) {
+        ("s", s) => Value::from(String::from_utf8(unhex(s).unwrap()).unwrap()),
+        ("n", n) => Value::from(n.parse::<f64>().unwrap()),
+        ("t", _) => Value::from(true),
+        ("f", _) => Value::from(false),
+        ("x", x) => Value::new_buffer_from_vec(agent, unhex(x).unwrap()),
+        _ => unreachable!(),
+    }
+}
+
+fn format_ops(ops: &[Op]) -> String {
+    let mut line = Vec::new();
+    for op in ops {
+        match op {
+            Op::Set(key, value) => {
+                line.push(format!("set {} {}", hex(key.as_bytes()), value));
+            }
+            Op::Delete(key) => line.push(format!("delete {}", hex(key.as_bytes()))),
+        }
+    }
+    line.join(" ")
+}
+
+fn parse_ops(line: &str) -> Option<Vec<Op>> {
+    let mut ops = Vec::new();
+    let mut words = line.split(' ');
+    while let Some(op) = words.next() {
+        let key = String::from_utf8(unhex(words.next()?)?).ok()?;
+        match op {
+            "set" => {
+                let value = words.next()?;
+                if !valid(value) {
+                    return None;
+                }
+                ops.push(Op::Set(key, value.to_string()));
+            }
+            "delete" => ops.push(Op::Delete(key)),
+            _ => return None,
+        }
+    }
+    Some(ops)
+}
+
+fn apply(agent: &Agent, entries: &Value, ops: &[Op]) {
+    for op in ops {
+        match op {
+            Op::Set(key, value) => entries.set(
+                agent,
+                ObjectKey::from(key.as_str()),
+                Value::from(value.as_str()),
+            ),
+            Op::Delete(key) => entries.set(agent, ObjectKey::from(key.as_str()), Value::Null),
+        }
+        .unwrap();
+    }
+}
+
+// the live entries, sorted by key
+fn live(agent: &Agent, entries: &Value) -> Vec<(String, String)> {
+    let mut live = Vec::new();
+    for key in entries.keys(agent).unwrap() {
+        if let Value::String(value) = entries.get(agent, key.clone()).unwrap() {
+            live.push((key.to_string(), value.to_string()));
+        }
+    }
+    live.sort();
+    live
+}
+
+fn io_error(agent: &Agent, path: &str, e: std::io::Error) -> Value {
+    Value::new_error(agent, &format!("unable to write storage '{}': {}", path, e))
+}
+
+// writes the live entries to a new file and moves it over the log
+fn compact(agent: &Agent, store: &Value) -> Result<(), Value> {
+    let path = match store.get_slot("storage path") {
+        Value::String(path) => path.to_string(),
+        _ => unreachable!(),
+    };
+    let live = live(agent, &store.get_slot("storage entries"));
+    let mut contents = format!("{}\n", HEADER);
+    for (key, value) in &live {
+        contents += &format_ops(&[Op::Set(key.clone(), value.clone())]);
+        contents.push('\n');
+    }
+    let temporary = format!("{}.tmp", path);
+    let result = std::fs::File::create(&temporary)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temporary, &path));
+    result.map_err(|e| io_error(agent, &path, e))?;
+    store.set_slot("storage lines", Value::from(live.len() as f64));
+    Ok(())
+}
+
+fn write(agent: &Agent, store: &Value, ops: Vec<Op>) -> Result<(), Value> {
+    if ops.is_empty() {
+        return Ok(());
+    }
+    let path = match store.get_slot("storage path") {
+        Value::String(path) => path.to_string(),
+        _ => unreachable!(),
+    };
+    let line = format!("{}\n", format_ops(&ops));
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.write_all(line.as_bytes())?;
+            file.sync_data()
+        })
+        .map_err(|e| io_error(agent, &path, e))?;
+    let entries = store.get_slot("storage entries");
+    apply(agent, &entries, &ops);
+
+    let lines = match store.get_slot("storage lines") {
+        Value::Number(n) => n + 1.0,
+        _ => unreachable!(),
+    };
+    store.set_slot("storage lines", Value::from(lines));
+    if oversized(lines, live(agent, &entries).len()) {
+        compact(agent, store)?;
+    }
+    Ok(())
+}
+
+fn oversized(lines: f64, live: usize) -> bool {
+    lines > 64.0 && lines > 4.0 * live as f64
+}
+
+fn this_store(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("storage entries") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn key(agent: &Agent, key: Option<&Value>) -> Result<String, Value> {
+    match key {
+        Some(Value::String(key)) => Ok(key.to_string()),
+        _ => Err(Value::new_error(agent, "key must be a string")),
+    }
+}
+
+fn get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let key = key(agent, args.get(0))?;
+    match store
+        .get_slot("storage entries")
+        .get(agent, ObjectKey::from(key.as_str()))?
+    {
+        Value::String(encoded) => Ok(decode(agent, &encoded)),
+        _ => Ok(Value::Null),
+    }
+}
+
+fn has(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let key = key(agent, args.get(0))?;
+    let value = store
+        .get_slot("storage entries")
+        .get(agent, ObjectKey::from(key.as_str()))?;
+    Ok(Value::from(value != Value::Null))
+}
+
+fn set(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let key = key(agent, args.get(0))?;
+    let value = encode(agent, args.get(1).unwrap_or(&Value::Null))?;
+    write(agent, &store, vec![Op::Set(key, value)])?;
+    Ok(Value::Null)
+}
+
+// whether there was anything to delete
+fn delete(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let key = key(agent, args.get(0))?;
+    let value = store
+        .get_slot("storage entries")
+        .get(agent, ObjectKey::from(key.as_str()))?;
+    if value == Value::Null {
+        return Ok(Value::from(false));
+    }
+    write(agent, &store, vec![Op::Delete(key)])?;
+    Ok(Value::from(true))
+}
+
+fn prefix(agent: &Agent, prefix: Option<&Value>) -> Result<String, Value> {
+    match prefix {
+        None | Some(Value::Null) => Ok(String::new()),
+        Some(Value::String(prefix)) => Ok(prefix.to_string()),
+        _ => Err(Value::new_error(agent, "prefix must be a string")),
+    }
+}
+
+// the keys starting with a prefix, in order
+fn keys(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let prefix = prefix(agent, args.get(0))?;
+    let keys = live(agent, &store.get_slot("storage entries"))
+        .into_iter()
+        .filter(|(key, _)| key.starts_with(&prefix))
+        .map(|(key, _)| key)
+        .collect::<Vec<String>>();
+    Ok(keys.into_value(agent))
+}
+
+// `(key, value)` tuples for the keys starting with a prefix, in order
+fn entries(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let prefix = prefix(agent, args.get(0))?;
+    let entries = live(agent, &store.get_slot("storage entries"))
+        .into_iter()
+        .filter(|(key, _)| key.starts_with(&prefix))
+        .map(|(key, value)| {
+            Value::Tuple(
+                Box::new(vec![Value::from(key), decode(agent, &value)]),
+                None,
+            )
+        })
+        .collect::<Vec<Value>>();
+    Ok(entries.into_value(agent))
+}
+
+// applies an array of `{ type: 'set', key, value }` and
+// `{ type: 'delete', key }` operations all together or not at all
+fn batch(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let store = this_store(agent, ctx)?;
+    let items = match args.get(0) {
+        Some(Value::Object(o)) => match &o.kind {
+            ObjectKind::Array(items) => items.borrow().clone(),
+            _ => return Err(Value::new_error(agent, "operations must be an array")),
+        },
+        _ => return Err(Value::new_error(agent, "operations must be an array")),
+    };
+    let mut ops = Vec::new();
+    for item in items {
+        let key = key(agent, Some(&item.get(agent, ObjectKey::from("key"))?))?;
+        match item.get(agent, ObjectKey::from("type"))? {
+            Value::String(t) if &*t == "set" => {
+                let value = item.get(agent, ObjectKey::from("value"))?;
+                ops.push(Op::Set(key, encode(agent, &value)?));
+            }
+            Value::String(t) if &*t == "delete" => ops.push(Op::Delete(key)),
+            _ => {
+                return Err(Value::new_error(
+                    agent,
+                    "operation type must be 'set' or 'delete'",
+                ))
+            }
+        }
+    }
+    write(agent, &store, ops)?;
+    Ok(Value::Null)
+}
+
+// opens the store at a path, creating it if it doesn't exist
+fn open(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let path = match args.get(0) {
+        Some(Value::String(path)) => path.to_string(),
+        _ => return Err(Value::new_error(agent, "path must be a string")),
+    };
+    agent.permissions.check_read(agent, &path)?;
+    agent.permissions.check_write(agent, &path)?;
+    let corrupt = |line: usize| {
+        Value::new_error(
+            agent,
+            &format!("storage '{}' is corrupt at line {}", path, line),
+        )
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(Value::new_error(
+                agent,
+                &format!("unable to read storage '{}': {}", path, e),
+            ))
+        }
+    };
+    let entries = Value::new_object(Value::Null);
+    let mut lines = 0;
+    // anything after the last newline is a batch that was never finished
+    let complete = &contents[..contents.rfind('\n').map_or(0, |i| i + 1)];
+    if !complete.is_empty() {
+        let mut complete = complete.lines();
+        if complete.next() != Some(HEADER) {
+            return Err(corrupt(1));
+        }
+        for (i, line) in complete.enumerate() {
+            let ops = parse_ops(line).ok_or_else(|| corrupt(i + 2))?;
+            apply(agent, &entries, &ops);
+            lines += 1;
+        }
+    }
+
+    let proto = ctx.function.clone().unwrap().get_slot("storage prototype");
+    let store = Value::new_custom_object(proto);
+    store.set_slot("storage path", Value::from(path));
+    store.set_slot("storage entries", entries.clone());
+    store.set_slot("storage lines", Value::from(lines as f64));
+    if complete.len() < contents.len()
+        || complete.is_empty()
+        || oversized(lines as f64, live(agent, &entries).len())
+    {
+        compact(agent, &store)?;
+    }
+    Ok(store)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("get", get);
+    method!("has", has);
+    method!("set", set);
+    method!("delete", delete);
+    method!("keys", keys);
+    method!("entries", entries);
+    method!("batch", batch);
+
+    let open = Value::new_builtin_function(agent, open);
+    open.set_slot("storage prototype", proto);
+    module.insert("open".to_string(), open);
+
+    module
+}
@@ -0,0 +1,130 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::HashMap;
+
+// a builder keeps its text in a buffer it grows in place, so appending to it
+// doesn't copy what is already there the way `+=` on a string does
+
+fn this_builder(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("builder buffer") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+// strings as they are, anything else by its toString method like in a
+// template literal
+fn text(agent: &Agent, value: &Value) -> Result<String, Value> {
+    let value = match value {
+        Value::String(s) => return Ok(s.to_string()),
+        v => {
+            let to_string = v.get(agent, ObjectKey::from("toString"))?;
+            if to_string.type_of() != "function" {
+                return Err(Value::new_error(
+                    agent,
+                    "value does not provide a toString method",
+                ));
+            }
+            to_string.call(agent, v.clone(), vec![])?
+        }
+    };
+    match value {
+        Value::String(s) => Ok(s.to_string()),
+        _ => Err(Value::new_error(agent, "toString must return a string")),
+    }
+}
+
+fn push(agent: &Agent, builder: &Value, args: &[Value], line: bool) -> Result<(), Value> {
+    let mut out = String::new();
+    for arg in args {
+        out += &text(agent, arg)?;
+    }
+    if line {
+        out.push('\n');
+    }
+    if let Value::Object(o) = builder.get_slot("builder buffer") {
+        if let ObjectKind::Buffer(buffer) = &o.kind {
+            agent.count_bytes(out.len());
+            buffer.borrow_mut().extend_from_slice(out.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+// each argument in turn, returning the builder so calls can be chained
+fn append(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let builder = this_builder(agent, ctx)?;
+    push(agent, &builder, &args, false)?;
+    Ok(builder)
+}
+
+// likewise, followed by a newline
+fn append_line(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let builder = this_builder(agent, ctx)?;
+    push(agent, &builder, &args, true)?;
+    Ok(builder)
+}
+
+fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let builder = this_builder(agent, ctx)?;
+    match builder.get_slot("builder buffer") {
+        Value::Object(o) => match &o.kind {
+            // only whole strings are appended, so this is always utf-8
+            ObjectKind::Buffer(buffer) => Ok(Value::from(
+                String::from_utf8(buffer.borrow().clone()).unwrap(),
+            )),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// with or without `new`, optionally starting with some text
+fn string_builder(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let prototype = ctx
+        .function
+        .clone()
+        .unwrap()
+        .get(agent, ObjectKey::from("prototype"))?;
+    let builder = Value::new_custom_object(prototype);
+    builder.set_slot(
+        "builder buffer",
+        Value::new_buffer_from_vec(agent, Vec::new()),
+    );
+    push(agent, &builder, &args[..args.len().min(1)], false)?;
+    Ok(builder)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            prototype
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("append", append);
+    method!("appendLine", append_line);
+    method!("toString", to_string);
+
+    let constructor = Value::new_builtin_function(agent, string_builder);
+    constructor
+        .set(agent, ObjectKey::from("prototype"), prototype.clone())
+        .unwrap();
+    prototype
+        .set(agent, ObjectKey::from("constructor"), constructor.clone())
+        .unwrap();
+    module.insert("StringBuilder".to_string(), constructor);
+
+    module
+}
@@ -0,0 +1,269 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::{new_promise_capability, promise_resolve_i};
+use crate::value::{ObjectKey, Value};
+use std::collections::HashMap;
+
+// a scope counts the tasks still running in it, the body passed to
+// withScope being the first. once the count is back to zero the scope is
+// done and the promise withScope returned settles. the prototypes are in the
+// module's state, which each of its functions carries.
+
+fn this_object(agent: &Agent, ctx: &Context, slot: &str) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot(slot) {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn aborted(agent: &Agent, signal: &Value) -> Result<bool, Value> {
+    Ok(signal.get(agent, ObjectKey::from("aborted"))? == Value::from(true))
+}
+
+// marks the signal aborted and calls its listeners, once
+fn abort(agent: &Agent, signal: &Value, reason: Value) -> Result<(), Value> {
+    if aborted(agent, signal)? {
+        return Ok(());
+    }
+    signal.set(agent, ObjectKey::from("aborted"), Value::from(true))?;
+    signal.set(agent, ObjectKey::from("reason"), reason.clone())?;
+    let listeners = signal.get_slot("signal listeners");
+    signal.set_slot("signal listeners", Value::new_array(agent));
+    let mut i = 0;
+    loop {
+        let listener = listeners.get(agent, ObjectKey::Number(i))?;
+        if listener == Value::Null {
+            return Ok(());
+        }
+        if let Err(e) = listener.call(agent, Value::Null, vec![reason.clone()]) {
+            agent.uncaught_exception(e);
+        }
+        i += 1;
+    }
+}
+
+// calls the listener with the reason once the signal is aborted, right away
+// if it already is
+fn on_abort(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let signal = this_object(agent, ctx, "signal listeners")?;
+    let listener = args.get(0).cloned().unwrap_or(Value::Null);
+    if listener.type_of() != "function" {
+        return Err(Value::new_error(agent, "listener must be a function"));
+    }
+    if aborted(agent, &signal)? {
+        let reason = signal.get(agent, ObjectKey::from("reason"))?;
+        listener.call(agent, Value::Null, vec![reason])?;
+    } else {
+        let listeners = signal.get_slot("signal listeners");
+        let mut i = 0;
+        while listeners.get(agent, ObjectKey::Number(i))? != Value::Null {
+            i += 1;
+        }
+        listeners.set(agent, ObjectKey::Number(i), listener)?;
+    }
+    Ok(Value::Null)
+}
+
+fn throw_if_aborted(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let signal = this_object(agent, ctx, "signal listeners")?;
+    if aborted(agent, &signal)? {
+        return Err(signal.get(agent, ObjectKey::from("reason"))?);
+    }
+    Ok(Value::Null)
+}
+
+fn pending(scope: &Value) -> f64 {
+    match scope.get_slot("scope pending") {
+        Value::Number(n) => n,
+        _ => unreachable!(),
+    }
+}
+
+// one task of the scope is done, the scope with it if it was the last
+fn settled(agent: &Agent, scope: &Value) -> Result<(), Value> {
+    let left = pending(scope) - 1.0;
+    scope.set_slot("scope pending", Value::from(left));
+    if left > 0.0 {
+        return Ok(());
+    }
+    let promise = scope.get_slot("scope promise");
+    if scope.get_slot("scope failed") == Value::from(true) {
+        let error = scope.get_slot("scope error");
+        promise
+            .get_slot("reject")
+            .call(agent, Value::Null, vec![error])?;
+    } else {
+        let result = scope.get_slot("scope result");
+        promise
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![result])?;
+    }
+    Ok(())
+}
+
+// the first failure is what the scope rejects with, and cancels the rest
+fn failed(agent: &Agent, scope: &Value, error: Value) -> Result<(), Value> {
+    if scope.get_slot("scope failed") != Value::from(true) {
+        scope.set_slot("scope failed", Value::from(true));
+        scope.set_slot("scope error", error.clone());
+        abort(agent, &scope.get_slot("scope signal"), error)?;
+    }
+    settled(agent, scope)
+}
+
+fn body_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let scope = ctx.function.clone().unwrap().get_slot("scope");
+    scope.set_slot("scope result", args.get(0).cloned().unwrap_or(Value::Null));
+    settled(agent, &scope)?;
+    Ok(Value::Null)
+}
+
+fn task_fulfilled(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let scope = ctx.function.clone().unwrap().get_slot("scope");
+    settled(agent, &scope)?;
+    Ok(Value::Null)
+}
+
+fn task_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let scope = ctx.function.clone().unwrap().get_slot("scope");
+    failed(agent, &scope, args.get(0).cloned().unwrap_or(Value::Null))?;
+    Ok(Value::Null)
+}
+
+// counts f(signal) as a task of the scope, returning the promise of its
+// result
+fn start(
+    agent: &Agent,
+    scope: &Value,
+    f: &Value,
+    on_fulfilled: fn(&Agent, Vec<Value>, &Context) -> Result<Value, Value>,
+) -> Result<Value, Value> {
+    scope.set_slot("scope pending", Value::from(pending(scope) + 1.0));
+    let result = f
+        .call(agent, Value::Null, vec![scope.get_slot("scope signal")])
+        .and_then(|r| promise_resolve_i(agent, agent.intrinsics.promise.clone(), r));
+    let promise = match result {
+        Ok(promise) => promise,
+        Err(e) => {
+            let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+            promise
+                .get_slot("reject")
+                .call(agent, Value::Null, vec![e])?;
+            promise
+        }
+    };
+    let fulfilled = Value::new_builtin_function(agent, on_fulfilled);
+    fulfilled.set_slot("scope", scope.clone());
+    let rejected = Value::new_builtin_function(agent, task_rejected);
+    rejected.set_slot("scope", scope.clone());
+    promise.get(agent, ObjectKey::from("then"))?.call(
+        agent,
+        promise.clone(),
+        vec![fulfilled, rejected],
+    )?;
+    Ok(promise)
+}
+
+// runs f(signal) as a task of the scope. tasks can only be spawned until the
+// scope is done, and not once it is cancelled.
+fn spawn(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let scope = this_object(agent, ctx, "scope pending")?;
+    let f = args.get(0).cloned().unwrap_or(Value::Null);
+    if f.type_of() != "function" {
+        return Err(Value::new_error(agent, "task must be a function"));
+    }
+    if pending(&scope) == 0.0 {
+        return Err(Value::new_error(agent, "scope has finished"));
+    }
+    let signal = scope.get_slot("scope signal");
+    if aborted(agent, &signal)? {
+        return Err(signal.get(agent, ObjectKey::from("reason"))?);
+    }
+    start(agent, &scope, &f, task_fulfilled)
+}
+
+// aborts the scope's signal with the reason, or an error saying it was
+// cancelled. tasks notice it themselves, the scope is done once they finish.
+fn cancel(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let scope = this_object(agent, ctx, "scope pending")?;
+    let reason = match args.get(0) {
+        None | Some(Value::Null) => Value::new_error(agent, "scope was cancelled"),
+        Some(reason) => reason.clone(),
+    };
+    abort(agent, &scope.get_slot("scope signal"), reason)?;
+    Ok(Value::Null)
+}
+
+// calls body(scope), and resolves to its result once it and every task it
+// spawned have finished. if any of them fails the scope is cancelled, and
+// the promise rejects with that first failure once the rest have finished.
+fn with_scope(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let body = args.get(0).cloned().unwrap_or(Value::Null);
+    if body.type_of() != "function" {
+        return Err(Value::new_error(agent, "body must be a function"));
+    }
+    let state = ctx.function.clone().unwrap().get_slot("tasks");
+
+    let signal = Value::new_custom_object(state.get_slot("signal prototype"));
+    signal.set_slot("signal listeners", Value::new_array(agent));
+    signal.set(agent, ObjectKey::from("aborted"), Value::from(false))?;
+    signal.set(agent, ObjectKey::from("reason"), Value::Null)?;
+
+    let scope = Value::new_custom_object(state.get_slot("scope prototype"));
+    scope.set_slot("scope pending", Value::from(0.0));
+    scope.set_slot("scope failed", Value::from(false));
+    scope.set_slot("scope error", Value::Null);
+    scope.set_slot("scope result", Value::Null);
+    scope.set_slot("scope signal", signal.clone());
+    scope.set_slot(
+        "scope promise",
+        new_promise_capability(agent, agent.intrinsics.promise.clone())?,
+    );
+    scope.set(agent, ObjectKey::from("signal"), signal)?;
+
+    // the body gets the scope rather than the signal
+    let call_body = Value::new_builtin_function(agent, call_body);
+    call_body.set_slot("body", body);
+    call_body.set_slot("scope", scope.clone());
+    start(agent, &scope, &call_body, body_fulfilled)?;
+    Ok(scope.get_slot("scope promise"))
+}
+
+fn call_body(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.clone().unwrap();
+    f.get_slot("body")
+        .call(agent, Value::Null, vec![f.get_slot("scope")])
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let state = Value::new_custom_object(Value::Null);
+    let scope_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let signal_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    state.set_slot("scope prototype", scope_prototype.clone());
+    state.set_slot("signal prototype", signal_prototype.clone());
+
+    let function = |f| {
+        let f = Value::new_builtin_function(agent, f);
+        f.set_slot("tasks", state.clone());
+        f
+    };
+    macro_rules! method {
+        ($proto:expr, $name:expr, $fn:ident) => {
+            $proto
+                .set(agent, ObjectKey::from($name), function($fn))
+                .unwrap();
+        };
+    }
+    method!(scope_prototype, "spawn", spawn);
+    method!(scope_prototype, "cancel", cancel);
+    method!(signal_prototype, "onAbort", on_abort);
+    method!(signal_prototype, "throwIfAborted", throw_if_aborted);
+
+    module.insert("withScope".to_string(), function(with_scope));
+
+    module
+}
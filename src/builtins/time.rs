@@ -0,0 +1,288 @@
+use super::timers;
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::promise::new_promise_capability;
+use crate::num_util;
+use crate::value::{ObjectKey, Symbol, Value};
+use std::collections::HashMap;
+
+// durations and instants are objects holding a number of milliseconds in a
+// slot. the functions that make them find the prototypes in the module's
+// state, which each of its functions carries.
+
+// milliseconds on a clock that never goes back, from whenever it was first
+// read. wasm32 without wasi has only the page's clock.
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn monotonic(_: &Agent) -> f64 {
+    lazy_static::lazy_static! {
+        static ref START: std::time::Instant = std::time::Instant::now();
+    }
+    START.elapsed().as_secs_f64() * 1000.0
+}
+
+#[cfg(all(target_arch = "wasm32", not(target_os = "wasi")))]
+fn monotonic(agent: &Agent) -> f64 {
+    agent.now()
+}
+
+fn new_duration(ctx: &Context, ms: f64) -> Value {
+    let state = ctx.function.clone().unwrap().get_slot("time");
+    let duration = Value::new_custom_object(state.get_slot("duration prototype"));
+    duration.set_slot("duration ms", Value::from(ms));
+    duration
+}
+
+fn duration_ms(value: &Value) -> Option<f64> {
+    match value {
+        Value::Object(..) if value.has_slot("duration ms") => match value.get_slot("duration ms") {
+            Value::Number(ms) => Some(ms),
+            _ => unreachable!(),
+        },
+        _ => None,
+    }
+}
+
+fn this_ms(agent: &Agent, ctx: &Context, slot: &str) -> Result<f64, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if !this.has_slot(slot) {
+        return Err(Value::new_error(agent, "invalid receiver"));
+    }
+    match this.get_slot(slot) {
+        Value::Number(ms) => Ok(ms),
+        _ => unreachable!(),
+    }
+}
+
+fn other_duration(agent: &Agent, args: &[Value]) -> Result<f64, Value> {
+    args.get(0)
+        .and_then(duration_ms)
+        .ok_or_else(|| Value::new_error(agent, "argument must be a Duration"))
+}
+
+fn factor(agent: &Agent, args: &[Value]) -> Result<f64, Value> {
+    match args.get(0) {
+        Some(Value::Number(n)) if n.is_finite() => Ok(*n),
+        _ => Err(Value::new_error(agent, "argument must be a finite number")),
+    }
+}
+
+// `250ms` under a second, otherwise the days, hours and minutes there are
+// and the seconds left, as in `1h 2m 3.5s`
+fn format(ms: f64) -> String {
+    if ms < 0.0 {
+        return format!("-{}", format(-ms));
+    }
+    if ms < 1000.0 {
+        return format!("{}ms", num_util::to_string(ms));
+    }
+    let mut parts = Vec::new();
+    let mut rest = ms;
+    for &(unit, size) in &[("d", 86_400_000.0), ("h", 3_600_000.0), ("m", 60_000.0)] {
+        if rest >= size {
+            let n = (rest / size).floor();
+            parts.push(format!("{}{}", num_util::to_string(n), unit));
+            rest -= n * size;
+        }
+    }
+    if rest > 0.0 || parts.is_empty() {
+        parts.push(format!("{}s", num_util::to_string(rest / 1000.0)));
+    }
+    parts.join(" ")
+}
+
+fn add(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    Ok(new_duration(ctx, ms + other_duration(agent, &args)?))
+}
+
+fn sub(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    Ok(new_duration(ctx, ms - other_duration(agent, &args)?))
+}
+
+fn mul(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    Ok(new_duration(ctx, ms * factor(agent, &args)?))
+}
+
+fn div(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    let n = factor(agent, &args)?;
+    if n == 0.0 {
+        return Err(Value::new_error(agent, "cannot divide a Duration by 0"));
+    }
+    Ok(new_duration(ctx, ms / n))
+}
+
+// -1, 0 or 1, for sorting
+fn compare(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    let other = other_duration(agent, &args)?;
+    Ok(Value::from(match ms.partial_cmp(&other).unwrap() {
+        std::cmp::Ordering::Less => -1.0,
+        std::cmp::Ordering::Equal => 0.0,
+        std::cmp::Ordering::Greater => 1.0,
+    }))
+}
+
+fn to_milliseconds(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    Ok(Value::from(this_ms(agent, ctx, "duration ms")?))
+}
+
+fn to_seconds(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    Ok(Value::from(this_ms(agent, ctx, "duration ms")? / 1000.0))
+}
+
+fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    Ok(Value::from(format(this_ms(agent, ctx, "duration ms")?)))
+}
+
+fn inspect_duration(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "duration ms")?;
+    Ok(Value::from(format!("Duration({})", format(ms))))
+}
+
+// the time since the instant
+fn elapsed(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "instant ms")?;
+    Ok(new_duration(ctx, monotonic(agent) - ms))
+}
+
+// the time from an earlier instant to this one
+fn since(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let ms = this_ms(agent, ctx, "instant ms")?;
+    let earlier = match args.get(0) {
+        Some(v @ Value::Object(..)) if v.has_slot("instant ms") => match v.get_slot("instant ms") {
+            Value::Number(ms) => ms,
+            _ => unreachable!(),
+        },
+        _ => return Err(Value::new_error(agent, "argument must be an Instant")),
+    };
+    Ok(new_duration(ctx, ms - earlier))
+}
+
+fn inspect_instant(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    this_ms(agent, ctx, "instant ms")?;
+    Ok(Value::from("Instant"))
+}
+
+fn instant(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.clone().unwrap().get_slot("time");
+    let instant = Value::new_custom_object(state.get_slot("instant prototype"));
+    instant.set_slot("instant ms", Value::from(monotonic(agent)));
+    Ok(instant)
+}
+
+// from milliseconds, or from an object of `days`, `hours`, `minutes`,
+// `seconds` and `milliseconds`
+fn duration(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let spec = args.get(0).cloned().unwrap_or(Value::Null);
+    let ms = match &spec {
+        Value::Number(ms) if ms.is_finite() => *ms,
+        Value::Object(..) | Value::Record(..) => {
+            let mut ms = 0.0;
+            for &(name, size) in &[
+                ("days", 86_400_000.0),
+                ("hours", 3_600_000.0),
+                ("minutes", 60_000.0),
+                ("seconds", 1000.0),
+                ("milliseconds", 1.0),
+            ] {
+                match spec.get(agent, ObjectKey::from(name))? {
+                    Value::Null => {}
+                    Value::Number(n) if n.is_finite() => ms += n * size,
+                    _ => {
+                        return Err(Value::new_error(
+                            agent,
+                            &format!("{} must be a finite number", name),
+                        ))
+                    }
+                }
+            }
+            ms
+        }
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "duration must be a number of milliseconds or an object",
+            ))
+        }
+    };
+    Ok(new_duration(ctx, ms))
+}
+
+fn wake(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let promise = ctx.function.clone().unwrap().get_slot("promise");
+    promise
+        .get_slot("resolve")
+        .call(agent, promise, vec![Value::Null])
+}
+
+// a promise resolved after a number of milliseconds or a Duration
+fn sleep(agent: &Agent, args: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    let ms = match args.get(0) {
+        Some(Value::Number(ms)) => Some(*ms),
+        Some(v) => duration_ms(v),
+        None => None,
+    };
+    let ms = match ms {
+        Some(ms) if ms >= 0.0 && ms.is_finite() => ms,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "duration must be a positive number or Duration",
+            ))
+        }
+    };
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    let callback = Value::new_builtin_function(agent, wake);
+    callback.set_slot("promise", promise.clone());
+    timers::start(agent, callback, ms.ceil() as u64);
+    Ok(promise)
+}
+
+pub fn create(agent: &Agent) -> HashMap<String, Value> {
+    let mut module = HashMap::new();
+
+    let state = Value::new_custom_object(Value::Null);
+    let duration_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    let instant_prototype = Value::new_object(agent.intrinsics.object_prototype.clone());
+    state.set_slot("duration prototype", duration_prototype.clone());
+    state.set_slot("instant prototype", instant_prototype.clone());
+
+    let function = |f| {
+        let f = Value::new_builtin_function(agent, f);
+        f.set_slot("time", state.clone());
+        f
+    };
+    let inspect = || ObjectKey::Symbol(Symbol::new_registered("inspect".to_string()));
+    macro_rules! method {
+        ($proto:expr, $name:expr, $fn:ident) => {
+            $proto
+                .set(agent, ObjectKey::from($name), function($fn))
+                .unwrap();
+        };
+    }
+    method!(duration_prototype, "add", add);
+    method!(duration_prototype, "sub", sub);
+    method!(duration_prototype, "mul", mul);
+    method!(duration_prototype, "div", div);
+    method!(duration_prototype, "compare", compare);
+    method!(duration_prototype, "toMilliseconds", to_milliseconds);
+    method!(duration_prototype, "toSeconds", to_seconds);
+    method!(duration_prototype, "toString", to_string);
+    duration_prototype
+        .set(agent, inspect(), function(inspect_duration))
+        .unwrap();
+    method!(instant_prototype, "elapsed", elapsed);
+    method!(instant_prototype, "since", since);
+    instant_prototype
+        .set(agent, inspect(), function(inspect_instant))
+        .unwrap();
+
+    module.insert("sleep".to_string(), function(sleep));
+    module.insert("instant".to_string(), function(instant));
+    module.insert("duration".to_string(), function(duration));
+
+    module
+}
@@ -1,56 +1,148 @@
 use crate::agent::{Agent, MioMapType};
+use crate::event_loop::{Registration, Token};
 use crate::interpreter::Context;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::linked_list::LinkedList;
-use crate::value::Value;
-use lazy_static::lazy_static;
-use mio::{PollOpt, Ready, Registration, SetReadiness, Token};
+use crate::value::{ObjectKey, Value};
+#[cfg(not(target_arch = "wasm32"))]
+use mio::{PollOpt, Ready, SetReadiness};
 use num::ToPrimitive;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::Thread;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::{Duration, Instant};
 
+#[cfg(not(target_arch = "wasm32"))]
 struct TimerList {
     instant: Instant,
-    timers: LinkedList<SetReadiness>,
+    // each with the id it can be cancelled by
+    timers: LinkedList<(u64, SetReadiness)>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl TimerList {
-    fn new(instant: Instant, timer: SetReadiness) -> Self {
+    fn new(instant: Instant, id: u64, timer: SetReadiness) -> Self {
         let mut timers = LinkedList::new();
-        timers.push_back(timer);
+        timers.push_back((id, timer));
         TimerList { instant, timers }
     }
 }
 
-lazy_static! {
-    static ref TIMERS: Mutex<LinkedList<TimerList>> = Mutex::new(LinkedList::new());
-    static ref THREAD: std::thread::JoinHandle<()> = std::thread::spawn(move || loop {
-        let mut timers = TIMERS.lock().unwrap();
-        if let Some(list) = timers.cursor().next() {
-            if Instant::now() >= list.instant {
-                while let Some(r) = list.timers.pop_front() {
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct Queue {
+    lists: LinkedList<TimerList>,
+    closed: bool,
+}
+
+// each agent fires its own timers, on a thread started by its first timeout
+// and stopped when the agent is dropped
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct Timers {
+    queue: Arc<Mutex<Queue>>,
+    thread: RefCell<Option<Thread>>,
+    next_id: Cell<u64>,
+}
+
+impl Timers {
+    fn next_id(&self) -> u64 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Timers {
+    fn insert(&self, instant: Instant, id: u64, timer: SetReadiness) {
+        insert(&mut self.queue.lock().unwrap().lists, instant, id, timer);
+        // it may be due before whatever the thread is sleeping until
+        self.thread().unpark();
+    }
+
+    // the thread is left to sleep, it finds nothing due when it wakes
+    fn cancel(&self, id: u64) {
+        let mut queue = self.queue.lock().unwrap();
+        let mut cursor = queue.lists.cursor();
+        while let Some(list) = cursor.peek_next() {
+            if remove(&mut list.timers, id) {
+                if list.timers.is_empty() {
+                    cursor.remove();
+                }
+                return;
+            }
+            cursor.next();
+        }
+    }
+
+    fn thread(&self) -> Thread {
+        self.thread
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let queue = self.queue.clone();
+                std::thread::spawn(move || run(&queue)).thread().clone()
+            })
+            .clone()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Timers {
+    fn drop(&mut self) {
+        self.queue.lock().unwrap().closed = true;
+        if let Some(thread) = self.thread.borrow().as_ref() {
+            thread.unpark();
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(queue: &Mutex<Queue>) {
+    loop {
+        let wait = {
+            let mut queue = queue.lock().unwrap();
+            if queue.closed {
+                return;
+            }
+            let now = Instant::now();
+            while queue
+                .lists
+                .front()
+                .map_or(false, |list| list.instant <= now)
+            {
+                let mut list = queue.lists.pop_front().unwrap();
+                while let Some((_, r)) = list.timers.pop_front() {
                     r.set_readiness(Ready::readable())
                         .expect("failed to set timer readiness");
                 }
-                timers.pop_front();
             }
-        } else {
-            std::thread::park();
+            queue.lists.front().map(|list| list.instant - now)
+        };
+        // park without holding the lock so new timers can be inserted, until
+        // the next timer is due. inserting unparks it early.
+        match wait {
+            Some(timeout) => std::thread::park_timeout(timeout),
+            None => std::thread::park(),
         }
-    });
+    }
 }
 
-fn insert(instant: Instant, timer: SetReadiness) {
-    let mut timers = TIMERS.lock().unwrap();
-    let mut cursor = timers.cursor();
+#[cfg(not(target_arch = "wasm32"))]
+fn insert(lists: &mut LinkedList<TimerList>, instant: Instant, id: u64, timer: SetReadiness) {
+    let mut cursor = lists.cursor();
     while let Some(item) = cursor.peek_next() {
         if item.instant == instant {
-            item.timers.push_back(timer);
+            item.timers.push_back((id, timer));
             return;
         }
 
         if item.instant > instant {
-            cursor.insert(TimerList::new(instant, timer));
+            cursor.insert(TimerList::new(instant, id, timer));
             return;
         }
 
@@ -58,7 +150,95 @@ fn insert(instant: Instant, timer: SetReadiness) {
     }
 
     // empty list or instant is greater than every item in the list
-    timers.push_back(TimerList::new(instant, timer));
+    lists.push_back(TimerList::new(instant, id, timer));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn remove(timers: &mut LinkedList<(u64, SetReadiness)>, id: u64) -> bool {
+    let mut cursor = timers.cursor();
+    while let Some((timer, _)) = cursor.peek_next() {
+        if *timer == id {
+            cursor.remove();
+            return true;
+        }
+        cursor.next();
+    }
+    false
+}
+
+// without threads the event loop checks for due timers itself on each turn
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+pub struct Timers {
+    // (deadline, id, token), soonest first
+    pending: RefCell<Vec<(f64, u64, Token)>>,
+    next_id: Cell<u64>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Timers {
+    fn insert(&self, deadline: f64, id: u64, token: Token) {
+        let mut pending = self.pending.borrow_mut();
+        // after any due at the same time, so they fire in order
+        let index = pending
+            .iter()
+            .position(|(d, _, _)| *d > deadline)
+            .unwrap_or_else(|| pending.len());
+        pending.insert(index, (deadline, id, token));
+    }
+
+    fn cancel(&self, id: u64) {
+        self.pending
+            .borrow_mut()
+            .retain(|(_, timer, _)| *timer != id);
+    }
+
+    pub(crate) fn due(&self, now: f64) -> Vec<Token> {
+        let mut pending = self.pending.borrow_mut();
+        let count = pending.iter().take_while(|(d, _, _)| *d <= now).count();
+        pending.drain(..count).map(|(_, _, token)| token).collect()
+    }
+
+    pub(crate) fn next(&self) -> Option<f64> {
+        self.pending
+            .borrow()
+            .first()
+            .map(|(deadline, _, _)| *deadline)
+    }
+}
+
+// starts the timer unless replaying, replayed timers fire when the log says
+// they did
+#[cfg(not(target_arch = "wasm32"))]
+fn schedule(agent: &Agent, token: Token, id: u64, ms: u64) -> Registration {
+    let end = Instant::now() + Duration::from_millis(ms);
+    let (registration, set_readiness) = Registration::new2();
+    agent
+        .mio
+        .register(&registration, token, Ready::readable(), PollOpt::edge())
+        .unwrap();
+    if !agent.replaying() {
+        agent.timers.insert(end, id, set_readiness);
+    }
+    registration
+}
+
+#[cfg(target_arch = "wasm32")]
+fn schedule(agent: &Agent, token: Token, id: u64, ms: u64) -> Registration {
+    if !agent.replaying() {
+        agent.timers.insert(agent.now() + ms as f64, id, token);
+    }
+    Registration
+}
+
+// calls the callback after ms, returning the token and id it can be
+// cancelled by
+pub(crate) fn start(agent: &Agent, callback: Value, ms: u64) -> (Token, u64) {
+    let token = agent.new_token();
+    let id = agent.timers.next_id();
+    let registration = schedule(agent, token, id, ms);
+    agent.add_operation(token, MioMapType::Timer(registration, callback, id));
+    (token, id)
 }
 
 fn create_timeout(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
@@ -68,36 +248,52 @@ fn create_timeout(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Val
     }
     match args.get(1).unwrap_or(&Value::Null) {
         Value::Number(n) => {
-            let end = Instant::now() + Duration::from_millis(n.to_u64().unwrap());
-
-            let (registration, set_readiness) = Registration::new2();
-            let token = Token(agent.mio_map.borrow().len());
+            let (token, id) = start(agent, callback.clone(), n.to_u64().unwrap());
 
-            agent
-                .mio
-                .register(&registration, token, Ready::readable(), PollOpt::edge())
-                .unwrap();
-            agent
-                .mio_map
-                .borrow_mut()
-                .insert(token, MioMapType::Timer(registration, callback.clone()));
-
-            insert(end, set_readiness);
-            THREAD.thread().unpark();
-
-            // TODO: return object with cancel()
-            Ok(Value::Null)
+            let timeout = Value::new_object(agent.intrinsics.object_prototype.clone());
+            let cancel = Value::new_builtin_function(agent, cancel_timeout);
+            cancel.set_slot("timer token", Value::from(token.0 as f64));
+            cancel.set_slot("timer id", Value::from(id as f64));
+            timeout.set(agent, ObjectKey::from("cancel"), cancel)?;
+            Ok(timeout)
         }
         _ => Err(Value::new_error(agent, "duration must be a number")),
     }
 }
 
+// returns whether the timer was still pending
+fn cancel_timeout(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let function = ctx.function.clone().unwrap();
+    let (token, id) = match (
+        function.get_slot("timer token"),
+        function.get_slot("timer id"),
+    ) {
+        (Value::Number(token), Value::Number(id)) => (Token(token as usize), id as u64),
+        _ => unreachable!(),
+    };
+    // once it has fired its token can be reused by another operation
+    let pending = match agent.mio_map.borrow().get(&token) {
+        Some(MioMapType::Timer(_, _, timer)) => *timer == id,
+        _ => false,
+    };
+    if pending {
+        agent.timers.cancel(id);
+        agent.remove_operation(token);
+    }
+    Ok(Value::from(pending))
+}
+
+fn now(agent: &Agent, _: Vec<Value>, _: &Context) -> Result<Value, Value> {
+    Ok(Value::from(agent.now()))
+}
+
 pub fn create(agent: &Agent) -> HashMap<String, Value> {
     let mut module = HashMap::new();
     module.insert(
         "createTimeout".to_string(),
         Value::new_builtin_function(agent, create_timeout),
     );
+    module.insert("now".to_string(), Value::new_builtin_function(agent, now));
 
     module
 }
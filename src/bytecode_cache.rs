@@ -0,0 +1,207 @@
+use crate::interpreter::{AssemblerFunctionInfo, Chunk};
+use crate::module::Import;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// compiled modules are kept next to their source, keyed by a hash of the
+// source so that an edited file is compiled again. bump the version when the
+// bytecode or this format changes.
+
+const MAGIC: &[u8] = b"SLBC";
+const CACHE_VERSION: u32 = 4;
+const CACHE_DIR: &str = ".slither-cache";
+
+// tells apart the temporary files of writers in the same process
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct CachedModule {
+    pub imports: Vec<Import>,
+    pub chunk: Chunk,
+}
+
+fn path(filename: &str, source: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    CACHE_VERSION.hash(&mut hasher);
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    source.hash(&mut hasher);
+    Path::new(filename)
+        .with_file_name(CACHE_DIR)
+        .join(format!("{:016x}", hasher.finish()))
+}
+
+pub fn load(filename: &str, source: &str) -> Option<CachedModule> {
+    let bytes = std::fs::read(path(filename, source)).ok()?;
+    // a truncated or stale entry is just a miss
+    read_module(&mut bytes.as_slice()).ok()
+}
+
+// failing to write the cache only costs a compile next time
+pub fn store(filename: &str, source: &str, imports: &[Import], chunk: &Chunk) {
+    let path = path(filename, source);
+    let mut bytes = Vec::new();
+    write_module(&mut bytes, imports, chunk).unwrap();
+    if std::fs::create_dir_all(path.parent().unwrap()).is_err() {
+        return;
+    }
+    // write then rename so a concurrent run never sees half an entry. each
+    // writer has its own temporary file, or two could write into one.
+    let tmp = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    if std::fs::write(&tmp, bytes).is_ok() && std::fs::rename(&tmp, &path).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) -> Result<()> {
+    out.write_u32::<LittleEndian>(s.len() as u32)?;
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn write_strings(out: &mut Vec<u8>, strings: &[String]) -> Result<()> {
+    out.write_u32::<LittleEndian>(strings.len() as u32)?;
+    for s in strings {
+        write_string(out, s)?;
+    }
+    Ok(())
+}
+
+fn write_module(out: &mut Vec<u8>, imports: &[Import], chunk: &Chunk) -> Result<()> {
+    out.extend_from_slice(MAGIC);
+    out.write_u32::<LittleEndian>(CACHE_VERSION)?;
+
+    out.write_u32::<LittleEndian>(imports.len() as u32)?;
+    for import in imports {
+        match import {
+            Import::Default(specifier, name) => {
+                out.write_u8(0)?;
+                write_string(out, specifier)?;
+                write_string(out, name)?;
+            }
+            Import::Named(specifier, names) => {
+                out.write_u8(1)?;
+                write_string(out, specifier)?;
+                write_strings(out, names)?;
+            }
+            Import::Standard(specifier, names) => {
+                out.write_u8(2)?;
+                write_string(out, specifier)?;
+                write_strings(out, names)?;
+            }
+        }
+    }
+
+    out.write_u32::<LittleEndian>(chunk.code.len() as u32)?;
+    out.extend_from_slice(&chunk.code);
+    write_strings(out, &chunk.strings)?;
+    out.write_u32::<LittleEndian>(chunk.functions.len() as u32)?;
+    for info in &chunk.functions {
+        out.write_u8(info.kind as u8)?;
+        match &info.name {
+            Some(name) => {
+                out.write_u8(1)?;
+                write_string(out, name)?;
+            }
+            None => out.write_u8(0)?,
+        }
+        write_strings(out, &info.parameters)?;
+        out.write_u32::<LittleEndian>(info.position as u32)?;
+        out.write_u32::<LittleEndian>(info.end as u32)?;
+    }
+    out.write_u32::<LittleEndian>(chunk.locations.len() as u32)?;
+    for (position, line, column) in &chunk.locations {
+        out.write_u32::<LittleEndian>(*position as u32)?;
+        out.write_u32::<LittleEndian>(*line as u32)?;
+        out.write_u32::<LittleEndian>(*column as u32)?;
+    }
+    Ok(())
+}
+
+fn invalid() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid bytecode cache")
+}
+
+fn read_usize(input: &mut &[u8]) -> Result<usize> {
+    Ok(input.read_u32::<LittleEndian>()? as usize)
+}
+
+fn read_bytes(input: &mut &[u8], len: usize) -> Result<Vec<u8>> {
+    if len > input.len() {
+        return Err(invalid());
+    }
+    let mut bytes = vec![0; len];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(input: &mut &[u8]) -> Result<String> {
+    let len = read_usize(input)?;
+    String::from_utf8(read_bytes(input, len)?).map_err(|_| invalid())
+}
+
+fn read_strings(input: &mut &[u8]) -> Result<Vec<String>> {
+    let len = read_usize(input)?;
+    (0..len).map(|_| read_string(input)).collect()
+}
+
+fn read_module(input: &mut &[u8]) -> Result<CachedModule> {
+    if read_bytes(input, MAGIC.len())? != MAGIC
+        || input.read_u32::<LittleEndian>()? != CACHE_VERSION
+    {
+        return Err(invalid());
+    }
+
+    let mut imports = Vec::new();
+    for _ in 0..read_usize(input)? {
+        let import = match input.read_u8()? {
+            0 => Import::Default(read_string(input)?, read_string(input)?),
+            1 => Import::Named(read_string(input)?, read_strings(input)?),
+            2 => Import::Standard(read_string(input)?, read_strings(input)?),
+            _ => return Err(invalid()),
+        };
+        imports.push(import);
+    }
+
+    let len = read_usize(input)?;
+    let code = read_bytes(input, len)?;
+    let strings = read_strings(input)?;
+    let mut functions = Vec::new();
+    for _ in 0..read_usize(input)? {
+        let kind = input.read_u8()?;
+        if kind == 0 || kind > 0b1111 {
+            return Err(invalid());
+        }
+        let name = match input.read_u8()? {
+            0 => None,
+            _ => Some(read_string(input)?),
+        };
+        functions.push(AssemblerFunctionInfo {
+            kind: kind.into(),
+            name,
+            parameters: read_strings(input)?,
+            position: read_usize(input)?,
+            end: read_usize(input)?,
+        });
+    }
+    let mut locations = Vec::new();
+    for _ in 0..read_usize(input)? {
+        locations.push((read_usize(input)?, read_usize(input)?, read_usize(input)?));
+    }
+
+    Ok(CachedModule {
+        imports,
+        chunk: Chunk {
+            code,
+            strings,
+            functions,
+            locations,
+        },
+    })
+}
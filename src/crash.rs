@@ -0,0 +1,192 @@
+use crate::agent::Agent;
+use crate::interpreter::Op;
+use crate::value::{ObjectKey, Value};
+use std::any::Any;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// when the interpreter itself panics, what the script was doing is more use in
+// a bug report than the panic message alone. an agent registers itself for as
+// long as it runs code, and the hook reports on whichever one was running.
+// the panic can happen with any of its state borrowed, so whatever is
+// borrowed is left out rather than risking a second panic.
+
+// how many of the last ops evaluated are kept
+pub const RECENT_OPS: usize = 16;
+
+// the positions of the last ops evaluated, oldest first once it wraps
+#[derive(Default)]
+pub(crate) struct History {
+    positions: [Cell<usize>; RECENT_OPS],
+    count: Cell<usize>,
+}
+
+impl History {
+    pub(crate) fn push(&self, position: usize) {
+        let count = self.count.get();
+        self.positions[count % RECENT_OPS].set(position);
+        self.count.set(count + 1);
+    }
+
+    fn recent(&self) -> Vec<usize> {
+        let count = self.count.get();
+        let start = count.saturating_sub(RECENT_OPS);
+        (start..count)
+            .map(|i| self.positions[i % RECENT_OPS].get())
+            .collect()
+    }
+}
+
+// the interpreter only records recent ops when there is a handler to report
+// them
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    static RUNNING: Cell<*const Agent> = Cell::new(std::ptr::null());
+}
+
+// marks the agent as running until dropped, restoring whichever ran before
+pub(crate) struct Running(*const Agent);
+
+impl Running {
+    pub(crate) fn new(agent: &Agent) -> Running {
+        Running(RUNNING.with(|r| r.replace(agent)))
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.with(|r| r.set(self.0));
+    }
+}
+
+// replaces the panic message with a crash report while an agent is running,
+// panics anywhere else are left to the previous hook
+pub fn install_crash_handler() {
+    INSTALLED.store(true, Ordering::Relaxed);
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let agent = RUNNING.with(|r| r.get());
+        if agent.is_null() {
+            previous(info);
+            return;
+        }
+        // the hook runs before unwinding, the agent is still alive
+        let agent = unsafe { &*agent };
+        let location = match info.location() {
+            Some(l) => l.to_string(),
+            None => "unknown".to_string(),
+        };
+        eprint!("{}", report(agent, message(info.payload()), &location));
+    }));
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<Any>"
+    }
+}
+
+fn op_name(agent: &Agent, position: usize) -> &'static str {
+    let assembler = match agent.assembler.try_borrow() {
+        Ok(assembler) => assembler,
+        Err(_) => return "<unavailable>",
+    };
+    match assembler.code.get(position) {
+        Some(op) => Op::from(*op).name(),
+        None => "<end>",
+    }
+}
+
+fn source_location(agent: &Agent, position: usize) -> String {
+    match agent.assembler.try_borrow() {
+        Ok(assembler) => assembler.source_location(position),
+        Err(_) => "<unavailable>".to_string(),
+    }
+}
+
+fn function_name(function: &Option<Value>) -> String {
+    match function {
+        Some(Value::Object(o)) => match o.try_get_own(&ObjectKey::from("name")) {
+            Some(Value::String(ref s)) if !s.is_empty() => s.to_string(),
+            _ => "<anonymous>".to_string(),
+        },
+        _ => "<module>".to_string(),
+    }
+}
+
+pub(crate) fn report(agent: &Agent, message: &str, location: &str) -> String {
+    let mut out = String::new();
+    out += "slither crashed, this is a bug in the interpreter\n";
+    out += &format!("message: {}\n", message);
+    out += &format!("panicked at: {}\n", location);
+
+    let position = agent.position.get();
+    out += &format!(
+        "position: {} {} ({})\n",
+        position,
+        op_name(agent, position),
+        source_location(agent, position)
+    );
+
+    out += "call stack:\n";
+    match agent.call_stack.try_borrow() {
+        Ok(stack) => {
+            for (i, frame) in stack.iter().enumerate().rev() {
+                let position = if i == stack.len() - 1 {
+                    position
+                } else {
+                    frame.position
+                };
+                out += &format!(
+                    "    at {} ({})\n",
+                    function_name(&frame.function),
+                    source_location(agent, position)
+                );
+            }
+        }
+        Err(_) => out += "    <unavailable>\n",
+    }
+
+    out += "last ops:\n";
+    for position in agent.history.recent() {
+        out += &format!(
+            "    {:04} {} ({})\n",
+            position,
+            op_name(agent, position),
+            source_location(agent, position)
+        );
+    }
+
+    out += "event loop:\n";
+    match agent.queued_jobs() {
+        Some(n) => out += &format!("    queued jobs: {}\n", n),
+        None => out += "    queued jobs: <unavailable>\n",
+    }
+    match agent.operations.try_borrow() {
+        Ok(operations) => {
+            out += &format!("    pending operations: {}\n", operations.len());
+            let mut operations = operations.values().collect::<Vec<_>>();
+            operations.sort_by_key(|o| o.async_id.id);
+            let now = agent.now();
+            for o in operations {
+                out += &format!(
+                    "        {} #{}, started {}ms ago\n",
+                    o.kind,
+                    o.async_id.id,
+                    (now - o.started).round()
+                );
+            }
+        }
+        Err(_) => out += "    pending operations: <unavailable>\n",
+    }
+    out
+}
@@ -0,0 +1,157 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::Value;
+use gc::{Gc, GcCell};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// the agent stops at the first statement of a line when it hits a breakpoint,
+// finishes a step, or was asked to pause. stopping calls the handler, and the
+// script stays paused until the handler returns how to go on.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    Continue,
+    // stop at the next line, in a called function if that comes first
+    StepIn,
+    // stop at the next line of this function or a caller
+    StepOver,
+    // stop once this function has returned
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+    Pause,
+}
+
+pub struct Paused {
+    pub reason: PauseReason,
+    pub file: String,
+    pub line: usize,
+    pub position: usize,
+    context: Gc<GcCell<Context>>,
+}
+
+impl Paused {
+    // the bindings of every scope from the innermost out, the last one being
+    // the globals
+    pub fn scopes(&self) -> Vec<Vec<(String, Value)>> {
+        let mut scopes = Vec::new();
+        let mut scope = Some(self.context.borrow().scope.clone());
+        while let Some(s) = scope {
+            let s = s.borrow();
+            scopes.push(s.locals());
+            scope = s.parent().cloned();
+        }
+        scopes
+    }
+
+    pub fn this(&self) -> Option<Value> {
+        let mut scope = Some(self.context.borrow().scope.clone());
+        while let Some(s) = scope {
+            let s = s.borrow();
+            if s.this.is_some() {
+                return s.this.clone();
+            }
+            scope = s.parent().cloned();
+        }
+        None
+    }
+}
+
+// asks the agent to stop at the next line, from any thread
+#[derive(Clone)]
+pub struct PauseHandle(Arc<AtomicBool>);
+
+impl PauseHandle {
+    pub fn pause(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+type Handler = dyn Fn(&Agent, &Paused) -> Resume;
+
+#[derive(Default)]
+pub(crate) struct Debugger {
+    handler: Option<Box<Handler>>,
+    breakpoints: RefCell<HashSet<(String, usize)>>,
+    // how the last pause was resumed, and the call depth it was at
+    step: Cell<Option<(Resume, usize)>>,
+    pause: Arc<AtomicBool>,
+}
+
+impl Debugger {
+    pub(crate) fn is_attached(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    pub(crate) fn attach(&mut self, handler: Box<Handler>) {
+        self.handler = Some(handler);
+    }
+
+    pub(crate) fn set_breakpoint(&self, file: &str, line: usize) {
+        self.breakpoints
+            .borrow_mut()
+            .insert((file.to_string(), line));
+    }
+
+    pub(crate) fn remove_breakpoint(&self, file: &str, line: usize) {
+        self.breakpoints
+            .borrow_mut()
+            .remove(&(file.to_string(), line));
+    }
+
+    pub(crate) fn pause_handle(&self) -> PauseHandle {
+        PauseHandle(self.pause.clone())
+    }
+
+    // a step doesn't carry over to the next evaluation
+    pub(crate) fn finish(&self) {
+        self.step.set(None);
+    }
+
+    // called before each op while a handler is attached
+    pub(crate) fn check(&self, agent: &Agent, position: usize, context: &Gc<GcCell<Context>>) {
+        let assembler = agent.assembler.borrow();
+        let (file, line) = match assembler.line_start(position) {
+            Some(l) => l,
+            None => return,
+        };
+        let depth = agent.call_stack.borrow().len();
+        let reason = if self.pause.swap(false, Ordering::SeqCst) {
+            PauseReason::Pause
+        } else if self
+            .breakpoints
+            .borrow()
+            .contains(&(file.to_string(), line))
+        {
+            PauseReason::Breakpoint
+        } else {
+            match self.step.get() {
+                Some((Resume::StepIn, _)) => PauseReason::Step,
+                Some((Resume::StepOver, d)) if depth <= d => PauseReason::Step,
+                Some((Resume::StepOut, d)) if depth < d => PauseReason::Step,
+                _ => return,
+            }
+        };
+        let paused = Paused {
+            reason,
+            file: file.to_string(),
+            line,
+            position,
+            context: context.clone(),
+        };
+        // the handler may evaluate code, which assembles it
+        drop(assembler);
+        let resume = (self.handler.as_ref().unwrap())(agent, &paused);
+        self.step.set(match resume {
+            Resume::Continue => None,
+            r => Some((r, depth)),
+        });
+    }
+}
@@ -0,0 +1,147 @@
+use crate::agent::Agent;
+use crate::parser::Error;
+use crate::value::{ObjectKey, Value};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub error: Error,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Diagnostic {
+    pub fn new(error: Error, (line, column): (usize, usize)) -> Diagnostic {
+        Diagnostic {
+            error,
+            line,
+            column,
+        }
+    }
+
+    // the input ended before the syntax did, so more input could fix it
+    pub fn is_incomplete(&self) -> bool {
+        self.error == Error::UnexpectedEOF
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self.error {
+            Error::NormalEOF | Error::UnexpectedEOF => "unexpected end of input",
+            Error::UnexpectedToken => "unexpected token",
+            Error::DuplicateBinding => "duplicate binding",
+            Error::InvalidAssignmentTarget => "invalid assignment target",
+            Error::ConstantAssignment => "assignment to constant binding",
+            Error::NonExhaustiveMatch => "non-exhaustive match",
+        }
+    }
+
+    pub fn hint(&self) -> &'static str {
+        match self.error {
+            Error::NormalEOF | Error::UnexpectedEOF => {
+                "check for an unclosed bracket, string or block"
+            }
+            Error::UnexpectedToken => "check for a missing operator, separator or semicolon",
+            Error::DuplicateBinding => "a name can only be declared once in each scope",
+            Error::InvalidAssignmentTarget => {
+                "only identifiers, members and patterns can be assigned to"
+            }
+            Error::ConstantAssignment => "declare the binding with `let` to make it mutable",
+            Error::NonExhaustiveMatch => {
+                "add an arm for each missing enum member or a catch-all binding"
+            }
+        }
+    }
+
+    // unexpected token
+    //  --> file.sl:1:9
+    //   |
+    // 1 | let a = ;
+    //   |         ^
+    //   = hint: ...
+    pub fn render(&self, filename: &str, source: &str) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        format!(
+            "{}\n{}\n{} = hint: {}",
+            self.message(),
+            excerpt(filename, source, self.line, self.column),
+            gutter,
+            self.hint(),
+        )
+    }
+
+    pub fn to_error(&self, agent: &Agent, filename: &str, source: &str) -> Value {
+        let e = Value::new_error(agent, &self.render(filename, source));
+        e.set(agent, ObjectKey::from("name"), Value::from("SyntaxError"))
+            .unwrap();
+        e.set(
+            agent,
+            ObjectKey::from("code"),
+            Value::from(format!("{:?}", self.error)),
+        )
+        .unwrap();
+        e.set(
+            agent,
+            ObjectKey::from("line"),
+            Value::from(self.line as f64),
+        )
+        .unwrap();
+        e.set(
+            agent,
+            ObjectKey::from("column"),
+            Value::from(self.column as f64),
+        )
+        .unwrap();
+        e
+    }
+}
+
+// the location and source line, with a caret under the column
+pub fn excerpt(filename: &str, source: &str, line: usize, column: usize) -> String {
+    let text = source.lines().nth(line - 1).unwrap_or("");
+    let gutter = " ".repeat(line.to_string().len());
+    // keep tabs so the caret lines up with the source line
+    let padding: String = text
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let source_row = format!("{} | {}", line, text);
+    let caret_row = format!("{} | {}^", gutter, padding);
+    format!(
+        "{}--> {}:{}:{}\n{} |\n{}\n{}",
+        gutter,
+        filename,
+        line,
+        column,
+        gutter,
+        source_row.trim_end(),
+        caret_row,
+    )
+}
+
+pub fn render_all(diagnostics: &[Diagnostic], filename: &str, source: &str) -> String {
+    diagnostics
+        .iter()
+        .map(|d| d.render(filename, source))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// the first diagnostic becomes the thrown error, the rest are listed in `errors`
+pub fn to_error(agent: &Agent, diagnostics: &[Diagnostic], filename: &str, source: &str) -> Value {
+    let message = render_all(diagnostics, filename, source);
+    let e = diagnostics[0].to_error(agent, filename, source);
+    e.set(agent, ObjectKey::from("message"), Value::from(message))
+        .unwrap();
+    let errors = Value::new_array(agent);
+    for (i, d) in diagnostics.iter().enumerate() {
+        errors
+            .set(
+                agent,
+                ObjectKey::from(i),
+                d.to_error(agent, filename, source),
+            )
+            .unwrap();
+    }
+    e.set(agent, ObjectKey::from("errors"), errors).unwrap();
+    e
+}
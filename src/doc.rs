@@ -0,0 +1,98 @@
+use crate::ast_json;
+use crate::formatter;
+use crate::json;
+use crate::parser::{Doc, FunctionKind, Node};
+
+// the api of a module: its exported functions, whether declared or bound to
+// a function expression, with their parameters and the `///` comments on
+// their export.
+
+struct Function<'a> {
+    name: &'a str,
+    kind: FunctionKind,
+    // as written, defaults included
+    params: Vec<String>,
+    doc: Option<&'a str>,
+    line: usize,
+}
+
+impl<'a> Function<'a> {
+    fn signature(&self) -> String {
+        format!(
+            "{}{}({})",
+            formatter::function_prefix(self.kind),
+            self.name,
+            self.params.join(", ")
+        )
+    }
+}
+
+fn exported<'a>(ast: &'a Node, docs: &'a [Doc]) -> Vec<Function<'a>> {
+    let stmts = match ast {
+        Node::Block(_, stmts) => stmts,
+        _ => unreachable!(),
+    };
+    let mut functions = Vec::new();
+    let mut position = (0, 0);
+    for stmt in stmts {
+        let decl = match stmt {
+            Node::Position(line, column) => {
+                position = (*line, *column);
+                continue;
+            }
+            Node::ExportDeclaration(decl) => decl,
+            _ => continue,
+        };
+        let (name, kind, params) = match &**decl {
+            Node::FunctionDeclaration(kind, name, params, _) => (name, *kind, params),
+            Node::LexicalInitialization(name, init) => match &**init {
+                Node::FunctionExpression(kind, _, params, _)
+                | Node::ArrowFunctionExpression(kind, params, _) => (name, *kind, params),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        functions.push(Function {
+            name,
+            kind,
+            params: params.iter().map(formatter::expression).collect(),
+            doc: docs
+                .iter()
+                .find(|d| (d.line, d.column) == position)
+                .map(|d| d.text.as_str()),
+            line: position.0,
+        });
+    }
+    functions
+}
+
+pub fn to_json(module: &str, ast: &Node, docs: &[Doc]) -> String {
+    let functions = exported(ast, docs).into_iter().map(|f| {
+        json::object(vec![
+            ("name", json::quote(f.name)),
+            ("kind", ast_json::kind(f.kind)),
+            (
+                "params",
+                json::array(f.params.iter().map(|p| json::quote(p))),
+            ),
+            ("signature", json::quote(&f.signature())),
+            ("doc", json::optional(f.doc.map(json::quote))),
+            ("line", json::number(f.line as f64)),
+        ])
+    });
+    json::object(vec![
+        ("module", json::quote(module)),
+        ("functions", json::array(functions)),
+    ])
+}
+
+pub fn to_markdown(module: &str, ast: &Node, docs: &[Doc]) -> String {
+    let mut out = format!("# {}", module);
+    for f in exported(ast, docs) {
+        out += &format!("\n\n## `{}`", f.signature());
+        if let Some(doc) = f.doc {
+            out += &format!("\n\n{}", doc);
+        }
+    }
+    out
+}
@@ -0,0 +1,48 @@
+use crate::agent::Agent;
+use std::time::Duration;
+
+// how the agent waits for io between jobs. natively that is a mio poll,
+// readied by the timer thread, the pool and sockets. built for wasm32 there
+// are no threads to wait on and nothing may block, so the page drives the
+// loop instead: it calls `Agent::turn` from a microtask or a timeout, and
+// timers fire on the first turn after they are due. `Agent::next_timer` says
+// when that is. a wasi build runs its fs calls in place, and they are picked
+// up the same way.
+pub trait EventLoop {
+    // the tokens of the operations that are ready, waiting up to timeout for
+    // one if none are
+    fn wait(&self, agent: &Agent, timeout: Option<Duration>) -> Vec<Token>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use mio::{Registration, Token};
+
+#[cfg(not(target_arch = "wasm32"))]
+impl EventLoop for mio::Poll {
+    fn wait(&self, _: &Agent, timeout: Option<Duration>) -> Vec<Token> {
+        let mut events = mio::Events::with_capacity(128);
+        self.poll(&mut events, timeout).expect("mio poll failed");
+        events.iter().map(|event| event.token()).collect()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Token(pub usize);
+
+// there is nothing to register, the loop checks for what is ready itself
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+pub struct Registration;
+
+#[cfg(target_arch = "wasm32")]
+pub struct Microtasks;
+
+#[cfg(target_arch = "wasm32")]
+impl EventLoop for Microtasks {
+    fn wait(&self, agent: &Agent, _: Option<Duration>) -> Vec<Token> {
+        let mut ready = std::mem::replace(&mut *agent.ready.borrow_mut(), Vec::new());
+        ready.extend(agent.timers.due(agent.now()));
+        ready
+    }
+}
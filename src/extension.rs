@@ -0,0 +1,88 @@
+use crate::agent::Agent;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+// a native extension is a shared library exporting a `slither_extension`
+// static, declared with `declare_extension!`. its create function is called
+// with the agent loading it and returns the exports of the module, just like
+// the builtin modules. the declaration passes rust types across the library
+// boundary, which is only sound if both sides were built by the same compiler
+// against the same slither, so that is checked before anything else is
+// touched. the host and the extension also have to share one copy of slither
+// and its heap, so both link it dynamically (`-C prefer-dynamic`).
+
+// bumped whenever ExtensionDeclaration changes
+pub const EXTENSION_ABI_VERSION: u32 = 1;
+
+pub const SLITHER_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const RUSTC_VERSION: &str = env!("SLITHER_RUSTC_VERSION");
+
+const SYMBOL: &[u8] = b"slither_extension";
+
+#[repr(C)]
+pub struct ExtensionDeclaration {
+    // read before any other field, so it has to stay first
+    pub abi_version: u32,
+    pub slither_version: &'static str,
+    pub rustc_version: &'static str,
+    // imported as `standard:name`
+    pub name: &'static str,
+    pub create: fn(&Agent) -> HashMap<String, Value>,
+}
+
+// exports the declaration of an extension from a shared library:
+// `declare_extension!("sqlite", create);`
+#[macro_export]
+macro_rules! declare_extension {
+    ($name:expr, $create:path) => {
+        #[no_mangle]
+        pub static slither_extension: $crate::ExtensionDeclaration = $crate::ExtensionDeclaration {
+            abi_version: $crate::EXTENSION_ABI_VERSION,
+            slither_version: $crate::SLITHER_VERSION,
+            rustc_version: $crate::RUSTC_VERSION,
+            name: $name,
+            create: $create,
+        };
+    };
+}
+
+pub(crate) fn load(agent: &Agent, path: &Path) -> Result<(String, HashMap<String, Value>), Value> {
+    let error = |message: String| {
+        Value::new_error(
+            agent,
+            &format!("unable to load extension '{}': {}", path.display(), message),
+        )
+    };
+    let library = unsafe { libloading::Library::new(path) }.map_err(|e| error(e.to_string()))?;
+    let declaration = unsafe {
+        let abi_version = library
+            .get::<*const u32>(SYMBOL)
+            .map_err(|e| error(e.to_string()))?;
+        if **abi_version != EXTENSION_ABI_VERSION {
+            return Err(error(format!(
+                "built for extension abi {}, expected {}",
+                **abi_version, EXTENSION_ABI_VERSION
+            )));
+        }
+        &*(*abi_version as *const ExtensionDeclaration)
+    };
+    if declaration.slither_version != SLITHER_VERSION {
+        return Err(error(format!(
+            "built against slither {}, expected {}",
+            declaration.slither_version, SLITHER_VERSION
+        )));
+    }
+    if declaration.rustc_version != RUSTC_VERSION {
+        return Err(error(format!(
+            "built with {}, expected {}",
+            declaration.rustc_version, RUSTC_VERSION
+        )));
+    }
+    let name = declaration.name.to_string();
+    let exports = (declaration.create)(agent);
+    // the exports point into the library's code, and values can outlive the
+    // agent through Gc handles, so it is never unloaded
+    std::mem::forget(library);
+    Ok((name, exports))
+}
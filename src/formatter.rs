@@ -0,0 +1,1056 @@
+use crate::parser::{
+    Comment, FunctionKind, Node, Operator, Parser, Pattern, Scope, ScopeKind, SourceToken,
+    TokenKind,
+};
+
+// prints a syntax tree back out as source in one canonical style: two space
+// indents, single quoted strings, and lists that go on one line if they fit
+// in WIDTH columns and get one item per line with a trailing comma if not.
+//
+// the tree has no place for comments, so they are put back by line: before
+// the first statement that starts after them or the closing brace of their
+// block, or after a statement that ends on their line. a list with a comment
+// between its brackets stays one item per line, with the comment next to the
+// item it was next to. a comment anywhere else in an expression ends up next
+// to the statement it was in. comments are never dropped.
+//
+// the tree has no place for how literals were spelled either, so the number
+// and string tokens of the source are matched up with the literals as they
+// are printed, like the brackets are with the lists they hold. both are in
+// the order the source has them, which is the order they are printed in.
+
+const WIDTH: usize = 80;
+const INDENT: &str = "  ";
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    comments: Vec<Comment>,
+    // the first comment not printed yet
+    next: usize,
+    literals: Vec<Literal>,
+    // the first literal not matched yet
+    literal: usize,
+    brackets: Vec<Bracket>,
+    // the first bracket not matched yet
+    bracket: usize,
+    indent: usize,
+    // the line the last block printed ends on
+    end: usize,
+}
+
+// a number or string token, what it parses to, and whether it is a tuple
+// index as in `t.0`
+struct Literal {
+    text: String,
+    value: Node,
+    index: bool,
+}
+
+// a pair of brackets in the source, where each item between them starts and
+// where the closing one is
+struct Bracket {
+    open: String,
+    start: (usize, usize),
+    items: Vec<(usize, usize)>,
+    end: (usize, usize),
+}
+
+fn scan(code: &str) -> (Vec<Literal>, Vec<Bracket>) {
+    let tokens = match Parser::tokenize(code) {
+        Ok(tokens) => tokens,
+        Err(_) => return (Vec::new(), Vec::new()),
+    };
+    let mut literals = Vec::new();
+    let mut brackets: Vec<Bracket> = Vec::new();
+    // the brackets still open, and whether an item is expected next in each
+    let mut open: Vec<(usize, bool)> = Vec::new();
+    let mut previous: Option<&SourceToken> = None;
+    for token in tokens.iter().filter(|t| t.kind != TokenKind::Comment) {
+        let closes = token.kind == TokenKind::Punctuation
+            && (token.text == ")" || token.text == "]" || token.text == "}");
+        if let Some((i, expected)) = open.last_mut() {
+            if *expected && !closes {
+                brackets[*i].items.push(token.start);
+            }
+            *expected = token.kind == TokenKind::Punctuation && token.text == ",";
+        }
+        match token.kind {
+            TokenKind::Number | TokenKind::String => {
+                if let Some(value) = Parser::parse_literal(&token.text) {
+                    literals.push(Literal {
+                        text: token.text.clone(),
+                        value,
+                        index: previous.map_or(false, |t| t.text == "."),
+                    });
+                }
+            }
+            TokenKind::Punctuation => match token.text.as_str() {
+                "(" | "[" | "{" | "#{" => {
+                    open.push((brackets.len(), true));
+                    brackets.push(Bracket {
+                        open: token.text.clone(),
+                        start: token.start,
+                        items: Vec::new(),
+                        end: token.start,
+                    });
+                }
+                ")" | "]" | "}" => {
+                    if let Some((i, _)) = open.pop() {
+                        brackets[i].end = token.start;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        previous = Some(token);
+    }
+    (literals, brackets)
+}
+
+pub fn format(code: &str, ast: &Node, comments: Vec<Comment>) -> String {
+    let (literals, brackets) = scan(code);
+    let mut f = Formatter {
+        lines: code.lines().collect(),
+        comments,
+        next: 0,
+        literals,
+        literal: 0,
+        brackets,
+        bracket: 0,
+        indent: 0,
+        end: 0,
+    };
+    let mut out = String::new();
+    let hashbang = code.starts_with("#!");
+    if hashbang {
+        out += f.lines[0];
+        out.push('\n');
+    }
+    if let Node::Block(scope, stmts) = ast {
+        out += &f.statements(scope, stmts, !hashbang);
+    }
+    out
+}
+
+// an expression on its own, with no source to take comments from
+pub fn expression(node: &Node) -> String {
+    let mut f = Formatter {
+        lines: Vec::new(),
+        comments: Vec::new(),
+        next: 0,
+        literals: Vec::new(),
+        literal: 0,
+        brackets: Vec::new(),
+        bracket: 0,
+        indent: 0,
+        end: 0,
+    };
+    f.expression(node, 0)
+}
+
+impl<'a> Formatter<'a> {
+    fn indentation(&self) -> String {
+        INDENT.repeat(self.indent)
+    }
+
+    fn peek_comment(&self) -> Option<&Comment> {
+        self.comments.get(self.next)
+    }
+
+    // a single blank line is kept between statements and comments
+    fn blank_line(&self, out: &mut String, line: usize, first: &mut bool) {
+        if !*first
+            && line >= 2
+            && self
+                .lines
+                .get(line - 2)
+                .map_or(false, |l| l.trim().is_empty())
+        {
+            out.push('\n');
+        }
+        *first = false;
+    }
+
+    // the literal the source spelled this value with, if any
+    fn literal(&mut self, value: &Node) -> Option<&Literal> {
+        let i = self.literal
+            + self.literals[self.literal..]
+                .iter()
+                .position(|l| l.value == *value)?;
+        self.literal = i + 1;
+        Some(&self.literals[i])
+    }
+
+    fn number(&mut self, n: f64) -> String {
+        match self.literal(&Node::NumberLiteral(n)) {
+            Some(l) => l.text.clone(),
+            None => number(n),
+        }
+    }
+
+    fn quote(&mut self, s: &str) -> String {
+        match self.literal(&Node::StringLiteral(s.to_string())) {
+            Some(l) => requote(&l.text),
+            None => quote(s),
+        }
+    }
+
+    // the brackets of the next list or block, and whether it has as many
+    // items as the source
+    fn bracket(&mut self, open: &str, items: usize) -> Option<usize> {
+        let i = self.bracket
+            + self.brackets[self.bracket..]
+                .iter()
+                .position(|b| b.open == open)?;
+        self.bracket = i + 1;
+        if self.brackets[i].items.len() == items {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    // the comments between the brackets that nothing inside them printed.
+    // one further out that hasn't been printed either leaves them be.
+    fn loose_comments(&self, bracket: usize) -> bool {
+        let b = &self.brackets[bracket];
+        match self.peek_comment() {
+            Some(c) => (c.line, c.column) > b.start && (c.line, c.column) < b.end,
+            None => false,
+        }
+    }
+
+    fn starts_line(&self, comment: &Comment) -> bool {
+        match self.lines.get(comment.line - 1) {
+            Some(line) => line
+                .chars()
+                .take(comment.column - 1)
+                .all(char::is_whitespace),
+            None => true,
+        }
+    }
+
+    // comments before `until` that follow code on their line, kept after
+    // what was just printed. nothing can follow a line comment.
+    fn trailing_comments(&mut self, out: &mut String, until: (usize, usize)) {
+        while let Some(c) = self.peek_comment() {
+            if (c.line, c.column) >= until || self.starts_line(c) {
+                break;
+            }
+            let text = c.text.clone();
+            self.next += 1;
+            out.push(' ');
+            out.push_str(&text);
+            if text.starts_with("//") {
+                break;
+            }
+        }
+    }
+
+    // the rest of the comments before `until`, each on its own line
+    fn comment_lines(&mut self, out: &mut String, until: (usize, usize)) {
+        while let Some(c) = self.peek_comment() {
+            if (c.line, c.column) >= until {
+                break;
+            }
+            let text = c.text.clone();
+            self.next += 1;
+            out.push_str(&self.indentation());
+            out.push_str(&text);
+            out.push('\n');
+        }
+    }
+
+    fn comment(&mut self, out: &mut String, first: &mut bool) {
+        let comment = self.comments[self.next].clone();
+        self.next += 1;
+        self.blank_line(out, comment.line, first);
+        out.push_str(&self.indentation());
+        out.push_str(&comment.text);
+        out.push('\n');
+    }
+
+    // the statements of a block, one per line at the current indent
+    fn statements(&mut self, scope: &Scope, stmts: &[Node], mut first: bool) -> String {
+        let mut out = String::new();
+        let (mut line, mut column) = (0, 0);
+        for (i, stmt) in stmts.iter().enumerate() {
+            if let Node::Position(l, c) = stmt {
+                line = *l;
+                column = *c;
+                while self.peek_comment().map_or(false, |c| c.line < line) {
+                    self.comment(&mut out, &mut first);
+                }
+                continue;
+            }
+            self.blank_line(&mut out, line, &mut first);
+            self.end = 0;
+            // the parser keeps the value of the last expression statement
+            let text = match stmt {
+                Node::ParenthesizedExpression(expr)
+                    if scope.kind == ScopeKind::TopLevel && i == stmts.len() - 1 =>
+                {
+                    let column = self.indent * INDENT.len();
+                    format!("{};", self.expression(expr, column))
+                }
+                _ => self.statement(scope, stmt, line, column),
+            };
+            out.push_str(&self.indentation());
+            out.push_str(&text);
+            // a comment after the statement, or after the brace it ends with
+            let end = scope.end.unwrap_or((std::usize::MAX, 0));
+            if let Some(c) = self.peek_comment() {
+                if (c.line == line || c.line == self.end) && (c.line, c.column) < end {
+                    out.push(' ');
+                    out.push_str(&c.text);
+                    self.next += 1;
+                }
+            }
+            out.push('\n');
+        }
+        // whatever is left before the closing brace
+        let end = scope.end.unwrap_or((std::usize::MAX, 0));
+        while self
+            .peek_comment()
+            .map_or(false, |c| (c.line, c.column) < end)
+        {
+            self.comment(&mut out, &mut first);
+        }
+        out
+    }
+
+    fn block(&mut self, node: &Node) -> String {
+        match node {
+            Node::Block(scope, stmts) => {
+                self.bracket("{", 0);
+                self.indent += 1;
+                let body = self.statements(scope, stmts, true);
+                self.indent -= 1;
+                self.end = scope.end.map_or(0, |(line, _)| line);
+                if body.is_empty() {
+                    "{}".to_string()
+                } else {
+                    format!("{{\n{}{}}}", body, self.indentation())
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn statement(
+        &mut self,
+        scope: &Scope,
+        node: &Node,
+        line: usize,
+        source_column: usize,
+    ) -> String {
+        let column = self.indent * INDENT.len();
+        match node {
+            Node::ExpressionStatement(expr) => format!("{};", self.expression(expr, column)),
+            Node::LexicalInitialization(name, init) => {
+                if self.decorated(line, source_column) {
+                    return self.decorated_function(name, init, column);
+                }
+                let keyword = if scope.bindings.get(name) == Some(&true) {
+                    "let"
+                } else {
+                    "const"
+                };
+                let prefix = format!("{} {} = ", keyword, name);
+                let init = self.expression(init, column + prefix.len());
+                format!("{}{};", prefix, init)
+            }
+            Node::LexicalDestructuring(pattern, init) => {
+                let mutable = binding(pattern)
+                    .and_then(|name| scope.bindings.get(name))
+                    .cloned()
+                    .unwrap_or(false);
+                let keyword = if mutable { "let" } else { "const" };
+                let prefix = format!("{} {} = ", keyword, self.pattern(pattern));
+                let init = self.expression(init, column + prefix.len());
+                format!("{}{};", prefix, init)
+            }
+            Node::FunctionDeclaration(kind, name, params, body) => {
+                let prefix = format!("{}function {}", function_prefix(*kind), name);
+                self.function(prefix, params, body, column)
+            }
+            Node::ClassDeclaration(name, extends, members) => {
+                self.class(name, extends, members, column)
+            }
+            Node::EnumDeclaration(name, members) => {
+                let prefix = format!("enum {} ", name);
+                let mut previous: Option<f64> = None;
+                let members = members
+                    .iter()
+                    .map(|(member, value)| {
+                        // numbering that counts up from the previous one is left out
+                        let text = match (value, previous) {
+                            (Some(n), Some(p)) if *n == p + 1.0 => member.clone(),
+                            (Some(n), _) => format!("{} = {}", member, self.number(*n)),
+                            (None, _) => member.clone(),
+                        };
+                        previous = *value;
+                        text
+                    })
+                    .collect::<Vec<String>>();
+                let list = self.list(
+                    column + prefix.len(),
+                    "{",
+                    "}",
+                    true,
+                    &members,
+                    |_, m, _| m.clone(),
+                );
+                format!("{}{}", prefix, list)
+            }
+            Node::IfStatement(test, consequent, alternative) => {
+                let test = self.expression(test, column + 3);
+                let mut out = format!("if {} {}", test, self.block(consequent));
+                if let Some(alternative) = alternative {
+                    out += " else ";
+                    out += &match &**alternative {
+                        Node::IfStatement(..) => {
+                            self.statement(scope, alternative, line, source_column)
+                        }
+                        _ => self.block(alternative),
+                    };
+                }
+                out
+            }
+            Node::WhileLoop(test, body) => {
+                let test = self.expression(test, column + 6);
+                format!("while {} {}", test, self.block(body))
+            }
+            Node::ForLoop(r#async, binding, target, body) => {
+                let prefix = format!(
+                    "for {}{} in ",
+                    if *r#async { "await " } else { "" },
+                    binding
+                );
+                let target = self.expression(target, column + prefix.len());
+                format!("{}{} {}", prefix, target, self.block(body))
+            }
+            Node::ReturnStatement(None) => "return;".to_string(),
+            Node::ReturnStatement(Some(expr)) => {
+                format!("return {};", self.expression(expr, column + 7))
+            }
+            Node::ThrowStatement(expr) => format!("throw {};", self.expression(expr, column + 6)),
+            Node::BreakStatement => "break;".to_string(),
+            Node::ContinueStatement => "continue;".to_string(),
+            Node::TryStatement(try_clause, binding, catch_clause, finally_clause) => {
+                let mut out = format!("try {}", self.block(try_clause));
+                if let Some(catch_clause) = catch_clause {
+                    out += " catch ";
+                    if let Some(binding) = binding {
+                        out += binding;
+                        out += " ";
+                    }
+                    out += &self.block(catch_clause);
+                }
+                if let Some(finally_clause) = finally_clause {
+                    out += " finally ";
+                    out += &self.block(finally_clause);
+                }
+                out
+            }
+            Node::ImportDeclaration(specifier) => {
+                format!("import {};", self.quote(specifier))
+            }
+            Node::ImportNamedDeclaration(specifier, bindings) => {
+                let list = self.list(column + 7, "{", "}", true, bindings, |_, b, _| b.clone());
+                format!("import {} from {};", list, self.quote(specifier))
+            }
+            Node::ImportDefaultDeclaration(specifier, binding) => {
+                format!("import {} from {};", binding, self.quote(specifier))
+            }
+            Node::ImportStandardDeclaration(namespace, bindings) => {
+                let list = self.list(column + 7, "{", "}", true, bindings, |_, b, _| b.clone());
+                format!("import {} from standard:{};", list, namespace)
+            }
+            Node::ExportDeclaration(declaration) => {
+                format!(
+                    "export {}",
+                    self.statement(scope, declaration, line, source_column)
+                )
+            }
+            Node::Block(..) => self.block(node),
+            _ => format!("{};", self.expression(node, column)),
+        }
+    }
+
+    // `@d function f() {}` is parsed as `const f = d(function() {})`, which
+    // can't be written as such, so the source is checked for the `@`
+    fn decorated(&self, line: usize, column: usize) -> bool {
+        match self.lines.get(line.wrapping_sub(1)) {
+            Some(text) => text.chars().nth(column.wrapping_sub(1)) == Some('@'),
+            None => false,
+        }
+    }
+
+    fn decorated_function(&mut self, name: &str, init: &Node, column: usize) -> String {
+        let mut decorators = Vec::new();
+        let mut node = init;
+        while let Node::CallExpression(decorator, args) = node {
+            decorators.push(decorator);
+            node = &args[0];
+        }
+        let mut out = String::new();
+        for decorator in decorators {
+            out += "@";
+            out += &self.expression(decorator, column + 1);
+            out.push('\n');
+            out += &self.indentation();
+        }
+        if let Node::FunctionExpression(kind, _, params, body) = node {
+            let keyword = match kind {
+                FunctionKind::Async => "async",
+                FunctionKind::Generator => "gen",
+                _ => "function",
+            };
+            let prefix = format!("{} {}", keyword, name);
+            out += &self.function(prefix, params, body, column);
+        }
+        out
+    }
+
+    fn function(&mut self, prefix: String, params: &[Node], body: &Node, column: usize) -> String {
+        let params = self.list(column + prefix.len(), "(", ")", false, params, |f, p, c| {
+            f.expression(p, c)
+        });
+        format!("{}{} {}", prefix, params, self.block(body))
+    }
+
+    fn class(
+        &mut self,
+        name: &str,
+        extends: &Option<Box<Node>>,
+        members: &[Node],
+        column: usize,
+    ) -> String {
+        let mut out = format!("class {}", name);
+        if let Some(extends) = extends {
+            out += " extends ";
+            let column = column + out.len();
+            out += &self.expression(extends, column);
+        }
+        self.bracket("{", 0);
+        if members.is_empty() {
+            return out + " {}";
+        }
+        out += " {\n";
+        self.indent += 1;
+        let mut previous_multiline = false;
+        for (i, member) in members.iter().enumerate() {
+            let text = self.entry(member, self.indent * INDENT.len());
+            // methods are set apart unless they are one-liners
+            let multiline = text.contains('\n');
+            if i > 0 && (multiline || previous_multiline) {
+                out.push('\n');
+            }
+            previous_multiline = multiline;
+            out += &self.indentation();
+            out += &text;
+            out.push('\n');
+        }
+        self.indent -= 1;
+        out += &self.indentation();
+        out + "}"
+    }
+
+    // lays out a list on one line if it fits and has no comments, otherwise
+    // one item per line. braces are padded with spaces, and everything in them
+    // has to fit on the line. in parens and brackets a multiline first or last
+    // item can stay on the line, like a callback in a call.
+    fn list<T>(
+        &mut self,
+        column: usize,
+        open: &str,
+        close: &str,
+        pad: bool,
+        items: &[T],
+        item: impl Fn(&mut Self, &T, usize) -> String,
+    ) -> String {
+        let bracket = self.bracket(open, items.len());
+        if items.is_empty() && !bracket.map_or(false, |b| self.loose_comments(b)) {
+            return format!("{}{}", open, close);
+        }
+        let start = (self.next, self.literal, self.bracket);
+        let mut flat = open.to_string();
+        if pad {
+            flat.push(' ');
+        }
+        let mut multiline = 0;
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                flat += ", ";
+            }
+            let text = item(self, it, end_column(column, &flat));
+            if text.contains('\n') {
+                multiline += if pad || (i > 0 && i < items.len() - 1) {
+                    2
+                } else {
+                    1
+                };
+            }
+            flat += &text;
+        }
+        if pad {
+            flat.push(' ');
+        }
+        flat += close;
+        let first = flat.lines().next().unwrap_or("");
+        let commented = bracket.map_or(false, |b| self.loose_comments(b));
+        if !commented
+            && multiline <= 1
+            && column + first.chars().count() <= WIDTH
+            && end_column(column, &flat) <= WIDTH
+        {
+            return flat;
+        }
+
+        let (next, literal, bracket_index) = start;
+        self.next = next;
+        self.literal = literal;
+        self.bracket = bracket_index;
+        // where the items start and the list ends, to put comments back by
+        let bounds = match bracket {
+            Some(b) if self.loose_comments(b) => {
+                let b = &self.brackets[b];
+                let mut bounds = b.items.clone();
+                bounds.push(b.end);
+                Some(bounds)
+            }
+            _ => None,
+        };
+        self.indent += 1;
+        let indentation = self.indentation();
+        let mut out = open.to_string();
+        if let Some(bounds) = &bounds {
+            self.trailing_comments(&mut out, bounds[0]);
+        }
+        out.push('\n');
+        for (i, it) in items.iter().enumerate() {
+            if let Some(bounds) = &bounds {
+                self.comment_lines(&mut out, bounds[i]);
+            }
+            let text = item(self, it, indentation.len());
+            out += &indentation;
+            out += &text;
+            out.push(',');
+            if let Some(bounds) = &bounds {
+                self.trailing_comments(&mut out, bounds[i + 1]);
+            }
+            out.push('\n');
+        }
+        if let Some(bounds) = &bounds {
+            self.comment_lines(&mut out, bounds[items.len()]);
+        }
+        self.indent -= 1;
+        out += &self.indentation();
+        out + close
+    }
+
+    fn expressions(&mut self, column: usize, open: &str, close: &str, items: &[Node]) -> String {
+        self.list(column, open, close, false, items, |f, e, c| {
+            f.expression(e, c)
+        })
+    }
+
+    // an object or class entry, `key: value`, `key` or `key() {}`
+    fn entry(&mut self, node: &Node, column: usize) -> String {
+        let (key, value) = match node {
+            Node::Initializer(key, value) => (key, value),
+            _ => return self.expression(node, column),
+        };
+        let key = match &**key {
+            Node::StringLiteral(s) => {
+                if let Node::Identifier(name) = &**value {
+                    if name == s {
+                        return name.clone();
+                    }
+                }
+                if is_identifier(s) {
+                    s.clone()
+                } else {
+                    self.quote(s)
+                }
+            }
+            Node::NumberLiteral(n) => self.number(*n),
+            key => {
+                self.bracket("[", 1);
+                format!("[{}]", self.expression(key, column + 1))
+            }
+        };
+        match &**value {
+            Node::FunctionExpression(FunctionKind::Normal, None, params, body) => {
+                self.function(key, params, body, column)
+            }
+            value => {
+                let prefix = format!("{}: ", key);
+                let value = self.expression(value, column + prefix.len());
+                format!("{}{}", prefix, value)
+            }
+        }
+    }
+
+    fn expression(&mut self, node: &Node, column: usize) -> String {
+        match node {
+            Node::NullLiteral => "null".to_string(),
+            Node::TrueLiteral => "true".to_string(),
+            Node::FalseLiteral => "false".to_string(),
+            Node::NumberLiteral(n) => self.number(*n),
+            Node::StringLiteral(s) => self.quote(s),
+            Node::SymbolLiteral(s) => format!(":{}", s),
+            Node::RegexLiteral(s) => format!("/{}/", s),
+            Node::ObjectLiteral(entries) => {
+                self.list(column, "{", "}", true, entries, |f, e, c| f.entry(e, c))
+            }
+            Node::RecordLiteral(entries) => {
+                self.list(column, "#{", "}", true, entries, |f, e, c| f.entry(e, c))
+            }
+            Node::ArrayLiteral(items) => self.expressions(column, "[", "]", items),
+            Node::TupleLiteral(items) if items.len() == 1 => {
+                let start = (self.next, self.literal, self.bracket);
+                self.bracket("(", 1);
+                let item = self.expression(&items[0], column + 1);
+                if item.contains('\n') || column + item.len() + 3 > WIDTH {
+                    self.next = start.0;
+                    self.literal = start.1;
+                    self.bracket = start.2;
+                    self.expressions(column, "(", ")", items)
+                } else {
+                    format!("({},)", item)
+                }
+            }
+            Node::TupleLiteral(items) => self.expressions(column, "(", ")", items),
+            Node::NamedTupleLiteral(items) => {
+                self.list(column, "(", ")", false, items, |f, i, c| {
+                    let prefix = format!("{}: ", i.0);
+                    let value = f.expression(&i.1, c + prefix.len());
+                    format!("{}{}", prefix, value)
+                })
+            }
+            Node::TemplateLiteral(quasis, expressions) => {
+                let mut out = "`".to_string();
+                for (i, quasi) in quasis.iter().enumerate() {
+                    out += &template_quasi(quasi);
+                    if let Some(expr) = expressions.get(i) {
+                        out += "${";
+                        let column = end_column(column, &out);
+                        out += &self.expression(expr, column);
+                        out += "}";
+                    }
+                }
+                out + "`"
+            }
+            Node::Identifier(name) => name.clone(),
+            Node::ThisExpression => "this".to_string(),
+            Node::ConditionalExpression(test, consequent, alternative) => {
+                let mut out = self.expression(test, column);
+                out += " ? ";
+                let c = end_column(column, &out);
+                out += &self.expression(consequent, c);
+                out += " : ";
+                let c = end_column(column, &out);
+                out + &self.expression(alternative, c)
+            }
+            Node::UnaryExpression(op, expr) => {
+                let op = match op {
+                    Operator::Typeof | Operator::Void => format!("{} ", op.symbol()),
+                    op => op.symbol().to_string(),
+                };
+                let expr = self.expression(expr, column + op.len());
+                // `- -x` rather than `--x`
+                if expr.starts_with(&op) {
+                    op + " " + &expr
+                } else {
+                    op + &expr
+                }
+            }
+            Node::BinaryExpression(op, left, right) => {
+                let mut out = self.expression(left, column);
+                out += " ";
+                out += op.symbol();
+                out += " ";
+                let c = end_column(column, &out);
+                out + &self.expression(right, c)
+            }
+            Node::ParenthesizedExpression(expr) => {
+                self.bracket("(", 1);
+                format!("({})", self.expression(expr, column + 1))
+            }
+            Node::YieldExpression(None) => "yield".to_string(),
+            Node::YieldExpression(Some(expr)) => {
+                format!("yield {}", self.expression(expr, column + 6))
+            }
+            Node::AwaitExpression(expr) => format!("await {}", self.expression(expr, column + 6)),
+            Node::NewExpression(expr) => format!("new {}", self.expression(expr, column + 4)),
+            Node::MemberExpression(base, property) => {
+                format!("{}.{}", self.expression(base, column), property)
+            }
+            Node::ComputedMemberExpression(base, property) => {
+                let base = self.expression(base, column);
+                let c = end_column(column, &base) + 1;
+                if let Node::NumberLiteral(n) = **property {
+                    let index = match self.literal(&Node::NumberLiteral(n)) {
+                        Some(l) if l.index => Ok(l.text.clone()),
+                        Some(l) => Err(l.text.clone()),
+                        None => Err(number(n)),
+                    };
+                    return match index {
+                        Ok(index) => format!("{}.{}", base, index),
+                        Err(n) => {
+                            self.bracket("[", 1);
+                            format!("{}[{}]", base, n)
+                        }
+                    };
+                }
+                self.bracket("[", 1);
+                format!("{}[{}]", base, self.expression(property, c))
+            }
+            Node::CallExpression(callee, args) | Node::TailCallExpression(callee, args) => {
+                let callee = self.expression(callee, column);
+                let args = self.expressions(end_column(column, &callee), "(", ")", args);
+                callee + &args
+            }
+            Node::FunctionExpression(kind, name, params, body) => {
+                let mut prefix = format!("{}function", function_prefix(*kind));
+                if let Some(name) = name {
+                    prefix += " ";
+                    prefix += name;
+                }
+                self.function(prefix, params, body, column)
+            }
+            Node::ArrowFunctionExpression(kind, params, body) => {
+                let prefix = if *kind as u8 & FunctionKind::Async as u8 != 0 {
+                    "async "
+                } else {
+                    ""
+                };
+                let params = self.expressions(column + prefix.len(), "(", ")", params);
+                let out = format!("{}{} => ", prefix, params);
+                match &**body {
+                    // an expression body has no position, a block always does
+                    Node::Block(_, stmts) if stmts.len() == 1 => match &stmts[0] {
+                        Node::ReturnStatement(Some(expr)) => {
+                            let c = end_column(column, &out);
+                            out + &self.expression(expr, c)
+                        }
+                        _ => out + &self.block(body),
+                    },
+                    _ => out + &self.block(body),
+                }
+            }
+            Node::ClassExpression(name, extends, members) => {
+                self.class(name, extends, members, column)
+            }
+            Node::MatchExpression(value, arms) => {
+                let value = self.expression(value, column + 6);
+                let prefix = format!("match {} ", value);
+                let c = end_column(column, &prefix);
+                let arms = self.list(c, "{", "}", true, arms, |f, arm, c| f.arm(arm, c));
+                prefix + &arms
+            }
+            Node::Initializer(target, init) => {
+                let target = self.expression(target, column);
+                let c = end_column(column, &target) + 3;
+                format!("{} = {}", target, self.expression(init, c))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn arm(&mut self, arm: &Node, column: usize) -> String {
+        match arm {
+            Node::MatchArm(_, pattern, guard, body) => {
+                let mut out = self.pattern(pattern);
+                if let Some(guard) = guard {
+                    out += " if ";
+                    let c = end_column(column, &out);
+                    out += &self.expression(guard, c);
+                }
+                out += " => ";
+                let c = end_column(column, &out);
+                out + &self.expression(body, c)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> String {
+        let join = |f: &mut Self, patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .map(|p| f.pattern(p))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match pattern {
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Binding(name) => name.clone(),
+            // `-1` is parsed into the literal
+            Pattern::Value(value) => match **value {
+                Node::NumberLiteral(n) if n.is_sign_negative() => format!("-{}", self.number(-n)),
+                _ => self.expression(value, 0),
+            },
+            Pattern::Tuple(patterns) if patterns.len() == 1 => {
+                self.bracket("(", 1);
+                format!("({},)", self.pattern(&patterns[0]))
+            }
+            Pattern::Tuple(patterns) => {
+                self.bracket("(", patterns.len());
+                format!("({})", join(self, patterns))
+            }
+            Pattern::Array(patterns) => {
+                self.bracket("[", patterns.len());
+                format!("[{}]", join(self, patterns))
+            }
+            Pattern::NamedTuple(fields) => {
+                self.bracket("(", fields.len());
+                let fields = fields
+                    .iter()
+                    .map(|(name, p)| format!("{}: {}", name, self.pattern(p)))
+                    .collect::<Vec<String>>();
+                format!("({})", fields.join(", "))
+            }
+            Pattern::Object(fields) if fields.is_empty() => {
+                self.bracket("{", 0);
+                "{}".to_string()
+            }
+            Pattern::Object(fields) => {
+                self.bracket("{", fields.len());
+                let fields = fields
+                    .iter()
+                    .map(|(key, p)| match p {
+                        Pattern::Binding(name) if name == key => name.clone(),
+                        p => {
+                            let key = if is_identifier(key) {
+                                key.clone()
+                            } else {
+                                self.quote(key)
+                            };
+                            format!("{}: {}", key, self.pattern(p))
+                        }
+                    })
+                    .collect::<Vec<String>>();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Pattern::Or(patterns) => patterns
+                .iter()
+                .map(|p| self.pattern(p))
+                .collect::<Vec<String>>()
+                .join(" | "),
+        }
+    }
+}
+
+// a name bound by the pattern
+fn binding(pattern: &Pattern) -> Option<&str> {
+    match pattern {
+        Pattern::Binding(name) => Some(name),
+        Pattern::Tuple(patterns) | Pattern::Array(patterns) | Pattern::Or(patterns) => {
+            patterns.iter().find_map(binding)
+        }
+        Pattern::NamedTuple(fields) | Pattern::Object(fields) => {
+            fields.iter().find_map(|(_, p)| binding(p))
+        }
+        Pattern::Wildcard | Pattern::Value(..) => None,
+    }
+}
+
+pub(crate) fn function_prefix(kind: FunctionKind) -> &'static str {
+    if kind as u8 & FunctionKind::Async as u8 != 0 {
+        "async "
+    } else if kind as u8 & FunctionKind::Generator as u8 != 0 {
+        "gen "
+    } else {
+        ""
+    }
+}
+
+// the column text placed at `column` ends on
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(i) => text[i + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn number(n: f64) -> String {
+    format!("{}", n)
+}
+
+fn escape(c: char, out: &mut String) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\t' => out.push_str("\\t"),
+        '\r' => out.push_str("\\r"),
+        '\0' => out.push_str("\\0"),
+        c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        c => out.push(c),
+    }
+}
+
+// a string token in single quotes, keeping its escapes as written
+fn requote(text: &str) -> String {
+    if !text.starts_with('"') || text.starts_with("\"\"\"") {
+        return text.to_string();
+    }
+    let mut out = "'".to_string();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('"') => out.push('"'),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            '\'' => out.push_str("\\'"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn quote(s: &str) -> String {
+    let mut out = "'".to_string();
+    for c in s.chars() {
+        match c {
+            '\'' => out.push_str("\\'"),
+            '\n' => out.push_str("\\n"),
+            c => escape(c, &mut out),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+// template literals may span lines, only what would end the string or start
+// an expression is escaped
+fn template_quasi(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => out.push_str("\\`"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            '\n' => out.push('\n'),
+            c => escape(c, &mut out),
+        }
+    }
+    out
+}
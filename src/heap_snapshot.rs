@@ -0,0 +1,289 @@
+use crate::interpreter::{Context, Scope};
+use crate::json::{array, number, object, quote};
+use crate::value::{ObjectInfo, ObjectKind, Value};
+use gc::GcCell;
+use std::collections::HashMap;
+
+// everything on the heap of the current thread as a graph of objects and the
+// scopes and contexts that keep them alive. the retained size of a node is what would
+// be freed if it were gone, which is its own size plus that of every node it
+// dominates. roots are the nodes referenced from outside the heap, anything
+// that can't be reached from them is garbage or is held by something the
+// snapshot doesn't look into, and is treated as a root as well.
+
+// bump when the shape of the output changes
+const VERSION: f64 = 1.0;
+
+struct Node {
+    kind: &'static str,
+    size: usize,
+    root: bool,
+    // (name, address of the target)
+    references: Vec<(String, usize)>,
+}
+
+fn kind(kind: &ObjectKind) -> &'static str {
+    match kind {
+        ObjectKind::Ordinary => "object",
+        ObjectKind::Array(..) => "array",
+        ObjectKind::Boolean(..) => "boolean",
+        ObjectKind::String(..) => "string",
+        ObjectKind::Number(..) => "number",
+        ObjectKind::Symbol(..) => "symbol",
+        ObjectKind::Regex(..) => "regex",
+        ObjectKind::Buffer(..) => "buffer",
+        ObjectKind::SharedBuffer(..) => "sharedBuffer",
+        ObjectKind::BytecodeFunction { .. } => "function",
+        ObjectKind::BuiltinFunction(..) => "builtinFunction",
+        ObjectKind::Custom(..) => "custom",
+    }
+}
+
+// strings are counted towards whatever holds them, tuples and records are
+// values too, so the objects in them are referenced directly
+fn reference(node: &mut Node, name: String, value: &Value) {
+    match value {
+        Value::Object(o) => node
+            .references
+            .push((name, &**o as *const ObjectInfo as usize)),
+        Value::String(s) => node.size += s.len(),
+        Value::Tuple(values, _) => {
+            for (i, value) in values.iter().enumerate() {
+                reference(node, format!("{}.{}", name, i), value);
+            }
+        }
+        Value::Record(fields) => {
+            for (key, value) in fields.iter() {
+                reference(node, format!("{}.{}", name, key), value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn nodes() -> Vec<(usize, Node)> {
+    let mut nodes = Vec::new();
+    gc::for_each(|o: &ObjectInfo, root| {
+        let mut node = Node {
+            kind: kind(&o.kind),
+            size: o.shallow_size(),
+            root,
+            references: Vec::new(),
+        };
+        o.for_each_reference(|name, value| reference(&mut node, name, value));
+        if let ObjectKind::BytecodeFunction { scope, .. } = &o.kind {
+            let scope = &**scope as *const GcCell<Scope> as usize;
+            node.references.push(("[[scope]]".to_string(), scope));
+        }
+        nodes.push((o as *const ObjectInfo as usize, node));
+    });
+    gc::for_each(|s: &GcCell<Scope>, root| {
+        let mut node = Node {
+            kind: "scope",
+            size: 0,
+            root,
+            references: Vec::new(),
+        };
+        if let Some(scope) = s.try_borrow() {
+            node.size = scope.shallow_size();
+            scope.for_each_reference(|name, value| reference(&mut node, name, value));
+            if let Some(parent) = scope.parent() {
+                let parent = &**parent as *const GcCell<Scope> as usize;
+                node.references.push(("[[parent]]".to_string(), parent));
+            }
+        }
+        nodes.push((s as *const GcCell<Scope> as usize, node));
+    });
+    gc::for_each(|c: &GcCell<Context>, root| {
+        let mut node = Node {
+            kind: "context",
+            size: std::mem::size_of::<Context>(),
+            root,
+            references: Vec::new(),
+        };
+        if let Some(context) = c.try_borrow() {
+            let scope = &*context.scope as *const GcCell<Scope> as usize;
+            node.references.push(("[[scope]]".to_string(), scope));
+            if let Some(function) = &context.function {
+                reference(&mut node, "[[function]]".to_string(), function);
+            }
+        }
+        nodes.push((c as *const GcCell<Context> as usize, node));
+    });
+    nodes
+}
+
+fn visit(
+    start: usize,
+    successors: &[Vec<usize>],
+    visited: &mut [bool],
+    postorder: &mut Vec<usize>,
+) {
+    let mut stack = vec![(start, 0)];
+    visited[start] = true;
+    while let Some((node, i)) = stack.pop() {
+        match successors[node].get(i) {
+            Some(&next) => {
+                stack.push((node, i + 1));
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push((next, 0));
+                }
+            }
+            None => postorder.push(node),
+        }
+    }
+}
+
+// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy. node
+// `n` is a virtual root that points at every root.
+fn dominators(edges: &[Vec<usize>], roots: &[usize]) -> Vec<usize> {
+    let n = edges.len();
+    let mut successors = edges.to_vec();
+    successors.push(roots.to_vec());
+
+    let mut postorder = Vec::with_capacity(n + 1);
+    let mut visited = vec![false; n + 1];
+    visit(n, &successors, &mut visited, &mut postorder);
+    // whatever is left is held from somewhere the snapshot doesn't see. start
+    // from the nodes nothing points at, then from cycles.
+    let mut referenced = vec![false; n];
+    for targets in edges {
+        for target in targets {
+            referenced[*target] = true;
+        }
+    }
+    let unreferenced = (0..n).filter(|node| !referenced[*node]);
+    for node in unreferenced.chain(0..n).collect::<Vec<usize>>() {
+        if !visited[node] {
+            successors[n].push(node);
+            visit(node, &successors, &mut visited, &mut postorder);
+        }
+    }
+    // the virtual root has to come last
+    postorder.retain(|node| *node != n);
+    postorder.push(n);
+
+    let mut order = vec![0; n + 1];
+    for (i, node) in postorder.iter().enumerate() {
+        order[*node] = i;
+    }
+    let mut predecessors = vec![Vec::new(); n + 1];
+    for (node, targets) in successors.iter().enumerate() {
+        for target in targets {
+            predecessors[*target].push(node);
+        }
+    }
+
+    const UNDEFINED: usize = usize::MAX;
+    let mut idom = vec![UNDEFINED; n + 1];
+    idom[n] = n;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for node in postorder.iter().rev().skip(1) {
+            let mut new = UNDEFINED;
+            for p in &predecessors[*node] {
+                if idom[*p] == UNDEFINED {
+                    continue;
+                }
+                new = if new == UNDEFINED {
+                    *p
+                } else {
+                    let (mut a, mut b) = (*p, new);
+                    while a != b {
+                        while order[a] < order[b] {
+                            a = idom[a];
+                        }
+                        while order[b] < order[a] {
+                            b = idom[b];
+                        }
+                    }
+                    a
+                };
+            }
+            if idom[*node] != new {
+                idom[*node] = new;
+                changed = true;
+            }
+        }
+    }
+    idom.truncate(n);
+    idom
+}
+
+pub fn take() -> String {
+    let nodes = nodes();
+    let ids = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, (address, _))| (*address, i))
+        .collect::<HashMap<usize, usize>>();
+    let edges = nodes
+        .iter()
+        .map(|(_, node)| {
+            node.references
+                .iter()
+                .filter_map(|(_, target)| ids.get(target).cloned())
+                .collect::<Vec<usize>>()
+        })
+        .collect::<Vec<Vec<usize>>>();
+    let roots = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, node))| node.root)
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    let idom = dominators(&edges, &roots);
+
+    // sum up the dominator tree, children first
+    let mut retained = nodes.iter().map(|(_, n)| n.size).collect::<Vec<usize>>();
+    let mut children = vec![Vec::new(); nodes.len()];
+    for (node, parent) in idom.iter().enumerate() {
+        if *parent < nodes.len() {
+            children[*parent].push(node);
+        }
+    }
+    let mut stack = (0..nodes.len())
+        .filter(|node| idom[*node] >= nodes.len())
+        .map(|node| (node, false))
+        .collect::<Vec<(usize, bool)>>();
+    while let Some((node, done)) = stack.pop() {
+        if done {
+            let parent = idom[node];
+            if parent < nodes.len() {
+                retained[parent] += retained[node];
+            }
+        } else {
+            stack.push((node, true));
+            stack.extend(children[node].iter().map(|child| (*child, false)));
+        }
+    }
+
+    let json_nodes = nodes.iter().enumerate().map(|(i, (_, node))| {
+        object(vec![
+            ("id", i.to_string()),
+            ("kind", quote(node.kind)),
+            ("size", node.size.to_string()),
+            ("retainedSize", retained[i].to_string()),
+            ("root", node.root.to_string()),
+        ])
+    });
+    let json_edges = nodes.iter().enumerate().flat_map(|(i, (_, node))| {
+        let ids = &ids;
+        node.references.iter().filter_map(move |(name, target)| {
+            ids.get(target).map(|target| {
+                object(vec![
+                    ("from", i.to_string()),
+                    ("to", target.to_string()),
+                    ("name", quote(name)),
+                ])
+            })
+        })
+    });
+    object(vec![
+        ("version", number(VERSION)),
+        ("nodes", array(json_nodes)),
+        ("edges", array(json_edges)),
+    ])
+}
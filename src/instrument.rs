@@ -0,0 +1,19 @@
+use crate::agent::Agent;
+use crate::value::Value;
+
+// hooks for tracers, coverage and profilers. `function` is None for the top
+// level of a module, and a generator or async function exits each time it
+// suspends and enters again when it resumes. `op` runs before every
+// instruction, which is slow, so it is only called when `ops` says so.
+pub trait Instrument {
+    fn enter(&self, _agent: &Agent, _function: Option<&Value>) {}
+
+    fn exit(&self, _agent: &Agent, _function: Option<&Value>) {}
+
+    fn ops(&self) -> bool {
+        false
+    }
+
+    // `position` can be turned into a location with `Agent::source_location`
+    fn op(&self, _agent: &Agent, _position: usize, _name: &'static str) {}
+}
@@ -1,6 +1,10 @@
-use crate::interpreter::{Op, REGISTER_COUNT};
-use crate::parser::{FunctionKind, Node, Operator, Scope, ScopeKind};
-use byteorder::{LittleEndian, WriteBytesExt};
+use crate::interpreter::fold::{self, Constant};
+use crate::interpreter::{Op, OpArg, REGISTER_COUNT};
+use crate::parser::{FunctionKind, Node, Operator, Pattern, Scope, ScopeKind};
+use crate::source_map::SourceMap;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+use std::rc::Rc;
 
 struct Register {
     id: u32,
@@ -62,17 +66,69 @@ pub struct AssemblerFunctionInfo {
     pub name: Option<String>,
     pub parameters: Vec<String>,
     pub position: usize,
+    // where the body ends
+    pub end: usize,
+}
+
+// a self-contained slice of code: string and function ids index into the
+// chunk's own tables and positions are relative to its start
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub strings: Vec<String>,
+    pub functions: Vec<AssemblerFunctionInfo>,
+    pub locations: Vec<(usize, usize, usize)>,
+}
+
+// rewrites the position, string and function operands in a run of code
+fn relocate<F: FnMut(&OpArg, usize) -> usize>(code: &mut [u8], mut f: F) {
+    let mut pc = 0;
+    while pc < code.len() {
+        let (_, args) = Op::from(code[pc]).describe();
+        pc += 1;
+        for arg in args {
+            match arg {
+                OpArg::Position | OpArg::String | OpArg::FunctionInfo | OpArg::Cache => {
+                    let n = LittleEndian::read_u32(&code[pc..]) as usize;
+                    LittleEndian::write_u32(&mut code[pc..], f(&arg, n) as u32);
+                }
+                _ => {}
+            }
+            pc += arg.size();
+        }
+    }
+}
+
+// a computed key that is a constant string gets the same property as a named
+// one, so `o['key']` can be assembled like `o.key`
+fn constant_key(key: &Node) -> Option<String> {
+    match fold::constant(key)? {
+        Constant::String(s) => Some(s),
+        _ => None,
+    }
 }
 
 pub struct Assembler {
     pub code: Vec<u8>,
-    pub string_table: Vec<String>,
+    // shared with the string values and property keys made from them
+    pub string_table: Vec<Rc<str>>,
+    // the field names of named tuples, by the id of the string listing them,
+    // shared by every tuple made from the same literal
+    tuple_names: HashMap<u32, Rc<Vec<String>>>,
     pub function_info: Vec<AssemblerFunctionInfo>,
+    // (code position, file) for each assembled module
+    pub files: Vec<(usize, String)>,
+    // (code position, line, column) for each statement
+    locations: Vec<(usize, usize, usize)>,
+    // maps for files that were generated from another source
+    pub source_maps: HashMap<String, SourceMap>,
     register_index: u32,
     register_max: u32,
+    // how many property get and set sites have been given an inline cache
+    cache_count: u32,
     break_label: Option<*mut Label>,
     continue_label: Option<*mut Label>,
-    throw_label: Option<*mut Label>,
+    // how many try blocks of the current function are being assembled
+    try_depth: u32,
 }
 
 impl Assembler {
@@ -80,12 +136,17 @@ impl Assembler {
         Assembler {
             code: Vec::new(),
             string_table: Vec::new(),
+            tuple_names: HashMap::new(),
             function_info: Vec::new(),
+            files: Vec::new(),
+            locations: Vec::new(),
+            source_maps: HashMap::new(),
             register_index: 0,
             register_max: REGISTER_COUNT as u32,
+            cache_count: 0,
             break_label: None,
             continue_label: None,
-            throw_label: None,
+            try_depth: 0,
         }
     }
 
@@ -96,8 +157,150 @@ impl Assembler {
         start
     }
 
+    pub fn export_chunk(&self, start: usize, end: usize) -> Chunk {
+        let mut code = self.code[start..end].to_vec();
+        let mut strings = Vec::new();
+        let mut functions = Vec::new();
+        let mut ids = HashMap::new();
+        relocate(&mut code, |arg, n| match arg {
+            OpArg::Position => n - start,
+            // given a new id on import
+            OpArg::Cache => 0,
+            OpArg::String => *ids.entry((0, n)).or_insert_with(|| {
+                strings.push(self.string_table[n].to_string());
+                strings.len() - 1
+            }),
+            _ => *ids.entry((1, n)).or_insert_with(|| {
+                let info = &self.function_info[n];
+                functions.push(AssemblerFunctionInfo {
+                    kind: info.kind,
+                    name: info.name.clone(),
+                    parameters: info.parameters.clone(),
+                    position: info.position - start,
+                    end: info.end - start,
+                });
+                functions.len() - 1
+            }),
+        });
+        Chunk {
+            code,
+            strings,
+            functions,
+            locations: self
+                .locations
+                .iter()
+                .filter(|(p, ..)| *p >= start && *p < end)
+                .map(|(p, line, column)| (p - start, *line, *column))
+                .collect(),
+        }
+    }
+
+    // appends a chunk from export_chunk, returning where it starts
+    pub fn import_chunk(&mut self, chunk: &Chunk) -> usize {
+        let start = self.code.len();
+        let functions = self.function_info.len();
+        let mut code = chunk.code.clone();
+        relocate(&mut code, |arg, n| match arg {
+            OpArg::Position => n + start,
+            OpArg::String => self.string_id(&chunk.strings[n]) as usize,
+            // numbered in code order, the same as when assembled
+            OpArg::Cache => self.cache_id() as usize,
+            _ => n + functions,
+        });
+        self.code.extend_from_slice(&code);
+        for info in &chunk.functions {
+            self.function_info.push(AssemblerFunctionInfo {
+                kind: info.kind,
+                name: info.name.clone(),
+                parameters: info.parameters.clone(),
+                position: info.position + start,
+                end: info.end + start,
+            });
+        }
+        for (p, line, column) in &chunk.locations {
+            self.locations.push((p + start, *line, *column));
+        }
+        start
+    }
+
+    fn file(&self, position: usize) -> &str {
+        match self.files.iter().rev().find(|(p, _)| *p <= position) {
+            Some((_, file)) => file.as_str(),
+            None => "<unknown>",
+        }
+    }
+
+    fn original_location<'a>(
+        &'a self,
+        file: &'a str,
+        line: usize,
+        column: usize,
+    ) -> (&'a str, usize, usize) {
+        match self.source_maps.get(file) {
+            Some(map) => map.lookup(line, column).unwrap_or((file, line, column)),
+            None => (file, line, column),
+        }
+    }
+
+    pub fn source_location(&self, position: usize) -> String {
+        let file = self.file(position);
+        let index = match self.locations.binary_search_by_key(&position, |(p, ..)| *p) {
+            Ok(i) => i,
+            Err(0) => return file.to_string(),
+            Err(i) => i - 1,
+        };
+        let (_, line, column) = self.locations[index];
+        let (file, line, column) = self.original_location(file, line, column);
+        format!("{}:{}:{}", file, line, column)
+    }
+
+    // bytecode offsets are columns of a single generated line
+    pub fn source_map(&self) -> SourceMap {
+        let mut map = SourceMap::new();
+        for (position, line, column) in &self.locations {
+            let (file, line, column) = self.original_location(self.file(*position), *line, *column);
+            map.add((1, position + 1), file, line, column);
+        }
+        map
+    }
+
+    // the file and line of the statement at `position`, if it is the first
+    // one on its line. these are where the debugger stops.
+    pub fn line_start(&self, position: usize) -> Option<(&str, usize)> {
+        let index = self
+            .locations
+            .binary_search_by_key(&position, |(p, ..)| *p)
+            .ok()?;
+        let line = |i: usize| {
+            let (p, line, column) = self.locations[i];
+            let (file, line, _) = self.original_location(self.file(p), line, column);
+            (file, line)
+        };
+        let here = line(index);
+        if index > 0 && line(index - 1) == here {
+            None
+        } else {
+            Some(here)
+        }
+    }
+
+    pub fn has_location(&self, position: usize) -> bool {
+        self.locations
+            .binary_search_by_key(&position, |(p, ..)| *p)
+            .is_ok()
+    }
+
+    fn set_location(&mut self, line: usize, column: usize) {
+        let position = self.code.len();
+        match self.locations.last_mut() {
+            Some(last) if last.0 == position => *last = (position, line, column),
+            _ => self.locations.push((position, line, column)),
+        }
+    }
+
     fn visit(&mut self, node: &Node) {
         match node {
+            Node::Position(line, column) => self.set_location(*line, *column),
             Node::NullLiteral => self.visit_null(),
             Node::TrueLiteral => self.visit_true(),
             Node::FalseLiteral => self.visit_false(),
@@ -108,6 +311,8 @@ impl Assembler {
             Node::ObjectLiteral(inits) => self.visit_object(inits),
             Node::ArrayLiteral(exprs) => self.visit_array(exprs),
             Node::TupleLiteral(exprs) => self.visit_tuple(exprs),
+            Node::NamedTupleLiteral(fields) => self.visit_named_tuple(fields),
+            Node::RecordLiteral(inits) => self.visit_record(inits),
             Node::TemplateLiteral(quasis, exprs) => self.visit_template(quasis, exprs),
             Node::Identifier(var) => self.visit_identifier(var),
             Node::Block(scope, stmts) => self.visit_block(scope, stmts),
@@ -134,7 +339,7 @@ impl Assembler {
                 self.visit_computed_member_expression(target, expr)
             }
             Node::CallExpression(callee, args) => self.visit_call(callee, args, false),
-            Node::TailCallExpression(callee, args) => self.visit_call(callee, args, true),
+            Node::TailCallExpression(..) => self.visit_tail(node),
             Node::FunctionExpression(kind, name, args, body) => {
                 self.visit_function_expression(*kind, name, args, body)
             }
@@ -150,7 +355,11 @@ impl Assembler {
             Node::ClassDeclaration(name, extends, body) => {
                 self.visit_class_declaration(name, extends, body)
             }
+            Node::EnumDeclaration(name, members) => self.visit_enum_declaration(name, members),
             Node::LexicalInitialization(var, expr) => self.visit_lexical_initialization(var, expr),
+            Node::LexicalDestructuring(pattern, expr) => {
+                self.visit_lexical_destructuring(pattern, expr)
+            }
             Node::ReturnStatement(expr) => self.visit_return(expr),
             Node::ThrowStatement(expr) => self.visit_throw(expr),
             Node::BreakStatement => self.visit_break(),
@@ -163,7 +372,8 @@ impl Assembler {
             | Node::ImportDefaultDeclaration(..)
             | Node::ImportStandardDeclaration(..) => {}
             Node::ExportDeclaration(decl) => self.visit_export(decl),
-            Node::Initializer(..) => unreachable!(),
+            Node::MatchExpression(value, arms) => self.visit_match(value, arms),
+            Node::Initializer(..) | Node::MatchArm(..) => unreachable!(),
         }
     }
 
@@ -224,6 +434,46 @@ impl Assembler {
         self.load_accumulator_with_register(&tuple);
     }
 
+    fn visit_named_tuple(&mut self, fields: &[(String, Node)]) {
+        let rscope = RegisterScope::new(self);
+        let tuple = rscope.register();
+        self.push_op(Op::CreateEmptyTuple);
+        self.store_accumulator_in_register(&tuple);
+        for (_, expr) in fields {
+            self.visit(expr);
+            self.push_op(Op::StoreInTuple);
+            self.push_u32(tuple.id);
+        }
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        let id = self.string_id(&names.join(","));
+        self.tuple_names(id);
+        self.push_op(Op::NameTuple);
+        self.push_u32(tuple.id);
+        self.push_u32(id);
+        self.load_accumulator_with_register(&tuple);
+    }
+
+    fn visit_record(&mut self, inits: &[Node]) {
+        let rscope = RegisterScope::new(self);
+        let record = rscope.register();
+        self.push_op(Op::CreateEmptyRecord);
+        self.store_accumulator_in_register(&record);
+        for init in inits {
+            if let Node::Initializer(name, value) = init {
+                if let Node::StringLiteral(name) = &**name {
+                    self.visit(value);
+                    self.push_op(Op::StoreInRecord);
+                    self.push_u32(record.id);
+                    let id = self.string_id(name);
+                    self.push_u32(id);
+                    continue;
+                }
+            }
+            unreachable!();
+        }
+        self.load_accumulator_with_register(&record);
+    }
+
     fn visit_object(&mut self, inits: &[Node]) {
         let rscope = RegisterScope::new(self);
         let obj = rscope.register();
@@ -313,31 +563,220 @@ impl Assembler {
     }
 
     fn visit_if(&mut self, test: &Node, consequent: &Node, alternative: &Option<Box<Node>>) {
+        // only the branch that would run is assembled
+        if let Some(c) = fold::constant(test) {
+            if c.to_bool() {
+                self.visit(consequent);
+            } else if let Some(alternative) = alternative {
+                self.visit(alternative);
+            }
+            return;
+        }
         let mut alt = self.label();
+        let mut end = self.label();
         self.visit(test);
         self.jump_if_false(&mut alt);
         self.visit(consequent);
-        self.mark(&mut alt);
         if let Some(alternative) = alternative {
+            self.jump(&mut end);
+            self.mark(&mut alt);
             self.visit(alternative);
+        } else {
+            self.mark(&mut alt);
         }
+        self.mark(&mut end);
     }
 
     fn visit_conditional(&mut self, test: &Node, consequent: &Node, alternative: &Node) {
+        if let Some(c) = fold::constant(test) {
+            self.visit(if c.to_bool() { consequent } else { alternative });
+            return;
+        }
         let mut alt = self.label();
+        let mut end = self.label();
         self.visit(test);
         self.jump_if_false(&mut alt);
         self.visit(consequent);
+        self.jump(&mut end);
         self.mark(&mut alt);
         self.visit(alternative);
+        self.mark(&mut end);
+    }
+
+    fn visit_match(&mut self, value: &Node, arms: &[Node]) {
+        /*
+        match VALUE { PATTERN if GUARD => BODY, ... }
+
+        @=>
+
+        value = VALUE
+        for each arm {
+          (enter scope, declare bindings)
+          test PATTERN against value, jump next on mismatch
+          if !GUARD { jump next }
+          BODY
+          (exit scope)
+          jump end
+          next:
+          (exit scope)
+        }
+        throw no match
+        end:
+        */
+
+        let mut end = self.label();
+
+        let rscope = RegisterScope::new(self);
+        let value_reg = rscope.register();
+        self.visit(value);
+        self.store_accumulator_in_register(&value_reg);
+
+        for arm in arms {
+            if let Node::MatchArm(scope, pattern, guard, body) = arm {
+                let mut next = self.label();
+                let scoped = !scope.bindings.is_empty();
+                if scoped {
+                    self.push_op(Op::EnterScope);
+                    for (name, mutable) in &scope.bindings {
+                        self.lexical_declaration(name, *mutable);
+                    }
+                }
+                self.visit_pattern(pattern, &value_reg, &mut next);
+                if let Some(guard) = guard {
+                    self.visit(guard);
+                    self.jump_if_false(&mut next);
+                }
+                self.visit(body);
+                if scoped {
+                    self.push_op(Op::ExitScope);
+                }
+                self.jump(&mut end);
+                self.mark(&mut next);
+                if scoped {
+                    self.push_op(Op::ExitScope);
+                }
+            } else {
+                unreachable!();
+            }
+        }
+
+        self.load_accumulator_with_register(&value_reg);
+        self.push_op(Op::ThrowNoMatch);
+
+        self.mark(&mut end);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern, value: &Register, fail: &mut Label) {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Binding(name) => {
+                self.load_accumulator_with_register(value);
+                self.lexical_initialization(name);
+            }
+            Pattern::Value(node) => {
+                self.visit(node);
+                self.push_op(Op::Eq);
+                self.push_u32(value.id);
+                self.jump_if_false(fail);
+            }
+            Pattern::Tuple(items) | Pattern::Array(items) => {
+                self.push_op(if let Pattern::Tuple(..) = pattern {
+                    Op::TestTuple
+                } else {
+                    Op::TestArray
+                });
+                self.push_u32(value.id);
+                self.push_u32(items.len() as u32);
+                self.jump_if_false(fail);
+                for (i, item) in items.iter().enumerate() {
+                    if let Pattern::Wildcard = item {
+                        continue;
+                    }
+                    let rscope = RegisterScope::new(self);
+                    let item_reg = rscope.register();
+                    self.load_f64(i as f64);
+                    self.load_computed_property(value);
+                    self.store_accumulator_in_register(&item_reg);
+                    self.visit_pattern(item, &item_reg, fail);
+                }
+            }
+            Pattern::NamedTuple(fields) => {
+                for (name, item) in fields {
+                    self.push_op(Op::TestTupleField);
+                    self.push_u32(value.id);
+                    let id = self.string_id(name);
+                    self.push_u32(id);
+                    self.jump_if_false(fail);
+                    if let Pattern::Wildcard = item {
+                        continue;
+                    }
+                    let rscope = RegisterScope::new(self);
+                    let item_reg = rscope.register();
+                    self.load_accumulator_with_register(value);
+                    self.load_named_property(name);
+                    self.store_accumulator_in_register(&item_reg);
+                    self.visit_pattern(item, &item_reg, fail);
+                }
+            }
+            Pattern::Object(fields) => {
+                self.push_op(Op::TestObject);
+                self.push_u32(value.id);
+                self.jump_if_false(fail);
+                for (key, item) in fields {
+                    if let Pattern::Wildcard = item {
+                        continue;
+                    }
+                    let rscope = RegisterScope::new(self);
+                    let item_reg = rscope.register();
+                    self.load_accumulator_with_register(value);
+                    self.load_named_property(key);
+                    self.store_accumulator_in_register(&item_reg);
+                    self.visit_pattern(item, &item_reg, fail);
+                }
+            }
+            Pattern::Or(alternatives) => {
+                let mut matched = self.label();
+                let (last, rest) = alternatives.split_last().unwrap();
+                for alternative in rest {
+                    let mut next = self.label();
+                    self.visit_pattern(alternative, value, &mut next);
+                    self.jump(&mut matched);
+                    self.mark(&mut next);
+                }
+                self.visit_pattern(last, value, fail);
+                self.mark(&mut matched);
+            }
+        }
+    }
+
+    fn visit_lexical_destructuring(&mut self, pattern: &Pattern, init: &Node) {
+        let mut fail = self.label();
+        let mut end = self.label();
+        let rscope = RegisterScope::new(self);
+        let value = rscope.register();
+        self.visit(init);
+        self.store_accumulator_in_register(&value);
+        self.visit_pattern(pattern, &value, &mut fail);
+        self.jump(&mut end);
+        self.mark(&mut fail);
+        self.load_accumulator_with_register(&value);
+        self.push_op(Op::ThrowNoMatch);
+        self.mark(&mut end);
     }
 
     fn visit_while(&mut self, test: &Node, body: &Node) {
+        let constant = fold::constant(test).map(|c| c.to_bool());
+        if constant == Some(false) {
+            self.load_null();
+            return;
+        }
         let mut head = self.label();
         let mut end = self.label();
         self.mark(&mut head);
-        self.visit(test);
-        self.jump_if_false(&mut end);
+        if constant.is_none() {
+            self.visit(test);
+            self.jump_if_false(&mut end);
+        }
         let pbl = self.break_label;
         self.break_label = Some(&mut end as *mut Label);
         let pcl = self.continue_label;
@@ -432,6 +871,10 @@ impl Assembler {
     }
 
     fn visit_unary(&mut self, op: Operator, expr: &Node) {
+        if let Some(c) = fold::unary(op, expr) {
+            self.load_constant(&c);
+            return;
+        }
         self.visit(expr);
         match op {
             Operator::Not => self.push_op(Op::LNOT),
@@ -444,6 +887,21 @@ impl Assembler {
     }
 
     fn visit_binary(&mut self, op: Operator, lhs: &Node, rhs: &Node) {
+        if let Some(c) = fold::binary(op, lhs, rhs) {
+            self.load_constant(&c);
+            return;
+        }
+        // a constant lhs that doesn't short circuit leaves just the rhs
+        match op {
+            Operator::LogicalAND | Operator::LogicalOR | Operator::NullishCoalescing
+                if fold::constant(lhs).is_some() =>
+            {
+                self.visit(rhs);
+                return;
+            }
+            _ => {}
+        }
+
         if op == Operator::LogicalAND {
             let mut end = self.label();
             self.visit(lhs);
@@ -462,6 +920,25 @@ impl Assembler {
             return;
         }
 
+        if op == Operator::NullishCoalescing {
+            let mut end = self.label();
+            self.visit(lhs);
+            self.jump_if_not_null(&mut end);
+            self.visit(rhs);
+            self.mark(&mut end);
+            return;
+        }
+
+        match op {
+            Operator::LogicalANDAssign
+            | Operator::LogicalORAssign
+            | Operator::NullishCoalescingAssign => {
+                self.visit_logical_assignment(op, lhs, rhs);
+                return;
+            }
+            _ => {}
+        }
+
         let rscope = RegisterScope::new(self);
 
         if op == Operator::Assign {
@@ -484,10 +961,18 @@ impl Assembler {
                     let keyr = rscope.register();
                     self.visit(base);
                     self.store_accumulator_in_register(&obj);
-                    self.visit(key);
-                    self.store_accumulator_in_register(&keyr);
-                    self.visit(rhs);
-                    self.store_computed_property(&obj, &keyr);
+                    match constant_key(key) {
+                        Some(key) => {
+                            self.visit(rhs);
+                            self.store_named_property(&obj, &key);
+                        }
+                        None => {
+                            self.visit(key);
+                            self.store_accumulator_in_register(&keyr);
+                            self.visit(rhs);
+                            self.store_computed_property(&obj, &keyr);
+                        }
+                    }
                 }
                 _ => unreachable!(),
             }
@@ -495,7 +980,42 @@ impl Assembler {
         }
 
         let lhsr = rscope.register();
-        self.visit(lhs);
+        let assign = match op {
+            Operator::AddAssign
+            | Operator::SubAssign
+            | Operator::MulAssign
+            | Operator::DivAssign
+            | Operator::ModAssign
+            | Operator::PowAssign => true,
+            _ => false,
+        };
+
+        // member targets are evaluated once and reused for the store. only
+        // they take registers beyond the lhs, as every level of a nested
+        // rhs holds its own until it is done.
+        let mut obj = None;
+        let mut keyr = None;
+        match lhs {
+            Node::MemberExpression(base, name) if assign => {
+                let o = rscope.register();
+                self.visit(base);
+                self.store_accumulator_in_register(&o);
+                self.load_named_property(name);
+                obj = Some(o);
+            }
+            Node::ComputedMemberExpression(base, key) if assign => {
+                let o = rscope.register();
+                let k = rscope.register();
+                self.visit(base);
+                self.store_accumulator_in_register(&o);
+                self.visit(key);
+                self.store_accumulator_in_register(&k);
+                self.load_computed_property(&o);
+                obj = Some(o);
+                keyr = Some(k);
+            }
+            _ => self.visit(lhs),
+        }
         self.store_accumulator_in_register(&lhsr);
         self.visit(rhs);
         // accumulator = lhs @ rhs
@@ -522,43 +1042,66 @@ impl Assembler {
         self.push_u32(lhsr.id);
 
         // lhs = accumulator
-        match op {
-            Operator::AddAssign
-            | Operator::SubAssign
-            | Operator::MulAssign
-            | Operator::DivAssign
-            | Operator::ModAssign
-            | Operator::PowAssign => match lhs {
+        if assign {
+            match lhs {
                 Node::Identifier(s) => {
                     self.push_op(Op::AssignIdentifier);
                     let id = self.string_id(s);
                     self.push_u32(id);
                 }
-                Node::MemberExpression(base, name) => {
-                    let value = rscope.register();
-                    let obj = rscope.register();
-                    self.store_accumulator_in_register(&value);
-                    self.visit(base);
-                    self.store_accumulator_in_register(&obj);
-                    self.load_accumulator_with_register(&value);
-                    self.store_named_property(&obj, name);
+                Node::MemberExpression(_, name) => {
+                    self.store_named_property(obj.as_ref().unwrap(), name);
                 }
-                Node::ComputedMemberExpression(base, key) => {
-                    let value = rscope.register();
-                    let obj = rscope.register();
-                    let keyr = rscope.register();
-                    self.store_accumulator_in_register(&value);
-                    self.visit(base);
-                    self.store_accumulator_in_register(&obj);
-                    self.visit(key);
-                    self.store_accumulator_in_register(&keyr);
-                    self.load_accumulator_with_register(&value);
-                    self.store_computed_property(&obj, &keyr);
+                Node::ComputedMemberExpression(..) => {
+                    self.store_computed_property(obj.as_ref().unwrap(), keyr.as_ref().unwrap());
                 }
                 _ => unreachable!(),
-            },
-            _ => {}
+            }
+        }
+    }
+
+    fn visit_logical_assignment(&mut self, op: Operator, lhs: &Node, rhs: &Node) {
+        let rscope = RegisterScope::new(self);
+        let mut end = self.label();
+        let short_circuit = |a: &mut Assembler, end: &mut Label| match op {
+            Operator::LogicalANDAssign => a.jump_if_false(end),
+            Operator::LogicalORAssign => a.jump_if_true(end),
+            Operator::NullishCoalescingAssign => a.jump_if_not_null(end),
+            _ => unreachable!(),
+        };
+        match lhs {
+            Node::Identifier(s) => {
+                self.visit_identifier(s);
+                short_circuit(self, &mut end);
+                self.visit(rhs);
+                self.push_op(Op::AssignIdentifier);
+                let id = self.string_id(s);
+                self.push_u32(id);
+            }
+            Node::MemberExpression(base, name) => {
+                let obj = rscope.register();
+                self.visit(base);
+                self.store_accumulator_in_register(&obj);
+                self.load_named_property(name);
+                short_circuit(self, &mut end);
+                self.visit(rhs);
+                self.store_named_property(&obj, name);
+            }
+            Node::ComputedMemberExpression(base, key) => {
+                let obj = rscope.register();
+                let keyr = rscope.register();
+                self.visit(base);
+                self.store_accumulator_in_register(&obj);
+                self.visit(key);
+                self.store_accumulator_in_register(&keyr);
+                self.load_computed_property(&obj);
+                short_circuit(self, &mut end);
+                self.visit(rhs);
+                self.store_computed_property(&obj, &keyr);
+            }
+            _ => unreachable!(),
         }
+        self.mark(&mut end);
     }
 
     fn visit_parenthesized_expression(&mut self, expr: &Node) {
@@ -589,6 +1132,10 @@ impl Assembler {
     }
 
     fn visit_computed_member_expression(&mut self, base: &Node, key: &Node) {
+        if let Some(key) = constant_key(key) {
+            self.visit_member_expression(base, &key);
+            return;
+        }
         let rscope = RegisterScope::new(self);
         let obj = rscope.register();
         self.visit(base);
@@ -613,8 +1160,13 @@ impl Assembler {
             Node::ComputedMemberExpression(base, key) => {
                 self.visit(base);
                 self.store_accumulator_in_register(&receiver);
-                self.visit(key);
-                self.load_computed_property(&receiver);
+                match constant_key(key) {
+                    Some(key) => self.load_named_property(&key),
+                    None => {
+                        self.visit(key);
+                        self.load_computed_property(&receiver);
+                    }
+                }
                 self.store_accumulator_in_register(&callee);
             }
             _ => {
@@ -708,10 +1260,15 @@ impl Assembler {
         body: &Node,
     ) {
         let mut end = self.label();
+        let location = self
+            .locations
+            .last()
+            .map(|(_, line, column)| (*line, *column));
 
         self.push_op(Op::NewFunction);
         let info = AssemblerFunctionInfo {
             position: self.code.len() + 9,
+            end: 0,
             kind,
             name,
             parameters: params
@@ -733,6 +1290,8 @@ impl Assembler {
         self.function_info.push(info);
         self.push_u32(id as u32); // 4
         self.jump(&mut end); // 5
+                             // a try around the function doesn't cover its body
+        let try_depth = std::mem::replace(&mut self.try_depth, 0);
 
         if let Node::Block(scope, stmts) = body {
             for param in params {
@@ -767,7 +1326,13 @@ impl Assembler {
             unreachable!();
         }
 
+        self.try_depth = try_depth;
+        self.function_info[id].end = self.code.len();
         self.mark(&mut end);
+        // code after the body belongs to the enclosing statement again
+        if let Some((line, column)) = location {
+            self.set_location(line, column);
+        }
     }
 
     fn visit_class_expression(&mut self, name: &str, extends: &Option<Box<Node>>, fields: &[Node]) {
@@ -848,6 +1413,32 @@ impl Assembler {
         self.load_accumulator_with_register(&class);
     }
 
+    fn visit_enum_declaration(&mut self, name: &str, members: &[(String, Option<f64>)]) {
+        let rscope = RegisterScope::new(self);
+        let obj = rscope.register();
+        let key = rscope.register();
+        self.push_op(Op::CreateEmptyObject);
+        self.store_accumulator_in_register(&obj);
+        for (member, value) in members {
+            self.load_string(member);
+            self.store_accumulator_in_register(&key);
+            match value {
+                Some(n) => self.load_f64(*n),
+                None => {
+                    self.push_op(Op::NewSymbol);
+                    let id = self.string_id(&format!("{}.{}", name, member));
+                    self.push_u32(id);
+                }
+            }
+            self.push_op(Op::StoreInObjectLiteral);
+            self.push_u32(obj.id);
+            self.push_u32(key.id);
+        }
+        self.load_accumulator_with_register(&obj);
+        self.push_op(Op::Freeze);
+        self.lexical_initialization(name);
+    }
+
     fn visit_lexical_initialization(&mut self, name: &str, init: &Node) {
         self.visit(init);
         self.lexical_initialization(name);
@@ -855,24 +1446,70 @@ impl Assembler {
 
     fn visit_return(&mut self, expr: &Option<Box<Node>>) {
         if let Some(expr) = expr {
-            self.visit(expr);
+            self.visit_tail(expr);
         } else {
             self.load_null();
         }
         self.push_op(Op::Return);
     }
 
+    // `expr` is what the function returns, so a call that produces it can
+    // replace the caller's frame instead of adding one. not inside a try,
+    // which has to see what the call throws.
+    fn visit_tail(&mut self, expr: &Node) {
+        if self.try_depth > 0 {
+            match expr {
+                Node::TailCallExpression(callee, args) => self.visit_call(callee, args, false),
+                _ => self.visit(expr),
+            }
+            return;
+        }
+        match expr {
+            Node::CallExpression(callee, args) | Node::TailCallExpression(callee, args) => {
+                self.visit_call(callee, args, true);
+            }
+            Node::ParenthesizedExpression(expr) => self.visit_tail(expr),
+            Node::ConditionalExpression(test, consequent, alternative) => {
+                if let Some(c) = fold::constant(test) {
+                    self.visit_tail(if c.to_bool() { consequent } else { alternative });
+                    return;
+                }
+                let mut alt = self.label();
+                let mut end = self.label();
+                self.visit(test);
+                self.jump_if_false(&mut alt);
+                self.visit_tail(consequent);
+                self.jump(&mut end);
+                self.mark(&mut alt);
+                self.visit_tail(alternative);
+                self.mark(&mut end);
+            }
+            Node::BinaryExpression(op, lhs, rhs) => match op {
+                Operator::LogicalAND | Operator::LogicalOR | Operator::NullishCoalescing
+                    if fold::binary(*op, lhs, rhs).is_none() =>
+                {
+                    let mut end = self.label();
+                    if fold::constant(lhs).is_none() {
+                        self.visit(lhs);
+                        match op {
+                            Operator::LogicalAND => self.jump_if_false(&mut end),
+                            Operator::LogicalOR => self.jump_if_true(&mut end),
+                            _ => self.jump_if_not_null(&mut end),
+                        }
+                    }
+                    self.visit_tail(rhs);
+                    self.mark(&mut end);
+                }
+                _ => self.visit(expr),
+            },
+            _ => self.visit(expr),
+        }
+    }
+
     fn visit_throw(&mut self, expr: &Node) {
         self.visit(expr);
         self.push_op(Op::SetException);
-        if let Some(throw_label) = self.throw_label {
-            self.push_op(Op::PopTry);
-            unsafe {
-                self.jump(&mut *throw_label);
-            }
-        } else {
-            self.push_op(Op::ThrowDynamic);
-        }
+        self.push_op(Op::ThrowDynamic);
     }
 
     fn visit_break(&mut self) {
@@ -900,12 +1537,12 @@ impl Assembler {
         self.push_op(Op::PushTry);
         self.jmp(&mut catch); // interpreter eats this for the try_stack
 
-        let ptl = self.throw_label;
-        self.throw_label = Some(&mut catch as *mut Label);
+        self.try_depth += 1;
         self.visit(tryc);
-        self.throw_label = ptl;
+        self.try_depth -= 1;
 
         self.push_op(Op::PopTry);
+        self.jump(&mut finally);
 
         self.mark(&mut catch);
         if let Some(catchc) = catchc {
@@ -943,17 +1580,34 @@ impl Assembler {
     }
 
     fn string_id(&mut self, string: &str) -> u32 {
-        let index = self.string_table.iter().position(|s| s == string);
+        let index = self.string_table.iter().position(|s| &**s == string);
         match index {
             Some(i) => i as u32,
             None => {
                 let id = self.string_table.len();
-                self.string_table.push(string.to_string());
+                self.string_table.push(string.into());
                 id as u32
             }
         }
     }
 
+    // names are identifiers, so they can be listed with commas. code imported
+    // from a chunk has them interned the first time it runs.
+    pub(crate) fn tuple_names(&mut self, id: u32) -> Rc<Vec<String>> {
+        let strings = &self.string_table;
+        self.tuple_names
+            .entry(id)
+            .or_insert_with(|| {
+                Rc::new(
+                    strings[id as usize]
+                        .split(',')
+                        .map(|name| name.to_string())
+                        .collect(),
+                )
+            })
+            .clone()
+    }
+
     fn label(&self) -> Label {
         Label {
             index: None,
@@ -1002,6 +1656,11 @@ impl Assembler {
         self.jmp(label);
     }
 
+    fn jump_if_not_null(&mut self, label: &mut Label) {
+        self.push_op(Op::JumpIfNotNull);
+        self.jmp(label);
+    }
+
     fn push_op(&mut self, op: Op) {
         self.push_u8(op as u8);
     }
@@ -1045,6 +1704,16 @@ impl Assembler {
         self.code.write_f64::<LittleEndian>(n).unwrap();
     }
 
+    fn load_constant(&mut self, c: &Constant) {
+        match c {
+            Constant::Null => self.load_null(),
+            Constant::Boolean(true) => self.load_true(),
+            Constant::Boolean(false) => self.load_false(),
+            Constant::Number(n) => self.load_f64(*n),
+            Constant::String(s) => self.load_string(s),
+        }
+    }
+
     fn load_string(&mut self, s: &str) {
         let id = self.string_id(s);
         self.push_op(Op::LoadString);
@@ -1061,6 +1730,8 @@ impl Assembler {
         self.push_op(Op::LoadNamedProperty);
         let id = self.string_id(s);
         self.push_u32(id);
+        let cache = self.cache_id();
+        self.push_u32(cache);
     }
 
     fn store_named_property(&mut self, obj: &Register, name: &str) {
@@ -1068,6 +1739,13 @@ impl Assembler {
         self.push_u32(obj.id);
         let id = self.string_id(name);
         self.push_u32(id);
+        let cache = self.cache_id();
+        self.push_u32(cache);
+    }
+
+    fn cache_id(&mut self) -> u32 {
+        self.cache_count += 1;
+        self.cache_count - 1
     }
 
     fn load_computed_property(&mut self, obj: &Register) {
@@ -1,5 +1,6 @@
 use crate::interpreter::{Assembler, Op, OpArg};
 use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashSet;
 
 const ANSI_RED: &str = "\x1b[31m";
 const ANSI_GREEN: &str = "\x1b[32m";
@@ -8,103 +9,127 @@ const ANSI_RESET: &str = "\x1b[39m";
 const ANSI_GREY: &str = "\x1b[90m";
 const ANSI_BLUE: &str = "\x1b[94m";
 
-pub fn disassemble(assembler: &Assembler, mut pc: usize, n_instructions: usize) {
-    let format_args = |pc: &mut usize, args: Vec<OpArg>| -> String {
-        args.iter()
-            .map(|arg: &OpArg| -> String {
-                match arg {
-                    OpArg::U8 => {
-                        *pc += 1;
-                        let n = assembler.code[*pc - 1];
-                        format!("[{}{}{}]", ANSI_BLUE, n, ANSI_RESET)
-                    }
-                    OpArg::U32 => {
-                        *pc += 4;
-                        let n = (&assembler.code[(*pc - 4)..])
-                            .read_u32::<LittleEndian>()
-                            .unwrap();
-                        format!("[{}{}{}]", ANSI_BLUE, n, ANSI_RESET)
-                    }
-                    OpArg::F64 => {
-                        *pc += 8;
-                        let n = (&assembler.code[(*pc - 8)..])
-                            .read_f64::<LittleEndian>()
-                            .unwrap();
-                        format!("[{}{}{}]", ANSI_BLUE, n, ANSI_RESET)
-                    }
-                    OpArg::String => {
-                        *pc += 4;
-                        let sid = (&assembler.code[(*pc - 4)..])
-                            .read_u32::<LittleEndian>()
-                            .unwrap() as usize;
-                        format!(
-                            "[{}\"{}\"{}]",
-                            ANSI_GREEN, assembler.string_table[sid], ANSI_RESET
-                        )
-                    }
-                    OpArg::Boolean => {
-                        *pc += 1;
-                        let b = assembler.code[*pc] == 1;
-                        format!("[{}{}{}]", ANSI_YELLOW, b, ANSI_RESET)
-                    }
-                    OpArg::Position => {
-                        *pc += 4;
-                        let position = (&assembler.code[(*pc - 4)..])
-                            .read_u32::<LittleEndian>()
-                            .unwrap();
-                        format!("[{}@{}{}]", ANSI_YELLOW, position, ANSI_RESET)
-                    }
-                    OpArg::Register => {
-                        *pc += 4;
-                        let r = (&assembler.code[(*pc - 4)..])
-                            .read_u32::<LittleEndian>()
-                            .unwrap();
-                        format!("{}r{}{}", ANSI_RED, r, ANSI_RESET)
-                    }
+fn read_u32(assembler: &Assembler, pc: usize) -> u32 {
+    (&assembler.code[pc..]).read_u32::<LittleEndian>().unwrap()
+}
+
+// lists the instructions between start and end, with jump targets marked by
+// `>` and a comment at the start of each statement
+pub fn disassemble(assembler: &Assembler, start: usize, end: usize, color: bool) -> String {
+    let end = end.min(assembler.code.len());
+    let paint = |ansi: &'static str| if color { ansi } else { "" };
+
+    let mut targets = HashSet::new();
+    let mut pc = start;
+    while pc < end {
+        let (_, args) = Op::from(assembler.code[pc]).describe();
+        pc += 1;
+        for arg in args {
+            if let OpArg::Position = arg {
+                targets.insert(read_u32(assembler, pc) as usize);
+            }
+            pc += arg.size();
+        }
+    }
+
+    let mut out = String::new();
+    let mut pc = start;
+    while pc < end {
+        if assembler.has_location(pc) {
+            out += &format!(
+                "{}; {}{}\n",
+                paint(ANSI_GREY),
+                assembler.source_location(pc),
+                paint(ANSI_RESET)
+            );
+        }
+        let (name, args) = Op::from(assembler.code[pc]).describe();
+        let position = pc;
+        pc += 1;
+        let args = args
+            .iter()
+            .map(|arg| {
+                let formatted = match arg {
+                    OpArg::U8 => format!(
+                        "[{}{}{}]",
+                        paint(ANSI_BLUE),
+                        assembler.code[pc],
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::U32 => format!(
+                        "[{}{}{}]",
+                        paint(ANSI_BLUE),
+                        read_u32(assembler, pc),
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::F64 => format!(
+                        "[{}{}{}]",
+                        paint(ANSI_BLUE),
+                        (&assembler.code[pc..]).read_f64::<LittleEndian>().unwrap(),
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::String => format!(
+                        "[{}{:?}{}]",
+                        paint(ANSI_GREEN),
+                        assembler.string_table[read_u32(assembler, pc) as usize],
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::Boolean => format!(
+                        "[{}{}{}]",
+                        paint(ANSI_YELLOW),
+                        assembler.code[pc] == 1,
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::Position => format!(
+                        "[{}@{:03}{}]",
+                        paint(ANSI_YELLOW),
+                        read_u32(assembler, pc),
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::Cache => format!(
+                        "{}#{}{}",
+                        paint(ANSI_GREY),
+                        read_u32(assembler, pc),
+                        paint(ANSI_RESET)
+                    ),
+                    OpArg::Register => format!(
+                        "{}r{}{}",
+                        paint(ANSI_RED),
+                        read_u32(assembler, pc),
+                        paint(ANSI_RESET)
+                    ),
                     OpArg::FunctionInfo => {
-                        *pc += 4;
-                        let r = (&assembler.code[(*pc - 4)..])
-                            .read_u32::<LittleEndian>()
-                            .unwrap() as usize;
-                        let f = &assembler.function_info[r];
+                        let f = &assembler.function_info[read_u32(assembler, pc) as usize];
                         format!(
-                            "<FunctionInfo {} {}@{}{} {:?}>",
-                            r, ANSI_YELLOW, f.position, ANSI_RESET, f.parameters
+                            "<function {} {}@{:03}{} ({})>",
+                            f.name.as_ref().map_or("<anonymous>", |n| n.as_str()),
+                            paint(ANSI_YELLOW),
+                            f.position,
+                            paint(ANSI_RESET),
+                            f.parameters.join(", ")
                         )
                     }
-                }
+                };
+                pc += arg.size();
+                formatted
             })
             .collect::<Vec<String>>()
-            .join(", ")
-    };
-
-    for _ in 0..n_instructions {
-        if pc >= assembler.code.len() {
-            break;
-        }
-        let op = assembler.code[pc].into();
-        pc += 1;
-
-        macro_rules! define_matcher {
-            ( $( ( $name:ident, $acu:expr $( , $arg:expr )* ), )* ) => (
-
-                match op {
-                    $(
-                    Op::$name => {
-                        println!("{}{:03}{} {} {}",
-                            ANSI_GREY,
-                            pc - 1,
-                            ANSI_RESET,
-                            stringify!($name),
-                            format_args(&mut pc, vec![ $( $arg, )* ]),
-                        );
-                    }
-                    )*
-                }
-
-            );
-        }
-
-        OPS!(define_matcher);
+            .join(", ");
+        let line = format!(
+            "{} {}{:03}{} {} {}",
+            if targets.contains(&position) {
+                ">"
+            } else {
+                " "
+            },
+            paint(ANSI_GREY),
+            position,
+            paint(ANSI_RESET),
+            name,
+            args
+        );
+        out += line.trim_end();
+        out.push('\n');
     }
+    out
 }
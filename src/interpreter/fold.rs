@@ -0,0 +1,115 @@
+use crate::num_util::{f64_band, f64_bnot, f64_bor, f64_bxor, f64_shl, f64_shr};
+use crate::parser::{Node, Operator};
+
+// expressions whose value is known when assembling. folding follows what the
+// ops would do at runtime, anything that would throw is left for the runtime
+// to throw.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Constant {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Constant {
+    pub(crate) fn to_bool(&self) -> bool {
+        match self {
+            Constant::Null => false,
+            Constant::Boolean(b) => *b,
+            Constant::Number(n) => *n != 0.0,
+            Constant::String(s) => !s.is_empty(),
+        }
+    }
+
+    fn type_of(&self) -> &'static str {
+        match self {
+            Constant::Null => "null",
+            Constant::Boolean(..) => "boolean",
+            Constant::Number(..) => "number",
+            Constant::String(..) => "string",
+        }
+    }
+}
+
+pub(crate) fn constant(node: &Node) -> Option<Constant> {
+    match node {
+        Node::NullLiteral => Some(Constant::Null),
+        Node::TrueLiteral => Some(Constant::Boolean(true)),
+        Node::FalseLiteral => Some(Constant::Boolean(false)),
+        Node::NumberLiteral(n) => Some(Constant::Number(*n)),
+        Node::StringLiteral(s) => Some(Constant::String(s.clone())),
+        Node::ParenthesizedExpression(expr) => constant(expr),
+        Node::UnaryExpression(op, expr) => unary(*op, expr),
+        Node::BinaryExpression(op, lhs, rhs) => binary(*op, lhs, rhs),
+        Node::ConditionalExpression(test, consequent, alternative) => {
+            if constant(test)?.to_bool() {
+                constant(consequent)
+            } else {
+                constant(alternative)
+            }
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn unary(op: Operator, expr: &Node) -> Option<Constant> {
+    let value = constant(expr)?;
+    match (op, value) {
+        (Operator::Not, v) => Some(Constant::Boolean(!v.to_bool())),
+        (Operator::Typeof, v) => Some(Constant::String(v.type_of().to_string())),
+        (Operator::Void, _) => Some(Constant::Null),
+        (Operator::Sub, Constant::Number(n)) => Some(Constant::Number(-n)),
+        (Operator::BitwiseNOT, Constant::Number(n)) => Some(Constant::Number(f64_bnot(n))),
+        _ => None,
+    }
+}
+
+pub(crate) fn binary(op: Operator, lhs: &Node, rhs: &Node) -> Option<Constant> {
+    let l = constant(lhs)?;
+    // the logical operators only need the lhs to know which side they take
+    match op {
+        Operator::LogicalAND if !l.to_bool() => return Some(l),
+        Operator::LogicalOR if l.to_bool() => return Some(l),
+        Operator::NullishCoalescing if l != Constant::Null => return Some(l),
+        Operator::LogicalAND | Operator::LogicalOR | Operator::NullishCoalescing => {
+            return constant(rhs);
+        }
+        _ => {}
+    }
+    let r = constant(rhs)?;
+    let number = |f: fn(f64, f64) -> f64| match (&l, &r) {
+        (Constant::Number(a), Constant::Number(b)) => Some(Constant::Number(f(*a, *b))),
+        _ => None,
+    };
+    let compare = |f: fn(&f64, &f64) -> bool| match (&l, &r) {
+        (Constant::Number(a), Constant::Number(b)) => Some(Constant::Boolean(f(a, b))),
+        _ => None,
+    };
+    match op {
+        Operator::Add => match (&l, &r) {
+            (Constant::String(a), Constant::String(b)) => {
+                Some(Constant::String(format!("{}{}", a, b)))
+            }
+            _ => number(|a, b| a + b),
+        },
+        Operator::Sub => number(|a, b| a - b),
+        Operator::Mul => number(|a, b| a * b),
+        Operator::Div => number(|a, b| a / b),
+        Operator::Mod => number(|a, b| a % b),
+        Operator::Pow => number(f64::powf),
+        Operator::BitwiseOR => number(f64_bor),
+        Operator::BitwiseXOR => number(f64_bxor),
+        Operator::BitwiseAND => number(f64_band),
+        Operator::LeftShift => number(f64_shl),
+        Operator::RightShift => number(f64_shr),
+        Operator::GreaterThan => compare(f64::gt),
+        Operator::LessThan => compare(f64::lt),
+        Operator::GreaterThanOrEqual => compare(f64::ge),
+        Operator::LessThanOrEqual => compare(f64::le),
+        Operator::Equal => Some(Constant::Boolean(l == r)),
+        Operator::NotEqual => Some(Constant::Boolean(l != r)),
+        _ => None,
+    }
+}
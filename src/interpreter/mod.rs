@@ -1,9 +1,9 @@
+use crate::crash::Running;
 use crate::module::Module;
 use crate::num_util::{f64_band, f64_bnot, f64_bor, f64_bxor, f64_shl, f64_shr};
 use crate::parser::FunctionKind;
 use crate::value::{ObjectKey, ObjectKind};
 use crate::{Agent, Value};
-use byteorder::{LittleEndian, ReadBytesExt};
 use gc::{Gc, GcCell};
 use indexmap::IndexMap;
 use std::ops::{Div, Mul, Rem, Sub};
@@ -25,6 +25,23 @@ pub enum OpArg {
     Register,
     Position,
     FunctionInfo,
+    // the id of the site's inline cache
+    Cache,
+}
+
+impl OpArg {
+    pub fn size(&self) -> usize {
+        match self {
+            OpArg::U8 | OpArg::Boolean => 1,
+            OpArg::F64 => 8,
+            OpArg::U32
+            | OpArg::String
+            | OpArg::Position
+            | OpArg::Register
+            | OpArg::FunctionInfo
+            | OpArg::Cache => 4,
+        }
+    }
 }
 
 #[rustfmt::skip]
@@ -38,20 +55,25 @@ macro_rules! OPS {
             (LoadF64, AccumulatorUse::Write, OpArg::F64),
             (LoadString, AccumulatorUse::Write, OpArg::String),
             (LoadSymbol, AccumulatorUse::Write, OpArg::String),
+            (NewSymbol, AccumulatorUse::Write, OpArg::String),
 
             (BuildRegex, AccumulatorUse::Write, OpArg::String),
             (CreateEmptyArray, AccumulatorUse::Write),
             (StoreInArrayLiteral, AccumulatorUse::Read, OpArg::Register, OpArg::U32),
             (CreateEmptyTuple, AccumulatorUse::Write),
             (StoreInTuple, AccumulatorUse::Read, OpArg::Register),
+            (NameTuple, AccumulatorUse::None, OpArg::Register, OpArg::String),
+            (CreateEmptyRecord, AccumulatorUse::Write),
+            (StoreInRecord, AccumulatorUse::Read, OpArg::Register, OpArg::String),
             (CreateEmptyObject, AccumulatorUse::Write),
             (StoreInObjectLiteral, AccumulatorUse::Read, OpArg::Register, OpArg::Register),
             (NewFunction, AccumulatorUse::ReadWrite, OpArg::FunctionInfo),
             (FinishClass, AccumulatorUse::ReadWrite, OpArg::Register, OpArg::Register, OpArg::String),
+            (Freeze, AccumulatorUse::ReadWrite),
 
-            (LoadNamedProperty, AccumulatorUse::ReadWrite, OpArg::String),
+            (LoadNamedProperty, AccumulatorUse::ReadWrite, OpArg::String, OpArg::Cache),
             (LoadComputedProperty, AccumulatorUse::ReadWrite, OpArg::Register),
-            (StoreNamedProperty, AccumulatorUse::ReadWrite, OpArg::Register, OpArg::String),
+            (StoreNamedProperty, AccumulatorUse::ReadWrite, OpArg::Register, OpArg::String, OpArg::Cache),
             (StoreComputedProperty, AccumulatorUse::ReadWrite, OpArg::Register, OpArg::Register),
 
             (LexicalDeclaration, AccumulatorUse::None, OpArg::String, OpArg::Boolean),
@@ -75,6 +97,13 @@ macro_rules! OPS {
             (JumpIfTrue, AccumulatorUse::Read, OpArg::Position),
             (JumpIfFalse, AccumulatorUse::Read, OpArg::Position),
             (JumpIfNotEmpty, AccumulatorUse::Read, OpArg::Position),
+            (JumpIfNotNull, AccumulatorUse::Read, OpArg::Position),
+
+            (TestTuple, AccumulatorUse::Write, OpArg::Register, OpArg::U32),
+            (TestTupleField, AccumulatorUse::Write, OpArg::Register, OpArg::String),
+            (TestArray, AccumulatorUse::Write, OpArg::Register, OpArg::U32),
+            (TestObject, AccumulatorUse::Write, OpArg::Register),
+            (ThrowNoMatch, AccumulatorUse::Read),
 
             (PushTry, AccumulatorUse::None,OpArg::Position),
             (PopTry, AccumulatorUse::None),
@@ -139,6 +168,21 @@ macro_rules! define_op_enum {
             }
         }
 
+        impl Op {
+            // name and operands, for tools that walk the bytecode
+            pub fn describe(&self) -> (&'static str, Vec<OpArg>) {
+                match self {
+                    $( Op::$name => (stringify!($name), vec![ $( $arg, )* ]), )*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $( Op::$name => stringify!($name), )*
+                }
+            }
+        }
+
     );
 }
 
@@ -148,8 +192,9 @@ pub const REGISTER_COUNT: usize = 16;
 
 mod assembler;
 mod disassembler;
+mod fold;
 
-pub use assembler::{Assembler, AssemblerFunctionInfo};
+pub use assembler::{Assembler, AssemblerFunctionInfo, Chunk};
 pub use disassembler::disassemble;
 
 #[derive(Trace, Finalize, Debug)]
@@ -175,6 +220,40 @@ impl Scope {
         }))
     }
 
+    pub(crate) fn parent(&self) -> Option<&Gc<GcCell<Scope>>> {
+        self.parent.as_ref()
+    }
+
+    pub(crate) fn shallow_size(&self) -> usize {
+        std::mem::size_of::<Scope>()
+            + self.bindings.capacity() * std::mem::size_of::<(String, Binding)>()
+    }
+
+    // initialized bindings, in the order they were declared
+    pub fn locals(&self) -> Vec<(String, Value)> {
+        self.bindings
+            .iter()
+            .filter_map(|(name, binding)| {
+                binding
+                    .value
+                    .as_ref()
+                    .map(|value| (name.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    // the values of initialized bindings, and `this`
+    pub(crate) fn for_each_reference<F: FnMut(String, &Value)>(&self, mut f: F) {
+        for (name, binding) in self.bindings.iter() {
+            if let Some(value) = &binding.value {
+                f(name.clone(), value);
+            }
+        }
+        if let Some(this) = &self.this {
+            f("this".to_string(), this);
+        }
+    }
+
     pub fn create(&mut self, agent: &Agent, name: &str, mutable: bool) -> Result<(), Value> {
         if self.bindings.contains_key(name) {
             Err(Value::new_error(
@@ -223,7 +302,7 @@ impl Scope {
         self.bindings.get_mut(name).unwrap().value = Some(value);
     }
 
-    fn get(&self, agent: &Agent, name: &str) -> Result<Value, Value> {
+    pub fn get(&self, agent: &Agent, name: &str) -> Result<Value, Value> {
         match self.bindings.get(name) {
             Some(Binding { value: Some(v), .. }) => Ok(v.clone()),
             Some(Binding {
@@ -287,16 +366,21 @@ impl Context {
 #[derive(Debug, Trace, Finalize)]
 pub struct SuspendValue(pub Value);
 
+// a bytecode function (or module, if `function` is None) being evaluated. for
+// frames below the top, `position` is the call they are waiting on
+pub struct StackFrame {
+    pub function: Option<Value>,
+    pub position: usize,
+}
+
 #[derive(Debug, Trace, Finalize)]
 struct Registers {
-    last: Option<Box<Registers>>,
     registers: [Value; REGISTER_COUNT],
 }
 
 impl Registers {
-    fn new(last: Option<Box<Registers>>) -> Registers {
+    fn new() -> Registers {
         Registers {
-            last,
             registers: [
                 Value::Empty,
                 Value::Empty,
@@ -340,10 +424,14 @@ pub struct Interpreter {
     pub accumulator: Value,
     pub exception: Option<Value>,
     pc: usize,
-    try_stack: Vec<usize>,
+    // catch position, and the call depth and scope to unwind to
+    try_stack: Vec<(usize, usize, Gc<GcCell<Scope>>)>,
     context: Vec<Gc<GcCell<Context>>>,
     positions: Vec<usize>,
     registers: Registers,
+    // the registers of the calls below the current one, kept in a vec that
+    // grows to the deepest call instead of boxing them for every call
+    saved_registers: Vec<Registers>,
 }
 
 impl Interpreter {
@@ -355,33 +443,70 @@ impl Interpreter {
             try_stack: Vec::new(),
             context: vec![ctx],
             positions: Vec::new(),
-            registers: Registers::new(None),
+            registers: Registers::new(),
+            saved_registers: Vec::new(),
         }
     }
 
     pub fn run(&mut self, agent: &Agent) -> Result<Result<Value, Value>, SuspendValue> {
+        if let Err(e) = agent.check_call_depth() {
+            return Ok(Err(e));
+        }
+        let depth = agent.call_stack.borrow().len();
+        let _running = if depth == 0 {
+            agent.start_budget();
+            Some(Running::new(agent))
+        } else {
+            None
+        };
+        for ctx in &self.context {
+            agent.push_frame(ctx.borrow().function.clone());
+        }
+        let result = self.evaluate(agent);
+        agent.pop_frames(depth);
+        if depth == 0 {
+            agent.clear_exhausted();
+            agent.debugger.finish();
+        }
+        result
+    }
+
+    fn evaluate(&mut self, agent: &Agent) -> Result<Result<Value, Value>, SuspendValue> {
         macro_rules! push_context {
             ($ctx:expr) => {
                 self.context.push($ctx);
-                unsafe {
-                    std::ptr::write(
-                        &mut self.registers,
-                        Registers::new(Some(Box::new(std::ptr::read(&self.registers)))),
-                    );
-                }
+                self.saved_registers
+                    .push(std::mem::replace(&mut self.registers, Registers::new()));
             };
         }
 
         macro_rules! pop_context {
             () => {
-                self.context.pop().unwrap();
-                self.registers = *self.registers.last.take().unwrap();
+                // the first context came from whoever made the interpreter, the
+                // rest were made by calls and can't be held on to elsewhere
+                let ctx = self.context.pop().unwrap();
+                if !self.context.is_empty() {
+                    agent.recycle_context(ctx);
+                }
+                self.registers = self.saved_registers.pop().unwrap();
+                agent.pop_frame();
+            };
+        }
+
+        // drop the frames of calls made and scopes entered since the try was pushed
+        macro_rules! unwind {
+            ($depth:expr, $scope:expr) => {
+                while self.positions.len() > $depth {
+                    self.positions.pop();
+                    pop_context!();
+                }
+                self.context.last().unwrap().borrow_mut().scope = $scope;
             };
         }
 
         macro_rules! read_u8 {
             () => {{
-                let n = agent.assembler.code[self.pc];
+                let n = agent.assembler.borrow().code[self.pc];
                 self.pc += 1;
                 n
             }};
@@ -389,21 +514,19 @@ impl Interpreter {
 
         macro_rules! read_u32 {
             () => {{
-                let n = (&agent.assembler.code[self.pc..])
-                    .read_u32::<LittleEndian>()
-                    .unwrap();
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&agent.assembler.borrow().code[self.pc..self.pc + 4]);
                 self.pc += 4;
-                n
+                u32::from_le_bytes(bytes)
             }};
         }
 
         macro_rules! read_f64 {
             () => {{
-                let n = (&agent.assembler.code[self.pc..])
-                    .read_f64::<LittleEndian>()
-                    .unwrap();
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&agent.assembler.borrow().code[self.pc..self.pc + 8]);
                 self.pc += 8;
-                n
+                f64::from_bits(u64::from_le_bytes(bytes))
             }};
         }
 
@@ -437,9 +560,10 @@ impl Interpreter {
             }};
         }
 
-        if self.exception.is_some() {
+        if self.exception.is_some() && !agent.budget_exhausted() {
             match self.try_stack.pop() {
-                Some(position) => {
+                Some((position, depth, scope)) => {
+                    unwind!(depth, scope);
                     self.pc = position;
                 }
                 None => {
@@ -448,15 +572,26 @@ impl Interpreter {
             }
         }
 
+        // none of these can change while the agent is borrowed to run, so one
+        // check here keeps them off the path of every op
+        let hooked =
+            agent.debugger.is_attached() || agent.instrument_ops || crate::crash::installed();
+
         'main: loop {
             macro_rules! handle {
-                ($ex:expr) => {
-                    match $ex {
+                ($ex:expr) => {{
+                    // bind first so borrows made by $ex end before unwinding
+                    let result = $ex;
+                    match result {
                         Ok(v) => v,
                         Err(e) => {
                             self.exception = Some(e);
+                            if agent.budget_exhausted() {
+                                break 'main;
+                            }
                             match self.try_stack.pop() {
-                                Some(position) => {
+                                Some((position, depth, scope)) => {
+                                    unwind!(depth, scope);
                                     self.pc = position;
                                     continue 'main;
                                 }
@@ -466,13 +601,29 @@ impl Interpreter {
                             }
                         }
                     }
-                };
+                }};
             }
 
-            if self.pc >= agent.assembler.code.len() {
-                break;
+            let op: Op = match agent.assembler.borrow().code.get(self.pc) {
+                Some(&byte) => byte.into(),
+                None => break,
+            };
+            if let Err(e) = agent.tick() {
+                // skips every try, the host sees it instead
+                return Ok(Err(e));
+            }
+            agent.position.set(self.pc);
+            if hooked {
+                agent.history.push(self.pc);
+                if agent.debugger.is_attached() {
+                    agent
+                        .debugger
+                        .check(agent, self.pc, self.context.last().unwrap());
+                }
+                if agent.instrument_ops {
+                    agent.instrument_op(self.pc, op.name());
+                }
             }
-            let op = agent.assembler.code[self.pc].into();
             self.pc += 1;
 
             match op {
@@ -497,26 +648,35 @@ impl Interpreter {
                 }
                 Op::LoadString => {
                     let sid = read_u32!() as usize;
-                    let s = agent.assembler.string_table[sid].as_str();
-                    self.accumulator = Value::from(s);
+                    let s = Value::from(agent.assembler.borrow().string_table[sid].clone());
+                    self.accumulator = s;
                 }
                 Op::LoadSymbol => {
                     let nid = read_u32!() as usize;
-                    let name = agent.assembler.string_table[nid].to_string();
+                    let name = agent.assembler.borrow().string_table[nid].to_string();
                     let sym = Value::new_well_known_symbol(name);
                     self.accumulator = sym;
                 }
+                Op::NewSymbol => {
+                    let did = read_u32!() as usize;
+                    let description = agent.assembler.borrow().string_table[did].to_string();
+                    self.accumulator = Value::new_symbol(Some(description));
+                }
                 Op::BuildRegex => {
                     let pid = read_u32!() as usize;
-                    let pattern = &agent.assembler.string_table[pid];
+                    let assembler = agent.assembler.borrow();
+                    let pattern = &assembler.string_table[pid];
                     let r = handle!(Value::new_regex_object(agent, pattern));
                     self.accumulator = r;
                 }
                 Op::LoadNamedProperty => {
                     let sid = read_u32!() as usize;
-                    let key = agent.assembler.string_table[sid].as_str();
-                    let key = ObjectKey::from(key);
-                    self.accumulator = handle!(self.accumulator.get(agent, key));
+                    let cid = read_u32!() as usize;
+                    let key = ObjectKey::from(agent.assembler.borrow().string_table[sid].clone());
+                    self.accumulator = match &self.accumulator {
+                        Value::Object(o) => o.get_cached(key, &mut agent.inline_cache(cid)),
+                        _ => handle!(self.accumulator.get(agent, key)),
+                    };
                 }
                 Op::LoadComputedProperty => {
                     let objid = read_u32!() as usize;
@@ -526,8 +686,17 @@ impl Interpreter {
                 Op::StoreNamedProperty => {
                     let oid = read_u32!() as usize;
                     let sid = read_u32!() as usize;
-                    let key = ObjectKey::from(agent.assembler.string_table[sid].as_str());
-                    handle!(self.registers[oid].set(agent, key, self.accumulator.clone()));
+                    let cid = read_u32!() as usize;
+                    let key = ObjectKey::from(agent.assembler.borrow().string_table[sid].clone());
+                    let stored = match &self.registers[oid] {
+                        Value::Object(o) => {
+                            o.set_cached(&key, &self.accumulator, &mut agent.inline_cache(cid))
+                        }
+                        _ => false,
+                    };
+                    if !stored {
+                        handle!(self.registers[oid].set(agent, key, self.accumulator.clone()));
+                    }
                 }
                 Op::StoreComputedProperty => {
                     let oid = read_u32!() as usize;
@@ -565,7 +734,8 @@ impl Interpreter {
                 Op::LexicalDeclaration => {
                     let sid = read_u32!() as usize;
                     let mutable = read_u8!() == 1;
-                    let name = &agent.assembler.string_table[sid];
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
                     handle!(self
                         .context
                         .last()
@@ -577,7 +747,8 @@ impl Interpreter {
                 }
                 Op::LexicalInitialization => {
                     let sid = read_u32!() as usize;
-                    let name = &agent.assembler.string_table[sid];
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
                     let value = std::mem::replace(&mut self.accumulator, Value::Null);
                     self.context
                         .last()
@@ -589,7 +760,8 @@ impl Interpreter {
                 }
                 Op::OverwriteBinding => {
                     let sid = read_u32!() as usize;
-                    let name = &agent.assembler.string_table[sid];
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
                     let value = std::mem::replace(&mut self.accumulator, Value::Null);
                     self.context
                         .last()
@@ -601,7 +773,8 @@ impl Interpreter {
                 }
                 Op::ResolveIdentifier => {
                     let sid = read_u32!() as usize;
-                    let name = &agent.assembler.string_table[sid];
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
                     self.accumulator = handle!(self
                         .context
                         .last()
@@ -613,7 +786,8 @@ impl Interpreter {
                 }
                 Op::AssignIdentifier => {
                     let sid = read_u32!() as usize;
-                    let name = &agent.assembler.string_table[sid];
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
                     handle!(self
                         .context
                         .last()
@@ -696,7 +870,7 @@ impl Interpreter {
                                 ..
                             } => {
                                 let scope = Scope::new(Some(scope.clone()));
-                                let ctx = Context::new(scope.clone());
+                                let ctx = agent.call_context(scope.clone());
                                 for (i, param) in parameters.iter().enumerate() {
                                     handle!(scope.borrow_mut().create(agent, param, false));
                                     let value = if i >= argc {
@@ -714,12 +888,17 @@ impl Interpreter {
                                     let r = handle!(self.registers[rid].to_object(agent));
                                     scope.borrow_mut().this = Some(r);
                                 }
-                                if op == Op::TailCall {
+                                // the first context belongs to whoever made the
+                                // interpreter, a builtin calling back into script
+                                // say, so a tail call from it is a plain call
+                                if op == Op::TailCall && !self.positions.is_empty() {
                                     pop_context!();
                                 } else {
+                                    handle!(agent.check_call_depth());
                                     self.positions.push(self.pc);
                                 }
                                 push_context!(ctx);
+                                agent.push_frame(Some(callee.clone()));
                                 self.pc = *position;
                             }
                             _ => handle!(Err(Value::new_error(agent, "value is not a function"))),
@@ -727,15 +906,25 @@ impl Interpreter {
                         _ => handle!(Err(Value::new_error(agent, "value is not a function"))),
                     }
                 }
-                Op::Return => match self.positions.pop() {
-                    Some(p) => {
-                        pop_context!();
-                        self.pc = p;
+                Op::Return => {
+                    // trys of the returning function can't catch anything now
+                    let depth = self.positions.len();
+                    while let Some((_, d, _)) = self.try_stack.last() {
+                        if *d < depth {
+                            break;
+                        }
+                        self.try_stack.pop();
                     }
-                    None => {
-                        break 'main;
+                    match self.positions.pop() {
+                        Some(p) => {
+                            pop_context!();
+                            self.pc = p;
+                        }
+                        None => {
+                            break 'main;
+                        }
                     }
-                },
+                }
                 Op::GetIterator | Op::GetAsyncIterator => {
                     let sym = handle!(Value::new_well_known_symbol(
                         if op == Op::GetAsyncIterator {
@@ -791,9 +980,61 @@ impl Interpreter {
                         self.pc = position;
                     }
                 }
+                Op::JumpIfNotNull => {
+                    let position = read_u32!() as usize;
+                    if self.accumulator != Value::Null {
+                        self.pc = position;
+                    }
+                }
+                Op::TestTuple => {
+                    let rid = read_u32!() as usize;
+                    let len = read_u32!() as usize;
+                    self.accumulator = Value::from(match &self.registers[rid] {
+                        Value::Tuple(items, ..) => items.len() == len,
+                        _ => false,
+                    });
+                }
+                Op::TestTupleField => {
+                    let rid = read_u32!() as usize;
+                    let sid = read_u32!() as usize;
+                    let assembler = agent.assembler.borrow();
+                    let name = &assembler.string_table[sid];
+                    self.accumulator = Value::from(match &self.registers[rid] {
+                        Value::Tuple(_, Some(names)) => names.iter().any(|n| **n == **name),
+                        _ => false,
+                    });
+                }
+                Op::TestArray => {
+                    let rid = read_u32!() as usize;
+                    let len = read_u32!() as usize;
+                    self.accumulator = Value::from(match &self.registers[rid] {
+                        Value::Object(o) => match &o.kind {
+                            ObjectKind::Array(values) => values.borrow().len() == len,
+                            _ => false,
+                        },
+                        _ => false,
+                    });
+                }
+                Op::TestObject => {
+                    let rid = read_u32!() as usize;
+                    self.accumulator = Value::from(match &self.registers[rid] {
+                        Value::Object(..) | Value::Record(..) => true,
+                        _ => false,
+                    });
+                }
+                Op::ThrowNoMatch => {
+                    handle!(Err(Value::new_error(
+                        agent,
+                        &format!(
+                            "no pattern matched {}",
+                            Value::inspect(agent, &self.accumulator)
+                        )
+                    )));
+                }
                 Op::PushTry => {
                     let pos = read_u32!() as usize;
-                    self.try_stack.push(pos);
+                    let scope = self.context.last().unwrap().borrow().scope.clone();
+                    self.try_stack.push((pos, self.positions.len(), scope));
                 }
                 Op::PopTry => {
                     self.try_stack.pop();
@@ -801,7 +1042,8 @@ impl Interpreter {
                 Op::ThrowDynamic => {
                     debug_assert!(self.exception.is_some());
                     match self.try_stack.pop() {
-                        Some(position) => {
+                        Some((position, depth, scope)) => {
+                            unwind!(depth, scope);
                             self.pc = position;
                             continue 'main;
                         }
@@ -824,12 +1066,40 @@ impl Interpreter {
                 }
                 Op::StoreInTuple => {
                     let tid = read_u32!() as usize;
-                    if let Value::Tuple(items) = &mut self.registers[tid] {
+                    if let Value::Tuple(items, ..) = &mut self.registers[tid] {
                         items.push(std::mem::replace(&mut self.accumulator, Value::Empty));
                     } else {
                         unreachable!();
                     }
                 }
+                Op::NameTuple => {
+                    let tid = read_u32!() as usize;
+                    let sid = read_u32!();
+                    if let Value::Tuple(_, names) = &mut self.registers[tid] {
+                        *names = Some(agent.assembler.borrow_mut().tuple_names(sid));
+                    } else {
+                        unreachable!();
+                    }
+                }
+                Op::CreateEmptyRecord => {
+                    self.accumulator = Value::new_record();
+                }
+                Op::StoreInRecord => {
+                    let rid = read_u32!() as usize;
+                    let sid = read_u32!() as usize;
+                    if !self.accumulator.is_deeply_immutable() {
+                        handle!(Err(Value::new_error(
+                            agent,
+                            "records may only contain primitives, tuples and records"
+                        )));
+                    }
+                    if let Value::Record(fields) = &mut self.registers[rid] {
+                        let key = agent.assembler.borrow().string_table[sid].to_string();
+                        fields.insert(key, std::mem::replace(&mut self.accumulator, Value::Empty));
+                    } else {
+                        unreachable!();
+                    }
+                }
                 Op::CreateEmptyObject => {
                     self.accumulator = Value::new_object(agent.intrinsics.object_prototype.clone());
                 }
@@ -841,7 +1111,8 @@ impl Interpreter {
                 }
                 Op::NewFunction => {
                     let id = read_u32!() as usize;
-                    let info = &agent.assembler.function_info[id];
+                    let assembler = agent.assembler.borrow();
+                    let info = &assembler.function_info[id];
                     let scope = Scope::new(match self.context.last() {
                         Some(c) => Some(c.borrow().scope.clone()),
                         None => None,
@@ -853,16 +1124,17 @@ impl Interpreter {
                     let eid = read_u32!() as usize;
                     let nid = read_u32!() as usize;
 
-                    let name = agent.assembler.string_table[nid].as_str();
-                    handle!(self.registers[cid].set(
-                        agent,
-                        ObjectKey::from("name"),
-                        Value::from(name)
-                    ));
+                    let name = Value::from(agent.assembler.borrow().string_table[nid].clone());
+                    handle!(self.registers[cid].set(agent, ObjectKey::from("name"), name));
                     if self.registers[eid] != Value::Empty {
                         // FIXME: self.registers[cid].set_prototype(self.registers[eid]);
                     }
                 }
+                Op::Freeze => {
+                    if let Value::Object(o) = &self.accumulator {
+                        o.freeze();
+                    }
+                }
                 Op::ToString => {
                     if self.accumulator.type_of() != "string" {
                         let ts = handle!(self.accumulator.get(agent, ObjectKey::from("toString")));
@@ -887,6 +1159,7 @@ impl Interpreter {
                         },
                         Value::String(ref ls) => match self.accumulator {
                             Value::String(ref rs) => {
+                                agent.count_bytes(ls.len() + rs.len());
                                 self.accumulator = Value::from(format!("{}{}", ls, rs));
                             }
                             _ => handle!(Err(Value::new_error(agent, "rhs must be a string"))),
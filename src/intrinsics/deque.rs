@@ -0,0 +1,38 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::deque_prototype::{update_size, with_values};
+use crate::intrinsics::promise::collect;
+use crate::value::{ObjectKey, Value};
+
+// with or without `new`, optionally filled from an array or iterable
+fn deque(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    let deque = Value::new_custom_object(agent.intrinsics.deque_prototype.clone());
+    deque.set_slot("deque values", Value::new_list());
+    match args.get(0) {
+        None | Some(Value::Null) => {}
+        Some(items) => {
+            let items = collect(agent, items)?;
+            with_values(&deque, |values| values.extend(items));
+        }
+    }
+    update_size(agent, &deque)?;
+    Ok(deque)
+}
+
+pub fn create_deque(agent: &Agent) -> Value {
+    let d = Value::new_builtin_function(agent, deque);
+
+    d.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.deque_prototype.clone(),
+    )
+    .expect("failed to set prototype on deque constructor");
+    agent
+        .intrinsics
+        .deque_prototype
+        .set(agent, ObjectKey::from("constructor"), d.clone())
+        .expect("failed to set constructor on deque prototype");
+
+    d
+}
@@ -0,0 +1,133 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use std::collections::VecDeque;
+
+// a deque keeps its values in a list slot. reading the slot would copy the
+// list, so it is borrowed where it is stored instead.
+pub fn with_values<R>(deque: &Value, f: impl FnOnce(&mut VecDeque<Value>) -> R) -> R {
+    if let Value::Object(o) = deque {
+        if let ObjectKind::Custom(slots) = &o.kind {
+            if let Some(Value::List(values)) = slots.borrow().get("deque values") {
+                return f(&mut values.borrow_mut());
+            }
+        }
+    }
+    unreachable!();
+}
+
+pub fn update_size(agent: &Agent, deque: &Value) -> Result<(), Value> {
+    let size = with_values(deque, |values| values.len());
+    deque.set(agent, ObjectKey::from("size"), Value::from(size as f64))?;
+    Ok(())
+}
+
+fn this_deque(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("deque values") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn push_back(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    with_values(&deque, |values| values.extend(args));
+    update_size(agent, &deque)?;
+    Ok(Value::Null)
+}
+
+fn push_front(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    // so that the values end up at the front in the order they were given
+    with_values(&deque, |values| {
+        for arg in args.into_iter().rev() {
+            values.push_front(arg);
+        }
+    });
+    update_size(agent, &deque)?;
+    Ok(Value::Null)
+}
+
+// null if the deque is empty
+fn pop_back(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    let value = with_values(&deque, |values| values.pop_back());
+    update_size(agent, &deque)?;
+    Ok(value.unwrap_or(Value::Null))
+}
+
+fn pop_front(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    let value = with_values(&deque, |values| values.pop_front());
+    update_size(agent, &deque)?;
+    Ok(value.unwrap_or(Value::Null))
+}
+
+fn peek_back(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    Ok(with_values(&deque, |values| values.back().cloned()).unwrap_or(Value::Null))
+}
+
+fn peek_front(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    Ok(with_values(&deque, |values| values.front().cloned()).unwrap_or(Value::Null))
+}
+
+// the value i places from the front
+fn get(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    let value = match args.get(0) {
+        Some(Value::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => {
+            with_values(&deque, |values| values.get(*n as usize).cloned())
+        }
+        _ => None,
+    };
+    value.ok_or_else(|| Value::new_error(agent, "index out of range"))
+}
+
+fn clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    with_values(&deque, |values| values.clear());
+    update_size(agent, &deque)?;
+    Ok(Value::Null)
+}
+
+fn to_array(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let deque = this_deque(agent, ctx)?;
+    let array = Value::new_array(agent);
+    if let Value::Object(o) = &array {
+        if let ObjectKind::Array(items) = &o.kind {
+            *items.borrow_mut() = with_values(&deque, |values| values.iter().cloned().collect());
+        }
+    }
+    Ok(array)
+}
+
+pub fn create_deque_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("pushBack", push_back);
+    method!("pushFront", push_front);
+    method!("popBack", pop_back);
+    method!("popFront", pop_front);
+    method!("peekBack", peek_back);
+    method!("peekFront", peek_front);
+    method!("get", get);
+    method!("clear", clear);
+    method!("toArray", to_array);
+
+    proto
+}
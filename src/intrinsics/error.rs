@@ -0,0 +1,44 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+
+fn error(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    let e = match args.get(0) {
+        Some(Value::String(s)) => Value::new_error(agent, s),
+        Some(Value::Null) | None => {
+            let e = Value::new_error(agent, "");
+            e.set(agent, ObjectKey::from("message"), Value::Null)?;
+            e
+        }
+        Some(_) => return Err(Value::new_error(agent, "message must be a string")),
+    };
+    match args.get(1) {
+        Some(options @ Value::Object(..)) => {
+            let cause = options.get(agent, ObjectKey::from("cause"))?;
+            if cause != Value::Null {
+                e.set(agent, ObjectKey::from("cause"), cause)?;
+            }
+        }
+        Some(Value::Null) | None => {}
+        Some(_) => return Err(Value::new_error(agent, "options must be an object")),
+    }
+    Ok(e)
+}
+
+pub fn create_error(agent: &Agent) -> Value {
+    let e = Value::new_builtin_function(agent, error);
+
+    e.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.error_prototype.clone(),
+    )
+    .expect("failed to set prototype on error constructor");
+    agent
+        .intrinsics
+        .error_prototype
+        .set(agent, ObjectKey::from("constructor"), e.clone())
+        .expect("failed to set constructor on error prototype");
+
+    e
+}
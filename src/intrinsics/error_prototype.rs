@@ -2,20 +2,41 @@ use crate::agent::Agent;
 use crate::interpreter::Context;
 use crate::value::{ObjectKey, Value};
 
-fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
-    let this = ctx.scope.borrow().get_this(agent)?;
-
-    let name = match this.get(agent, ObjectKey::from("name"))? {
+fn describe(agent: &Agent, error: &Value) -> Result<String, Value> {
+    let name = match error.get(agent, ObjectKey::from("name"))? {
         Value::String(s) => s,
         _ => return Err(Value::new_error(agent, "Invalid error object")),
     };
-    let message = match this.get(agent, ObjectKey::from("message"))? {
+    let message = match error.get(agent, ObjectKey::from("message"))? {
         Value::String(s) => format!(": {}", s),
         Value::Null => "".to_string(),
         _ => return Err(Value::new_error(agent, "Invalid error object")),
     };
+    Ok(format!("{}{}", name, message))
+}
+
+fn to_string(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+
+    let mut out = describe(agent, &this)?;
+    // follow the cause chain, stopping at anything that isn't an error
+    let mut seen = vec![this.clone()];
+    let mut cause = this.get(agent, ObjectKey::from("cause"))?;
+    while cause != Value::Null {
+        if seen.contains(&cause) {
+            out += "\ncaused by: [Circular]";
+            break;
+        }
+        if !cause.is_error(agent) {
+            out += &format!("\ncaused by: {}", Value::inspect(agent, &cause));
+            break;
+        }
+        out += &format!("\ncaused by: {}", describe(agent, &cause)?);
+        let next = cause.get(agent, ObjectKey::from("cause"))?;
+        seen.push(std::mem::replace(&mut cause, next));
+    }
 
-    Ok(Value::from(format!("{}{}", name, message)))
+    Ok(Value::from(out))
 }
 
 pub fn create_error_prototype(agent: &Agent) -> Value {
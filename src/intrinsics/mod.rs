@@ -1,14 +1,21 @@
 mod array_prototype;
 mod async_iterator_prototype;
 mod boolean_prototype;
+mod deque;
+mod deque_prototype;
+mod error;
 mod error_prototype;
 mod function_prototype;
 mod generator_prototype;
 mod iterator_prototype;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod net_client_prototype;
 mod number_prototype;
+mod object;
 mod object_prototype;
 pub mod perform_await;
+mod priority_queue;
+mod priority_queue_prototype;
 pub mod promise;
 mod promise_prototype;
 mod regex_prototype;
@@ -21,13 +28,20 @@ pub use perform_await::perform_await;
 pub use array_prototype::create_array_prototype;
 pub use async_iterator_prototype::create_async_iterator_prototype;
 pub use boolean_prototype::create_boolean_prototype;
+pub use deque::create_deque;
+pub use deque_prototype::create_deque_prototype;
+pub use error::create_error;
 pub use error_prototype::create_error_prototype;
 pub use function_prototype::create_function_prototype;
 pub use generator_prototype::create_generator_prototype;
 pub use iterator_prototype::create_iterator_prototype;
+#[cfg(not(target_arch = "wasm32"))]
 pub use net_client_prototype::create_net_client_prototype;
 pub use number_prototype::create_number_prototype;
+pub use object::create_object;
 pub use object_prototype::create_object_prototype;
+pub use priority_queue::create_priority_queue;
+pub use priority_queue_prototype::create_priority_queue_prototype;
 pub use promise::create_promise;
 pub use promise_prototype::create_promise_prototype;
 pub use regex_prototype::create_regex_prototype;
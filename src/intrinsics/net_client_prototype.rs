@@ -81,7 +81,10 @@ fn write(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value>
         if let MioMapType::Net(crate::builtins::net::Net::Client(s, ..)) =
             map.get(&token).expect("socket missing in mio_map")
         {
-            let mut s = s;
+            let mut s = match s {
+                Some(s) => s,
+                None => return Ok(Value::Null),
+            };
             match args.get(0) {
                 Some(Value::String(str)) => {
                     match s.write_all(str.as_bytes()) {
@@ -121,7 +124,7 @@ fn close(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
 
     if let Value::Number(t) = this.get_slot("net client token") {
         let token = mio::Token(t.to_usize().unwrap());
-        agent.mio_map.borrow_mut().remove(&token);
+        agent.remove_operation(token);
         Ok(Value::Null)
     } else {
         unreachable!();
@@ -0,0 +1,39 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, Value};
+
+fn object(agent: &Agent, _: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    Ok(Value::new_object(agent.intrinsics.object_prototype.clone()))
+}
+
+// like ==, except NaN is NaN and 0 is not -0
+fn is(_: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    let a = args.get(0).unwrap_or(&Value::Null);
+    let b = args.get(1).unwrap_or(&Value::Null);
+    Ok(Value::from(a.same_value(b)))
+}
+
+pub fn create_object(agent: &Agent) -> Value {
+    let o = Value::new_builtin_function(agent, object);
+
+    o.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.object_prototype.clone(),
+    )
+    .expect("failed to set prototype on object constructor");
+    agent
+        .intrinsics
+        .object_prototype
+        .set(agent, ObjectKey::from("constructor"), o.clone())
+        .expect("failed to set constructor on object prototype");
+
+    o.set(
+        agent,
+        ObjectKey::from("is"),
+        Value::new_builtin_function(agent, is),
+    )
+    .expect("failed to set is on object constructor");
+
+    o
+}
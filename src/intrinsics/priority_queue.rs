@@ -0,0 +1,37 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::intrinsics::priority_queue_prototype::update_size;
+use crate::value::{ObjectKey, Value};
+
+// with or without `new`. values come out smallest first, numbers or strings
+// by default, or by compare(a, b), which is negative when a goes first.
+fn priority_queue(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    let compare = args.get(0).cloned().unwrap_or(Value::Null);
+    if compare != Value::Null && compare.type_of() != "function" {
+        return Err(Value::new_error(agent, "compare must be a function"));
+    }
+    let queue = Value::new_custom_object(agent.intrinsics.priority_queue_prototype.clone());
+    queue.set_slot("queue heap", Value::new_array(agent));
+    queue.set_slot("queue compare", compare);
+    queue.set_slot("queue pushed", Value::from(0.0));
+    update_size(agent, &queue)?;
+    Ok(queue)
+}
+
+pub fn create_priority_queue(agent: &Agent) -> Value {
+    let q = Value::new_builtin_function(agent, priority_queue);
+
+    q.set(
+        agent,
+        ObjectKey::from("prototype"),
+        agent.intrinsics.priority_queue_prototype.clone(),
+    )
+    .expect("failed to set prototype on priority queue constructor");
+    agent
+        .intrinsics
+        .priority_queue_prototype
+        .set(agent, ObjectKey::from("constructor"), q.clone())
+        .expect("failed to set constructor on priority queue prototype");
+
+    q
+}
@@ -0,0 +1,189 @@
+use crate::agent::Agent;
+use crate::interpreter::Context;
+use crate::value::{ObjectKey, ObjectKind, Value};
+use gc::GcCell;
+use std::cmp::Ordering;
+
+// a binary heap in an array slot. each entry is a tuple of the value and the
+// order it was pushed in, so values that compare equal come out first in,
+// first out.
+
+fn values(heap: &Value) -> &GcCell<Vec<Value>> {
+    match heap {
+        Value::Object(o) => match &o.kind {
+            ObjectKind::Array(values) => values,
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn entry(heap: &Value, i: usize) -> (Value, f64) {
+    match &values(heap).borrow()[i] {
+        Value::Tuple(entry, _) => match entry[1] {
+            Value::Number(n) => (entry[0].clone(), n),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn default_order(agent: &Agent, a: &Value, b: &Value) -> Result<Ordering, Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(a.partial_cmp(b).unwrap_or(Ordering::Equal)),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        _ => Err(Value::new_error(
+            agent,
+            "values must all be numbers or all be strings without a compare function",
+        )),
+    }
+}
+
+// whether the entry at i comes out before the one at j
+fn before(agent: &Agent, queue: &Value, i: usize, j: usize) -> Result<bool, Value> {
+    let heap = queue.get_slot("queue heap");
+    let (a, a_order) = entry(&heap, i);
+    let (b, b_order) = entry(&heap, j);
+    let order = match queue.get_slot("queue compare") {
+        Value::Null => default_order(agent, &a, &b)?,
+        compare => match compare.call(agent, Value::Null, vec![a, b])? {
+            Value::Number(n) if n < 0.0 => Ordering::Less,
+            Value::Number(n) if n > 0.0 => Ordering::Greater,
+            Value::Number(_) => Ordering::Equal,
+            _ => return Err(Value::new_error(agent, "compare must return a number")),
+        },
+    };
+    Ok(order.then(a_order.partial_cmp(&b_order).unwrap()) == Ordering::Less)
+}
+
+fn sift_up(agent: &Agent, queue: &Value, mut i: usize) -> Result<(), Value> {
+    let heap = queue.get_slot("queue heap");
+    while i > 0 {
+        let parent = (i - 1) / 2;
+        if !before(agent, queue, i, parent)? {
+            break;
+        }
+        values(&heap).borrow_mut().swap(i, parent);
+        i = parent;
+    }
+    Ok(())
+}
+
+fn sift_down(agent: &Agent, queue: &Value, mut i: usize) -> Result<(), Value> {
+    let heap = queue.get_slot("queue heap");
+    let len = values(&heap).borrow().len();
+    loop {
+        let mut first = i;
+        for child in &[2 * i + 1, 2 * i + 2] {
+            if *child < len && before(agent, queue, *child, first)? {
+                first = *child;
+            }
+        }
+        if first == i {
+            return Ok(());
+        }
+        values(&heap).borrow_mut().swap(i, first);
+        i = first;
+    }
+}
+
+pub fn update_size(agent: &Agent, queue: &Value) -> Result<(), Value> {
+    let size = values(&queue.get_slot("queue heap")).borrow().len();
+    queue.set(agent, ObjectKey::from("size"), Value::from(size as f64))?;
+    Ok(())
+}
+
+fn this_queue(agent: &Agent, ctx: &Context) -> Result<Value, Value> {
+    let this = ctx.scope.borrow().get_this(agent)?;
+    if this.has_slot("queue heap") {
+        Ok(this)
+    } else {
+        Err(Value::new_error(agent, "invalid receiver"))
+    }
+}
+
+fn push_value(agent: &Agent, queue: &Value, value: Value) -> Result<(), Value> {
+    let heap = queue.get_slot("queue heap");
+    let order = match queue.get_slot("queue pushed") {
+        Value::Number(n) => n,
+        _ => unreachable!(),
+    };
+    queue.set_slot("queue pushed", Value::from(order + 1.0));
+    let len = {
+        let mut values = values(&heap).borrow_mut();
+        values.push(Value::Tuple(
+            Box::new(vec![value, Value::from(order)]),
+            None,
+        ));
+        values.len()
+    };
+    let result = sift_up(agent, queue, len - 1);
+    update_size(agent, queue)?;
+    result
+}
+
+fn push(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let queue = this_queue(agent, ctx)?;
+    for value in args {
+        push_value(agent, &queue, value)?;
+    }
+    Ok(Value::Null)
+}
+
+// takes out the first value, null if the queue is empty
+fn pop(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let queue = this_queue(agent, ctx)?;
+    let heap = queue.get_slot("queue heap");
+    let first = {
+        let mut values = values(&heap).borrow_mut();
+        if values.is_empty() {
+            return Ok(Value::Null);
+        }
+        values.swap_remove(0)
+    };
+    let result = sift_down(agent, &queue, 0);
+    update_size(agent, &queue)?;
+    result?;
+    match first {
+        Value::Tuple(entry, _) => Ok(entry[0].clone()),
+        _ => unreachable!(),
+    }
+}
+
+fn peek(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let queue = this_queue(agent, ctx)?;
+    let heap = queue.get_slot("queue heap");
+    if values(&heap).borrow().is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(entry(&heap, 0).0)
+}
+
+fn clear(agent: &Agent, _: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let queue = this_queue(agent, ctx)?;
+    values(&queue.get_slot("queue heap")).borrow_mut().clear();
+    update_size(agent, &queue)?;
+    Ok(Value::Null)
+}
+
+pub fn create_priority_queue_prototype(agent: &Agent) -> Value {
+    let proto = Value::new_object(agent.intrinsics.object_prototype.clone());
+
+    macro_rules! method {
+        ($name:expr, $fn:ident) => {
+            proto
+                .set(
+                    agent,
+                    ObjectKey::from($name),
+                    Value::new_builtin_function(agent, $fn),
+                )
+                .unwrap();
+        };
+    }
+    method!("push", push);
+    method!("pop", pop);
+    method!("peek", peek);
+    method!("clear", clear);
+
+    proto
+}
@@ -1,6 +1,8 @@
 use crate::agent::Agent;
+use crate::builtins::async_hooks::promise_id;
 use crate::interpreter::Context;
-use crate::value::{ObjectKey, Value};
+use crate::value::{ObjectInfo, ObjectKey, ObjectKind, Value};
+use crate::IntoValue;
 
 fn trigger_promise_reactions(
     agent: &Agent,
@@ -11,12 +13,7 @@ fn trigger_promise_reactions(
         loop {
             let item = list.borrow_mut().pop_front();
             match item {
-                Some(reaction) => {
-                    agent.enqueue_job(
-                        promise_reaction_job,
-                        vec![reaction.clone(), argument.clone()],
-                    );
-                }
+                Some(reaction) => enqueue_reaction(agent, reaction, argument.clone()),
                 None => break,
             }
         }
@@ -26,6 +23,14 @@ fn trigger_promise_reactions(
     Ok(Value::Null)
 }
 
+// reactions run as callbacks of the promise `then` returned
+pub fn enqueue_reaction(agent: &Agent, reaction: Value, argument: Value) {
+    match promise_id(&reaction.get_slot("promise")) {
+        Some(id) => agent.enqueue_job_for(id, promise_reaction_job, vec![reaction, argument]),
+        None => agent.enqueue_job(promise_reaction_job, vec![reaction, argument]),
+    }
+}
+
 pub fn promise_reaction_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value> {
     let reaction = args[0].clone();
     let argument = args[1].clone();
@@ -60,6 +65,9 @@ pub fn promise_reaction_job(agent: &Agent, args: Vec<Value>) -> Result<(), Value
 }
 
 fn fulfill_promise(agent: &Agent, promise: Value, value: Value) -> Result<Value, Value> {
+    if let Some(id) = promise_id(&promise) {
+        agent.async_hooks.resolve(agent, id);
+    }
     let reactions = promise.get_slot("fulfill reactions");
     promise.set_slot("result", value.clone());
     promise.set_slot("promise state", Value::from("fulfilled"));
@@ -69,6 +77,9 @@ fn fulfill_promise(agent: &Agent, promise: Value, value: Value) -> Result<Value,
 }
 
 fn reject_promise(agent: &Agent, promise: Value, reason: Value) -> Result<Value, Value> {
+    if let Some(id) = promise_id(&promise) {
+        agent.async_hooks.resolve(agent, id);
+    }
     let reactions = promise.get_slot("reject reactions");
     promise.set_slot("result", reason.clone());
     promise.set_slot("promise state", Value::from("rejected"));
@@ -113,7 +124,7 @@ fn promise_resolve_function(
 
     let promise = f.get_slot("promise");
     let resolution = args.get(0).unwrap_or(&Value::Null).clone();
-    if promise == resolution {
+    if promise.same_value(&resolution) {
         reject_promise(
             agent,
             promise,
@@ -161,6 +172,10 @@ fn promise(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Val
     promise.set_slot("promise state", Value::from("pending"));
     promise.set_slot("fulfill reactions", Value::new_list());
     promise.set_slot("reject reactions", Value::new_list());
+    promise.set_slot("created at", Value::from(agent.position.get() as f64));
+    let id = agent.async_hooks.init(agent, "Promise", &promise);
+    promise.set_slot("async id", Value::from(id.id as f64));
+    promise.set_slot("async trigger", Value::from(id.trigger as f64));
 
     let ResolvingFunctions { resolve, reject } = create_resolving_functions(agent, &promise);
 
@@ -241,6 +256,171 @@ fn promise_reject(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Valu
     Ok(capability)
 }
 
+// the items of an array, or the values an iterable yields, read all at once
+pub(crate) fn collect(agent: &Agent, iterable: &Value) -> Result<Vec<Value>, Value> {
+    if let Value::Object(o) = iterable {
+        if let ObjectKind::Array(values) = &o.kind {
+            return Ok(values.borrow().clone());
+        }
+    }
+    let key = Value::new_well_known_symbol("iterator".to_string()).to_object_key(agent)?;
+    let iterator = match iterable {
+        Value::Object(_) => iterable.get(agent, key)?,
+        _ => Value::Null,
+    };
+    if iterator.type_of() != "function" {
+        return Err(Value::new_error(
+            agent,
+            "items must be an array or iterable",
+        ));
+    }
+    let iterator = iterator.call(agent, iterable.clone(), vec![])?;
+    let next = iterator.get(agent, ObjectKey::from("next"))?;
+    let mut values = Vec::new();
+    loop {
+        let result = next.call(agent, iterator.clone(), vec![])?;
+        if result.get(agent, ObjectKey::from("done"))? == Value::from(true) {
+            return Ok(values);
+        }
+        values.push(result.get(agent, ObjectKey::from("value"))?);
+    }
+}
+
+fn slot_number(value: &Value, slot: &str) -> f64 {
+    match value.get_slot(slot) {
+        Value::Number(n) => n,
+        _ => unreachable!(),
+    }
+}
+
+// calls the mapper on items until `concurrency` of the promises it returned
+// are pending at once, or every item has been started
+fn map_start(agent: &Agent, state: &Value) -> Result<(), Value> {
+    let items = state.get_slot("items");
+    let count = slot_number(state, "count");
+    loop {
+        let started = slot_number(state, "started");
+        let running = slot_number(state, "running");
+        if state.get_slot("failed") == Value::from(true)
+            || started == count
+            || running >= slot_number(state, "concurrency")
+        {
+            return Ok(());
+        }
+        state.set_slot("started", Value::from(started + 1.0));
+        state.set_slot("running", Value::from(running + 1.0));
+
+        let index = Value::from(started);
+        let item = items.get(agent, ObjectKey::Number(started as usize))?;
+        let result = state
+            .get_slot("mapper")
+            .call(agent, Value::Null, vec![item, index.clone()])
+            .and_then(|r| promise_resolve_i(agent, agent.intrinsics.promise.clone(), r));
+        let result = match result {
+            Ok(r) => r,
+            Err(e) => return map_reject(agent, state, e),
+        };
+        let on_fulfilled = Value::new_builtin_function(agent, map_fulfilled);
+        on_fulfilled.set_slot("state", state.clone());
+        on_fulfilled.set_slot("index", index);
+        let on_rejected = Value::new_builtin_function(agent, map_rejected);
+        on_rejected.set_slot("state", state.clone());
+        result.get(agent, ObjectKey::from("then"))?.call(
+            agent,
+            result,
+            vec![on_fulfilled, on_rejected],
+        )?;
+    }
+}
+
+// the first rejection rejects the whole map, and no more items are started
+fn map_reject(agent: &Agent, state: &Value, reason: Value) -> Result<(), Value> {
+    state.set_slot("failed", Value::from(true));
+    let promise = state.get_slot("promise");
+    promise
+        .get_slot("reject")
+        .call(agent, Value::Null, vec![reason])?;
+    Ok(())
+}
+
+fn map_fulfilled(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let f = ctx.function.clone().unwrap();
+    let state = f.get_slot("state");
+    let index = slot_number(&f, "index") as usize;
+    let results = state.get_slot("results");
+    results.set(
+        agent,
+        ObjectKey::Number(index),
+        args.get(0).cloned().unwrap_or(Value::Null),
+    )?;
+    state.set_slot("running", Value::from(slot_number(&state, "running") - 1.0));
+    let finished = slot_number(&state, "finished") + 1.0;
+    state.set_slot("finished", Value::from(finished));
+    if finished == slot_number(&state, "count") {
+        let promise = state.get_slot("promise");
+        promise
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![results])?;
+    } else {
+        map_start(agent, &state)?;
+    }
+    Ok(Value::Null)
+}
+
+fn map_rejected(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let state = ctx.function.clone().unwrap().get_slot("state");
+    map_reject(agent, &state, args.get(0).cloned().unwrap_or(Value::Null))?;
+    Ok(Value::Null)
+}
+
+// calls `mapper(item, index)` for each item with at most `concurrency` of
+// the promises it returns pending at a time, and resolves to their results
+// in the order of the items
+fn promise_map(agent: &Agent, args: Vec<Value>, _ctx: &Context) -> Result<Value, Value> {
+    let items = collect(agent, args.get(0).unwrap_or(&Value::Null))?;
+    let mapper = args.get(1).cloned().unwrap_or(Value::Null);
+    if mapper.type_of() != "function" {
+        return Err(Value::new_error(agent, "mapper must be a function"));
+    }
+    let concurrency = match args.get(2) {
+        None | Some(Value::Null) => Value::Null,
+        Some(options @ Value::Object(_)) => options.get(agent, ObjectKey::from("concurrency"))?,
+        _ => return Err(Value::new_error(agent, "options must be an object")),
+    };
+    let concurrency = match concurrency {
+        Value::Null => f64::INFINITY,
+        Value::Number(n) if n >= 1.0 && (n.fract() == 0.0 || n.is_infinite()) => n,
+        _ => {
+            return Err(Value::new_error(
+                agent,
+                "concurrency must be a positive integer",
+            ))
+        }
+    };
+
+    let promise = new_promise_capability(agent, agent.intrinsics.promise.clone())?;
+    let results = vec![Value::Null; items.len()].into_value(agent);
+    if items.is_empty() {
+        promise
+            .get_slot("resolve")
+            .call(agent, Value::Null, vec![results])?;
+        return Ok(promise);
+    }
+    let state = Value::new_custom_object(Value::Null);
+    state.set_slot("count", Value::from(items.len() as f64));
+    state.set_slot("items", items.into_value(agent));
+    state.set_slot("results", results);
+    state.set_slot("mapper", mapper);
+    state.set_slot("concurrency", Value::from(concurrency));
+    state.set_slot("started", Value::from(0.0));
+    state.set_slot("running", Value::from(0.0));
+    state.set_slot("finished", Value::from(0.0));
+    state.set_slot("failed", Value::from(false));
+    state.set_slot("promise", promise.clone());
+    map_start(agent, &state)?;
+    Ok(promise)
+}
+
 pub fn create_promise(agent: &Agent) -> Value {
     let p = Value::new_builtin_function(agent, promise);
 
@@ -262,6 +442,12 @@ pub fn create_promise(agent: &Agent) -> Value {
         Value::new_builtin_function(agent, promise_reject),
     )
     .unwrap();
+    p.set(
+        agent,
+        ObjectKey::from("map"),
+        Value::new_builtin_function(agent, promise_map),
+    )
+    .unwrap();
     agent
         .intrinsics
         .promise_prototype
@@ -270,3 +456,26 @@ pub fn create_promise(agent: &Agent) -> Value {
 
     p
 }
+
+// the bytecode positions promises that are still pending were created at.
+// this looks at every promise on the heap, so it finds the ones that can never
+// settle because nothing refers to them, as long as they haven't been
+// collected yet.
+pub fn pending_positions() -> Vec<usize> {
+    let mut positions = Vec::new();
+    gc::for_each(|o: &ObjectInfo, _| {
+        if let ObjectKind::Custom(slots) = &o.kind {
+            if let Some(slots) = slots.try_borrow() {
+                if let (Some(Value::String(state)), Some(Value::Number(position))) =
+                    (slots.get("promise state"), slots.get("created at"))
+                {
+                    if &**state == "pending" {
+                        positions.push(*position as usize);
+                    }
+                }
+            }
+        }
+    });
+    positions.sort();
+    positions
+}
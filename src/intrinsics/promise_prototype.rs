@@ -1,6 +1,6 @@
 use crate::agent::Agent;
 use crate::interpreter::Context;
-use crate::intrinsics::promise::{new_promise_capability, promise_reaction_job, promise_resolve_i};
+use crate::intrinsics::promise::{enqueue_reaction, new_promise_capability, promise_resolve_i};
 use crate::value::{ObjectKey, Value};
 
 fn promise_proto_then(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
@@ -21,7 +21,7 @@ fn promise_proto_then(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<
     }
 
     let fulfill_reaction = Value::new_custom_object(Value::Null);
-    fulfill_reaction.set_slot("kind", Value::from("resolve"));
+    fulfill_reaction.set_slot("kind", Value::from("fulfill"));
     fulfill_reaction.set_slot("promise", promise.clone());
     fulfill_reaction.set_slot("handler", on_fulfilled);
 
@@ -32,26 +32,29 @@ fn promise_proto_then(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<
 
     let state = this.get_slot("promise state");
     if let Value::String(s) = &state {
-        match s.as_str() {
+        match &**s {
             "pending" => {
-                if let Value::List(reactions) = &this.get_slot("fulfill reactions") {
-                    reactions.borrow_mut().push_back(fulfill_reaction);
-                } else {
-                    unreachable!();
-                }
-                if let Value::List(reactions) = &this.get_slot("reject reactions") {
-                    reactions.borrow_mut().push_back(reject_reaction);
-                } else {
-                    unreachable!();
+                // slots hold lists by value, so write the updated lists back
+                for (slot, reaction) in &[
+                    ("fulfill reactions", fulfill_reaction),
+                    ("reject reactions", reject_reaction),
+                ] {
+                    let reactions = this.get_slot(slot);
+                    if let Value::List(list) = &reactions {
+                        list.borrow_mut().push_back(reaction.clone());
+                    } else {
+                        unreachable!();
+                    }
+                    this.set_slot(slot, reactions);
                 }
             }
             "fulfilled" => {
                 let value = this.get_slot("result");
-                agent.enqueue_job(promise_reaction_job, vec![fulfill_reaction, value]);
+                enqueue_reaction(agent, fulfill_reaction, value);
             }
             "rejected" => {
                 let reason = this.get_slot("result");
-                agent.enqueue_job(promise_reaction_job, vec![reject_reaction, reason]);
+                enqueue_reaction(agent, reject_reaction, reason);
             }
             _ => unreachable!(),
         }
@@ -9,7 +9,7 @@ fn match_(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value
             if let ObjectKind::Regex(re) = &o.kind {
                 let mut args = args;
                 match args.pop().unwrap_or(Value::Null) {
-                    Value::String(s) => match re.captures(s.as_str()) {
+                    Value::String(s) => match re.captures(&s) {
                         Some(captures) => {
                             let o = Value::new_array(agent);
                             let mut i = 0;
@@ -53,7 +53,7 @@ fn test(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value>
             if let ObjectKind::Regex(re) = &o.kind {
                 let mut args = args;
                 match args.pop().unwrap_or(Value::Null) {
-                    Value::String(s) => Ok(Value::from(re.is_match(s.as_str()))),
+                    Value::String(s) => Ok(Value::from(re.is_match(&s))),
                     _ => Err(Value::new_error(agent, "input must be a string")),
                 }
             } else {
@@ -1,4 +1,5 @@
 use crate::interpreter::Context;
+use crate::locale;
 use crate::value::{ObjectKey, ObjectKind};
 use crate::{Agent, Value};
 use unic::normal::StrNormalForm;
@@ -7,7 +8,7 @@ fn normalize(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Va
     if let Value::Object(o) = ctx.scope.borrow().get_this(agent)? {
         if let ObjectKind::String(s) = &o.kind {
             match args.get(0).unwrap_or(&Value::Null) {
-                Value::String(form) => Ok(Value::from(match form.as_str() {
+                Value::String(form) => Ok(Value::from(match &**form {
                     "NFC" => s.nfc().collect::<String>(),
                     "NFD" => s.nfd().collect::<String>(),
                     "NFKC" => s.nfkc().collect::<String>(),
@@ -35,6 +36,50 @@ fn normalize(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Va
     }
 }
 
+fn this_string(agent: &Agent, ctx: &Context) -> Result<String, Value> {
+    if let Value::Object(o) = ctx.scope.borrow().get_this(agent)? {
+        if let ObjectKind::String(s) = &o.kind {
+            return Ok(s.to_string());
+        }
+    }
+    Err(Value::new_error(agent, "invalid receiver"))
+}
+
+// the language of a locale argument, the root locale if none is given
+fn language(agent: &Agent, locale: Option<&Value>) -> Result<String, Value> {
+    match locale.unwrap_or(&Value::Null) {
+        Value::String(tag) => Ok(locale::language(tag)),
+        Value::Null => Ok(String::new()),
+        _ => Err(Value::new_error(agent, "locale must be a string")),
+    }
+}
+
+fn locale_compare(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    let other = match args.get(0) {
+        Some(Value::String(other)) => other.clone(),
+        _ => return Err(Value::new_error(agent, "argument must be a string")),
+    };
+    let language = language(agent, args.get(1))?;
+    Ok(Value::from(match locale::compare(&s, &other, &language) {
+        std::cmp::Ordering::Less => -1.0,
+        std::cmp::Ordering::Equal => 0.0,
+        std::cmp::Ordering::Greater => 1.0,
+    }))
+}
+
+fn to_locale_lower_case(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    let language = language(agent, args.get(0))?;
+    Ok(Value::from(locale::to_lowercase(&s, &language)))
+}
+
+fn to_locale_upper_case(agent: &Agent, args: Vec<Value>, ctx: &Context) -> Result<Value, Value> {
+    let s = this_string(agent, ctx)?;
+    let language = language(agent, args.get(0))?;
+    Ok(Value::from(locale::to_uppercase(&s, &language)))
+}
+
 pub fn create_string_prototype(agent: &Agent) -> Value {
     let p = Value::new_object(agent.intrinsics.object_prototype.clone());
 
@@ -44,6 +89,24 @@ pub fn create_string_prototype(agent: &Agent) -> Value {
         Value::new_builtin_function(agent, normalize),
     )
     .unwrap();
+    p.set(
+        agent,
+        ObjectKey::from("localeCompare"),
+        Value::new_builtin_function(agent, locale_compare),
+    )
+    .unwrap();
+    p.set(
+        agent,
+        ObjectKey::from("toLocaleLowerCase"),
+        Value::new_builtin_function(agent, to_locale_lower_case),
+    )
+    .unwrap();
+    p.set(
+        agent,
+        ObjectKey::from("toLocaleUpperCase"),
+        Value::new_builtin_function(agent, to_locale_upper_case),
+    )
+    .unwrap();
 
     p
 }
@@ -0,0 +1,42 @@
+// helpers for writing json, values are passed around already serialized
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn number(n: f64) -> String {
+    if n.is_finite() {
+        format!("{}", n)
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<String>>().join(","))
+}
+
+pub fn object(fields: Vec<(&str, String)>) -> String {
+    let fields = fields
+        .into_iter()
+        .map(|(k, v)| format!("{}:{}", quote(k), v))
+        .collect::<Vec<String>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+pub fn optional(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
@@ -43,37 +43,174 @@ macro_rules! custom_trace {
             let $this = self;
             $body
         }
-    }
+    };
+}
+
+// implements IntoValue and FromValue for a struct as an object with one
+// property per field: `value_struct!(Point { x, y });`
+#[macro_export]
+macro_rules! value_struct {
+    ($name:ident { $($field:ident),* $(,)* }) => {
+        impl $crate::IntoValue for $name {
+            fn into_value(&self, agent: &$crate::Agent) -> $crate::Value {
+                let object = $crate::Value::new_object(agent.intrinsics.object_prototype.clone());
+                $(
+                    object
+                        .set(
+                            agent,
+                            $crate::ObjectKey::from(stringify!($field)),
+                            $crate::IntoValue::into_value(&self.$field, agent),
+                        )
+                        .unwrap();
+                )*
+                object
+            }
+        }
+
+        impl $crate::FromValue for $name {
+            fn from_value(
+                agent: &$crate::Agent,
+                value: $crate::Value,
+            ) -> Result<Self, $crate::Value> {
+                if value.type_of() != "object" {
+                    return Err($crate::Value::new_error(agent, "expected an object"));
+                }
+                Ok($name {
+                    $(
+                        $field: $crate::FromValue::from_value(
+                            agent,
+                            value.get(agent, $crate::ObjectKey::from(stringify!($field)))?,
+                        )?,
+                    )*
+                })
+            }
+        }
+    };
 }
 
 mod agent;
+mod ast_json;
 mod builtins;
+mod bytecode_cache;
+mod crash;
+mod debugger;
+mod diagnostics;
+mod doc;
+mod event_loop;
+#[cfg(not(target_arch = "wasm32"))]
+mod extension;
+mod formatter;
+mod heap_snapshot;
+mod instrument;
 mod interpreter;
 mod intrinsics;
+mod json;
 mod linked_list;
+mod lint;
+mod locale;
 mod module;
 mod num_util;
+mod output;
 mod parser;
+mod permissions;
+mod pool;
+mod replay;
 mod sort;
+mod source_map;
+#[cfg(feature = "tokio")]
+mod tokio_runtime;
 mod value;
 
 pub trait IntoValue: Sized {
     fn into_value(&self, _: &agent::Agent) -> value::Value;
 }
 
-pub use agent::Agent;
+pub trait FromValue: Sized {
+    fn from_value(_: &agent::Agent, _: value::Value) -> Result<Self, value::Value>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use agent::Waker;
+pub use agent::{Agent, AgentBuilder, JobPriority, TerminateHandle};
+pub use builtins::atomics::SharedMemory;
+pub use crash::install_crash_handler;
+pub use debugger::{PauseHandle, PauseReason, Paused, Resume};
+#[cfg(not(target_arch = "wasm32"))]
+pub use extension::{ExtensionDeclaration, EXTENSION_ABI_VERSION, RUSTC_VERSION, SLITHER_VERSION};
+pub use gc::{GcConfig, GcPhase, GcStats};
+pub use instrument::Instrument;
 pub use interpreter::{Context, Interpreter, Scope};
+pub use lint::{Lint, LintConfig, Severity};
+pub use output::{OutputSink, Stream};
 pub use parser::Parser;
-pub use value::Value;
+pub use permissions::Permissions;
+pub use pool::Executor;
+pub use replay::EventLog;
+pub use value::{InspectOptions, ObjectKey, Value};
+
+pub fn check(filename: &str, code: &str) -> Result<(), String> {
+    let ast = match Parser::parse(code) {
+        Ok(ast) => ast,
+        Err(d) => return Err(diagnostics::render_all(&d, filename, code)),
+    };
+    interpreter::Assembler::new().assemble(&ast);
+    Ok(())
+}
+
+pub fn dump_ast(filename: &str, code: &str) -> Result<String, String> {
+    match Parser::parse(code) {
+        Ok(ast) => Ok(ast_json::to_json(&ast)),
+        Err(d) => Err(diagnostics::render_all(&d, filename, code)),
+    }
+}
+
+// the source in canonical style, comments included
+pub fn format(filename: &str, code: &str) -> Result<String, String> {
+    match Parser::parse_with_comments(code) {
+        Ok((ast, comments)) => Ok(formatter::format(code, &ast, comments)),
+        Err(d) => Err(diagnostics::render_all(&d, filename, code)),
+    }
+}
+
+// the exported functions of a module and their doc comments, as markdown or
+// as json
+pub fn doc(filename: &str, code: &str, markdown: bool) -> Result<String, String> {
+    let (ast, docs) = match Parser::parse_with_docs(code) {
+        Ok(parsed) => parsed,
+        Err(d) => return Err(diagnostics::render_all(&d, filename, code)),
+    };
+    let module = std::path::Path::new(filename)
+        .file_stem()
+        .map_or(filename.into(), |s| s.to_string_lossy());
+    if markdown {
+        Ok(doc::to_markdown(&module, &ast, &docs))
+    } else {
+        Ok(doc::to_json(&module, &ast, &docs))
+    }
+}
+
+pub fn lint(filename: &str, code: &str, config: &LintConfig) -> Result<Vec<Lint>, String> {
+    match Parser::parse(code) {
+        Ok(ast) => Ok(lint::lint(&ast, config)),
+        Err(d) => Err(diagnostics::render_all(&d, filename, code)),
+    }
+}
 
-pub fn disassemble(code: &str) {
+pub fn disassemble(filename: &str, code: &str, color: bool) -> Result<String, String> {
     let mut agent = Agent::new();
 
     let ast = match Parser::parse(code) {
         Ok(ast) => ast,
-        Err(e) => panic!(format!("{:?}", e)),
+        Err(d) => return Err(diagnostics::render_all(&d, filename, code)),
     };
-    let _idx = agent.assembler.assemble(&ast);
+    let assembler = agent.assembler.get_mut();
+    let idx = assembler.assemble(&ast);
+    assembler.files.push((idx, filename.to_string()));
 
-    interpreter::disassemble(&agent.assembler, 0, std::usize::MAX);
+    Ok(interpreter::disassemble(
+        assembler,
+        0,
+        std::usize::MAX,
+        color,
+    ))
 }
@@ -0,0 +1,560 @@
+use crate::diagnostics;
+use crate::parser::{Node, Operator, Pattern};
+use std::collections::HashMap;
+
+// checks for code that runs but is likely a mistake. one pass over the tree
+// resolves every name to where it was declared, and each rule reports from
+// what that pass found. the tree only keeps positions for statements, so
+// findings point at the statement they are in.
+
+type Position = (usize, usize);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+struct Rule {
+    name: &'static str,
+    check: fn(&Analysis, &mut Vec<(Position, String)>),
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        name: "unused-variable",
+        check: unused_variable,
+    },
+    Rule {
+        name: "unreachable-code",
+        check: unreachable_code,
+    },
+    Rule {
+        name: "shadowing",
+        check: shadowing,
+    },
+    Rule {
+        name: "missing-await",
+        check: missing_await,
+    },
+];
+
+// the builtins that return a promise, by module
+const PROMISE_BUILTINS: &[(&str, &[&str])] = &[(
+    "fs",
+    &[
+        "readFile",
+        "writeFile",
+        "removeFile",
+        "getMetadata",
+        "copy",
+        "move",
+        "createSymbolicLink",
+        "exists",
+        "createDirectory",
+        "removeDirectory",
+    ],
+)];
+
+// how each rule is reported, every rule is a warning unless set otherwise
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    severities: HashMap<&'static str, Severity>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            severities: RULES.iter().map(|r| (r.name, Severity::Warning)).collect(),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn set(&mut self, rule: &str, severity: Severity) -> Result<(), String> {
+        match RULES.iter().find(|r| r.name == rule) {
+            Some(rule) => {
+                self.severities.insert(rule.name, severity);
+                Ok(())
+            }
+            None => Err(format!("unknown lint rule '{}'", rule)),
+        }
+    }
+
+    pub fn severity(&self, rule: &str) -> Severity {
+        self.severities
+            .get(rule)
+            .cloned()
+            .unwrap_or(Severity::Warning)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Lint {
+    // warning[unused-variable]: `a` is declared but never read
+    //  --> file.sl:1:1
+    //   |
+    // 1 | let a = 1;
+    //   | ^
+    pub fn render(&self, filename: &str, source: &str) -> String {
+        format!(
+            "{}[{}]: {}\n{}",
+            if self.severity == Severity::Error {
+                "error"
+            } else {
+                "warning"
+            },
+            self.rule,
+            self.message,
+            diagnostics::excerpt(filename, source, self.line, self.column)
+        )
+    }
+}
+
+pub fn lint(ast: &Node, config: &LintConfig) -> Vec<Lint> {
+    let mut resolver = Resolver {
+        declarations: Vec::new(),
+        scopes: Vec::new(),
+        position: (1, 1),
+        dropped: Vec::new(),
+    };
+    resolver.visit(ast);
+    let analysis = Analysis {
+        ast,
+        declarations: resolver.declarations,
+        dropped: resolver.dropped,
+    };
+
+    let mut lints = Vec::new();
+    for rule in RULES {
+        let severity = config.severity(rule.name);
+        if severity == Severity::Off {
+            continue;
+        }
+        let mut findings = Vec::new();
+        (rule.check)(&analysis, &mut findings);
+        lints.extend(findings.into_iter().map(|((line, column), message)| Lint {
+            rule: rule.name,
+            severity,
+            message,
+            line,
+            column,
+        }));
+    }
+    lints.sort_by_key(|l| (l.line, l.column));
+    lints
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    // let and const
+    Variable,
+    Import,
+    // functions, classes and enums
+    Declaration,
+    Parameter,
+    // loop, catch and match bindings
+    Binding,
+}
+
+struct Declaration {
+    name: String,
+    kind: Kind,
+    position: Position,
+    read: bool,
+    exported: bool,
+    // there is a binding of the same name in an enclosing scope
+    shadows: bool,
+    // an imported builtin that returns a promise
+    promise: bool,
+}
+
+struct Analysis<'a> {
+    ast: &'a Node,
+    declarations: Vec<Declaration>,
+    // calls to promise returning builtins whose result is thrown away
+    dropped: Vec<(Position, String)>,
+}
+
+struct Resolver {
+    declarations: Vec<Declaration>,
+    // indices into declarations
+    scopes: Vec<Vec<usize>>,
+    position: Position,
+    dropped: Vec<(Position, String)>,
+}
+
+impl Resolver {
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .cloned()
+            .find(|i| self.declarations[*i].name == name)
+    }
+
+    fn declare(&mut self, name: &str, kind: Kind, position: Position, exported: bool) {
+        let shadows = self.lookup(name).is_some();
+        self.declarations.push(Declaration {
+            name: name.to_string(),
+            kind,
+            position,
+            read: false,
+            exported,
+            shadows,
+            promise: false,
+        });
+        let i = self.declarations.len() - 1;
+        self.scopes.last_mut().unwrap().push(i);
+    }
+
+    fn read(&mut self, name: &str) {
+        if let Some(i) = self.lookup(name) {
+            self.declarations[i].read = true;
+        }
+    }
+
+    // declarations are visible to the whole block, functions declared
+    // further down can be called before them
+    fn declare_statement(&mut self, node: &Node, position: Position, exported: bool) {
+        match node {
+            Node::LexicalInitialization(name, _) => {
+                self.declare(name, Kind::Variable, position, exported)
+            }
+            Node::LexicalDestructuring(pattern, _) => {
+                for name in pattern.bindings() {
+                    self.declare(name, Kind::Variable, position, exported);
+                }
+            }
+            Node::FunctionDeclaration(_, name, ..)
+            | Node::ClassDeclaration(name, ..)
+            | Node::EnumDeclaration(name, ..) => {
+                self.declare(name, Kind::Declaration, position, exported)
+            }
+            Node::ImportDefaultDeclaration(_, name) => {
+                self.declare(name, Kind::Import, position, exported)
+            }
+            Node::ImportNamedDeclaration(_, names) => {
+                for name in names {
+                    self.declare(name, Kind::Import, position, exported);
+                }
+            }
+            Node::ImportStandardDeclaration(namespace, names) => {
+                let promises = PROMISE_BUILTINS
+                    .iter()
+                    .find(|(n, _)| n == namespace)
+                    .map_or(&[][..], |(_, names)| names);
+                for name in names {
+                    self.declare(name, Kind::Import, position, exported);
+                    self.declarations.last_mut().unwrap().promise =
+                        promises.contains(&name.as_str());
+                }
+            }
+            Node::ExportDeclaration(declaration) => {
+                self.declare_statement(declaration, position, true)
+            }
+            _ => {}
+        }
+    }
+
+    fn block(&mut self, stmts: &[Node]) {
+        let saved = self.position;
+        self.scopes.push(Vec::new());
+        let mut position = self.position;
+        for stmt in stmts {
+            match stmt {
+                Node::Position(line, column) => position = (*line, *column),
+                stmt => self.declare_statement(stmt, position, false),
+            }
+        }
+        for stmt in stmts {
+            match stmt {
+                Node::Position(line, column) => self.position = (*line, *column),
+                stmt => self.visit(stmt),
+            }
+        }
+        self.scopes.pop();
+        self.position = saved;
+    }
+
+    fn function(&mut self, params: &[Node], body: &Node) {
+        self.scopes.push(Vec::new());
+        for param in params {
+            match param {
+                Node::Identifier(name) => self.declare(name, Kind::Parameter, self.position, false),
+                Node::Initializer(target, init) => {
+                    self.visit(init);
+                    if let Node::Identifier(name) = &**target {
+                        self.declare(name, Kind::Parameter, self.position, false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.visit(body);
+        self.scopes.pop();
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Block(_, stmts) => self.block(stmts),
+            Node::Identifier(name) => self.read(name),
+            // assigning to a binding isn't reading it
+            Node::BinaryExpression(Operator::Assign, target, value)
+                if match &**target {
+                    Node::Identifier(..) => true,
+                    _ => false,
+                } =>
+            {
+                self.visit(value)
+            }
+            Node::MemberExpression(base, _) => self.visit(base),
+            // keys are names rather than reads, unless computed
+            Node::Initializer(key, value) => {
+                match &**key {
+                    Node::StringLiteral(..) | Node::NumberLiteral(..) | Node::Identifier(..) => {}
+                    key => self.visit(key),
+                }
+                self.visit(value);
+            }
+            Node::ExpressionStatement(expr) => {
+                if let Node::CallExpression(callee, _) = &**expr {
+                    if let Node::Identifier(name) = &**callee {
+                        if let Some(i) = self.lookup(name) {
+                            if self.declarations[i].promise {
+                                self.dropped.push((self.position, name.clone()));
+                            }
+                        }
+                    }
+                }
+                self.visit(expr);
+            }
+            Node::FunctionDeclaration(_, _, params, body)
+            | Node::FunctionExpression(_, _, params, body)
+            | Node::ArrowFunctionExpression(_, params, body) => self.function(params, body),
+            Node::ForLoop(_, binding, target, body) => {
+                self.visit(target);
+                self.scopes.push(Vec::new());
+                self.declare(binding, Kind::Binding, self.position, false);
+                self.visit(body);
+                self.scopes.pop();
+            }
+            Node::TryStatement(try_clause, binding, catch_clause, finally_clause) => {
+                self.visit(try_clause);
+                if let Some(catch_clause) = catch_clause {
+                    self.scopes.push(Vec::new());
+                    if let Some(binding) = binding {
+                        self.declare(binding, Kind::Binding, self.position, false);
+                    }
+                    self.visit(catch_clause);
+                    self.scopes.pop();
+                }
+                if let Some(finally_clause) = finally_clause {
+                    self.visit(finally_clause);
+                }
+            }
+            Node::MatchArm(_, pattern, ..) => {
+                self.scopes.push(Vec::new());
+                for name in pattern.bindings() {
+                    self.declare(name, Kind::Binding, self.position, false);
+                }
+                for_each_child(node, &mut |child| self.visit(child));
+                self.scopes.pop();
+            }
+            node => for_each_child(node, &mut |child| self.visit(child)),
+        }
+    }
+}
+
+fn pattern_values<'a>(pattern: &'a Pattern, f: &mut dyn FnMut(&'a Node)) {
+    match pattern {
+        Pattern::Value(node) => f(node),
+        Pattern::Tuple(patterns) | Pattern::Array(patterns) | Pattern::Or(patterns) => {
+            for p in patterns {
+                pattern_values(p, f);
+            }
+        }
+        Pattern::NamedTuple(fields) | Pattern::Object(fields) => {
+            for (_, p) in fields {
+                pattern_values(p, f);
+            }
+        }
+        Pattern::Wildcard | Pattern::Binding(..) => {}
+    }
+}
+
+fn for_each_child<'a>(node: &'a Node, f: &mut dyn FnMut(&'a Node)) {
+    match node {
+        Node::ObjectLiteral(nodes)
+        | Node::ArrayLiteral(nodes)
+        | Node::TupleLiteral(nodes)
+        | Node::RecordLiteral(nodes)
+        | Node::TemplateLiteral(_, nodes)
+        | Node::Block(_, nodes)
+        | Node::ClassExpression(_, None, nodes)
+        | Node::ClassDeclaration(_, None, nodes) => nodes.iter().for_each(f),
+        Node::ClassExpression(_, Some(extends), nodes)
+        | Node::ClassDeclaration(_, Some(extends), nodes) => {
+            f(extends);
+            nodes.iter().for_each(f);
+        }
+        Node::NamedTupleLiteral(fields) => fields.iter().for_each(|(_, n)| f(n)),
+        Node::IfStatement(test, consequent, alternative) => {
+            f(test);
+            f(consequent);
+            if let Some(alternative) = alternative {
+                f(alternative);
+            }
+        }
+        Node::ConditionalExpression(a, b, c) => {
+            f(a);
+            f(b);
+            f(c);
+        }
+        Node::WhileLoop(a, b)
+        | Node::ForLoop(_, _, a, b)
+        | Node::BinaryExpression(_, a, b)
+        | Node::ComputedMemberExpression(a, b)
+        | Node::Initializer(a, b) => {
+            f(a);
+            f(b);
+        }
+        Node::ExpressionStatement(a)
+        | Node::UnaryExpression(_, a)
+        | Node::ParenthesizedExpression(a)
+        | Node::YieldExpression(Some(a))
+        | Node::AwaitExpression(a)
+        | Node::NewExpression(a)
+        | Node::MemberExpression(a, _)
+        | Node::LexicalInitialization(_, a)
+        | Node::ReturnStatement(Some(a))
+        | Node::ThrowStatement(a)
+        | Node::ExportDeclaration(a) => f(a),
+        Node::CallExpression(callee, args) | Node::TailCallExpression(callee, args) => {
+            f(callee);
+            args.iter().for_each(f);
+        }
+        Node::FunctionExpression(_, _, params, body)
+        | Node::FunctionDeclaration(_, _, params, body)
+        | Node::ArrowFunctionExpression(_, params, body) => {
+            params.iter().for_each(&mut *f);
+            f(body);
+        }
+        Node::LexicalDestructuring(pattern, init) => {
+            pattern_values(pattern, f);
+            f(init);
+        }
+        Node::TryStatement(a, _, b, c) => {
+            f(a);
+            if let Some(b) = b {
+                f(b);
+            }
+            if let Some(c) = c {
+                f(c);
+            }
+        }
+        Node::MatchExpression(value, arms) => {
+            f(value);
+            arms.iter().for_each(f);
+        }
+        Node::MatchArm(_, pattern, guard, body) => {
+            pattern_values(pattern, f);
+            if let Some(guard) = guard {
+                f(guard);
+            }
+            f(body);
+        }
+        _ => {}
+    }
+}
+
+fn unused_variable(analysis: &Analysis, findings: &mut Vec<(Position, String)>) {
+    for d in &analysis.declarations {
+        if d.read || d.exported || d.name.starts_with('_') {
+            continue;
+        }
+        match d.kind {
+            Kind::Variable => findings.push((
+                d.position,
+                format!("`{}` is declared but never read", d.name),
+            )),
+            Kind::Import => findings.push((
+                d.position,
+                format!("`{}` is imported but never read", d.name),
+            )),
+            _ => {}
+        }
+    }
+}
+
+fn shadowing(analysis: &Analysis, findings: &mut Vec<(Position, String)>) {
+    for d in &analysis.declarations {
+        if d.shadows && d.kind != Kind::Import && !d.name.starts_with('_') {
+            findings.push((
+                d.position,
+                format!("`{}` shadows a binding in an enclosing scope", d.name),
+            ));
+        }
+    }
+}
+
+fn missing_await(analysis: &Analysis, findings: &mut Vec<(Position, String)>) {
+    for (position, name) in &analysis.dropped {
+        findings.push((
+            *position,
+            format!("the promise returned by `{}` is never awaited", name),
+        ));
+    }
+}
+
+// whether control never gets past the statement
+fn exits(node: &Node) -> bool {
+    match node {
+        Node::ReturnStatement(..)
+        | Node::ThrowStatement(..)
+        | Node::BreakStatement
+        | Node::ContinueStatement => true,
+        Node::Block(_, stmts) => stmts.iter().any(exits),
+        Node::IfStatement(_, consequent, Some(alternative)) => {
+            exits(consequent) && exits(alternative)
+        }
+        Node::TryStatement(try_clause, _, catch_clause, finally_clause) => {
+            finally_clause.as_ref().map_or(false, |f| exits(f))
+                || (exits(try_clause) && catch_clause.as_ref().map_or(true, |c| exits(c)))
+        }
+        _ => false,
+    }
+}
+
+fn unreachable_code(analysis: &Analysis, findings: &mut Vec<(Position, String)>) {
+    fn visit(node: &Node, findings: &mut Vec<(Position, String)>) {
+        if let Node::Block(_, stmts) = node {
+            let mut exited = false;
+            let mut position = None;
+            for stmt in stmts {
+                match stmt {
+                    Node::Position(line, column) => position = Some((*line, *column)),
+                    _ if exited => {
+                        if let Some(position) = position {
+                            findings.push((position, "this code can never run".to_string()));
+                        }
+                        break;
+                    }
+                    stmt => exited = exits(stmt),
+                }
+            }
+        }
+        for_each_child(node, &mut |child| visit(child, findings));
+    }
+    visit(analysis.ast, findings);
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+use unic::normal::StrNormalForm;
+use unic::ucd::GeneralCategory;
+
+// just enough of a locale to sort and case text the way its readers expect.
+// only the language of a tag is looked at, and anything not known is
+// treated like the root locale.
+
+// the language of a tag like `tr-TR` or `sv_SE`, lowercased
+pub fn language(tag: &str) -> String {
+    tag.split(&['-', '_'][..])
+        .next()
+        .unwrap()
+        .to_ascii_lowercase()
+}
+
+// turkish and azerbaijani have a dotted and a dotless i in both cases
+fn dotted_i(language: &str) -> bool {
+    language == "tr" || language == "az"
+}
+
+pub fn to_lowercase(s: &str, language: &str) -> String {
+    if dotted_i(language) {
+        s.replace("I\u{307}", "i")
+            .replace('I', "ı")
+            .replace('İ', "i")
+            .to_lowercase()
+    } else {
+        s.to_lowercase()
+    }
+}
+
+pub fn to_uppercase(s: &str, language: &str) -> String {
+    if dotted_i(language) {
+        s.replace('i', "İ").to_uppercase()
+    } else {
+        s.to_uppercase()
+    }
+}
+
+// letters a language sorts as letters of their own, each placed after the
+// letter it is paired with, in the order given
+fn tailoring(language: &str) -> &'static [(char, char)] {
+    match language {
+        "sv" | "fi" => &[('å', 'z'), ('ä', 'z'), ('ö', 'z')],
+        "da" | "nb" | "nn" | "no" => &[('æ', 'z'), ('ø', 'z'), ('å', 'z')],
+        "es" => &[('ñ', 'n')],
+        "tr" | "az" => &[
+            ('ç', 'c'),
+            ('ğ', 'g'),
+            ('ı', 'h'),
+            ('ö', 'o'),
+            ('ş', 's'),
+            ('ü', 'u'),
+        ],
+        _ => &[],
+    }
+}
+
+// a string broken into three levels, compared in turn: the letters, then
+// their accents, then their case
+#[derive(Default)]
+struct SortKey {
+    letters: Vec<u32>,
+    accents: Vec<u32>,
+    uppercase: Vec<bool>,
+}
+
+fn sort_key(s: &str, language: &str) -> SortKey {
+    let tailoring = tailoring(language);
+    let mut key = SortKey::default();
+    for c in s.nfc() {
+        let lower = to_lowercase(&c.to_string(), language);
+        let uppercase = lower != c.to_string();
+        let mut chars = lower.chars();
+        if let (Some(l), None) = (chars.next(), chars.next()) {
+            if let Some(i) = tailoring.iter().position(|(t, _)| *t == l) {
+                let base = tailoring[i].1;
+                let nth = tailoring[..i].iter().filter(|(_, b)| *b == base).count();
+                key.letters.push(base as u32 * 8 + 1 + nth as u32);
+                key.accents.push(0);
+                key.uppercase.push(uppercase);
+                continue;
+            }
+        }
+        for d in lower.nfd() {
+            if GeneralCategory::of(d).is_mark() {
+                key.accents.push(d as u32);
+            } else {
+                key.letters.push(d as u32 * 8);
+                key.accents.push(0);
+                key.uppercase.push(uppercase);
+            }
+        }
+    }
+    key
+}
+
+// lowercase sorts before uppercase, and strings that only differ in ways the
+// levels ignore fall back to comparing code points
+pub fn compare(a: &str, b: &str, language: &str) -> Ordering {
+    let (ka, kb) = (sort_key(a, language), sort_key(b, language));
+    ka.letters
+        .cmp(&kb.letters)
+        .then_with(|| ka.accents.cmp(&kb.accents))
+        .then_with(|| ka.uppercase.cmp(&kb.uppercase))
+        .then_with(|| a.cmp(b))
+}
+
+// how numbers and dates are written
+pub struct Conventions {
+    pub decimal: &'static str,
+    pub group: &'static str,
+    // `$1.00` rather than `1,00 €`
+    pub currency_first: bool,
+    pub months: [&'static str; 12],
+    // from sunday
+    pub days: [&'static str; 7],
+}
+
+static EN: Conventions = Conventions {
+    decimal: ".",
+    group: ",",
+    currency_first: true,
+    months: [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    days: [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ],
+};
+
+static DE: Conventions = Conventions {
+    decimal: ",",
+    group: ".",
+    currency_first: false,
+    months: [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    days: [
+        "Sonntag",
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+    ],
+};
+
+static ES: Conventions = Conventions {
+    decimal: ",",
+    group: ".",
+    currency_first: false,
+    months: [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    days: [
+        "domingo",
+        "lunes",
+        "martes",
+        "miércoles",
+        "jueves",
+        "viernes",
+        "sábado",
+    ],
+};
+
+static FR: Conventions = Conventions {
+    decimal: ",",
+    group: "\u{202f}",
+    currency_first: false,
+    months: [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    days: [
+        "dimanche", "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi",
+    ],
+};
+
+static SV: Conventions = Conventions {
+    decimal: ",",
+    group: "\u{a0}",
+    currency_first: false,
+    months: [
+        "januari",
+        "februari",
+        "mars",
+        "april",
+        "maj",
+        "juni",
+        "juli",
+        "augusti",
+        "september",
+        "oktober",
+        "november",
+        "december",
+    ],
+    days: [
+        "söndag", "måndag", "tisdag", "onsdag", "torsdag", "fredag", "lördag",
+    ],
+};
+
+static TR: Conventions = Conventions {
+    decimal: ",",
+    group: ".",
+    currency_first: true,
+    months: [
+        "Ocak", "Şubat", "Mart", "Nisan", "Mayıs", "Haziran", "Temmuz", "Ağustos", "Eylül", "Ekim",
+        "Kasım", "Aralık",
+    ],
+    days: [
+        "Pazar",
+        "Pazartesi",
+        "Salı",
+        "Çarşamba",
+        "Perşembe",
+        "Cuma",
+        "Cumartesi",
+    ],
+};
+
+// english for any language without its own
+pub fn conventions(language: &str) -> &'static Conventions {
+    match language {
+        "de" => &DE,
+        "es" => &ES,
+        "fr" => &FR,
+        "sv" => &SV,
+        "tr" => &TR,
+        _ => &EN,
+    }
+}
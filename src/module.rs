@@ -1,8 +1,12 @@
+use crate::bytecode_cache;
+use crate::diagnostics;
 use crate::interpreter::{Context, Interpreter, Scope};
 use crate::parser::{Node, Parser};
-use crate::{Agent, IntoValue, Value};
+use crate::source_map::{self, Reference, SourceMap};
+use crate::{Agent, Value};
 use gc::{Gc, GcCell};
 use std::collections::HashSet;
+use std::path::Path;
 
 #[derive(Debug, PartialEq, Clone)]
 enum ModuleStatus {
@@ -30,12 +34,157 @@ unsafe impl gc::Trace for Module {
     });
 }
 
+// an import declaration, kept apart from the syntax tree so that modules
+// loaded from the bytecode cache can be linked without parsing them
+pub enum Import {
+    Default(String, String),
+    Named(String, Vec<String>),
+    Standard(String, Vec<String>),
+}
+
+pub fn compile(filename: &str, source: &str, agent: &Agent) -> Result<(usize, Vec<Import>), Value> {
+    let ast = match Parser::parse(&source) {
+        Ok(v) => v,
+        Err(d) => return Err(diagnostics::to_error(agent, &d, filename, source)),
+    };
+
+    let bytecode_position = agent.assembler.borrow_mut().assemble(&ast);
+
+    Ok((bytecode_position, imports(&ast)))
+}
+
+// the imports declared at the top level of a program
+pub fn imports(ast: &Node) -> Vec<Import> {
+    let mut imports = Vec::new();
+    if let Node::Block(_scope, stmts) = ast {
+        for stmt in stmts {
+            match stmt {
+                Node::ImportDefaultDeclaration(specifier, name) => {
+                    imports.push(Import::Default(specifier.clone(), name.clone()));
+                }
+                Node::ImportNamedDeclaration(specifier, names) => {
+                    imports.push(Import::Named(specifier.clone(), names.clone()));
+                }
+                Node::ImportStandardDeclaration(specifier, names) => {
+                    imports.push(Import::Standard(specifier.clone(), names.clone()));
+                }
+                _ => {}
+            }
+        }
+    } else {
+        unreachable!();
+    }
+    imports
+}
+
+// binds the imported names in the context's scope, returning the specifiers
+// of the modules that were loaded
+pub fn bind_imports(
+    agent: &Agent,
+    filename: &str,
+    context: &Gc<GcCell<Context>>,
+    imports: Vec<Import>,
+) -> Result<HashSet<String>, Value> {
+    let mut specifiers = HashSet::new();
+    for import in imports {
+        match import {
+            Import::Default(specifier, name) => {
+                let mr = agent.load(&specifier, filename)?;
+                context.borrow().scope.borrow_mut().create_import(&name, mr);
+                specifiers.insert(specifier);
+            }
+            Import::Named(specifier, names) => {
+                let mr = agent.load(&specifier, filename)?;
+                for name in names {
+                    context
+                        .borrow()
+                        .scope
+                        .borrow_mut()
+                        .create_import(&name, mr.clone());
+                }
+                specifiers.insert(specifier);
+            }
+            Import::Standard(specifier, names) => match agent.builtins.get(&specifier) {
+                Some(s) => {
+                    for name in names {
+                        match s.get(&name) {
+                            Some(v) => {
+                                let ctx = context.borrow();
+                                let mut scope = ctx.scope.borrow_mut();
+                                scope.create(agent, &name, false)?;
+                                scope.initialize(&name, v.clone());
+                            }
+                            None => {
+                                return Err(Value::new_error(agent, "unknown export"));
+                            }
+                        }
+                    }
+                }
+                None => return Err(Value::new_error(agent, "unknown standard module")),
+            },
+        }
+    }
+
+    Ok(specifiers)
+}
+
 impl Module {
-    pub fn new(filename: &str, source: &str, agent: &mut Agent) -> Result<Module, Value> {
-        let ast = match Parser::parse(&source) {
-            Ok(v) => v,
-            Err(e) => return Err(e.into_value(agent)),
+    pub fn new(filename: &str, source: &str, agent: &Agent) -> Result<Module, Value> {
+        let (bytecode_position, imports) = compile(filename, source, agent)?;
+        Module::link(filename, source, bytecode_position, imports, agent)
+    }
+
+    // like new, but reuses the bytecode from an earlier run if the source is unchanged
+    pub fn new_cached(filename: &str, source: &str, agent: &Agent) -> Result<Module, Value> {
+        let (bytecode_position, imports) = match bytecode_cache::load(filename, source) {
+            Some(cached) => (
+                agent.assembler.borrow_mut().import_chunk(&cached.chunk),
+                cached.imports,
+            ),
+            None => {
+                let (bytecode_position, imports) = compile(filename, source, agent)?;
+                let assembler = agent.assembler.borrow();
+                let chunk = assembler.export_chunk(bytecode_position, assembler.code.len());
+                bytecode_cache::store(filename, source, &imports, &chunk);
+                (bytecode_position, imports)
+            }
+        };
+        Module::link(filename, source, bytecode_position, imports, agent)
+    }
+
+    fn link(
+        filename: &str,
+        source: &str,
+        bytecode_position: usize,
+        imports: Vec<Import>,
+        agent: &Agent,
+    ) -> Result<Module, Value> {
+        agent
+            .assembler
+            .borrow_mut()
+            .files
+            .push((bytecode_position, filename.to_string()));
+        // a missing or malformed map just leaves locations in the generated code
+        let map = match source_map::find_reference(source) {
+            Some(Reference::Inline(json)) => SourceMap::parse(&json),
+            Some(Reference::Path(path)) => {
+                let path = Path::new(filename).with_file_name(path);
+                agent
+                    .permissions
+                    .check_read(agent, &path.to_string_lossy())
+                    .ok()
+                    .and_then(|_| std::fs::read_to_string(&path).ok())
+                    .and_then(|json| SourceMap::parse(&json))
+            }
+            None => None,
         };
+        if let Some(map) = map {
+            agent
+                .assembler
+                .borrow_mut()
+                .source_maps
+                .insert(filename.to_string(), map);
+        }
 
         let mut module = Module {
             filename: filename.to_string(),
@@ -44,78 +193,27 @@ impl Module {
             status: ModuleStatus::Uninstantiated,
             dfs_index: 0,
             dfs_ancestor_index: 0,
-            bytecode_position: agent.assembler.assemble(&ast),
+            bytecode_position,
         };
 
-        if let Node::Block(_scope, stmts) = ast {
-            for stmt in stmts {
-                match stmt {
-                    Node::ImportDefaultDeclaration(specifier, name) => {
-                        let mr = agent.load(&specifier, filename)?;
-                        module
-                            .context
-                            .borrow()
-                            .scope
-                            .borrow_mut()
-                            .create_import(&name, mr);
-                        module.imports.insert(specifier);
-                    }
-                    Node::ImportNamedDeclaration(specifier, names) => {
-                        let mr = agent.load(&specifier, filename)?;
-                        for name in names {
-                            module
-                                .context
-                                .borrow()
-                                .scope
-                                .borrow_mut()
-                                .create_import(&name, mr.clone());
-                        }
-                        module.imports.insert(specifier);
-                    }
-                    Node::ImportStandardDeclaration(specifier, names) => {
-                        match agent.builtins.get(&specifier) {
-                            Some(s) => {
-                                for name in names {
-                                    match s.get(&name) {
-                                        Some(v) => {
-                                            let ctx = module.context.borrow();
-                                            let mut scope = ctx.scope.borrow_mut();
-                                            scope.create(agent, &name, false)?;
-                                            scope.initialize(&name, v.clone());
-                                        }
-                                        None => {
-                                            return Err(Value::new_error(agent, "unknown export"));
-                                        }
-                                    }
-                                }
-                            }
-                            None => return Err(Value::new_error(agent, "unknown standard module")),
-                        }
-                    }
-                    Node::ExportDeclaration(..) => {}
-                    _ => {}
-                }
-            }
-        } else {
-            unreachable!();
-        }
+        module.imports = bind_imports(agent, filename, &module.context, imports)?;
 
         Ok(module)
     }
 
-    pub fn instantiate(agent: &mut Agent, module: Gc<GcCell<Module>>) -> Result<(), Value> {
+    pub fn instantiate(agent: &Agent, module: Gc<GcCell<Module>>) -> Result<(), Value> {
         inner_module_instantiation(agent, module, &mut Vec::new(), 0)?;
         Ok(())
     }
 
-    pub fn evaluate(agent: &mut Agent, module: Gc<GcCell<Module>>) -> Result<(), Value> {
+    pub fn evaluate(agent: &Agent, module: Gc<GcCell<Module>>) -> Result<(), Value> {
         inner_module_evaluation(agent, module, &mut Vec::new(), 0)?;
         Ok(())
     }
 }
 
 fn inner_module_instantiation(
-    agent: &mut Agent,
+    agent: &Agent,
     module: Gc<GcCell<Module>>,
     stack: &mut Vec<Gc<GcCell<Module>>>,
     mut index: u32,
@@ -159,7 +257,7 @@ fn inner_module_instantiation(
 }
 
 fn inner_module_evaluation(
-    agent: &mut Agent,
+    agent: &Agent,
     module: Gc<GcCell<Module>>,
     stack: &mut Vec<Gc<GcCell<Module>>>,
     mut index: u32,
@@ -0,0 +1,45 @@
+// where script output goes. the default writes to the process's stdout and
+// stderr, embedders can capture or redirect it.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub trait OutputSink {
+    fn write(&self, stream: Stream, text: &str);
+
+    // whether text written to the stream ends up on a terminal, so that
+    // colors can be used
+    fn is_terminal(&self, _stream: Stream) -> bool {
+        false
+    }
+}
+
+pub struct Stdio;
+
+impl OutputSink for Stdio {
+    fn write(&self, stream: Stream, text: &str) {
+        use std::io::Write;
+        // a closed pipe shouldn't take the script down with it
+        let _ = match stream {
+            Stream::Stdout => std::io::stdout().write_all(text.as_bytes()),
+            Stream::Stderr => std::io::stderr().write_all(text.as_bytes()),
+        };
+    }
+
+    fn is_terminal(&self, stream: Stream) -> bool {
+        use std::io::IsTerminal;
+        match stream {
+            Stream::Stdout => std::io::stdout().is_terminal(),
+            Stream::Stderr => std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl<F: Fn(Stream, &str)> OutputSink for F {
+    fn write(&self, stream: Stream, text: &str) {
+        self(stream, text)
+    }
+}
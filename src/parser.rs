@@ -1,4 +1,4 @@
-use crate::{Agent, IntoValue, Value};
+use crate::diagnostics::Diagnostic;
 use indexmap::IndexMap;
 use std::collections::VecDeque;
 use std::iter::Peekable;
@@ -32,7 +32,11 @@ pub enum Operator {
     BitwiseXOR,
     BitwiseNOT,
     LogicalAND,
+    LogicalANDAssign,
     LogicalOR,
+    LogicalORAssign,
+    NullishCoalescing,
+    NullishCoalescingAssign,
     Assign,
     Equal,
     NotEqual,
@@ -40,6 +44,89 @@ pub enum Operator {
     Void,
 }
 
+impl Operator {
+    // as written in the source
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::AddAssign => "+=",
+            Operator::Sub => "-",
+            Operator::SubAssign => "-=",
+            Operator::Mul => "*",
+            Operator::MulAssign => "*=",
+            Operator::Pow => "**",
+            Operator::PowAssign => "**=",
+            Operator::Div => "/",
+            Operator::DivAssign => "/=",
+            Operator::Mod => "%",
+            Operator::ModAssign => "%=",
+            Operator::LeftShift => "<<",
+            Operator::RightShift => ">>",
+            Operator::GreaterThan => ">",
+            Operator::GreaterThanOrEqual => ">=",
+            Operator::Not => "!",
+            Operator::LessThan => "<",
+            Operator::LessThanOrEqual => "<=",
+            Operator::BitwiseAND => "&",
+            Operator::BitwiseOR => "|",
+            Operator::BitwiseXOR => "^",
+            Operator::BitwiseNOT => "~",
+            Operator::LogicalAND => "&&",
+            Operator::LogicalANDAssign => "&&=",
+            Operator::LogicalOR => "||",
+            Operator::LogicalORAssign => "||=",
+            Operator::NullishCoalescing => "??",
+            Operator::NullishCoalescingAssign => "??=",
+            Operator::Assign => "=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Typeof => "typeof",
+            Operator::Void => "void",
+        }
+    }
+
+    // `-` is always Sub, whether it is unary or binary
+    pub fn from_symbol(symbol: &str) -> Option<Operator> {
+        Some(match symbol {
+            "+" => Operator::Add,
+            "+=" => Operator::AddAssign,
+            "-" => Operator::Sub,
+            "-=" => Operator::SubAssign,
+            "*" => Operator::Mul,
+            "*=" => Operator::MulAssign,
+            "**" => Operator::Pow,
+            "**=" => Operator::PowAssign,
+            "/" => Operator::Div,
+            "/=" => Operator::DivAssign,
+            "%" => Operator::Mod,
+            "%=" => Operator::ModAssign,
+            "<<" => Operator::LeftShift,
+            ">>" => Operator::RightShift,
+            ">" => Operator::GreaterThan,
+            ">=" => Operator::GreaterThanOrEqual,
+            "!" => Operator::Not,
+            "<" => Operator::LessThan,
+            "<=" => Operator::LessThanOrEqual,
+            "&" => Operator::BitwiseAND,
+            "|" => Operator::BitwiseOR,
+            "^" => Operator::BitwiseXOR,
+            "~" => Operator::BitwiseNOT,
+            "&&" => Operator::LogicalAND,
+            "&&=" => Operator::LogicalANDAssign,
+            "||" => Operator::LogicalOR,
+            "||=" => Operator::LogicalORAssign,
+            "??" => Operator::NullishCoalescing,
+            "??=" => Operator::NullishCoalescingAssign,
+            "=" => Operator::Assign,
+            "==" => Operator::Equal,
+            "!=" => Operator::NotEqual,
+            "typeof" => Operator::Typeof,
+            "void" => Operator::Void,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 enum Token {
     Null,
@@ -52,6 +139,7 @@ enum Token {
     Identifier(String),
 
     LeftBrace,
+    HashBrace,
     RightBrace,
     LeftBracket,
     RightBracket,
@@ -94,8 +182,12 @@ enum Token {
     Export,
     Default,
     From,
+    Match,
+    Enum,
 
     Operator(Operator),
+    // a char that starts no token, left for the parser to reject
+    Unknown(char),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -137,6 +229,9 @@ pub enum ScopeKind {
 pub struct Scope {
     pub kind: ScopeKind,
     pub bindings: IndexMap<String, bool>,
+    enums: IndexMap<String, Vec<String>>,
+    // the line and column of the closing brace, if the block has one
+    pub end: Option<(usize, usize)>,
 }
 
 impl Scope {
@@ -151,6 +246,18 @@ impl Scope {
                 ParseScope::GeneratorFunction => ScopeKind::Block,
             },
             bindings: IndexMap::new(),
+            enums: IndexMap::new(),
+            end: None,
+        }
+    }
+
+    // a block scope for a syntax tree that was built rather than parsed
+    pub(crate) fn block(bindings: IndexMap<String, bool>) -> Scope {
+        Scope {
+            kind: ScopeKind::Block,
+            bindings,
+            enums: IndexMap::new(),
+            end: None,
         }
     }
 
@@ -166,6 +273,8 @@ impl Scope {
 
 #[derive(Debug, PartialEq)]
 pub enum Node {
+    // line and column of the statement that follows
+    Position(usize, usize),
     NullLiteral,
     TrueLiteral,
     FalseLiteral,
@@ -176,6 +285,8 @@ pub enum Node {
     ObjectLiteral(Vec<Node>),
     ArrayLiteral(Vec<Node>),
     TupleLiteral(Vec<Node>),
+    NamedTupleLiteral(Vec<(String, Node)>),
+    RecordLiteral(Vec<Node>),
     TemplateLiteral(Vec<String>, Vec<Node>),
 
     Identifier(String),
@@ -210,7 +321,10 @@ pub enum Node {
     ClassExpression(String, Option<Box<Node>>, Vec<Node>),
     ClassDeclaration(String, Option<Box<Node>>, Vec<Node>),
 
+    EnumDeclaration(String, Vec<(String, Option<f64>)>),
+
     LexicalInitialization(String, Box<Node>),
+    LexicalDestructuring(Pattern, Box<Node>),
 
     ReturnStatement(Option<Box<Node>>),
     ThrowStatement(Box<Node>),
@@ -230,6 +344,51 @@ pub enum Node {
     ExportDeclaration(Box<Node>),
 
     Initializer(Box<Node>, Box<Node>),
+
+    MatchExpression(Box<Node>, Vec<Node>),
+    MatchArm(Scope, Pattern, Option<Box<Node>>, Box<Node>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Binding(String),
+    Value(Box<Node>),
+    Tuple(Vec<Pattern>),
+    NamedTuple(Vec<(String, Pattern)>),
+    Array(Vec<Pattern>),
+    Object(Vec<(String, Pattern)>),
+    Or(Vec<Pattern>),
+}
+
+impl Pattern {
+    // the names it binds, in order
+    pub fn bindings(&self) -> Vec<&str> {
+        fn collect<'p>(pattern: &'p Pattern, out: &mut Vec<&'p str>) {
+            match pattern {
+                Pattern::Binding(name) => {
+                    // alternatives of an or pattern bind the same names
+                    if !out.contains(&name.as_str()) {
+                        out.push(name);
+                    }
+                }
+                Pattern::Tuple(patterns) | Pattern::Array(patterns) | Pattern::Or(patterns) => {
+                    for p in patterns {
+                        collect(p, out);
+                    }
+                }
+                Pattern::NamedTuple(fields) | Pattern::Object(fields) => {
+                    for (_, p) in fields {
+                        collect(p, out);
+                    }
+                }
+                Pattern::Wildcard | Pattern::Value(..) => {}
+            }
+        }
+        let mut out = Vec::new();
+        collect(self, &mut out);
+        out
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -244,38 +403,147 @@ enum ParseScope {
     GeneratorFunction = 0b0010_1000,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Error {
     NormalEOF,
     UnexpectedEOF,
     UnexpectedToken,
     DuplicateBinding,
     InvalidAssignmentTarget,
+    ConstantAssignment,
+    NonExhaustiveMatch,
+}
+
+// tracks the line and column of the next char
+#[derive(Clone)]
+struct SourceChars<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    offset: usize,
+}
+
+impl<'a> SourceChars<'a> {
+    fn new(code: &'a str) -> SourceChars<'a> {
+        SourceChars {
+            chars: code.chars().peekable(),
+            line: 1,
+            column: 1,
+            offset: 0,
+        }
+    }
+
+    #[inline]
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+        }
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(..) => self.column += 1,
+            None => {}
+        }
+        c
+    }
+}
+
+// a comment and the line and column it starts at, the text includes the
+// `//` or `/* */`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
 }
 
-impl IntoValue for Error {
-    fn into_value(&self, agent: &Agent) -> Value {
-        Value::new_error(agent, &format!("{:?}", self))
+// the text of the `///` comments on the lines right above a declaration,
+// without the slashes, and the line and column the declaration starts at
+#[derive(Debug, PartialEq, Clone)]
+pub struct Doc {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenKind {
+    Comment,
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    // a template literal up to its first `${`, between a `}` and the next
+    // `${`, or up to its closing backquote
+    Template,
+    Regex,
+    Operator,
+    Punctuation,
+}
+
+impl TokenKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenKind::Comment => "comment",
+            TokenKind::Keyword => "keyword",
+            TokenKind::Identifier => "identifier",
+            TokenKind::Number => "number",
+            TokenKind::String => "string",
+            TokenKind::Template => "template",
+            TokenKind::Regex => "regex",
+            TokenKind::Operator => "operator",
+            TokenKind::Punctuation => "punctuation",
+        }
     }
 }
 
+// a token as written in the source, from the line and column it starts at to
+// the line and column just past its end
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceToken {
+    pub kind: TokenKind,
+    pub text: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
 struct Lexer<'a> {
-    chars: Peekable<Chars<'a>>,
+    chars: SourceChars<'a>,
     peeked: Option<Option<Token>>,
+    // line and column of the last token lexed
+    position: (usize, usize),
+    // and its byte offset
+    offset: usize,
+    comments: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(code: &'a str) -> Lexer<'a> {
         Lexer {
             peeked: None,
-            chars: code.chars().peekable(),
+            chars: SourceChars::new(code),
+            position: (1, 1),
+            offset: 0,
+            comments: Vec::new(),
         }
     }
 
     fn next(&mut self) -> Option<Token> {
         match self.peeked.take() {
             Some(v) => v,
-            None => match self.chars.next() {
+            None => match {
+                self.position = (self.chars.line, self.chars.column);
+                self.offset = self.chars.offset;
+                self.chars.next()
+            } {
                 Some(char) => match char {
                     ' ' | '\t' | '\r' | '\n' => self.next(),
                     '0'...'9' => Some(Token::NumberLiteralStart(char)),
@@ -318,6 +586,8 @@ impl<'a> Lexer<'a> {
                             "export" => Token::Export,
                             "default" => Token::Default,
                             "from" => Token::From,
+                            "match" => Token::Match,
+                            "enum" => Token::Enum,
                             "async" => Token::Async,
                             "await" => Token::Await,
                             "gen" => Token::Gen,
@@ -335,7 +605,19 @@ impl<'a> Lexer<'a> {
                     ')' => Some(Token::RightParen),
                     ':' => Some(Token::Colon),
                     ';' => Some(Token::Semicolon),
-                    '?' => Some(Token::Question),
+                    '?' => Some(match self.chars.peek() {
+                        Some('?') => {
+                            self.chars.next();
+                            match self.chars.peek() {
+                                Some('=') => {
+                                    self.chars.next();
+                                    Token::Operator(Operator::NullishCoalescingAssign)
+                                }
+                                _ => Token::Operator(Operator::NullishCoalescing),
+                            }
+                        }
+                        _ => Token::Question,
+                    }),
                     '.' => Some(match self.chars.peek() {
                         Some('.') => {
                             self.chars.next();
@@ -343,7 +625,7 @@ impl<'a> Lexer<'a> {
                                 self.chars.next();
                                 Token::Ellipsis
                             } else {
-                                panic!();
+                                Token::Unknown('.')
                             }
                         }
                         _ => Token::Dot,
@@ -389,27 +671,30 @@ impl<'a> Lexer<'a> {
                             Some(Token::Operator(Operator::DivAssign))
                         }
                         Some('*') => {
+                            let mut text = "/".to_string();
                             loop {
                                 if self.chars.peek() == None {
                                     return None; // Err(Error::UnexpectedEOF);
                                 }
-                                if let Some('*') = self.chars.next() {
-                                    if let Some('/') = self.chars.next() {
-                                        break;
-                                    }
+                                let c = self.chars.next().unwrap();
+                                text.push(c);
+                                if c == '*' && self.chars.peek() == Some(&'/') {
+                                    text.push(self.chars.next().unwrap());
+                                    break;
                                 }
                             }
+                            self.comment(text);
                             self.next()
                         }
                         Some('/') => {
+                            let mut text = "/".to_string();
                             loop {
-                                if self.chars.peek() == None {
-                                    return None; // Err(Error::UnexpectedEOF);
-                                }
-                                if let Some('\n') = self.chars.next() {
-                                    break;
+                                match self.chars.next() {
+                                    Some('\n') | None => break,
+                                    Some(c) => text.push(c),
                                 }
                             }
+                            self.comment(text);
                             self.next()
                         }
                         _ => Some(Token::Operator(Operator::Div)),
@@ -453,14 +738,26 @@ impl<'a> Lexer<'a> {
                     '&' => Some(match self.chars.peek() {
                         Some('&') => {
                             self.chars.next();
-                            Token::Operator(Operator::LogicalAND)
+                            match self.chars.peek() {
+                                Some('=') => {
+                                    self.chars.next();
+                                    Token::Operator(Operator::LogicalANDAssign)
+                                }
+                                _ => Token::Operator(Operator::LogicalAND),
+                            }
                         }
                         _ => Token::Operator(Operator::BitwiseAND),
                     }),
                     '|' => Some(match self.chars.peek() {
                         Some('|') => {
                             self.chars.next();
-                            Token::Operator(Operator::LogicalOR)
+                            match self.chars.peek() {
+                                Some('=') => {
+                                    self.chars.next();
+                                    Token::Operator(Operator::LogicalORAssign)
+                                }
+                                _ => Token::Operator(Operator::LogicalOR),
+                            }
                         }
                         _ => Token::Operator(Operator::BitwiseOR),
                     }),
@@ -478,15 +775,26 @@ impl<'a> Lexer<'a> {
                         _ => Token::Operator(Operator::Assign),
                     }),
                     '@' => Some(Token::At),
-                    _ => {
-                        panic!("unexpected token {}", char);
+                    '#' if self.chars.peek() == Some(&'{') => {
+                        self.chars.next();
+                        Some(Token::HashBrace)
                     }
+                    _ => Some(Token::Unknown(char)),
                 },
                 None => None,
             },
         }
     }
 
+    fn comment(&mut self, text: String) {
+        let (line, column) = self.position;
+        self.comments.push(Comment {
+            line,
+            column,
+            text: text.trim_end().to_string(),
+        });
+    }
+
     #[inline]
     pub fn peek(&mut self) -> Option<&Token> {
         if self.peeked.is_none() {
@@ -512,32 +820,75 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_hashbang(&mut self) {
-        if self.chars.peek() == Some(&'#') {
-            self.chars.next();
-            if self.chars.peek() == Some(&'!') {
-                loop {
-                    match self.chars.next() {
-                        Some('\n') | None => break,
-                        _ => {}
-                    }
+        let mut ahead = self.chars.clone();
+        if ahead.next() == Some('#') && ahead.next() == Some('!') {
+            loop {
+                match self.chars.next() {
+                    Some('\n') | None => break,
+                    _ => {}
                 }
             }
         }
     }
 }
 
+fn is_declaration(node: &Node) -> bool {
+    match node {
+        Node::FunctionDeclaration(..)
+        | Node::ClassDeclaration(..)
+        | Node::EnumDeclaration(..)
+        | Node::LexicalInitialization(..)
+        | Node::LexicalDestructuring(..)
+        | Node::ExportDeclaration(..) => true,
+        _ => false,
+    }
+}
+
+// whether a `/` after the token would divide rather than start a regex
+fn ends_value(token: &SourceToken) -> bool {
+    match token.kind {
+        TokenKind::Identifier | TokenKind::Number | TokenKind::String | TokenKind::Regex => true,
+        TokenKind::Template => token.text.ends_with('`'),
+        TokenKind::Keyword => match token.text.as_str() {
+            "null" | "true" | "false" | "this" => true,
+            _ => false,
+        },
+        TokenKind::Punctuation => match token.text.as_str() {
+            ")" | "]" | "}" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn parameter_names(parameters: &[Node]) -> Vec<&str> {
+    parameters
+        .iter()
+        .map(|p| match p {
+            Node::Identifier(name) => name.as_str(),
+            Node::Initializer(target, _) => match &**target {
+                Node::Identifier(name) => name.as_str(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
 macro_rules! binop_production {
     ( $name:ident, $lower:ident, [ $( $op:path ),* ] ) => {
         fn $name(&mut self) -> Result<Node, Error> {
             let mut lhs = self.$lower()?;
-            match self.lexer.peek() {
-                Some(Token::Operator(op)) if $( op == &$op )||* => {
-                    let op = op.clone();
-                    self.lexer.next();
-                    let rhs = self.$name()?;
-                    lhs = Node::BinaryExpression(op, Box::new(lhs), Box::new(rhs));
+            loop {
+                match self.lexer.peek() {
+                    Some(Token::Operator(op)) if $( op == &$op )||* => {
+                        let op = op.clone();
+                        self.lexer.next();
+                        let rhs = self.$lower()?;
+                        lhs = Node::BinaryExpression(op, Box::new(lhs), Box::new(rhs));
+                    }
+                    _ => break,
                 }
-                _ => {},
             }
             Ok(lhs)
         }
@@ -548,36 +899,295 @@ pub struct Parser<'a> {
     lexer: Lexer<'a>,
     scope: Vec<Scope>,
     scope_bits: u8,
+    diagnostics: Vec<Diagnostic>,
+    docs: Vec<Doc>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn parse(code: &'a str) -> Result<Node, Error> {
+    pub fn parse(code: &'a str) -> Result<Node, Vec<Diagnostic>> {
+        Parser::parse_with_comments(code).map(|(ast, _)| ast)
+    }
+
+    // also returns the comments, in the order they appear
+    pub fn parse_with_comments(code: &'a str) -> Result<(Node, Vec<Comment>), Vec<Diagnostic>> {
+        Parser::parse_all(code).map(|(ast, comments, _)| (ast, comments))
+    }
+
+    // also returns the doc comments of declarations, in the order they appear
+    pub fn parse_with_docs(code: &'a str) -> Result<(Node, Vec<Doc>), Vec<Diagnostic>> {
+        Parser::parse_all(code).map(|(ast, _, docs)| (ast, docs))
+    }
+
+    fn parse_all(code: &'a str) -> Result<(Node, Vec<Comment>, Vec<Doc>), Vec<Diagnostic>> {
         let mut parser = Parser {
             lexer: Lexer::new(code),
             scope_bits: 0,
             scope: Vec::new(),
+            diagnostics: Vec::new(),
+            docs: Vec::new(),
         };
 
         parser.lexer.skip_hashbang();
 
-        if let Node::Block(scope, mut stmts) = parser.parse_block(ParseScope::TopLevel)? {
+        let block = parser.parse_block(ParseScope::TopLevel);
+        if !parser.diagnostics.is_empty() {
+            return Err(parser.diagnostics);
+        }
+        let comments = std::mem::replace(&mut parser.lexer.comments, Vec::new());
+        let docs = parser.docs;
+        if let Ok(Node::Block(scope, mut stmts)) = block {
             if let Some(Node::ExpressionStatement(..)) = stmts.last() {
                 // if the last item is an expression statement, replace it with the expression
                 // so that the value will be left on the stack to inspect in tests
                 if let Node::ExpressionStatement(expr) = stmts.pop().unwrap() {
                     stmts.push(Node::ParenthesizedExpression(expr));
-                    Ok(Node::Block(scope, stmts))
+                    Ok((Node::Block(scope, stmts), comments, docs))
                 } else {
                     unreachable!();
                 }
             } else {
-                Ok(Node::Block(scope, stmts))
+                Ok((Node::Block(scope, stmts), comments, docs))
             }
         } else {
             unreachable!();
         }
     }
 
+    // the tokens of the source without parsing them, for tools that need less
+    // than a syntax tree. `/` is read as the start of a regex wherever a value
+    // could not have just ended.
+    pub fn tokenize(code: &'a str) -> Result<Vec<SourceToken>, Diagnostic> {
+        let mut parser = Parser {
+            lexer: Lexer::new(code),
+            scope_bits: 0,
+            scope: Vec::new(),
+            diagnostics: Vec::new(),
+            docs: Vec::new(),
+        };
+        parser.lexer.skip_hashbang();
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        // the brace depth at each `${` still open
+        let mut templates = Vec::new();
+        loop {
+            let token = parser.lexer.next();
+            for comment in parser.lexer.comments.drain(..) {
+                let mut end = (comment.line, comment.column);
+                for c in comment.text.chars() {
+                    end = match c {
+                        '\n' => (end.0 + 1, 1),
+                        _ => (end.0, end.1 + 1),
+                    };
+                }
+                tokens.push(SourceToken {
+                    kind: TokenKind::Comment,
+                    text: comment.text,
+                    start: (comment.line, comment.column),
+                    end,
+                });
+            }
+            let token = match token {
+                Some(token) => token,
+                None => break,
+            };
+            let start = parser.lexer.position;
+            let error = |e| Diagnostic::new(e, start);
+            let kind = match token {
+                Token::Unknown(..) => return Err(error(Error::UnexpectedToken)),
+                Token::Identifier(..) => TokenKind::Identifier,
+                // `t.0.1` is two indices rather than `t.` and a float
+                Token::NumberLiteralStart(..)
+                    if tokens
+                        .iter()
+                        .rev()
+                        .find(|t: &&SourceToken| t.kind != TokenKind::Comment)
+                        .map_or(false, |t| t.text == ".") =>
+                {
+                    while parser
+                        .lexer
+                        .chars
+                        .peek()
+                        .map_or(false, char::is_ascii_digit)
+                    {
+                        parser.lexer.chars.next();
+                    }
+                    TokenKind::Number
+                }
+                Token::NumberLiteralStart(c) => {
+                    parser.parse_number_literal(c).map_err(error)?;
+                    TokenKind::Number
+                }
+                Token::StringLiteralStart(c) => {
+                    parser.parse_string_literal(c).map_err(error)?;
+                    TokenKind::String
+                }
+                Token::BackQuote => {
+                    parser.skip_template(&mut templates, depth).map_err(error)?;
+                    TokenKind::Template
+                }
+                Token::RightBrace if templates.last() == Some(&depth) => {
+                    templates.pop();
+                    parser.skip_template(&mut templates, depth).map_err(error)?;
+                    TokenKind::Template
+                }
+                Token::Operator(Operator::Div) | Token::Operator(Operator::DivAssign)
+                    if !tokens
+                        .iter()
+                        .rev()
+                        .find(|t: &&SourceToken| t.kind != TokenKind::Comment)
+                        .map_or(false, ends_value) =>
+                {
+                    // a `/=` has already taken the first char of the pattern
+                    loop {
+                        match parser.lexer.chars.next() {
+                            Some('/') => break,
+                            Some('\\') => {
+                                parser.lexer.chars.next();
+                            }
+                            Some('\n') | None => return Err(error(Error::UnexpectedEOF)),
+                            Some(..) => {}
+                        }
+                    }
+                    TokenKind::Regex
+                }
+                Token::Operator(ref op) => match op {
+                    Operator::Typeof | Operator::Void => TokenKind::Keyword,
+                    _ => TokenKind::Operator,
+                },
+                Token::LeftBrace | Token::HashBrace => {
+                    depth += 1;
+                    TokenKind::Punctuation
+                }
+                Token::RightBrace => {
+                    depth -= 1;
+                    TokenKind::Punctuation
+                }
+                Token::LeftBracket
+                | Token::RightBracket
+                | Token::LeftParen
+                | Token::RightParen
+                | Token::Semicolon
+                | Token::Colon
+                | Token::Question
+                | Token::Dot
+                | Token::At
+                | Token::Comma
+                | Token::Ellipsis
+                | Token::Arrow => TokenKind::Punctuation,
+                _ => TokenKind::Keyword,
+            };
+            tokens.push(SourceToken {
+                kind,
+                text: code[parser.lexer.offset..parser.lexer.chars.offset].to_string(),
+                start,
+                end: (parser.lexer.chars.line, parser.lexer.chars.column),
+            });
+        }
+        Ok(tokens)
+    }
+
+    // what a number or string token from tokenize parses to
+    pub fn parse_literal(text: &'a str) -> Option<Node> {
+        let mut parser = Parser {
+            lexer: Lexer::new(text),
+            scope_bits: 0,
+            scope: Vec::new(),
+            diagnostics: Vec::new(),
+            docs: Vec::new(),
+        };
+        match parser.parse_primary_expression() {
+            Ok(node @ Node::NumberLiteral(..)) | Ok(node @ Node::StringLiteral(..))
+                if parser.lexer.chars.peek().is_none() =>
+            {
+                Some(node)
+            }
+            _ => None,
+        }
+    }
+
+    // up to the closing backquote or the next `${`, which is remembered with
+    // the depth its `}` will be at
+    fn skip_template(&mut self, templates: &mut Vec<i32>, depth: i32) -> Result<(), Error> {
+        loop {
+            match self.lexer.chars.next() {
+                Some('`') => return Ok(()),
+                Some('\\') => {
+                    self.lexer.chars.next();
+                }
+                Some('$') if self.lexer.chars.peek() == Some(&'{') => {
+                    self.lexer.chars.next();
+                    templates.push(depth);
+                    return Ok(());
+                }
+                Some(..) => {}
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+    }
+
+    fn report(&mut self, error: Error) {
+        let error = match error {
+            Error::NormalEOF => Error::UnexpectedEOF,
+            e => e,
+        };
+        // an error that escapes several blocks is only reported once
+        let position = self.lexer.position;
+        if self.diagnostics.last().map(|d| (d.line, d.column)) != Some(position) {
+            self.diagnostics.push(Diagnostic::new(error, position));
+        }
+    }
+
+    // skip to the end of the current statement, or the start of the next one,
+    // so that parsing can resume after an error
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.lexer.peek() {
+                None => return,
+                Some(Token::RightBrace) if depth == 0 => return,
+                Some(Token::Let)
+                | Some(Token::Const)
+                | Some(Token::Function)
+                | Some(Token::Class)
+                | Some(Token::Enum)
+                | Some(Token::If)
+                | Some(Token::While)
+                | Some(Token::For)
+                | Some(Token::Try)
+                | Some(Token::Return)
+                | Some(Token::Throw)
+                | Some(Token::Import)
+                | Some(Token::Export)
+                    if depth == 0 =>
+                {
+                    return
+                }
+                Some(Token::Semicolon) if depth == 0 => {
+                    self.lexer.next();
+                    return;
+                }
+                _ => {}
+            }
+            match self.lexer.next() {
+                Some(Token::LeftBrace) | Some(Token::HashBrace) => depth += 1,
+                Some(Token::RightBrace) => depth -= 1,
+                Some(Token::StringLiteralStart(c)) => {
+                    let _ = self.parse_string_literal(c);
+                }
+                Some(Token::BackQuote) => loop {
+                    match self.lexer.chars.next() {
+                        Some('\\') => {
+                            self.lexer.chars.next();
+                        }
+                        Some('`') | None => break,
+                        _ => {}
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
     fn scope(&self, scope: ParseScope) -> bool {
         (self.scope_bits & scope as u8) == scope as u8
     }
@@ -591,6 +1201,41 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // parameters, loop and catch bindings are created by the interpreter, so
+    // they are only tracked here to reject assignments to them
+    fn with_bindings<T>(
+        &mut self,
+        names: &[&str],
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut scope = Scope::new(ParseScope::Block);
+        for name in names {
+            scope.declare(name, false);
+        }
+        self.scope.push(scope);
+        let r = f(self);
+        self.scope.pop();
+        r
+    }
+
+    fn lookup_enum(&self, name: &str) -> Option<&Vec<String>> {
+        for scope in self.scope.iter().rev() {
+            if scope.bindings.contains_key(name) {
+                return scope.enums.get(name);
+            }
+        }
+        None
+    }
+
+    fn is_constant(&self, name: &str) -> bool {
+        for scope in self.scope.iter().rev() {
+            if let Some(mutable) = scope.bindings.get(name) {
+                return !mutable;
+            }
+        }
+        false
+    }
+
     fn peek(&mut self, token: Token) -> bool {
         self.lexer.peek() == Some(&token)
     }
@@ -635,6 +1280,7 @@ impl<'a> Parser<'a> {
                 self.parse_function(false, FunctionKind::Generator)
             }
             Some(Token::Class) => self.parse_class(false),
+            Some(Token::Enum) => self.parse_enum(),
             Some(Token::If) => self.parse_if_statement(),
             Some(Token::While) => self.parse_while(),
             Some(Token::For) => self.parse_for(),
@@ -669,23 +1315,73 @@ impl<'a> Parser<'a> {
         let saved = self.scope_bits;
         self.scope_bits |= scope as u8;
         self.scope.push(Scope::new(scope));
+        let depth = self.scope.len();
         let mut statements = Vec::new();
-        while !self.eat(Token::RightBrace) {
+        loop {
+            if self.eat(Token::RightBrace) {
+                if scope != ParseScope::TopLevel {
+                    break;
+                }
+                self.report(Error::UnexpectedToken);
+                continue;
+            }
+            let (line, column) = self.lexer.position;
+            let doc = self.doc_comment(line);
             match self.parse_statement() {
-                Ok(s) => statements.push(s),
+                Ok(s) => {
+                    if let (Some(text), true) = (doc, is_declaration(&s)) {
+                        self.docs.push(Doc { line, column, text });
+                    }
+                    statements.push(Node::Position(line, column));
+                    statements.push(s);
+                }
                 Err(Error::NormalEOF) if scope == ParseScope::TopLevel => break,
                 Err(e) => {
-                    self.scope_bits = saved;
-                    self.scope.pop();
-                    return Err(e);
+                    // nested functions and bindings may not have cleaned up
+                    self.scope.truncate(depth);
+                    self.scope_bits = saved | scope as u8;
+                    self.report(e);
+                    if scope != ParseScope::TopLevel && self.lexer.peek().is_none() {
+                        self.scope_bits = saved;
+                        self.scope.pop();
+                        return Err(Error::UnexpectedEOF);
+                    }
+                    self.synchronize();
                 }
             }
         }
-        let scope = self.scope.pop().unwrap();
+        let mut scope = self.scope.pop().unwrap();
+        if scope.kind != ScopeKind::TopLevel {
+            scope.end = Some(self.lexer.position);
+        }
         self.scope_bits = saved;
         Ok(Node::Block(scope, statements))
     }
 
+    // the `///` comments on the lines right above `line`, without the slashes
+    fn doc_comment(&self, line: usize) -> Option<String> {
+        let mut lines = Vec::new();
+        let mut next = line;
+        for comment in self.lexer.comments.iter().rev() {
+            let text = &comment.text;
+            if comment.line + 1 != next || !text.starts_with("///") || text.starts_with("////") {
+                break;
+            }
+            let text = &text[3..];
+            lines.push(if text.starts_with(' ') {
+                &text[1..]
+            } else {
+                text
+            });
+            next = comment.line;
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
     fn parse_lexical_declaration(&mut self) -> Result<Node, Error> {
         let mutable = if self.eat(Token::Let) {
             true
@@ -694,6 +1390,27 @@ impl<'a> Parser<'a> {
         } else {
             return Err(Error::UnexpectedToken);
         };
+        if let Some(Token::LeftParen) | Some(Token::LeftBracket) | Some(Token::LeftBrace) =
+            self.lexer.peek()
+        {
+            // const (x: a, y: b) = expr;
+            let start = self.scope.last().unwrap().bindings.len();
+            let pattern = self.parse_primary_pattern()?;
+            for (_, m) in self
+                .scope
+                .last_mut()
+                .unwrap()
+                .bindings
+                .iter_mut()
+                .skip(start)
+            {
+                *m = mutable;
+            }
+            self.expect(Token::Operator(Operator::Assign))?;
+            let init = self.parse_expression()?;
+            self.expect(Token::Semicolon)?;
+            return Ok(Node::LexicalDestructuring(pattern, Box::new(init)));
+        }
         let name = self.parse_identifier(false)?;
         self.declare(name.as_str(), mutable)?;
         self.expect(Token::Operator(Operator::Assign))?;
@@ -714,11 +1431,13 @@ impl<'a> Parser<'a> {
         };
         self.expect(Token::LeftParen)?;
         let args = self.parse_parameters(Token::RightParen)?;
-        let body = self.parse_block(match kind {
-            FunctionKind::Normal => ParseScope::Function,
-            FunctionKind::Async => ParseScope::AsyncFunction,
-            FunctionKind::Generator => ParseScope::GeneratorFunction,
-            _ => unreachable!(),
+        let body = self.with_bindings(&parameter_names(&args), |p| {
+            p.parse_block(match kind {
+                FunctionKind::Normal => ParseScope::Function,
+                FunctionKind::Async => ParseScope::AsyncFunction,
+                FunctionKind::Generator => ParseScope::GeneratorFunction,
+                _ => unreachable!(),
+            })
         })?;
         Ok(if expression {
             Node::FunctionExpression(kind, name, args, Box::new(body))
@@ -766,7 +1485,7 @@ impl<'a> Parser<'a> {
         let binding = self.parse_identifier(false)?;
         self.expect(Token::In)?;
         let target = self.parse_assignment_expression()?;
-        let body = self.parse_block(ParseScope::Loop)?;
+        let body = self.with_bindings(&[&binding], |p| p.parse_block(ParseScope::Loop))?;
         Ok(Node::ForLoop(
             r#async,
             binding,
@@ -817,7 +1536,13 @@ impl<'a> Parser<'a> {
             } else {
                 None
             };
-            let catch_clause = Box::new(self.parse_block(ParseScope::Block)?);
+            let catch_clause = match &binding {
+                Some(binding) => {
+                    self.with_bindings(&[binding], |p| p.parse_block(ParseScope::Block))?
+                }
+                None => self.parse_block(ParseScope::Block)?,
+            };
+            let catch_clause = Box::new(catch_clause);
             let finally_clause = if self.eat(Token::Finally) {
                 Some(Box::new(self.parse_block(ParseScope::Block)?))
             } else {
@@ -917,6 +1642,7 @@ impl<'a> Parser<'a> {
                 self.lexer.next();
                 self.parse_function(false, FunctionKind::Normal)
             }
+            Some(Token::Enum) => self.parse_enum(),
             _ => Err(Error::UnexpectedToken),
         }?;
         Ok(Node::ExportDeclaration(Box::new(decl)))
@@ -963,6 +1689,15 @@ impl<'a> Parser<'a> {
             Some(Token::Operator(Operator::PowAssign)) => op_assign!(Operator::PowAssign),
             Some(Token::Operator(Operator::DivAssign)) => op_assign!(Operator::DivAssign),
             Some(Token::Operator(Operator::ModAssign)) => op_assign!(Operator::ModAssign),
+            Some(Token::Operator(Operator::LogicalANDAssign)) => {
+                op_assign!(Operator::LogicalANDAssign)
+            }
+            Some(Token::Operator(Operator::LogicalORAssign)) => {
+                op_assign!(Operator::LogicalORAssign)
+            }
+            Some(Token::Operator(Operator::NullishCoalescingAssign)) => {
+                op_assign!(Operator::NullishCoalescingAssign)
+            }
             _ => {}
         }
 
@@ -971,6 +1706,7 @@ impl<'a> Parser<'a> {
 
     fn check_assignment_target(&self, node: &Node) -> Result<(), Error> {
         match node {
+            Node::Identifier(name) if self.is_constant(name) => Err(Error::ConstantAssignment),
             Node::Identifier(..) => Ok(()),
             Node::MemberExpression(..) => Ok(()),
             Node::ComputedMemberExpression(..) => Ok(()),
@@ -979,7 +1715,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_conditional_expression(&mut self) -> Result<Node, Error> {
-        let lhs = self.parse_logical_or_expression()?;
+        let lhs = self.parse_nullish_coalescing_expression()?;
         if self.eat(Token::Question) {
             let consequent = self.parse_assignment_expression()?;
             self.expect(Token::Colon)?;
@@ -993,6 +1729,12 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
 
+    binop_production!(
+        parse_nullish_coalescing_expression,
+        parse_logical_or_expression,
+        [Operator::NullishCoalescing]
+    );
+
     binop_production!(
         parse_logical_or_expression,
         parse_logical_and_expression,
@@ -1058,11 +1800,22 @@ impl<'a> Parser<'a> {
         [Operator::Mul, Operator::Div, Operator::Mod]
     );
 
-    binop_production!(
-        parse_exponentiation_expression,
-        parse_unary_expression,
-        [Operator::Pow]
-    );
+    fn parse_exponentiation_expression(&mut self) -> Result<Node, Error> {
+        let lhs = self.parse_unary_expression()?;
+        if self.eat(Token::Operator(Operator::Pow)) {
+            // `-a ** b` is ambiguous, require parens
+            if let Node::UnaryExpression(..) = lhs {
+                return Err(Error::UnexpectedToken);
+            }
+            let rhs = self.parse_exponentiation_expression()?;
+            return Ok(Node::BinaryExpression(
+                Operator::Pow,
+                Box::new(lhs),
+                Box::new(rhs),
+            ));
+        }
+        Ok(lhs)
+    }
 
     fn parse_unary_expression(&mut self) -> Result<Node, Error> {
         self.lexer.peek();
@@ -1110,6 +1863,22 @@ impl<'a> Parser<'a> {
         let mut base = self.parse_primary_expression()?;
         loop {
             if self.eat(Token::Dot) {
+                if let Some(Token::NumberLiteralStart(..)) = self.lexer.peek() {
+                    // `t.0`, read by hand so `t.0.1` isn't lexed as a float
+                    let mut index = match self.lexer.next() {
+                        Some(Token::NumberLiteralStart(c)) => c.to_digit(10).unwrap() as f64,
+                        _ => unreachable!(),
+                    };
+                    while let Some(d) = self.lexer.chars.peek().and_then(|c| c.to_digit(10)) {
+                        self.lexer.chars.next();
+                        index = index * 10.0 + d as f64;
+                    }
+                    base = Node::ComputedMemberExpression(
+                        Box::new(base),
+                        Box::new(Node::NumberLiteral(index)),
+                    );
+                    continue;
+                }
                 let property = self.parse_identifier(true)?;
                 base = Node::MemberExpression(Box::new(base), property);
             } else if self.eat(Token::LeftBracket) {
@@ -1156,12 +1925,15 @@ impl<'a> Parser<'a> {
             Some(Token::Export) if allow_keyword => Ok("export".to_string()),
             Some(Token::Default) if allow_keyword => Ok("default".to_string()),
             Some(Token::From) if allow_keyword => Ok("from".to_string()),
+            Some(Token::Match) if allow_keyword => Ok("match".to_string()),
+            Some(Token::Enum) if allow_keyword => Ok("enum".to_string()),
             Some(Token::Async) if allow_keyword => Ok("async".to_string()),
             Some(Token::Await) if allow_keyword => Ok("await".to_string()),
             Some(Token::Gen) if allow_keyword => Ok("gen".to_string()),
             Some(Token::Yield) if allow_keyword => Ok("yield".to_string()),
             Some(Token::Operator(Operator::Typeof)) if allow_keyword => Ok("typeof".to_string()),
             Some(Token::Operator(Operator::Void)) if allow_keyword => Ok("void".to_string()),
+            None => Err(Error::UnexpectedEOF),
             _ => Err(Error::UnexpectedToken),
         }
     }
@@ -1177,28 +1949,8 @@ impl<'a> Parser<'a> {
                 Ok(Node::StringLiteral(str))
             }
             Some(Token::NumberLiteralStart(c)) => {
-                let mut str = c.to_string();
-                let mut one_dot = false;
-                while let Some(c) = self.lexer.chars.peek() {
-                    match c {
-                        '0'...'9' => {
-                            str.push(self.lexer.chars.next().unwrap());
-                        }
-                        '.' => {
-                            if !one_dot {
-                                one_dot = true;
-                                str.push(self.lexer.chars.next().unwrap());
-                            } else {
-                                break;
-                            }
-                        }
-                        _ => break,
-                    }
-                }
-                match str.parse::<f64>() {
-                    Ok(n) => Ok(Node::NumberLiteral(n)),
-                    Err(_) => Err(Error::UnexpectedToken),
-                }
+                let n = self.parse_number_literal(c)?;
+                Ok(Node::NumberLiteral(n))
             }
             Some(Token::Colon) => {
                 let name = self.parse_identifier(false)?;
@@ -1243,31 +1995,100 @@ impl<'a> Parser<'a> {
                             break;
                         }
                     }
-                    let name = if self.eat(Token::LeftBracket) {
-                        let name = self.parse_expression()?;
-                        self.expect(Token::RightBracket)?;
-                        name
-                    } else {
-                        Node::StringLiteral(self.parse_identifier(true)?)
+                    let mut shorthand = None;
+                    self.lexer.peek();
+                    let name = match self.lexer.peek_immutable() {
+                        Some(Token::LeftBracket) => {
+                            self.lexer.next();
+                            let name = self.parse_expression()?;
+                            self.expect(Token::RightBracket)?;
+                            name
+                        }
+                        Some(Token::StringLiteralStart(c)) => {
+                            let c = *c;
+                            self.lexer.next();
+                            Node::StringLiteral(self.parse_string_literal(c)?)
+                        }
+                        Some(Token::NumberLiteralStart(c)) => {
+                            let c = *c;
+                            self.lexer.next();
+                            Node::NumberLiteral(self.parse_number_literal(c)?)
+                        }
+                        Some(Token::Identifier(..)) => {
+                            let name = self.parse_identifier(false)?;
+                            shorthand = Some(name.clone());
+                            Node::StringLiteral(name)
+                        }
+                        _ => Node::StringLiteral(self.parse_identifier(true)?),
                     };
-                    let mut init;
-                    if self.eat(Token::Colon) {
-                        init = self.parse_expression()?;
+                    let init = if self.eat(Token::Colon) {
+                        self.parse_expression()?
+                    } else if self.peek(Token::LeftParen) {
+                        self.parse_function(true, FunctionKind::Normal)?
+                    } else if let Some(name) = shorthand {
+                        // { x } is { x: x }
+                        Node::Identifier(name)
                     } else {
-                        init = self.parse_function(true, FunctionKind::Normal)?
-                    }
+                        return Err(Error::UnexpectedToken);
+                    };
                     fields.push(Node::Initializer(Box::new(name), Box::new(init)));
                 }
                 Ok(Node::ObjectLiteral(fields))
             }
-            Some(Token::LeftParen) => {
-                let (mut list, trailing) = self.parse_expression_list(Token::RightParen)?;
-                if self.eat(Token::Arrow) {
-                    // ( ... ) =>
-                    self.parse_arrow_function(FunctionKind::Normal, list)
-                } else if list.is_empty() {
-                    // ( )
-                    Err(Error::UnexpectedToken)
+            Some(Token::HashBrace) => {
+                let mut fields = Vec::new();
+                let mut first = true;
+                while !self.eat(Token::RightBrace) {
+                    if first {
+                        first = false;
+                    } else {
+                        self.expect(Token::Comma)?;
+                        if self.eat(Token::RightBrace) {
+                            break;
+                        }
+                    }
+                    let mut shorthand = false;
+                    let name = match self.lexer.peek() {
+                        Some(Token::StringLiteralStart(..)) => match self.lexer.next() {
+                            Some(Token::StringLiteralStart(c)) => self.parse_string_literal(c)?,
+                            _ => unreachable!(),
+                        },
+                        Some(Token::Identifier(..)) => {
+                            shorthand = true;
+                            self.parse_identifier(false)?
+                        }
+                        _ => self.parse_identifier(true)?,
+                    };
+                    let init = if self.eat(Token::Colon) {
+                        self.parse_expression()?
+                    } else if shorthand {
+                        Node::Identifier(name.clone())
+                    } else {
+                        return Err(Error::UnexpectedToken);
+                    };
+                    fields.push(Node::Initializer(
+                        Box::new(Node::StringLiteral(name)),
+                        Box::new(init),
+                    ));
+                }
+                Ok(Node::RecordLiteral(fields))
+            }
+            Some(Token::LeftParen) => {
+                let (mut list, trailing, names) = self.parse_tuple_elements()?;
+                if !names.is_empty() {
+                    // ( name: expr, name: expr )
+                    if names.len() != list.len() {
+                        return Err(Error::UnexpectedToken);
+                    }
+                    Ok(Node::NamedTupleLiteral(
+                        names.into_iter().zip(list).collect(),
+                    ))
+                } else if self.eat(Token::Arrow) {
+                    // ( ... ) =>
+                    self.parse_arrow_function(FunctionKind::Normal, list)
+                } else if list.is_empty() {
+                    // ( )
+                    Err(Error::UnexpectedToken)
                 } else if list.len() == 1 && !trailing {
                     // ( expr )
                     Ok(Node::ParenthesizedExpression(Box::new(list.pop().unwrap())))
@@ -1283,6 +2104,7 @@ impl<'a> Parser<'a> {
                 self.parse_arrow_function(FunctionKind::Async, list)
             }
             Some(Token::Class) => self.parse_class(true),
+            Some(Token::Match) => self.parse_match(),
             Some(Token::BackQuote) => {
                 let mut quasis = Vec::new();
                 let mut expressions = Vec::new();
@@ -1302,151 +2124,438 @@ impl<'a> Parser<'a> {
                             }
                         }
                         Some('`') => break,
-                        Some(c) => {
-                            if c == '\\' {
-                                match self.lexer.chars.next() {
-                                    Some('n') => current.push('\n'),
-                                    Some('t') => current.push('\t'),
-                                    Some('\\') => current.push('\\'),
-                                    Some('u') => {
-                                        if Some('{') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                        let mut n = String::new();
-                                        macro_rules! digit {
-                                            () => {
-                                                let next = self.lexer.chars.next();
-                                                match next {
-                                                    Some('0'...'9') | Some('a'...'f')
-                                                    | Some('A'...'F') => {
-                                                        n.push(next.unwrap());
-                                                    }
-                                                    _ => return Err(Error::UnexpectedToken),
-                                                }
-                                            };
-                                        }
-                                        digit!();
-                                        digit!();
-                                        digit!();
-                                        digit!();
-                                        match u32::from_str_radix(n.as_str(), 16) {
-                                            Ok(n) => match std::char::from_u32(n) {
-                                                Some(c) => current.push(c),
-                                                None => return Err(Error::UnexpectedToken),
-                                            },
-                                            Err(_) => return Err(Error::UnexpectedToken),
-                                        }
-                                        if Some('}') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                    }
-                                    Some('U') => {
-                                        if Some('{') != self.lexer.chars.next() {
-                                            return Err(Error::UnexpectedToken);
-                                        }
-                                        let mut name = String::new();
-                                        loop {
-                                            match self.lexer.chars.next() {
-                                                Some('}') => break,
-                                                None => return Err(Error::UnexpectedEOF),
-                                                Some(c) => name.push(c),
-                                            }
-                                        }
-                                        match UNICODE_NAME_MAP.get(name.as_str()) {
-                                            Some(c) => current.push(*c),
-                                            None => return Err(Error::UnexpectedToken),
-                                        };
-                                    }
-                                    None | _ => return Err(Error::UnexpectedEOF),
-                                }
-                            } else {
-                                current.push(c);
-                            }
-                        }
+                        Some('\\') => current.push(self.parse_escape()?),
+                        Some(c) => current.push(c),
                         None => return Err(Error::UnexpectedEOF),
                     }
                 }
                 quasis.push(current);
                 Ok(Node::TemplateLiteral(quasis, expressions))
             }
+            None => Err(Error::UnexpectedEOF),
             _ => Err(Error::UnexpectedToken),
         }
     }
 
-    fn parse_string_literal(&mut self, char: char) -> Result<String, Error> {
-        let mut str = String::new();
-        while let Some(c) = self.lexer.chars.peek() {
-            if c == &char {
-                self.lexer.chars.next();
-                break;
+    fn parse_match(&mut self) -> Result<Node, Error> {
+        let value = self.parse_expression()?;
+        self.expect(Token::LeftBrace)?;
+        let mut arms = Vec::new();
+        let mut first = true;
+        while !self.eat(Token::RightBrace) {
+            if first {
+                first = false;
+            } else {
+                self.expect(Token::Comma)?;
+                if self.eat(Token::RightBrace) {
+                    break;
+                }
             }
-            let c = self.lexer.chars.next().unwrap();
-            match c {
-                '\\' => match self.lexer.chars.next().unwrap() {
-                    'n' => str.push('\n'),
-                    't' => str.push('\t'),
-                    '"' => str.push('"'),
-                    '\'' => str.push('\''),
-                    '\\' => str.push('\\'),
-                    'u' => {
-                        if Some('{') != self.lexer.chars.next() {
-                            return Err(Error::UnexpectedToken);
-                        }
-                        let mut n = String::new();
-                        macro_rules! digit {
-                            () => {
-                                let next = self.lexer.chars.next();
-                                match next {
-                                    Some('0'...'9') | Some('a'...'f') | Some('A'...'F') => {
-                                        n.push(next.unwrap());
-                                    }
-                                    _ => return Err(Error::UnexpectedToken),
-                                }
-                            };
+            self.scope.push(Scope::new(ParseScope::Block));
+            let arm = self.parse_match_arm();
+            let scope = self.scope.pop().unwrap();
+            let (pattern, guard, body) = arm?;
+            arms.push(Node::MatchArm(scope, pattern, guard, Box::new(body)));
+        }
+        self.check_exhaustive(&arms)?;
+        Ok(Node::MatchExpression(Box::new(value), arms))
+    }
+
+    // a match over the members of a known enum must cover all of them
+    fn check_exhaustive(&self, arms: &[Node]) -> Result<(), Error> {
+        fn enum_members<'p>(pattern: &'p Pattern, out: &mut Vec<(&'p str, &'p str)>) -> bool {
+            match pattern {
+                Pattern::Value(node) => match &**node {
+                    Node::MemberExpression(base, member) => match &**base {
+                        Node::Identifier(name) => {
+                            out.push((name.as_str(), member.as_str()));
+                            true
                         }
-                        digit!();
-                        digit!();
-                        digit!();
-                        digit!();
-                        match u32::from_str_radix(n.as_str(), 16) {
-                            Ok(n) => match std::char::from_u32(n) {
-                                Some(c) => str.push(c),
-                                None => return Err(Error::UnexpectedToken),
-                            },
-                            Err(_) => return Err(Error::UnexpectedToken),
+                        _ => false,
+                    },
+                    _ => false,
+                },
+                Pattern::Or(alternatives) => alternatives.iter().all(|p| enum_members(p, out)),
+                _ => false,
+            }
+        }
+
+        let mut covered = Vec::new();
+        let mut name = None;
+        for arm in arms {
+            if let Node::MatchArm(_, pattern, guard, _) = arm {
+                match pattern {
+                    Pattern::Wildcard | Pattern::Binding(..) if guard.is_none() => return Ok(()),
+                    _ => {}
+                }
+                let mut members = Vec::new();
+                if !enum_members(pattern, &mut members) {
+                    return Ok(());
+                }
+                for (e, member) in members {
+                    if self.lookup_enum(e).is_none() || name.get_or_insert(e) != &e {
+                        return Ok(());
+                    }
+                    if guard.is_none() {
+                        covered.push(member);
+                    }
+                }
+            }
+        }
+        match name.and_then(|name| self.lookup_enum(name)) {
+            Some(members) if !members.iter().all(|m| covered.contains(&m.as_str())) => {
+                Err(Error::NonExhaustiveMatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn parse_match_arm(&mut self) -> Result<(Pattern, Option<Box<Node>>, Node), Error> {
+        let pattern = self.parse_pattern()?;
+        let guard = if self.eat(Token::If) {
+            Some(Box::new(self.parse_expression()?))
+        } else {
+            None
+        };
+        self.expect(Token::Arrow)?;
+        let body = self.parse_assignment_expression()?;
+        Ok((pattern, guard, body))
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, Error> {
+        let pattern = self.parse_primary_pattern()?;
+        if self.peek(Token::Operator(Operator::BitwiseOR)) {
+            let mut alternatives = vec![pattern];
+            while self.eat(Token::Operator(Operator::BitwiseOR)) {
+                alternatives.push(self.parse_primary_pattern()?);
+            }
+            Ok(Pattern::Or(alternatives))
+        } else {
+            Ok(pattern)
+        }
+    }
+
+    fn parse_primary_pattern(&mut self) -> Result<Pattern, Error> {
+        self.lexer.peek();
+        match self.lexer.peek_immutable() {
+            Some(Token::Identifier(name)) if name == "_" => {
+                self.lexer.next();
+                Ok(Pattern::Wildcard)
+            }
+            Some(Token::Identifier(..)) => {
+                let name = self.parse_identifier(false)?;
+                if self.peek(Token::Dot) {
+                    // `a.b` is compared against rather than bound
+                    let mut base = Node::Identifier(name);
+                    while self.eat(Token::Dot) {
+                        let property = self.parse_identifier(true)?;
+                        base = Node::MemberExpression(Box::new(base), property);
+                    }
+                    Ok(Pattern::Value(Box::new(base)))
+                } else {
+                    self.declare(name.as_str(), false)?;
+                    Ok(Pattern::Binding(name))
+                }
+            }
+            Some(Token::LeftParen) => {
+                self.lexer.next();
+                let (mut list, trailing) = self.parse_pattern_list(Token::RightParen)?;
+                if list.iter().any(|p| p.1.is_some()) {
+                    // ( name: pattern, name: pattern )
+                    let mut fields = Vec::new();
+                    for (pattern, name) in list {
+                        match (name, pattern) {
+                            (Some(name), pattern) => fields.push((name, pattern)),
+                            // `(x: 0, y)` binds field `y` to `y`
+                            (None, Pattern::Binding(name)) => {
+                                fields.push((name.clone(), Pattern::Binding(name)))
+                            }
+                            _ => return Err(Error::UnexpectedToken),
                         }
-                        if Some('}') != self.lexer.chars.next() {
-                            return Err(Error::UnexpectedToken);
+                    }
+                    Ok(Pattern::NamedTuple(fields))
+                } else if list.len() == 1 && !trailing {
+                    Ok(list.pop().unwrap().0)
+                } else {
+                    Ok(Pattern::Tuple(list.into_iter().map(|p| p.0).collect()))
+                }
+            }
+            Some(Token::LeftBracket) => {
+                self.lexer.next();
+                let (list, ..) = self.parse_pattern_list(Token::RightBracket)?;
+                if list.iter().any(|p| p.1.is_some()) {
+                    return Err(Error::UnexpectedToken);
+                }
+                Ok(Pattern::Array(list.into_iter().map(|p| p.0).collect()))
+            }
+            Some(Token::LeftBrace) => {
+                self.lexer.next();
+                let mut fields = Vec::new();
+                let mut first = true;
+                while !self.eat(Token::RightBrace) {
+                    if first {
+                        first = false;
+                    } else {
+                        self.expect(Token::Comma)?;
+                        if self.eat(Token::RightBrace) {
+                            break;
                         }
                     }
-                    'U' => {
-                        if Some('{') != self.lexer.chars.next() {
+                    let key = match self.lexer.peek() {
+                        Some(Token::StringLiteralStart(..)) => match self.lexer.next() {
+                            Some(Token::StringLiteralStart(c)) => self.parse_string_literal(c)?,
+                            _ => unreachable!(),
+                        },
+                        _ => self.parse_identifier(true)?,
+                    };
+                    let pattern = if self.eat(Token::Colon) {
+                        self.parse_pattern()?
+                    } else {
+                        self.declare(key.as_str(), false)?;
+                        Pattern::Binding(key.clone())
+                    };
+                    fields.push((key, pattern));
+                }
+                Ok(Pattern::Object(fields))
+            }
+            Some(Token::Operator(Operator::Sub)) => {
+                self.lexer.next();
+                match self.parse_primary_expression()? {
+                    Node::NumberLiteral(n) => Ok(Pattern::Value(Box::new(Node::NumberLiteral(-n)))),
+                    _ => Err(Error::UnexpectedToken),
+                }
+            }
+            Some(Token::Null)
+            | Some(Token::True)
+            | Some(Token::False)
+            | Some(Token::StringLiteralStart(..))
+            | Some(Token::NumberLiteralStart(..))
+            | Some(Token::Colon) => Ok(Pattern::Value(Box::new(self.parse_primary_expression()?))),
+            None => Err(Error::UnexpectedEOF),
+            _ => Err(Error::UnexpectedToken),
+        }
+    }
+
+    // elements may be labelled as `name: pattern`, which is only valid in tuples
+    fn parse_pattern_list(
+        &mut self,
+        close: Token,
+    ) -> Result<(Vec<(Pattern, Option<String>)>, bool), Error> {
+        let mut list: Vec<(Pattern, Option<String>)> = Vec::new();
+        let mut first = true;
+        let mut trailing = false;
+        while !self.eat(close.clone()) {
+            if first {
+                first = false;
+            } else {
+                self.expect(Token::Comma)?;
+                trailing = true;
+                if self.eat(close.clone()) {
+                    break;
+                }
+                trailing = false;
+            }
+            let pattern = self.parse_pattern()?;
+            match pattern {
+                Pattern::Binding(name) if self.eat(Token::Colon) => {
+                    // the label was declared as a binding, take it back
+                    self.scope.last_mut().unwrap().bindings.shift_remove(&name);
+                    if list.iter().any(|(_, n)| n.as_ref() == Some(&name)) {
+                        return Err(Error::DuplicateBinding);
+                    }
+                    list.push((self.parse_pattern()?, Some(name)));
+                }
+                _ => list.push((pattern, None)),
+            }
+        }
+        Ok((list, trailing))
+    }
+
+    fn parse_number_literal(&mut self, first: char) -> Result<f64, Error> {
+        if first == '0' {
+            let radix = match self.lexer.chars.peek() {
+                Some('x') | Some('X') => 16,
+                Some('o') | Some('O') => 8,
+                Some('b') | Some('B') => 2,
+                _ => 10,
+            };
+            if radix != 10 {
+                self.lexer.chars.next();
+                let mut n = 0f64;
+                let mut digits = 0;
+                let mut last_digit = false;
+                while let Some(c) = self.lexer.chars.peek() {
+                    if *c == '_' {
+                        if !last_digit {
                             return Err(Error::UnexpectedToken);
                         }
-                        let mut name = String::new();
-                        loop {
-                            match self.lexer.chars.next() {
-                                Some('}') => break,
-                                None => return Err(Error::UnexpectedEOF),
-                                Some(c) => name.push(c),
-                            }
-                        }
-                        match UNICODE_NAME_MAP.get(name.as_str()) {
-                            Some(c) => str.push(*c),
-                            None => return Err(Error::UnexpectedToken),
-                        };
+                        last_digit = false;
+                    } else if let Some(d) = c.to_digit(radix) {
+                        n = n * f64::from(radix) + f64::from(d);
+                        digits += 1;
+                        last_digit = true;
+                    } else {
+                        break;
                     }
-                    _ => return Err(Error::UnexpectedToken),
-                },
-                '\r' | '\n' => {
-                    panic!("unexpected end of string");
+                    self.lexer.chars.next();
+                }
+                if digits == 0 || !last_digit {
+                    return Err(Error::UnexpectedToken);
+                }
+                return Ok(n);
+            }
+        }
+
+        let mut str = first.to_string();
+        let mut one_dot = false;
+        let mut last_digit = true;
+        while let Some(c) = self.lexer.chars.peek() {
+            match c {
+                '0'...'9' => {
+                    str.push(self.lexer.chars.next().unwrap());
+                    last_digit = true;
+                }
+                '_' => {
+                    if !last_digit {
+                        return Err(Error::UnexpectedToken);
+                    }
+                    self.lexer.chars.next();
+                    last_digit = false;
+                }
+                '.' => {
+                    if !one_dot && last_digit {
+                        one_dot = true;
+                        str.push(self.lexer.chars.next().unwrap());
+                        last_digit = false;
+                    } else {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+        if !last_digit && !str.ends_with('.') {
+            return Err(Error::UnexpectedToken);
+        }
+        match str.parse::<f64>() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(Error::UnexpectedToken),
+        }
+    }
+
+    fn parse_string_literal(&mut self, char: char) -> Result<String, Error> {
+        let mut str = String::new();
+        let mut multiline = false;
+        if self.lexer.chars.peek() == Some(&char) {
+            self.lexer.chars.next();
+            if self.lexer.chars.peek() != Some(&char) {
+                return Ok(str);
+            }
+            // """ ... """ may span lines, a leading newline is dropped
+            self.lexer.chars.next();
+            multiline = true;
+            if self.lexer.chars.peek() == Some(&'\n') {
+                self.lexer.chars.next();
+            }
+        }
+        let mut quotes = 0;
+        loop {
+            let c = match self.lexer.chars.next() {
+                Some(c) => c,
+                None => return Err(Error::UnexpectedEOF),
+            };
+            if c == char {
+                if !multiline {
+                    break;
+                }
+                quotes += 1;
+                if quotes == 3 {
+                    str.truncate(str.len() - 2);
+                    break;
                 }
+                str.push(c);
+                continue;
+            }
+            quotes = 0;
+            match c {
+                '\\' => str.push(self.parse_escape()?),
+                '\r' | '\n' if !multiline => return Err(Error::UnexpectedToken),
                 c => str.push(c),
             }
         }
         Ok(str)
     }
 
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        macro_rules! hex {
+            ($n:expr) => {{
+                let c = self.lexer.chars.next();
+                match c.and_then(|c| c.to_digit(16)) {
+                    Some(d) => $n = $n * 16 + d,
+                    None if c.is_none() => return Err(Error::UnexpectedEOF),
+                    None => return Err(Error::UnexpectedToken),
+                }
+            }};
+        }
+
+        match self.lexer.chars.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some(c @ '"') | Some(c @ '\'') | Some(c @ '`') | Some(c @ '$') | Some(c @ '\\') => {
+                Ok(c)
+            }
+            Some('x') => {
+                let mut n = 0;
+                hex!(n);
+                hex!(n);
+                Ok(std::char::from_u32(n).unwrap())
+            }
+            Some('u') => {
+                if Some('{') != self.lexer.chars.next() {
+                    return Err(Error::UnexpectedToken);
+                }
+                let mut n = 0;
+                let mut digits = 0;
+                while self.lexer.chars.peek() != Some(&'}') {
+                    if digits == 6 {
+                        return Err(Error::UnexpectedToken);
+                    }
+                    hex!(n);
+                    digits += 1;
+                }
+                self.lexer.chars.next();
+                if digits == 0 {
+                    return Err(Error::UnexpectedToken);
+                }
+                match std::char::from_u32(n) {
+                    Some(c) => Ok(c),
+                    None => Err(Error::UnexpectedToken),
+                }
+            }
+            Some('U') => {
+                if Some('{') != self.lexer.chars.next() {
+                    return Err(Error::UnexpectedToken);
+                }
+                let mut name = String::new();
+                loop {
+                    match self.lexer.chars.next() {
+                        Some('}') => break,
+                        None => return Err(Error::UnexpectedEOF),
+                        Some(c) => name.push(c),
+                    }
+                }
+                match UNICODE_NAME_MAP.get(name.as_str()) {
+                    Some(c) => Ok(*c),
+                    None => Err(Error::UnexpectedToken),
+                }
+            }
+            Some(_) => Err(Error::UnexpectedToken),
+            None => Err(Error::UnexpectedEOF),
+        }
+    }
+
     fn parse_class(&mut self, expression: bool) -> Result<Node, Error> {
         if !expression {
             self.expect(Token::Class)?;
@@ -1463,12 +2572,16 @@ impl<'a> Parser<'a> {
         self.expect(Token::LeftBrace)?;
         let mut fields = Vec::new();
         while !self.eat(Token::RightBrace) {
-            let name = self.parse_identifier(false)?;
+            // a computed name, as in [:inspect]() {}
+            let name = if self.eat(Token::LeftBracket) {
+                let name = self.parse_expression()?;
+                self.expect(Token::RightBracket)?;
+                name
+            } else {
+                Node::StringLiteral(self.parse_identifier(false)?)
+            };
             let f = self.parse_function(true, FunctionKind::Normal)?;
-            fields.push(Node::Initializer(
-                Box::new(Node::StringLiteral(name)),
-                Box::new(f),
-            ));
+            fields.push(Node::Initializer(Box::new(name), Box::new(f)));
         }
         if expression {
             Ok(Node::ClassExpression(name, extends, fields))
@@ -1477,6 +2590,60 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_enum(&mut self) -> Result<Node, Error> {
+        self.expect(Token::Enum)?;
+        let name = self.parse_identifier(false)?;
+        self.expect(Token::LeftBrace)?;
+        let mut members: Vec<(String, Option<f64>)> = Vec::new();
+        let mut first = true;
+        while !self.eat(Token::RightBrace) {
+            if first {
+                first = false;
+            } else {
+                self.expect(Token::Comma)?;
+                if self.eat(Token::RightBrace) {
+                    break;
+                }
+            }
+            let member = self.parse_identifier(true)?;
+            if members.iter().any(|(m, _)| m == &member) {
+                return Err(Error::DuplicateBinding);
+            }
+            let value = if self.eat(Token::Operator(Operator::Assign)) {
+                let negative = self.eat(Token::Operator(Operator::Sub));
+                match self.lexer.next() {
+                    Some(Token::NumberLiteralStart(c)) => {
+                        let n = self.parse_number_literal(c)?;
+                        Some(if negative { -n } else { n })
+                    }
+                    None => return Err(Error::UnexpectedEOF),
+                    _ => return Err(Error::UnexpectedToken),
+                }
+            } else {
+                None
+            };
+            members.push((member, value));
+        }
+
+        // members are unique symbols unless any of them is given a number,
+        // in which case the rest count up from the previous one
+        if members.iter().any(|(_, v)| v.is_some()) {
+            let mut next = 0f64;
+            for (_, value) in &mut members {
+                let n = value.unwrap_or(next);
+                *value = Some(n);
+                next = n + 1.0;
+            }
+        }
+
+        self.declare(name.as_str(), false)?;
+        self.scope.last_mut().unwrap().enums.insert(
+            name.clone(),
+            members.iter().map(|(m, _)| m.clone()).collect(),
+        );
+        Ok(Node::EnumDeclaration(name, members))
+    }
+
     fn parse_arrow_function(
         &mut self,
         kind: FunctionKind,
@@ -1499,20 +2666,22 @@ impl<'a> Parser<'a> {
                 _ => return Err(Error::UnexpectedToken),
             }
         }
-        let body = if self.peek(Token::LeftBrace) {
-            self.parse_block(match kind {
-                FunctionKind::Normal => ParseScope::Function,
-                FunctionKind::Async => ParseScope::AsyncFunction,
-                FunctionKind::Generator => ParseScope::GeneratorFunction,
-                _ => unreachable!(),
-            })?
-        } else {
-            let expr = self.parse_assignment_expression()?;
-            Node::Block(
-                Scope::new(ParseScope::Function),
-                vec![Node::ReturnStatement(Some(Box::new(expr)))],
-            )
-        };
+        let body = self.with_bindings(&parameter_names(&args), |p| {
+            if p.peek(Token::LeftBrace) {
+                p.parse_block(match kind {
+                    FunctionKind::Normal => ParseScope::Function,
+                    FunctionKind::Async => ParseScope::AsyncFunction,
+                    FunctionKind::Generator => ParseScope::GeneratorFunction,
+                    _ => unreachable!(),
+                })
+            } else {
+                let expr = p.parse_assignment_expression()?;
+                Ok(Node::Block(
+                    Scope::new(ParseScope::Function),
+                    vec![Node::ReturnStatement(Some(Box::new(expr)))],
+                ))
+            }
+        })?;
         Ok(Node::ArrowFunctionExpression(
             kind | FunctionKind::Arrow,
             args,
@@ -1540,6 +2709,37 @@ impl<'a> Parser<'a> {
         Ok((list, trailing))
     }
 
+    fn parse_tuple_elements(&mut self) -> Result<(Vec<Node>, bool, Vec<String>), Error> {
+        let mut list = Vec::new();
+        let mut names = Vec::new();
+        let mut first = true;
+        let mut trailing = false;
+        while !self.eat(Token::RightParen) {
+            if first {
+                first = false;
+            } else {
+                self.expect(Token::Comma)?;
+                trailing = true;
+                if self.eat(Token::RightParen) {
+                    break;
+                }
+                trailing = false;
+            }
+            let expr = self.parse_expression()?;
+            match expr {
+                Node::Identifier(name) if self.eat(Token::Colon) => {
+                    if names.contains(&name) {
+                        return Err(Error::DuplicateBinding);
+                    }
+                    names.push(name);
+                    list.push(self.parse_expression()?);
+                }
+                _ => list.push(expr),
+            }
+        }
+        Ok((list, trailing, names))
+    }
+
     fn parse_identifier_list(&mut self, close: Token) -> Result<Vec<String>, Error> {
         let mut identifiers = Vec::new();
         let mut first = true;
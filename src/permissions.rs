@@ -0,0 +1,146 @@
+use crate::agent::Agent;
+use crate::value::{ObjectKey, Value};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+// what the builtins may touch on behalf of a script. `None` allows anything,
+// a list allows only those paths (and everything below them) or hosts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permissions {
+    pub read: Option<Vec<PathBuf>>,
+    pub write: Option<Vec<PathBuf>>,
+    // "host" or "host:port"
+    pub net: Option<Vec<String>>,
+    // reading environment variables
+    pub env: bool,
+}
+
+impl Permissions {
+    pub fn allow_all() -> Permissions {
+        Permissions {
+            read: None,
+            write: None,
+            net: None,
+            env: true,
+        }
+    }
+
+    pub fn deny_all() -> Permissions {
+        Permissions {
+            read: Some(Vec::new()),
+            write: Some(Vec::new()),
+            net: Some(Vec::new()),
+            env: false,
+        }
+    }
+
+    pub fn check_read(&self, agent: &Agent, path: &str) -> Result<(), Value> {
+        check_path(agent, &self.read, "read", path)
+    }
+
+    pub fn check_write(&self, agent: &Agent, path: &str) -> Result<(), Value> {
+        check_path(agent, &self.write, "write", path)
+    }
+
+    pub fn check_net(&self, agent: &Agent, addr: &SocketAddr) -> Result<(), Value> {
+        match &self.net {
+            Some(hosts) => {
+                if hosts.iter().any(|h| allows_addr(h, addr)) {
+                    Ok(())
+                } else {
+                    Err(denied(agent, &format!("net access to '{}'", addr)))
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_env(&self, agent: &Agent) -> Result<(), Value> {
+        if self.env {
+            Ok(())
+        } else {
+            Err(denied(agent, "reading environment variables"))
+        }
+    }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions::allow_all()
+    }
+}
+
+fn denied(agent: &Agent, what: &str) -> Value {
+    let e = Value::new_error(agent, &format!("permission denied: {}", what));
+    e.set(
+        agent,
+        ObjectKey::from("code"),
+        Value::from("PermissionDenied"),
+    )
+    .unwrap();
+    e
+}
+
+// an entry is an address, or a hostname which allows whatever it resolves
+// to. either may carry a port, which then has to match as well
+fn allows_addr(entry: &str, addr: &SocketAddr) -> bool {
+    if let Ok(a) = entry.parse::<SocketAddr>() {
+        return a == *addr;
+    }
+    if let Ok(ip) = entry.parse::<IpAddr>() {
+        return ip == addr.ip();
+    }
+    let (host, port) = match entry.rfind(':') {
+        Some(i) => match entry[i + 1..].parse::<u16>() {
+            Ok(port) => (&entry[..i], Some(port)),
+            Err(_) => return false,
+        },
+        None => (entry, None),
+    };
+    if port.is_some() && port != Some(addr.port()) {
+        return false;
+    }
+    match (host, 0).to_socket_addrs() {
+        Ok(mut resolved) => resolved.any(|a| a.ip() == addr.ip()),
+        Err(_) => false,
+    }
+}
+
+// resolves symlinks and `..` so a path can't escape its allowed directory,
+// paths that don't exist yet are resolved through their parent
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap().join(path)
+    };
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => absolute(parent).join(name),
+        _ => path,
+    }
+}
+
+fn check_path(
+    agent: &Agent,
+    allowed: &Option<Vec<PathBuf>>,
+    kind: &str,
+    path: &str,
+) -> Result<(), Value> {
+    match allowed {
+        Some(dirs) => {
+            let path = absolute(Path::new(path));
+            if dirs.iter().any(|d| path.starts_with(absolute(d))) {
+                Ok(())
+            } else {
+                Err(denied(
+                    agent,
+                    &format!("{} access to '{}'", kind, path.display()),
+                ))
+            }
+        }
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,65 @@
+// a wasm32 build has no threads to run blocking work on, and no fs calls
+// that need them
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use std::cell::RefCell;
+use threadpool::ThreadPool;
+
+// runs blocking work like fs calls off the agent's thread. embedders can
+// hand the agent their own pool through `AgentBuilder::executor`.
+pub trait Executor {
+    fn execute(&self, job: Box<dyn FnOnce() + Send>);
+}
+
+impl Executor for ThreadPool {
+    fn execute(&self, job: Box<dyn FnOnce() + Send>) {
+        ThreadPool::execute(self, job);
+    }
+}
+
+// the default: starts with a single thread, adds one whenever work would
+// queue behind busy threads (up to `max`), and gives one back when most of
+// them are idle
+pub struct AdaptivePool {
+    pool: RefCell<ThreadPool>,
+    max: usize,
+}
+
+impl AdaptivePool {
+    pub fn new(name: Option<String>, max: usize) -> AdaptivePool {
+        let mut builder = threadpool::Builder::new().num_threads(1);
+        if let Some(name) = name {
+            builder = builder.thread_name(name);
+        }
+        AdaptivePool {
+            pool: RefCell::new(builder.build()),
+            max: max.max(1),
+        }
+    }
+}
+
+impl Executor for AdaptivePool {
+    fn execute(&self, job: Box<dyn FnOnce() + Send>) {
+        let mut pool = self.pool.borrow_mut();
+        let size = pool.max_count();
+        let busy = pool.active_count() + pool.queued_count();
+        if busy >= size && size < self.max {
+            pool.set_num_threads(size + 1);
+        } else if busy < size / 2 {
+            pool.set_num_threads(size - 1);
+        }
+        pool.execute(job);
+    }
+}
+
+pub struct Pool(Box<dyn Executor>);
+
+impl Pool {
+    pub fn new(executor: Box<dyn Executor>) -> Pool {
+        Pool(executor)
+    }
+
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.0.execute(Box::new(job));
+    }
+}
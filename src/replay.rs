@@ -0,0 +1,397 @@
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+use crate::builtins::fs::{Entry, FsResponse, Progress, Stat};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::builtins::net::NetRead;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, ErrorKind, Write};
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+use std::str::Split;
+
+// what the outside world did during a run: which timers fired, what fs calls
+// came back with and what sockets received, in the order the event loop saw
+// them. a replayed run does no io and gets those outcomes from the log
+// instead, so a flaky interleaving can be reproduced as often as needed.
+// events are matched to operations by async id, which comes out the same from
+// run to run as long as the script does the same thing.
+//
+// one event per line, `<turn> <async id> <outcome...>`, where turn counts the
+// polls of the event loop. strings and bytes are written as hex, `-` if empty.
+
+const HEADER: &str = "slither-events 1";
+
+pub enum Outcome {
+    Timer,
+    #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+    Fs(FsResponse),
+    #[cfg(not(target_arch = "wasm32"))]
+    Net(NetRead),
+}
+
+impl Outcome {
+    // the kind of operation it belongs to
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Outcome::Timer => "Timer",
+            #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+            Outcome::Fs(..) => "FS",
+            #[cfg(not(target_arch = "wasm32"))]
+            Outcome::Net(..) => "Net",
+        }
+    }
+}
+
+pub struct Event {
+    pub turn: u64,
+    pub async_id: u64,
+    pub outcome: Outcome,
+}
+
+// a recorded log, read back to replay it
+pub struct EventLog(VecDeque<Event>);
+
+impl EventLog {
+    pub fn read<R: io::Read>(reader: R) -> io::Result<EventLog> {
+        let mut lines = io::BufReader::new(reader).lines();
+        match lines.next() {
+            Some(Ok(ref line)) if line == HEADER => {}
+            _ => return Err(invalid(1)),
+        }
+        let mut events = VecDeque::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            events.push_back(parse(&line).ok_or_else(|| invalid(i + 2))?);
+        }
+        Ok(EventLog(events))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+pub(crate) enum Mode {
+    Live,
+    Record {
+        out: Box<dyn Write>,
+        turn: u64,
+        recorded: bool,
+    },
+    Replay(VecDeque<Event>),
+}
+
+impl Mode {
+    pub(crate) fn record(mut out: Box<dyn Write>) -> Mode {
+        // like the events, a log that can't be written is left incomplete
+        let _ = writeln!(out, "{}", HEADER);
+        Mode::Record {
+            out,
+            turn: 0,
+            recorded: false,
+        }
+    }
+
+    pub(crate) fn replay(log: EventLog) -> Mode {
+        Mode::Replay(log.0)
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        match self {
+            Mode::Replay(..) => true,
+            _ => false,
+        }
+    }
+
+    // flushed as it goes, so the log survives the run crashing
+    pub(crate) fn add(&mut self, async_id: u64, outcome: &Outcome) {
+        if let Mode::Record {
+            out,
+            turn,
+            recorded,
+        } = self
+        {
+            let _ = writeln!(out, "{} {} {}", turn, async_id, format(outcome));
+            let _ = out.flush();
+            *recorded = true;
+        }
+    }
+
+    pub(crate) fn end_turn(&mut self) {
+        if let Mode::Record { turn, recorded, .. } = self {
+            if *recorded {
+                *turn += 1;
+                *recorded = false;
+            }
+        }
+    }
+
+    // the events that were dispatched together in the next recorded turn
+    pub(crate) fn next_turn(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        if let Mode::Replay(queue) = self {
+            let turn = match queue.front() {
+                Some(event) => event.turn,
+                None => return events,
+            };
+            while queue.front().map_or(false, |e| e.turn == turn) {
+                events.push(queue.pop_front().unwrap());
+            }
+        }
+        events
+    }
+
+    pub(crate) fn has_events(&self) -> bool {
+        match self {
+            Mode::Replay(queue) => !queue.is_empty(),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        if let Mode::Replay(queue) = self {
+            queue.clear();
+        }
+    }
+}
+
+fn invalid(line: usize) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid event log at line {}", line),
+    )
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "-".to_string();
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s == "-" {
+        return Some(Vec::new());
+    }
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn unhex_string(s: &str) -> Option<String> {
+    String::from_utf8(unhex(s)?).ok()
+}
+
+// the kinds fs calls fail with, anything else comes back as Other
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+const ERROR_KINDS: &[ErrorKind] = &[
+    ErrorKind::NotFound,
+    ErrorKind::PermissionDenied,
+    ErrorKind::ConnectionRefused,
+    ErrorKind::ConnectionReset,
+    ErrorKind::ConnectionAborted,
+    ErrorKind::NotConnected,
+    ErrorKind::AddrInUse,
+    ErrorKind::AddrNotAvailable,
+    ErrorKind::BrokenPipe,
+    ErrorKind::AlreadyExists,
+    ErrorKind::WouldBlock,
+    ErrorKind::InvalidInput,
+    ErrorKind::InvalidData,
+    ErrorKind::TimedOut,
+    ErrorKind::WriteZero,
+    ErrorKind::Interrupted,
+    ErrorKind::UnexpectedEof,
+];
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn error_kind(name: &str) -> ErrorKind {
+    ERROR_KINDS
+        .iter()
+        .cloned()
+        .find(|kind| format!("{:?}", kind) == name)
+        .unwrap_or(ErrorKind::Other)
+}
+
+fn format(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Timer => "timer".to_string(),
+        #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+        Outcome::Fs(response) => format_fs(response),
+        #[cfg(not(target_arch = "wasm32"))]
+        Outcome::Net(read) => format_net(read),
+    }
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn format_fs(response: &FsResponse) -> String {
+    match response {
+        FsResponse::Read(s) => format!("fs read {}", hex(s.as_bytes())),
+        FsResponse::Metadata(m) => format!(
+            "fs metadata {} {} {} {} {} {}",
+            m.kind, m.size, m.modified, m.accessed, m.created, m.readonly
+        ),
+        FsResponse::Exists(exists) => format!("fs exists {}", exists),
+        FsResponse::Success => "fs success".to_string(),
+        FsResponse::Error(message, e) => format!(
+            "fs error {} {:?} {}",
+            hex(message.as_bytes()),
+            e.kind(),
+            hex(e.to_string().as_bytes())
+        ),
+        FsResponse::Listed(entries) => {
+            let mut line = format!("fs listed {}", entries.len());
+            for entry in entries {
+                line += &format!(
+                    " {} {} {}",
+                    entry.kind,
+                    hex(entry.path.as_bytes()),
+                    entry.size
+                );
+            }
+            line
+        }
+        FsResponse::Progress(p) => format!(
+            "fs progress {} {} {} {} {}",
+            hex(p.path.as_bytes()),
+            p.files,
+            p.total_files,
+            p.bytes,
+            p.total_bytes
+        ),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn format_net(read: &NetRead) -> String {
+    match read {
+        NetRead::Data(data) => format!("net data {}", hex(data)),
+        NetRead::End => "net end".to_string(),
+        NetRead::Error(e) => format!("net error {}", hex(e.as_bytes())),
+        NetRead::Resolved(addresses, ttl) if addresses.is_empty() => {
+            format!("net resolved {} -", ttl)
+        }
+        NetRead::Resolved(addresses, ttl) => {
+            let addresses = addresses.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            format!("net resolved {} {}", ttl, addresses.join(","))
+        }
+    }
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn parse_fs(kind: &str, fields: &mut Split<char>) -> Option<FsResponse> {
+    Some(match kind {
+        "read" => FsResponse::Read(unhex_string(fields.next()?)?),
+        "metadata" => {
+            let kind = match fields.next()? {
+                "file" => "file",
+                "directory" => "directory",
+                "symlink" => "symlink",
+                _ => return None,
+            };
+            let mut number = || fields.next()?.parse::<u64>().ok();
+            let (size, modified, accessed, created) = (number()?, number()?, number()?, number()?);
+            FsResponse::Metadata(Stat {
+                kind,
+                size,
+                modified,
+                accessed,
+                created,
+                readonly: fields.next()?.parse().ok()?,
+            })
+        }
+        "exists" => FsResponse::Exists(fields.next()?.parse().ok()?),
+        "success" => FsResponse::Success,
+        "error" => {
+            let message = unhex_string(fields.next()?)?;
+            let kind = error_kind(fields.next()?);
+            let e = io::Error::new(kind, unhex_string(fields.next()?)?);
+            FsResponse::Error(message, e)
+        }
+        "listed" => {
+            let count = fields.next()?.parse::<usize>().ok()?;
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                let kind = match fields.next()? {
+                    "file" => "file",
+                    "directory" => "directory",
+                    "symlink" => "symlink",
+                    _ => return None,
+                };
+                entries.push(Entry {
+                    kind,
+                    path: unhex_string(fields.next()?)?,
+                    size: fields.next()?.parse().ok()?,
+                });
+            }
+            FsResponse::Listed(entries)
+        }
+        "progress" => {
+            let path = unhex_string(fields.next()?)?;
+            let mut number = || fields.next()?.parse::<u64>().ok();
+            FsResponse::Progress(Progress {
+                path,
+                files: number()?,
+                total_files: number()?,
+                bytes: number()?,
+                total_bytes: number()?,
+            })
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_net(kind: &str, fields: &mut Split<char>) -> Option<NetRead> {
+    Some(match kind {
+        "data" => NetRead::Data(unhex(fields.next()?)?),
+        "end" => NetRead::End,
+        "error" => NetRead::Error(unhex_string(fields.next()?)?),
+        "resolved" => {
+            let ttl = fields.next()?.parse().ok()?;
+            let addresses = match fields.next()? {
+                "-" => Vec::new(),
+                addresses => addresses
+                    .split(',')
+                    .map(|a| a.parse().ok())
+                    .collect::<Option<_>>()?,
+            };
+            NetRead::Resolved(addresses, ttl)
+        }
+        _ => return None,
+    })
+}
+
+fn parse(line: &str) -> Option<Event> {
+    let mut fields = line.split(' ');
+    let turn = fields.next()?.parse().ok()?;
+    let async_id = fields.next()?.parse().ok()?;
+    let outcome = match (fields.next()?, fields.next()) {
+        ("timer", None) => Outcome::Timer,
+        #[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+        ("fs", Some(kind)) => Outcome::Fs(parse_fs(kind, &mut fields)?),
+        #[cfg(not(target_arch = "wasm32"))]
+        ("net", Some(kind)) => Outcome::Net(parse_net(kind, &mut fields)?),
+        _ => return None,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Event {
+        turn,
+        async_id,
+        outcome,
+    })
+}
@@ -0,0 +1,293 @@
+use crate::json;
+
+// a subset of the source map v3 format: enough to read the maps that
+// compilers produce and to write maps of our own bytecode
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const INLINE_PREFIX: &str = "data:application/json;base64,";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    generated_column: usize,
+    source: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    lines: Vec<Vec<Segment>>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    // all positions are 1-based, as in the rest of the runtime
+    pub fn add(&mut self, generated: (usize, usize), source: &str, line: usize, column: usize) {
+        let source = match self.sources.iter().position(|s| s == source) {
+            Some(i) => i,
+            None => {
+                self.sources.push(source.to_string());
+                self.sources.len() - 1
+            }
+        };
+        while self.lines.len() < generated.0 {
+            self.lines.push(Vec::new());
+        }
+        let segments = &mut self.lines[generated.0 - 1];
+        let segment = Segment {
+            generated_column: generated.1 - 1,
+            source,
+            line: line - 1,
+            column: column - 1,
+        };
+        let index = segments
+            .iter()
+            .position(|s| s.generated_column > segment.generated_column)
+            .unwrap_or_else(|| segments.len());
+        segments.insert(index, segment);
+    }
+
+    pub fn lookup(&self, line: usize, column: usize) -> Option<(&str, usize, usize)> {
+        let segments = self.lines.get(line.checked_sub(1)?)?;
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|s| s.generated_column < column)
+            .or_else(|| segments.first())?;
+        Some((
+            self.sources[segment.source].as_str(),
+            segment.line + 1,
+            segment.column + 1,
+        ))
+    }
+
+    pub fn parse(json: &str) -> Option<SourceMap> {
+        let root = match json_value(json, "sourceRoot") {
+            Some(i) => {
+                let mut root = json_string(json, i)?.0;
+                if !root.is_empty() && !root.ends_with('/') {
+                    root.push('/');
+                }
+                root
+            }
+            None => String::new(),
+        };
+
+        let mut sources = Vec::new();
+        let mut i = json_value(json, "sources")?;
+        if json.as_bytes().get(i) != Some(&b'[') {
+            return None;
+        }
+        i += 1;
+        loop {
+            i = skip_whitespace(json, i);
+            match json.as_bytes().get(i)? {
+                b']' => break,
+                b',' => i += 1,
+                _ => {
+                    let (source, end) = json_string(json, i)?;
+                    sources.push(format!("{}{}", root, source));
+                    i = end;
+                }
+            }
+        }
+
+        let mappings = json_string(json, json_value(json, "mappings")?)?.0;
+        let mut lines = Vec::new();
+        let mut state = [0i64; 4];
+        for line in mappings.split(';') {
+            let mut segments = Vec::new();
+            state[0] = 0;
+            for segment in line.split(',').filter(|s| !s.is_empty()) {
+                let fields = decode_vlq(segment)?;
+                state[0] += fields[0];
+                if fields.len() < 4 {
+                    // a segment without a source doesn't map anywhere
+                    continue;
+                }
+                for (s, f) in state.iter_mut().zip(fields.iter()).skip(1) {
+                    *s += f;
+                }
+                if state.iter().any(|n| *n < 0) || state[1] as usize >= sources.len() {
+                    return None;
+                }
+                segments.push(Segment {
+                    generated_column: state[0] as usize,
+                    source: state[1] as usize,
+                    line: state[2] as usize,
+                    column: state[3] as usize,
+                });
+            }
+            lines.push(segments);
+        }
+
+        Some(SourceMap { sources, lines })
+    }
+
+    pub fn to_json(&self, file: &str) -> String {
+        let mut mappings = String::new();
+        let mut state = [0i64; 4];
+        for (i, segments) in self.lines.iter().enumerate() {
+            if i > 0 {
+                mappings.push(';');
+            }
+            state[0] = 0;
+            for (j, s) in segments.iter().enumerate() {
+                if j > 0 {
+                    mappings.push(',');
+                }
+                let fields = [
+                    s.generated_column as i64,
+                    s.source as i64,
+                    s.line as i64,
+                    s.column as i64,
+                ];
+                for (state, field) in state.iter_mut().zip(fields.iter()) {
+                    encode_vlq(&mut mappings, field - *state);
+                    *state = *field;
+                }
+            }
+        }
+        let sources = self
+            .sources
+            .iter()
+            .map(|s| json::quote(s))
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"version\":3,\"file\":{},\"sources\":[{}],\"names\":[],\"mappings\":{}}}",
+            json::quote(file),
+            sources,
+            json::quote(&mappings)
+        )
+    }
+}
+
+pub enum Reference {
+    Inline(String),
+    Path(String),
+}
+
+// the target of a trailing `//# sourceMappingURL=` comment, inline maps are
+// decoded in place
+pub fn find_reference(source: &str) -> Option<Reference> {
+    let line = source.lines().rev().find(|l| !l.trim().is_empty())?;
+    let url = line.trim().trim_start_matches("//# sourceMappingURL=");
+    if url.len() == line.trim().len() {
+        return None;
+    }
+    if url.starts_with(INLINE_PREFIX) {
+        let bytes = decode_base64(&url[INLINE_PREFIX.len()..])?;
+        Some(Reference::Inline(String::from_utf8(bytes).ok()?))
+    } else {
+        Some(Reference::Path(url.to_string()))
+    }
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.bytes().take_while(|c| *c != b'=') {
+        buffer = (buffer << 6 | BASE64.iter().position(|b| *b == c)? as u32) & 0xfff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn decode_vlq(segment: &str) -> Option<Vec<i64>> {
+    let mut fields = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = BASE64.iter().position(|b| *b == c)? as i64;
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 == 0 {
+            let negative = value & 1 == 1;
+            value >>= 1;
+            fields.push(if negative { -value } else { value });
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 5;
+        }
+    }
+    if shift != 0 || fields.is_empty() {
+        return None;
+    }
+    Some(fields)
+}
+
+fn encode_vlq(out: &mut String, n: i64) {
+    let mut value = if n < 0 { (-n << 1) | 1 } else { n << 1 };
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn skip_whitespace(json: &str, mut i: usize) -> usize {
+    while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = json.as_bytes().get(i) {
+        i += 1;
+    }
+    i
+}
+
+// index of the value of a top level key
+fn json_value(json: &str, key: &str) -> Option<usize> {
+    let needle = json::quote(key);
+    let mut start = 0;
+    while let Some(found) = json[start..].find(&needle) {
+        let i = skip_whitespace(json, start + found + needle.len());
+        if json.as_bytes().get(i) == Some(&b':') {
+            return Some(skip_whitespace(json, i + 1));
+        }
+        start += found + needle.len();
+    }
+    None
+}
+
+fn json_string(json: &str, start: usize) -> Option<(String, usize)> {
+    let mut chars = json[start..].char_indices();
+    if chars.next()?.1 != '"' {
+        return None;
+    }
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            (i, '"') => return Some((out, start + i + 1)),
+            (_, '\\') => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'u' => {
+                    let mut n = 0;
+                    for _ in 0..4 {
+                        n = n * 16 + chars.next()?.1.to_digit(16)?;
+                    }
+                    out.push(std::char::from_u32(n)?);
+                }
+                c => out.push(c),
+            },
+            (_, c) => out.push(c),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::agent::Agent;
+
+// runs an agent's event loop as a task on a tokio runtime. this only adapts
+// the waiting: files, sockets and timers are still driven by the agent's own
+// mio poll and thread pool, not by tokio's reactor. mio only signals them to
+// a thread that is asleep in `Poll::poll`, so while the agent is idle that
+// wait occupies a thread of tokio's blocking pool, and jobs run on the
+// runtime's thread in between.
+
+impl Agent {
+    // the agent isn't Send, so this has to run on a current thread runtime
+    // or a LocalSet
+    pub async fn run_jobs_with_blocking_poll(&self) {
+        // a replay does no io to wait for
+        if self.replaying() {
+            self.run_jobs();
+            return;
+        }
+        while self.turn() {
+            // jobs left over by the budget run next turn, after letting the
+            // runtime's other tasks in
+            if self.has_jobs() {
+                tokio::task::yield_now().await;
+                continue;
+            }
+            let poll = self.mio.clone();
+            let tokens = tokio::task::spawn_blocking(move || {
+                let mut events = mio::Events::with_capacity(128);
+                poll.poll(&mut events, None).expect("mio poll failed");
+                events.iter().map(|event| event.token()).collect::<Vec<_>>()
+            })
+            .await
+            .expect("mio poll panicked");
+            self.dispatch(&tokens);
+        }
+    }
+}
@@ -1,17 +1,23 @@
+use crate::builtins::atomics::SharedMemory;
 use crate::interpreter::{AssemblerFunctionInfo, Context, Interpreter, Scope};
 use crate::intrinsics::{perform_await, promise::new_promise_capability};
 use crate::parser::FunctionKind;
-use crate::{Agent, IntoValue};
+use crate::{Agent, FromValue, IntoValue};
 use gc::{Gc, GcCell};
 use indexmap::IndexMap;
 use regex::Regex;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 type BuiltinFunction = fn(&Agent, Vec<Value>, &Context) -> Result<Value, Value>;
 
 static SYMBOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+// starts at 1 so that an empty inline cache never matches an object
+static OBJECT_COUNTER: AtomicUsize = AtomicUsize::new(1);
 #[derive(Debug, Clone, Trace, Finalize, Eq)]
 pub enum Symbol {
     Unregistered {
@@ -58,6 +64,25 @@ impl Hash for Symbol {
     }
 }
 
+// unregistered symbols in the order they were made, then registered ones
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        match (self, other) {
+            (Symbol::Unregistered { id, .. }, Symbol::Unregistered { id: ido, .. }) => id.cmp(ido),
+            (Symbol::Registered(s), Symbol::Registered(so)) => s.cmp(so),
+            (Symbol::Unregistered { .. }, _) => Ordering::Less,
+            (_, Symbol::Unregistered { .. }) => Ordering::Greater,
+        }
+    }
+}
+
 impl std::fmt::Display for Symbol {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -87,10 +112,19 @@ impl Symbol {
     }
 }
 
-#[derive(Trace, Finalize, Debug, Eq, Clone)]
+// numbers from 0 up to this that have no fraction are array indices, and
+// so are the strings they print as. every other key is a string or symbol.
+pub const MAX_INDEX: usize = 4_294_967_294;
+
+#[derive(Trace, Finalize, Debug, PartialEq, Eq, Hash, Clone)]
 pub enum ObjectKey {
+    // always an index, a number key past MAX_INDEX or with a fraction is
+    // kept as the string it prints as
     Number(usize),
-    String(String),
+    // shared with string values and the assembler's string table, so turning
+    // one into the other is a refcount bump rather than a copy. never the
+    // string of an index, those are Number.
+    String(#[unsafe_ignore_trace] Rc<str>),
     Symbol(Symbol),
 }
 
@@ -98,78 +132,43 @@ impl ObjectKey {
     fn to_number(&self) -> Option<usize> {
         match self {
             ObjectKey::Number(n) => Some(*n),
-            ObjectKey::String(s) => match s.parse::<usize>() {
-                Ok(n) => Some(n),
-                Err(_) => None,
-            },
-            ObjectKey::Symbol(..) => None,
+            _ => None,
         }
     }
 }
 
-impl PartialEq for ObjectKey {
-    fn eq(&self, other: &Self) -> bool {
-        match self {
-            ObjectKey::Number(n) => match other {
-                ObjectKey::Number(nv) => n == nv,
-                ObjectKey::String(s) => &n.to_string() == s,
-                ObjectKey::Symbol(..) => false,
-            },
-            ObjectKey::String(s) => match other {
-                ObjectKey::String(sv) => s == sv,
-                ObjectKey::Number(n) => &n.to_string() == s,
-                ObjectKey::Symbol(..) => false,
-            },
-            ObjectKey::Symbol(s) => match other {
-                ObjectKey::Symbol(sv) => s == sv,
-                _ => false,
-            },
-        }
+// "0" and "12" are indices, "01", "+1" and "-0" are not
+fn parse_index(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if s.len() > 1 && s.starts_with('0') {
+        return None;
+    }
+    match s.parse::<usize>() {
+        Ok(n) if n <= MAX_INDEX => Some(n),
+        _ => None,
     }
 }
 
+// indices first in numeric order, then strings, then symbols
 impl PartialOrd for ObjectKey {
     fn partial_cmp(&self, other: &ObjectKey) -> Option<std::cmp::Ordering> {
-        match self {
-            ObjectKey::Number(n) => match other {
-                ObjectKey::Number(nv) => n.partial_cmp(nv),
-                ObjectKey::String(s) => n.to_string().partial_cmp(s),
-                ObjectKey::Symbol(..) => Some(std::cmp::Ordering::Less),
-            },
-            ObjectKey::String(s) => match other {
-                ObjectKey::String(sv) => s.partial_cmp(sv),
-                ObjectKey::Number(n) => n.to_string().partial_cmp(s),
-                ObjectKey::Symbol(..) => Some(std::cmp::Ordering::Less),
-            },
-            ObjectKey::Symbol(..) => match other {
-                ObjectKey::Symbol(..) => Some(std::cmp::Ordering::Equal),
-                _ => Some(std::cmp::Ordering::Greater),
-            },
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for ObjectKey {
     fn cmp(&self, other: &ObjectKey) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
-    }
-}
-
-impl Hash for ObjectKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        match self {
-            ObjectKey::Number(n) => {
-                0.hash(state);
-                n.to_string().hash(state);
-            }
-            ObjectKey::String(s) => {
-                1.hash(state);
-                s.hash(state);
-            }
-            ObjectKey::Symbol(s) => {
-                2.hash(state);
-                s.hash(state);
-            }
+        use std::cmp::Ordering;
+        match (self, other) {
+            (ObjectKey::Number(n), ObjectKey::Number(nv)) => n.cmp(nv),
+            (ObjectKey::String(s), ObjectKey::String(sv)) => s.cmp(sv),
+            (ObjectKey::Symbol(s), ObjectKey::Symbol(sv)) => s.cmp(sv),
+            (ObjectKey::Number(..), _) => Ordering::Less,
+            (_, ObjectKey::Number(..)) => Ordering::Greater,
+            (ObjectKey::String(..), _) => Ordering::Less,
+            (_, ObjectKey::String(..)) => Ordering::Greater,
         }
     }
 }
@@ -184,21 +183,36 @@ impl std::fmt::Display for ObjectKey {
     }
 }
 
+impl From<Rc<str>> for ObjectKey {
+    fn from(s: Rc<str>) -> Self {
+        match parse_index(&s) {
+            Some(n) => ObjectKey::Number(n),
+            None => ObjectKey::String(s),
+        }
+    }
+}
+
 impl From<String> for ObjectKey {
     fn from(s: String) -> Self {
-        ObjectKey::String(s)
+        match parse_index(&s) {
+            Some(n) => ObjectKey::Number(n),
+            None => ObjectKey::String(s.into()),
+        }
     }
 }
 
 impl From<&str> for ObjectKey {
     fn from(s: &str) -> Self {
-        ObjectKey::String(s.to_string())
+        match parse_index(s) {
+            Some(n) => ObjectKey::Number(n),
+            None => ObjectKey::String(s.into()),
+        }
     }
 }
 
 impl From<u32> for ObjectKey {
     fn from(n: u32) -> Self {
-        ObjectKey::Number(n as usize)
+        ObjectKey::from(n as usize)
     }
 }
 
@@ -207,23 +221,28 @@ impl From<i32> for ObjectKey {
         if n >= 0 {
             ObjectKey::Number(n as usize)
         } else {
-            ObjectKey::String(n.to_string())
+            ObjectKey::String(n.to_string().into())
         }
     }
 }
 
 impl From<usize> for ObjectKey {
     fn from(n: usize) -> Self {
-        ObjectKey::Number(n)
+        if n <= MAX_INDEX {
+            ObjectKey::Number(n)
+        } else {
+            ObjectKey::String(n.to_string().into())
+        }
     }
 }
 
 impl From<f64> for ObjectKey {
     fn from(n: f64) -> Self {
-        if n >= 0f64 {
+        // -0 is index 0, as it prints as 0
+        if (0f64..=MAX_INDEX as f64).contains(&n) && n.fract() == 0f64 {
             ObjectKey::Number(n as usize)
         } else {
-            ObjectKey::String(n.to_string())
+            ObjectKey::String(crate::num_util::to_string(n).into())
         }
     }
 }
@@ -238,6 +257,7 @@ pub enum ObjectKind {
     Symbol(Symbol),
     Regex(Regex),
     Buffer(GcCell<Vec<u8>>),
+    SharedBuffer(Arc<SharedMemory>),
     BytecodeFunction {
         kind: FunctionKind,
         parameters: Vec<String>,
@@ -251,6 +271,9 @@ pub enum ObjectKind {
 unsafe impl gc::Trace for ObjectKind {
     custom_trace!(this, {
         match this {
+            ObjectKind::Array(values) => {
+                mark(values);
+            }
             ObjectKind::BytecodeFunction { scope, .. } => {
                 mark(scope);
             }
@@ -273,6 +296,7 @@ impl std::fmt::Debug for ObjectKind {
             ObjectKind::Regex(r) => format!("Regex({})", r),
             ObjectKind::Symbol(s) => format!("Symbol({:?})", s),
             ObjectKind::Buffer(b) => format!("Buffer({:?})", b),
+            ObjectKind::SharedBuffer(m) => format!("{:?}", m),
             ObjectKind::Custom(..) => "Custom".to_string(),
             ObjectKind::BytecodeFunction { position, .. } => {
                 format!("CompiledFunction @ {}", position)
@@ -288,9 +312,208 @@ pub struct ObjectInfo {
     pub kind: ObjectKind,
     properties: GcCell<IndexMap<ObjectKey, Value>>,
     prototype: Value,
+    #[unsafe_ignore_trace]
+    frozen: Cell<bool>,
+    // never reused, unlike the address
+    id: usize,
+}
+
+fn next_object_id() -> usize {
+    OBJECT_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+// the deepest prototype an inline cache remembers a property on
+const MAX_CACHE_DEPTH: usize = 3;
+
+// where a get or set site last found its named property. objects never lose
+// properties or change prototype, so an index stays valid for as long as the
+// object lives. an own property hits on any object that has the same key at
+// the same index, as objects built the same way do. one found on a prototype
+// only hits on the same receiver, and only while it and every object on the
+// way to the holder have not gained any properties.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct InlineCache {
+    index: usize,
+    // how many prototypes up the property was found
+    depth: usize,
+    // the id and property count of the receiver and each prototype up to the
+    // holder
+    chain: [(usize, usize); MAX_CACHE_DEPTH + 1],
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KindUsage {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+value_struct!(KindUsage { count, bytes });
+
+impl KindUsage {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+// what the objects on this thread's heap hold, including garbage that hasn't
+// been collected yet. strings are only counted where objects and arrays hold
+// them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MemoryUsage {
+    pub objects: KindUsage,
+    pub arrays: KindUsage,
+    pub buffers: KindUsage,
+    pub strings: KindUsage,
+}
+
+pub fn memory_usage() -> MemoryUsage {
+    let mut usage = MemoryUsage::default();
+    let mut strings = KindUsage::default();
+    let mut count_string = |value: &Value| {
+        if let Value::String(s) = value {
+            strings.add(s.len());
+        }
+    };
+    gc::for_each(|o: &ObjectInfo, _| {
+        let bytes = o.shallow_size();
+        // skip whatever is being modified right now
+        if let Some(properties) = o.properties.try_borrow() {
+            properties.values().for_each(&mut count_string);
+        }
+        match &o.kind {
+            ObjectKind::Array(values) => {
+                if let Some(values) = values.try_borrow() {
+                    values.iter().for_each(&mut count_string);
+                }
+                usage.arrays.add(bytes);
+            }
+            ObjectKind::Buffer(..) | ObjectKind::SharedBuffer(..) => usage.buffers.add(bytes),
+            _ => usage.objects.add(bytes),
+        }
+    });
+    usage.strings = strings;
+    usage
+}
+
+// bytes of the strings and buffer contents still held by objects and scopes
+// on this thread's heap, which the gc doesn't count. a string held in more
+// than one place is counted once.
+pub(crate) fn string_bytes() -> usize {
+    fn count(value: &Value, seen: &mut HashSet<*const u8>, bytes: &mut usize) {
+        match value {
+            Value::String(s) if seen.insert(s.as_ptr()) => *bytes += s.len(),
+            Value::Tuple(values, _) => values.iter().for_each(|v| count(v, seen, bytes)),
+            Value::Record(fields) => fields.values().for_each(|v| count(v, seen, bytes)),
+            _ => {}
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut bytes = 0;
+    gc::for_each(|o: &ObjectInfo, _| {
+        if let Some(properties) = o.properties.try_borrow() {
+            for value in properties.values() {
+                count(value, &mut seen, &mut bytes);
+            }
+        }
+        match &o.kind {
+            ObjectKind::String(s) => bytes += s.len(),
+            ObjectKind::Array(values) => {
+                if let Some(values) = values.try_borrow() {
+                    for value in values.iter() {
+                        count(value, &mut seen, &mut bytes);
+                    }
+                }
+            }
+            ObjectKind::Buffer(buffer) => {
+                if let Some(buffer) = buffer.try_borrow() {
+                    bytes += buffer.capacity();
+                }
+            }
+            ObjectKind::Custom(slots) | ObjectKind::BuiltinFunction(_, slots) => {
+                if let Some(slots) = slots.try_borrow() {
+                    for value in slots.values() {
+                        count(value, &mut seen, &mut bytes);
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+    gc::for_each(|scope: &GcCell<Scope>, _| {
+        if let Some(scope) = scope.try_borrow() {
+            scope.for_each_reference(|_, value| count(value, &mut seen, &mut bytes));
+        }
+    });
+    bytes
 }
 
 impl ObjectInfo {
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    // the bytes owned by this object, not counting strings or other objects.
+    // parts that are borrowed mutably right now are left out.
+    pub(crate) fn shallow_size(&self) -> usize {
+        let mut bytes = std::mem::size_of::<ObjectInfo>();
+        if let Some(properties) = self.properties.try_borrow() {
+            bytes += properties.capacity() * std::mem::size_of::<(ObjectKey, Value)>();
+        }
+        match &self.kind {
+            ObjectKind::Array(values) => {
+                if let Some(values) = values.try_borrow() {
+                    bytes += values.capacity() * std::mem::size_of::<Value>();
+                }
+            }
+            ObjectKind::Buffer(buffer) => {
+                if let Some(buffer) = buffer.try_borrow() {
+                    bytes += buffer.capacity();
+                }
+            }
+            ObjectKind::SharedBuffer(memory) => bytes += memory.len(),
+            _ => {}
+        }
+        bytes
+    }
+
+    // every value this object holds, with the name it is held under
+    pub(crate) fn for_each_reference<F: FnMut(String, &Value)>(&self, mut f: F) {
+        f("__proto__".to_string(), &self.prototype);
+        if let Some(properties) = self.properties.try_borrow() {
+            for (key, value) in properties.iter() {
+                let name = match key {
+                    ObjectKey::Number(n) => n.to_string(),
+                    ObjectKey::String(s) => s.to_string(),
+                    ObjectKey::Symbol(s) => s.to_string(),
+                };
+                f(name, value);
+            }
+        }
+        match &self.kind {
+            ObjectKind::Array(values) => {
+                if let Some(values) = values.try_borrow() {
+                    for (i, value) in values.iter().enumerate() {
+                        f(i.to_string(), value);
+                    }
+                }
+            }
+            ObjectKind::Custom(slots) | ObjectKind::BuiltinFunction(_, slots) => {
+                if let Some(slots) = slots.try_borrow() {
+                    for (name, value) in slots.iter() {
+                        f(format!("[[{}]]", name), value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // an own property, unless the object is being written to
+    pub(crate) fn try_get_own(&self, property: &ObjectKey) -> Option<Value> {
+        self.properties.try_borrow()?.get(property).cloned()
+    }
+
     fn get(&self, property: ObjectKey) -> Value {
         if let Some(n) = property.to_number() {
             if let ObjectInfo {
@@ -325,6 +548,9 @@ impl ObjectInfo {
         value: Value,
         receiver: Gc<ObjectInfo>,
     ) -> Result<Value, Value> {
+        if receiver.frozen.get() {
+            return Err(Value::new_error(agent, "cannot modify frozen object"));
+        }
         if let Some(n) = property.to_number() {
             if let ObjectInfo {
                 kind: ObjectKind::Array(values),
@@ -365,6 +591,100 @@ impl ObjectInfo {
         }
     }
 
+    // the same as get for a named property, using and updating the cache of
+    // the site it is read at
+    pub(crate) fn get_cached(&self, property: ObjectKey, cache: &mut InlineCache) -> Value {
+        if cache.depth == 0 {
+            if let Some((key, value)) = self.properties.borrow().get_index(cache.index) {
+                if *key == property {
+                    return value.clone();
+                }
+            }
+        } else if cache.chain[0].0 == self.id {
+            if let Some(value) = self.get_cached_from_prototype(cache) {
+                return value;
+            }
+        }
+
+        // numeric keys of arrays are not in the properties
+        if property.to_number().is_some() {
+            return self.get(property);
+        }
+        let mut chain = [(0, 0); MAX_CACHE_DEPTH + 1];
+        let mut object = self;
+        let mut depth = 0;
+        loop {
+            let properties = object.properties.borrow();
+            if depth <= MAX_CACHE_DEPTH {
+                chain[depth] = (object.id, properties.len());
+            }
+            if let Some((index, _, value)) = properties.get_full(&property) {
+                if depth <= MAX_CACHE_DEPTH {
+                    *cache = InlineCache {
+                        index,
+                        depth,
+                        chain,
+                    };
+                }
+                return value.clone();
+            }
+            match &object.prototype {
+                Value::Object(o) => object = o,
+                Value::Null => return Value::Null,
+                _ => unreachable!(),
+            }
+            depth += 1;
+        }
+    }
+
+    fn get_cached_from_prototype(&self, cache: &InlineCache) -> Option<Value> {
+        let mut object = self;
+        for (id, len) in &cache.chain[..cache.depth] {
+            if object.id != *id || object.properties.borrow().len() != *len {
+                return None;
+            }
+            object = match &object.prototype {
+                Value::Object(o) => o,
+                _ => return None,
+            };
+        }
+        if object.id != cache.chain[cache.depth].0 {
+            return None;
+        }
+        let properties = object.properties.borrow();
+        properties.get_index(cache.index).map(|(_, v)| v.clone())
+    }
+
+    // stores to an own property through the cache of the site it is written
+    // at, false if the cache missed and the store is still to be done. a miss
+    // on a property the object already has fills the cache for next time.
+    pub(crate) fn set_cached(
+        &self,
+        property: &ObjectKey,
+        value: &Value,
+        cache: &mut InlineCache,
+    ) -> bool {
+        if self.frozen.get() {
+            return false;
+        }
+        let mut properties = self.properties.borrow_mut();
+        if cache.depth == 0 {
+            if let Some((key, v)) = properties.get_index_mut(cache.index) {
+                if key == property {
+                    *v = value.clone();
+                    return true;
+                }
+            }
+        }
+        if property.to_number().is_none() {
+            if let Some((index, ..)) = properties.get_full(property) {
+                cache.index = index;
+                cache.depth = 0;
+            }
+        }
+        false
+    }
+
     fn keys(&self) -> Vec<ObjectKey> {
         let mut keys = Vec::new();
         if let ObjectKind::Array(values) = &self.kind {
@@ -390,15 +710,19 @@ pub enum Value {
     // Language types
     Null,
     Boolean(bool),
-    String(String),
+    // immutable, so cloning a string value only bumps a refcount
+    String(Rc<str>),
     Number(f64),
-    Symbol(Symbol),
+    // the rarer variants are boxed so that no payload is wider than the
+    // string's fat pointer, keeping a value at three words
+    Symbol(Box<Symbol>),
     Object(Gc<ObjectInfo>),
-    Tuple(Vec<Value>),
+    Tuple(Box<Vec<Value>>, Option<Rc<Vec<String>>>),
+    Record(Box<BTreeMap<String, Value>>),
 
     // Internal types
     Empty,
-    List(GcCell<VecDeque<Value>>),
+    List(Box<GcCell<VecDeque<Value>>>),
     WrappedContext(Gc<GcCell<Context>>, Option<Box<Value>>),
     Iterator(Box<Value>, Box<Value>),
 }
@@ -420,6 +744,7 @@ unsafe impl gc::Trace for Value {
             | Value::Symbol(_) => {}
             Value::Object(o) => mark(o),
             Value::Tuple(items, ..) => mark(items),
+            Value::Record(fields) => mark(fields),
 
             Value::Empty => {}
             Value::List(list) => mark(list),
@@ -458,8 +783,10 @@ impl PartialOrd for Value {
                 Value::Symbol(..) => Some(std::cmp::Ordering::Equal),
                 _ => None,
             },
-            Value::Object(..) | Value::Tuple(..) => match other {
-                Value::Object(..) | Value::Tuple(..) => Some(std::cmp::Ordering::Equal),
+            Value::Object(..) | Value::Tuple(..) | Value::Record(..) => match other {
+                Value::Object(..) | Value::Tuple(..) | Value::Record(..) => {
+                    Some(std::cmp::Ordering::Equal)
+                }
                 _ => panic!(),
             },
             _ => None,
@@ -469,15 +796,15 @@ impl PartialOrd for Value {
 
 impl Value {
     pub fn new_symbol(desc: Option<String>) -> Value {
-        Value::Symbol(Symbol::new_unregistered(false, desc))
+        Value::Symbol(Box::new(Symbol::new_unregistered(false, desc)))
     }
 
     pub fn new_private_symbol(desc: Option<String>) -> Value {
-        Value::Symbol(Symbol::new_unregistered(true, desc))
+        Value::Symbol(Box::new(Symbol::new_unregistered(true, desc)))
     }
 
     pub fn new_well_known_symbol(desc: String) -> Value {
-        Value::Symbol(Symbol::new_registered(desc))
+        Value::Symbol(Box::new(Symbol::new_registered(desc)))
     }
 
     pub fn new_object(prototype: Value) -> Value {
@@ -485,6 +812,8 @@ impl Value {
             kind: ObjectKind::Ordinary,
             properties: GcCell::new(IndexMap::new()),
             prototype,
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
@@ -493,58 +822,113 @@ impl Value {
             kind: ObjectKind::Custom(GcCell::new(HashMap::new())),
             properties: GcCell::new(IndexMap::new()),
             prototype,
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
     pub fn new_error(agent: &Agent, message: &str) -> Value {
         let mut properties = IndexMap::new();
-        properties.insert(
-            ObjectKey::from("message"),
-            Value::String(message.to_string()),
-        );
+        properties.insert(ObjectKey::from("message"), Value::from(message));
+        properties.insert(ObjectKey::from("stack"), Value::from(agent.stack_trace()));
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Ordinary,
             properties: GcCell::new(properties),
             prototype: agent.intrinsics.error_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
+    pub fn new_error_with_cause(agent: &Agent, message: &str, cause: Value) -> Value {
+        let e = Value::new_error(agent, message);
+        e.set(agent, ObjectKey::from("cause"), cause).unwrap();
+        e
+    }
+
+    pub fn is_error(&self, agent: &Agent) -> bool {
+        match self {
+            Value::Object(o) => o.prototype == agent.intrinsics.error_prototype,
+            _ => false,
+        }
+    }
+
     pub fn new_array(agent: &Agent) -> Value {
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Array(GcCell::new(Vec::new())),
             properties: GcCell::new(IndexMap::new()),
             prototype: agent.intrinsics.array_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
     pub fn new_regex_object(agent: &Agent, r: &str) -> Result<Value, Value> {
-        let re = match Regex::new(r) {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Value::new_error(agent, &format!("{}", e)));
-            }
-        };
+        let re = agent.compile_regex(r)?;
         Ok(Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Regex(re),
             properties: GcCell::new(IndexMap::new()),
             prototype: agent.intrinsics.regex_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         })))
     }
 
     pub fn new_buffer_from_vec(agent: &Agent, vec: Vec<u8>) -> Value {
+        agent.count_bytes(vec.capacity());
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::Buffer(GcCell::new(vec)),
             properties: GcCell::new(IndexMap::new()),
             prototype: agent.intrinsics.array_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
+    pub fn new_shared_buffer(agent: &Agent, memory: Arc<SharedMemory>) -> Value {
+        Value::Object(Gc::new(ObjectInfo {
+            kind: ObjectKind::SharedBuffer(memory),
+            properties: GcCell::new(IndexMap::new()),
+            prototype: agent.intrinsics.object_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
+        }))
+    }
+
+    pub fn shared_memory(&self) -> Option<Arc<SharedMemory>> {
+        match self {
+            Value::Object(o) => match &o.kind {
+                ObjectKind::SharedBuffer(memory) => Some(memory.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn new_list() -> Value {
-        Value::List(GcCell::new(VecDeque::new()))
+        Value::List(Box::new(GcCell::new(VecDeque::new())))
     }
 
     pub fn new_tuple() -> Value {
-        Value::Tuple(Vec::new())
+        Value::Tuple(Box::new(Vec::new()), None)
+    }
+
+    pub fn new_record() -> Value {
+        Value::Record(Box::new(BTreeMap::new()))
+    }
+
+    // records may only hold values that can't be mutated
+    pub fn is_deeply_immutable(&self) -> bool {
+        match self {
+            Value::Null
+            | Value::Boolean(..)
+            | Value::String(..)
+            | Value::Number(..)
+            | Value::Symbol(..)
+            | Value::Record(..) => true,
+            Value::Tuple(items, ..) => items.iter().all(Value::is_deeply_immutable),
+            _ => false,
+        }
     }
 
     pub fn new_bytecode_function(
@@ -552,6 +936,10 @@ impl Value {
         info: &AssemblerFunctionInfo,
         scope: Gc<GcCell<Scope>>,
     ) -> Value {
+        let mut properties = IndexMap::new();
+        if let Some(name) = &info.name {
+            properties.insert(ObjectKey::from("name"), Value::from(name.as_str()));
+        }
         Value::Object(Gc::new(ObjectInfo {
             kind: ObjectKind::BytecodeFunction {
                 kind: info.kind,
@@ -559,8 +947,10 @@ impl Value {
                 parameters: info.parameters.clone(),
                 scope,
             },
-            properties: GcCell::new(IndexMap::new()),
+            properties: GcCell::new(properties),
             prototype: agent.intrinsics.function_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
@@ -569,6 +959,8 @@ impl Value {
             kind: ObjectKind::BuiltinFunction(f, GcCell::new(HashMap::new())),
             properties: GcCell::new(IndexMap::new()),
             prototype: agent.intrinsics.function_prototype.clone(),
+            frozen: Cell::new(false),
+            id: next_object_id(),
         }))
     }
 
@@ -581,6 +973,17 @@ impl Value {
 }
 
 impl Value {
+    // what Object.is compares by. objects are the same only if they are one
+    // object, as with ==, but NaN is NaN and 0 is not -0.
+    pub fn same_value(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(n), Value::Number(vn)) => {
+                (n.is_nan() && vn.is_nan()) || n.to_bits() == vn.to_bits()
+            }
+            _ => self == other,
+        }
+    }
+
     pub fn type_of(&self) -> &str {
         match &self {
             Value::Null => "null",
@@ -594,6 +997,7 @@ impl Value {
                 _ => "object",
             },
             Value::Tuple(..) => "tuple",
+            Value::Record(..) => "record",
             _ => unreachable!(),
         }
     }
@@ -607,6 +1011,7 @@ impl Value {
             Value::Symbol(..) => true,
             Value::Object(..) => true,
             Value::Tuple(..) => true,
+            Value::Record(..) => true,
             _ => unreachable!(),
         }
     }
@@ -614,8 +1019,14 @@ impl Value {
     pub fn get(&self, agent: &Agent, key: ObjectKey) -> Result<Value, Value> {
         match self {
             Value::Object(o) => Ok(o.get(key)),
-            Value::Tuple(t, ..) => {
-                if let Some(n) = key.to_number() {
+            Value::Tuple(t, names) => {
+                let field = match (&key, names) {
+                    (ObjectKey::String(s), Some(names)) => names.iter().position(|n| **n == **s),
+                    _ => None,
+                };
+                if let Some(n) = field {
+                    Ok(t[n].clone())
+                } else if let Some(n) = key.to_number() {
                     Ok(t.get(n).unwrap_or(&Value::Null).clone())
                 } else if key == ObjectKey::from("length") {
                     Ok(Value::from(t.len() as f64))
@@ -623,6 +1034,13 @@ impl Value {
                     Ok(Value::Null)
                 }
             }
+            Value::Record(fields) => Ok(match &key {
+                ObjectKey::String(s) => fields.get(&**s),
+                ObjectKey::Number(n) => fields.get(&n.to_string()),
+                ObjectKey::Symbol(..) => None,
+            }
+            .unwrap_or(&Value::Null)
+            .clone()),
             _ => self.to_object(agent)?.get(agent, key),
         }
     }
@@ -630,6 +1048,7 @@ impl Value {
     pub fn set(&self, agent: &Agent, key: ObjectKey, value: Value) -> Result<Value, Value> {
         match self {
             Value::Object(o) => o.set(agent, key, value, o.clone()),
+            Value::Record(..) => Err(Value::new_error(agent, "cannot assign to immutable record")),
             _ => Err(Value::new_error(agent, "base must be an object")),
         }
     }
@@ -637,9 +1056,15 @@ impl Value {
     pub fn keys(&self, agent: &Agent) -> Result<Vec<ObjectKey>, Value> {
         match self {
             Value::Object(o) => Ok(o.keys()),
-            Value::Tuple(vec) => Ok((0..vec.len())
+            Value::Tuple(_, Some(names)) => {
+                Ok(names.iter().map(|n| ObjectKey::from(n.as_str())).collect())
+            }
+            Value::Tuple(vec, None) => Ok((0..vec.len())
                 .map(ObjectKey::from)
                 .collect::<Vec<ObjectKey>>()),
+            Value::Record(fields) => {
+                Ok(fields.keys().map(|k| ObjectKey::from(k.as_str())).collect())
+            }
             _ => Err(Value::new_error(agent, "base must be an object")),
         }
     }
@@ -693,32 +1118,40 @@ impl Value {
                 kind: ObjectKind::Boolean(*b),
                 properties: GcCell::new(IndexMap::new()),
                 prototype: agent.intrinsics.boolean_prototype.clone(),
+                frozen: Cell::new(false),
+                id: next_object_id(),
             }))),
             Value::Object(_) => Ok(self.clone()),
             Value::Number(n) => Ok(Value::Object(Gc::new(ObjectInfo {
                 kind: ObjectKind::Number(*n),
                 properties: GcCell::new(IndexMap::new()),
                 prototype: agent.intrinsics.number_prototype.clone(),
+                frozen: Cell::new(false),
+                id: next_object_id(),
             }))),
             Value::String(s) => Ok(Value::Object(Gc::new(ObjectInfo {
                 kind: ObjectKind::String(s.to_string()),
                 properties: GcCell::new(IndexMap::new()),
                 prototype: agent.intrinsics.string_prototype.clone(),
+                frozen: Cell::new(false),
+                id: next_object_id(),
             }))),
             Value::Symbol(s) => Ok(Value::Object(Gc::new(ObjectInfo {
-                kind: ObjectKind::Symbol(s.clone()),
+                kind: ObjectKind::Symbol((**s).clone()),
                 properties: GcCell::new(IndexMap::new()),
                 prototype: agent.intrinsics.symbol_prototype.clone(),
+                frozen: Cell::new(false),
+                id: next_object_id(),
             }))),
-            Value::Tuple(_) => Ok(self.clone()),
+            Value::Tuple(..) | Value::Record(_) => Ok(self.clone()),
             _ => unreachable!(),
         }
     }
 
     pub fn to_object_key(&self, agent: &Agent) -> Result<ObjectKey, Value> {
         match self {
-            Value::Symbol(s) => Ok(ObjectKey::Symbol(s.clone())),
-            Value::String(s) => Ok(ObjectKey::from(s.to_string())),
+            Value::Symbol(s) => Ok(ObjectKey::Symbol((**s).clone())),
+            Value::String(s) => Ok(ObjectKey::from(s.clone())),
             Value::Number(n) => Ok(ObjectKey::from(*n)),
             _ => Err(Value::new_error(agent, "cannot convert to object key")),
         }
@@ -734,7 +1167,7 @@ impl Value {
                     parameters,
                     ..
                 } => {
-                    let ctx = Context::new(Scope::new(Some(scope.clone())));
+                    let ctx = agent.call_context(Scope::new(Some(scope.clone())));
                     if *kind & FunctionKind::Arrow == FunctionKind::Arrow {
                         // FIXME: doesn't have `this` vs inherited `this` needs to be clarified
                     } else {
@@ -825,7 +1258,11 @@ impl Value {
 
     #[inline]
     pub fn inspect(agent: &Agent, value: &Value) -> String {
-        inspect(agent, value, 0, &mut HashSet::new())
+        Value::inspect_with(agent, value, &InspectOptions::default())
+    }
+
+    pub fn inspect_with(agent: &Agent, value: &Value, options: &InspectOptions) -> String {
+        inspect(agent, value, 0, &mut HashSet::new(), options)
     }
 }
 
@@ -851,7 +1288,12 @@ fn evaluate_body(
     let mut interpreter = Interpreter::new(position, ctx.clone());
 
     if kind & FunctionKind::Normal == FunctionKind::Normal {
-        interpreter.run(agent).unwrap()
+        let result = interpreter.run(agent).unwrap();
+        // generators and async functions keep their context, a normal call's
+        // is done with once it returns
+        drop(interpreter);
+        agent.recycle_context(ctx);
+        result
     } else if kind & FunctionKind::Generator == FunctionKind::Generator {
         ctx.borrow_mut().interpreter = Some(interpreter);
         let o = Value::new_custom_object(agent.intrinsics.generator_prototype.clone());
@@ -917,15 +1359,19 @@ impl PartialEq for Value {
                 _ => false,
             },
             Value::Object(o) => match &other {
-                Value::Object(vo) => ref_eq(&*o.properties.borrow(), &*vo.properties.borrow()),
+                Value::Object(vo) => o.id == vo.id,
                 _ => false,
             },
-            Value::Tuple(i) => match &other {
-                Value::Tuple(vi) => {
-                    i.len() == vi.len() && i.iter().enumerate().all(|(i, v)| &vi[i] == v)
+            Value::Tuple(i, n) => match &other {
+                Value::Tuple(vi, vn) => {
+                    n == vn && i.len() == vi.len() && i.iter().enumerate().all(|(i, v)| &vi[i] == v)
                 }
                 _ => false,
             },
+            Value::Record(f) => match &other {
+                Value::Record(vf) => f == vf,
+                _ => false,
+            },
             Value::Empty => match other {
                 Value::Empty => true,
                 _ => false,
@@ -954,7 +1400,9 @@ impl Hash for Value {
             }
             Value::Number(n) => {
                 3.hash(state);
-                n.to_bits().hash(state);
+                // 0 and -0 are equal, so hash the same
+                let bits = if *n == 0f64 { 0 } else { n.to_bits() };
+                bits.hash(state);
             }
             Value::Symbol(s) => {
                 4.hash(state);
@@ -962,12 +1410,16 @@ impl Hash for Value {
             }
             Value::Object(o) => {
                 5.hash(state);
-                // hash the memory address of the map sigh
-                (&*o.properties.borrow() as *const IndexMap<ObjectKey, Value>).hash(state);
+                o.id.hash(state);
             }
-            Value::Tuple(items) => {
+            Value::Tuple(items, names) => {
                 6.hash(state);
                 items.hash(state);
+                names.hash(state);
+            }
+            Value::Record(fields) => {
+                7.hash(state);
+                fields.hash(state);
             }
             _ => unreachable!(),
         }
@@ -976,12 +1428,18 @@ impl Hash for Value {
 
 impl From<&str> for Value {
     fn from(s: &str) -> Self {
-        Value::String(s.to_string())
+        Value::String(s.into())
     }
 }
 
 impl From<String> for Value {
     fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<Rc<str>> for Value {
+    fn from(s: Rc<str>) -> Self {
         Value::String(s)
     }
 }
@@ -1016,7 +1474,209 @@ impl IntoValue for std::net::AddrParseError {
 
 impl IntoValue for std::io::Error {
     fn into_value(&self, agent: &Agent) -> Value {
-        Value::new_error(agent, &format!("{}", self))
+        let e = Value::new_error(agent, &format!("{}", self));
+        e.set(
+            agent,
+            ObjectKey::from("code"),
+            Value::from(format!("{:?}", self.kind())),
+        )
+        .unwrap();
+        e
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(&self, _: &Agent) -> Value {
+        self.clone()
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(&self, _: &Agent) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(&self, _: &Agent) -> Value {
+        Value::from(self.as_str())
+    }
+}
+
+impl<'a> IntoValue for &'a str {
+    fn into_value(&self, _: &Agent) -> Value {
+        Value::from(*self)
+    }
+}
+
+macro_rules! number_conversions {
+    ($($t:ty),*) => {
+        $(
+            impl IntoValue for $t {
+                fn into_value(&self, _: &Agent) -> Value {
+                    Value::Number(*self as f64)
+                }
+            }
+
+            impl FromValue for $t {
+                fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+                    match value {
+                        Value::Number(n)
+                            if n.fract() == 0.0
+                                && n >= <$t>::min_value() as f64
+                                && n <= <$t>::max_value() as f64 =>
+                        {
+                            Ok(n as $t)
+                        }
+                        _ => Err(Value::new_error(
+                            agent,
+                            concat!("expected an integer in the range of ", stringify!($t)),
+                        )),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+number_conversions!(i32, i64, u8, u32, u64, usize);
+
+impl IntoValue for f64 {
+    fn into_value(&self, _: &Agent) -> Value {
+        Value::Number(*self)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(&self, agent: &Agent) -> Value {
+        match self {
+            Some(v) => v.into_value(agent),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(&self, agent: &Agent) -> Value {
+        let array = Value::new_array(agent);
+        if let Value::Object(o) = &array {
+            if let ObjectKind::Array(values) = &o.kind {
+                *values.borrow_mut() = self.iter().map(|v| v.into_value(agent)).collect();
+            }
+        }
+        array
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(&self, agent: &Agent) -> Value {
+        let object = Value::new_object(agent.intrinsics.object_prototype.clone());
+        for (k, v) in self {
+            object
+                .set(agent, ObjectKey::from(k.as_str()), v.into_value(agent))
+                .unwrap();
+        }
+        object
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(_: &Agent, value: Value) -> Result<Self, Value> {
+        Ok(value)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(Value::new_error(agent, "expected a boolean")),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(Value::new_error(agent, "expected a number")),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(Value::new_error(agent, "expected a string")),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Null => Ok(None),
+            v => Ok(Some(T::from_value(agent, v)?)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        let values = match &value {
+            Value::Object(o) => match &o.kind {
+                ObjectKind::Array(values) => values.borrow().clone(),
+                _ => return Err(Value::new_error(agent, "expected an array")),
+            },
+            Value::Tuple(values, _) => (**values).clone(),
+            _ => return Err(Value::new_error(agent, "expected an array")),
+        };
+        values
+            .into_iter()
+            .map(|v| T::from_value(agent, v))
+            .collect()
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(agent: &Agent, value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Object(..) | Value::Record(..) => {}
+            _ => return Err(Value::new_error(agent, "expected an object")),
+        }
+        let mut map = HashMap::new();
+        for key in value.keys(agent)? {
+            if let ObjectKey::Symbol(..) = key {
+                continue;
+            }
+            let v = value.get(agent, key.clone())?;
+            map.insert(key.to_string(), T::from_value(agent, v)?);
+        }
+        Ok(map)
+    }
+}
+
+// how much of a value inspect shows. the default shows all of it.
+#[derive(Debug, Clone, Default)]
+pub struct InspectOptions {
+    // objects nested deeper than this are shown as [Object] or [Array]
+    pub depth: Option<usize>,
+    // array items past this are counted rather than shown
+    pub max_array_items: Option<usize>,
+    // strings longer than this are cut short with ...
+    pub max_string_length: Option<usize>,
+    // color primitives with ansi escapes
+    pub colors: bool,
+}
+
+impl InspectOptions {
+    fn paint(&self, color: &str, text: String) -> String {
+        if self.colors {
+            format!("{}{}\x1b[39m", color, text)
+        } else {
+            text
+        }
     }
 }
 
@@ -1024,21 +1684,49 @@ fn inspect(
     agent: &Agent,
     value: &Value,
     indent: usize,
-    inspected: &mut HashSet<*const IndexMap<ObjectKey, Value>>,
+    inspected: &mut HashSet<usize>,
+    options: &InspectOptions,
 ) -> String {
     match value {
-        Value::Null => "null".to_string(),
-        Value::Boolean(b) => b.to_string(),
-        Value::Number(n) => crate::num_util::to_string(*n),
-        Value::String(s) => format!("'{}'", s),
-        Value::Symbol(s) => format!("{}", s),
-        Value::Tuple(items) => {
+        Value::Null => options.paint("\x1b[90m", "null".to_string()),
+        Value::Boolean(b) => options.paint("\x1b[33m", b.to_string()),
+        Value::Number(n) => options.paint("\x1b[33m", crate::num_util::to_string(*n)),
+        Value::String(s) => {
+            let s = match options.max_string_length {
+                Some(max) if s.chars().count() > max => {
+                    format!("{}...", s.chars().take(max).collect::<String>())
+                }
+                _ => s.to_string(),
+            };
+            options.paint("\x1b[32m", format!("'{}'", s))
+        }
+        Value::Symbol(s) => options.paint("\x1b[32m", format!("{}", s)),
+        Value::Tuple(items, names) => {
             let mut ins = Vec::new();
-            for item in items {
-                ins.push(inspect(agent, item, indent, inspected));
+            for (i, item) in items.iter().enumerate() {
+                let item = inspect(agent, item, indent, inspected, options);
+                match names {
+                    Some(names) => ins.push(format!("{}: {}", names[i], item)),
+                    None => ins.push(item),
+                }
             }
             format!("({})", ins.join(", "))
         }
+        Value::Record(fields) => {
+            let mut ins = Vec::new();
+            for (key, value) in fields.iter() {
+                ins.push(format!(
+                    "{}: {}",
+                    key,
+                    inspect(agent, value, indent, inspected, options)
+                ));
+            }
+            if ins.is_empty() {
+                "#{}".to_string()
+            } else {
+                format!("#{{ {} }}", ins.join(", "))
+            }
+        }
         Value::Object(o) => {
             if let ObjectKind::Regex(re) = &o.kind {
                 return format!("/{}/", re);
@@ -1048,26 +1736,52 @@ fn inspect(
                     o.get(ObjectKey::from("toString"))
                         .call(agent, value.clone(), vec![])
                 {
-                    return s;
+                    return match o.get(ObjectKey::from("stack")) {
+                        Value::String(ref stack) if !stack.is_empty() => {
+                            // keep the stack next to its error, above any causes
+                            let (head, causes) =
+                                s.split_at(s.find("\ncaused by: ").unwrap_or(s.len()));
+                            format!("{}\n{}{}", head, stack, causes)
+                        }
+                        _ => s.to_string(),
+                    };
                 }
             }
-            let hash_key = &*o.properties.borrow() as *const IndexMap<ObjectKey, Value>;
-            if inspected.contains(&hash_key) {
+            if inspected.contains(&o.id) {
                 "[Circular]".to_string()
             } else {
-                inspected.insert(hash_key);
+                inspected.insert(o.id);
+                // an :inspect method returns a string to show as it is, or a
+                // value to inspect in the object's place
+                let hook = o.get(ObjectKey::Symbol(Symbol::new_registered(
+                    "inspect".to_string(),
+                )));
+                if hook.type_of() == "function" {
+                    match hook.call(agent, value.clone(), vec![]) {
+                        Ok(Value::String(s)) => return s.to_string(),
+                        Ok(v) => return inspect(agent, &v, indent, inspected, options),
+                        // a broken hook shouldn't stop the object being shown
+                        Err(_) => {}
+                    }
+                }
                 let array = match o.kind {
                     ObjectKind::Array(..) => true,
                     _ => false,
                 };
                 let function = value.type_of() == "function";
                 let keys = value.keys(agent).unwrap();
+                let collapse = options.depth.map_or(false, |depth| indent > depth);
+                if collapse && !function && !keys.is_empty() {
+                    return if array { "[Array]" } else { "[Object]" }.to_string();
+                }
+                let mut items = 0;
+                let mut hidden = 0;
                 let mut out = String::new();
                 if function {
                     out += "[Function";
                     if let Value::String(name) = o.get(ObjectKey::from("name")) {
                         out += " ";
-                        out += name.as_str();
+                        out += &name;
                         if keys.len() == 1 {
                             out += "]";
                             return out;
@@ -1087,6 +1801,15 @@ fn inspect(
                     if function && key == ObjectKey::from("name") {
                         continue;
                     }
+                    if array {
+                        if let ObjectKey::Number(..) = key {
+                            if options.max_array_items.map_or(false, |max| items >= max) {
+                                hidden += 1;
+                                continue;
+                            }
+                            items += 1;
+                        }
+                    }
                     out += &format!(
                         "\n{}{}: {},",
                         "  ".repeat(indent + 1),
@@ -1095,10 +1818,19 @@ fn inspect(
                             agent,
                             &value.get(agent, key).unwrap(),
                             indent + 1,
-                            inspected
+                            inspected,
+                            options
                         )
                     )
                 }
+                if hidden > 0 {
+                    out += &format!(
+                        "\n{}... {} more item{}",
+                        "  ".repeat(indent + 1),
+                        hidden,
+                        if hidden == 1 { "" } else { "s" }
+                    );
+                }
                 out += &format!("\n{}{}", "  ".repeat(indent), if array { "]" } else { "}" });
                 out
             }